@@ -0,0 +1,352 @@
+/// A local stand-in for an ad server
+///
+/// The mock ad server runs in the viewer's process and answers ad requests for a placement id and size from a
+/// TOML file. Each placement has a waterfall of line items, which are tried in order, and an optional house ad
+/// that is served when none of them fill. A line item can simulate a demand source that fails, so that fallback
+/// and no-fill can be tested without any external service.
+///
+/// Example:
+///
+/// ```toml
+/// [[creatives]]
+/// id = "teapot-display"
+/// kind = "display"
+/// path = ".."
+/// sizes = [[300, 250], [728, 90]]
+///
+/// [[creatives]]
+/// id = "partner-display"
+/// kind = "display"
+/// path = ".."
+/// status = "timeout"
+///
+/// [[placements]]
+/// id = "homepage-top"
+/// sizes = [[728, 90], [300, 250]]
+/// line_items = ["partner-display", "teapot-display"]
+/// ```
+///
+/// Paths are relative to the TOML file. A display creative's path is the asset root with the Teapot manifest. For
+/// the other kinds, it is the native ad, VAST or bid response file. A creative without sizes fits any size.
+///
+use quicksilver::{Error, Result};
+
+use serde::Deserialize;
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// The sample ad server setup, relative to the asset root
+pub const DEFAULT_AD_SERVER: &str = "adserver/placements.toml";
+
+/// The placement that is requested when none is specified
+pub const DEFAULT_PLACEMENT: &str = "homepage-top";
+
+/// The kinds of creatives that the viewer can show
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CreativeKind {
+    /// The Teapot ad, built from the assets in an asset root
+    Display,
+    /// Native components in a TOML file
+    Native,
+    /// A VAST file, which is shown as its companions
+    Vast,
+    /// An OpenRTB bid response, which is shown as the winning creative
+    Openrtb,
+}
+
+/// The simulated answer of the demand source behind a line item
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceStatus {
+    /// The creative is returned
+    Ok,
+    /// The source returns an error
+    Error,
+    /// The source does not answer in time
+    Timeout,
+    /// The source answers without an ad
+    Empty,
+}
+
+impl Default for SourceStatus {
+    fn default() -> Self {
+        SourceStatus::Ok
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CreativeDefinition {
+    pub id: String,
+    pub kind: CreativeKind,
+    /// The asset root or file, relative to the ad server file
+    pub path: String,
+    /// The sizes that the creative can be served at. Empty for any size.
+    #[serde(default)]
+    pub sizes: Vec<(u32, u32)>,
+    #[serde(default)]
+    pub status: SourceStatus,
+}
+
+impl CreativeDefinition {
+    pub fn fits(&self, size: (u32, u32)) -> bool {
+        self.sizes.is_empty() || self.sizes.contains(&size)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct PlacementDefinition {
+    pub id: String,
+    /// The sizes that can be requested. The first one is the default.
+    pub sizes: Vec<(u32, u32)>,
+    /// Creative ids in the order that they are tried
+    #[serde(default)]
+    pub line_items: Vec<String>,
+    /// The creative id that is served when no line item fills
+    pub house_ad: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct AdServerConfig {
+    #[serde(default)]
+    creatives: Vec<CreativeDefinition>,
+    #[serde(default)]
+    placements: Vec<PlacementDefinition>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdRequest {
+    pub placement_id: String,
+    pub size: (u32, u32),
+}
+
+impl AdRequest {
+    pub fn new(placement_id: &str, size: (u32, u32)) -> Self {
+        AdRequest { placement_id: placement_id.to_string(), size }
+    }
+}
+
+/// A creative that was selected for a request, with its path resolved relative to the working directory
+#[derive(Clone, Debug)]
+pub struct ServedAd {
+    pub creative: CreativeDefinition,
+    pub path: PathBuf,
+    pub size: (u32, u32),
+    /// True when the house ad was served because no line item filled
+    pub is_fallback: bool,
+}
+
+#[derive(Clone, Debug)]
+pub enum AdDecision {
+    Fill(ServedAd),
+    /// No ad, with the reason
+    NoFill(String),
+}
+
+/// The decision for a request and what happened to each line item that was tried
+#[derive(Clone, Debug)]
+pub struct AdResponse {
+    pub request: AdRequest,
+    pub decision: AdDecision,
+    /// The creative id and the outcome, in the order they were tried
+    pub attempts: Vec<(String, String)>,
+}
+
+pub struct MockAdServer {
+    config: AdServerConfig,
+    /// The folder of the ad server file, for resolving creative paths
+    base_dir: PathBuf,
+}
+
+impl MockAdServer {
+    pub fn from_toml<P: AsRef<Path>>(text: &str, base_dir: P) -> Result<MockAdServer> {
+        let config: AdServerConfig =
+            toml::from_str(text).map_err(|e| Error::ContextError(format!("Invalid ad server file: {}", e)))?;
+        let server = MockAdServer { config, base_dir: base_dir.as_ref().to_path_buf() };
+        for placement in server.config.placements.iter() {
+            for id in placement.line_items.iter().chain(placement.house_ad.iter()) {
+                if server.creative(id).is_none() {
+                    let message = format!("Placement {} refers to an unknown creative {}", placement.id, id);
+                    return Err(Error::ContextError(message));
+                }
+            }
+        }
+        Ok(server)
+    }
+
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<MockAdServer> {
+        let text = fs::read_to_string(path.as_ref())?;
+        let base_dir = path.as_ref().parent().map(|dir| dir.to_path_buf()).unwrap_or_default();
+        MockAdServer::from_toml(&text, base_dir)
+    }
+
+    pub fn placement(&self, id: &str) -> Option<&PlacementDefinition> {
+        self.config.placements.iter().find(|placement| placement.id == id)
+    }
+
+    pub fn creative(&self, id: &str) -> Option<&CreativeDefinition> {
+        self.config.creatives.iter().find(|creative| creative.id == id)
+    }
+
+    /// Run the waterfall for the placement. Line items that do not fit the size or whose source fails are skipped.
+    pub fn request(&self, request: &AdRequest) -> AdResponse {
+        let mut attempts = Vec::new();
+        let placement = match self.placement(&request.placement_id) {
+            Some(placement) => placement,
+            None => {
+                let reason = format!("Unknown placement {}", request.placement_id);
+                return AdResponse { request: request.clone(), decision: AdDecision::NoFill(reason), attempts };
+            }
+        };
+        if !placement.sizes.contains(&request.size) {
+            let reason = format!("Placement {} does not allow {}x{}", placement.id, request.size.0, request.size.1);
+            return AdResponse { request: request.clone(), decision: AdDecision::NoFill(reason), attempts };
+        }
+        for id in placement.line_items.iter() {
+            // Unknown ids are rejected when the file is loaded
+            let creative = match self.creative(id) {
+                Some(creative) => creative,
+                None => continue,
+            };
+            let outcome = match creative.status {
+                _ if !creative.fits(request.size) => "no matching size",
+                SourceStatus::Ok => {
+                    attempts.push((id.clone(), "filled".to_string()));
+                    let decision = AdDecision::Fill(self.serve(creative, request.size, false));
+                    return AdResponse { request: request.clone(), decision, attempts };
+                }
+                SourceStatus::Error => "error",
+                SourceStatus::Timeout => "timeout",
+                SourceStatus::Empty => "no ad",
+            };
+            attempts.push((id.clone(), outcome.to_string()));
+        }
+        let house_ad = placement.house_ad.as_ref().and_then(|id| self.creative(id));
+        let decision = match house_ad {
+            Some(creative) if creative.fits(request.size) => {
+                attempts.push((creative.id.clone(), "house ad".to_string()));
+                AdDecision::Fill(self.serve(creative, request.size, true))
+            }
+            _ => AdDecision::NoFill(format!("No line item filled placement {}", placement.id)),
+        };
+        AdResponse { request: request.clone(), decision, attempts }
+    }
+
+    fn serve(&self, creative: &CreativeDefinition, size: (u32, u32), is_fallback: bool) -> ServedAd {
+        let path = normalize_path(&self.base_dir.join(&creative.path));
+        ServedAd { creative: creative.clone(), path, size, is_fallback }
+    }
+}
+
+/// Remove the ".." components that follow a folder name, so that "static/adserver/.." becomes "static". Paths
+/// under the asset root must start with it, e.g. for the svg cache.
+pub(crate) fn normalize_path(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir if result.file_name().is_some() => {
+                result.pop();
+            }
+            component => result.push(component),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVER: &str = r#"
+[[creatives]]
+id = "partner"
+kind = "display"
+path = ".."
+status = "timeout"
+
+[[creatives]]
+id = "leaderboard"
+kind = "display"
+path = ".."
+sizes = [[728, 90]]
+
+[[creatives]]
+id = "teapot"
+kind = "display"
+path = ".."
+sizes = [[300, 250], [728, 90]]
+
+[[creatives]]
+id = "house"
+kind = "native"
+path = "../teapot/native.toml"
+
+[[placements]]
+id = "top"
+sizes = [[728, 90], [300, 250], [320, 50]]
+line_items = ["partner", "leaderboard", "teapot"]
+
+[[placements]]
+id = "sidebar"
+sizes = [[300, 250], [320, 50]]
+line_items = ["partner", "teapot"]
+house_ad = "house"
+"#;
+
+    fn server() -> MockAdServer {
+        MockAdServer::from_toml(SERVER, "static/adserver").unwrap()
+    }
+
+    fn outcomes(response: &AdResponse) -> Vec<(&str, &str)> {
+        response.attempts.iter().map(|(id, outcome)| (id.as_str(), outcome.as_str())).collect()
+    }
+
+    #[test]
+    fn waterfall_falls_through_to_the_first_line_item_that_fills() {
+        let response = server().request(&AdRequest::new("top", (300, 250)));
+        assert_eq!(
+            outcomes(&response),
+            vec![("partner", "timeout"), ("leaderboard", "no matching size"), ("teapot", "filled")]
+        );
+        match response.decision {
+            AdDecision::Fill(ad) => {
+                assert_eq!(ad.creative.id, "teapot");
+                assert_eq!(ad.path, PathBuf::from("static"));
+                assert!(!ad.is_fallback);
+            }
+            AdDecision::NoFill(reason) => panic!("No fill: {}", reason),
+        }
+
+        let response = server().request(&AdRequest::new("top", (728, 90)));
+        assert_eq!(outcomes(&response).last(), Some(&("leaderboard", "filled")));
+    }
+
+    #[test]
+    fn house_ad_is_served_when_no_line_item_fills() {
+        let response = server().request(&AdRequest::new("sidebar", (320, 50)));
+        assert_eq!(
+            outcomes(&response),
+            vec![("partner", "timeout"), ("teapot", "no matching size"), ("house", "house ad")]
+        );
+        match response.decision {
+            AdDecision::Fill(ad) => {
+                assert_eq!(ad.path, PathBuf::from("static/teapot/native.toml"));
+                assert!(ad.is_fallback);
+            }
+            AdDecision::NoFill(reason) => panic!("No fill: {}", reason),
+        }
+
+        let response = server().request(&AdRequest::new("top", (320, 50)));
+        assert!(matches!(response.decision, AdDecision::NoFill(_)));
+    }
+
+    #[test]
+    fn requests_must_match_a_placement_and_size() {
+        assert!(matches!(server().request(&AdRequest::new("footer", (300, 250))).decision, AdDecision::NoFill(_)));
+        assert!(matches!(server().request(&AdRequest::new("sidebar", (728, 90))).decision, AdDecision::NoFill(_)));
+        let unknown = SERVER.replace(r#"house_ad = "house""#, r#"house_ad = "missing""#);
+        assert!(MockAdServer::from_toml(&unknown, "static/adserver").is_err());
+    }
+}
@@ -0,0 +1,402 @@
+/// Campaigns, line items and creatives
+///
+/// A campaign belongs to an advertiser and has line items, which are the units that are trafficked. Each line item
+/// has a flight, targeting, the sizes it buys and the creatives that rotate in it with their weights. The creatives
+/// are defined once per campaign, so that line items can share them.
+///
+/// Example:
+///
+/// ```toml
+/// id = "teapot-spring"
+/// name = "Spring Tea Launch"
+/// advertiser = "Teapot Co."
+///
+/// [[creatives]]
+/// id = "teapot-display"
+/// name = "Teapot animation"
+/// kind = "display"
+/// path = ".."
+///
+/// [[line_items]]
+/// id = "homepage"
+/// name = "Homepage takeover"
+/// start = "2026-03-01"
+/// end = "2026-03-31"
+/// sizes = [[728, 90], [300, 250]]
+/// targeting = { geo = ["US", "CA"], devices = ["desktop"] }
+/// creatives = [{ id = "teapot-display", weight = 3 }]
+/// rotation = "weighted"
+/// frequency_cap = 5
+/// ```
+///
+/// Creative paths are relative to the campaign file, like in the mock ad server, and use the same kinds.
+///
+use super::*;
+
+use quicksilver::{Error, Result};
+
+use serde::Deserialize;
+
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The sample campaign, relative to the asset root
+pub const DEFAULT_CAMPAIGN: &str = "campaigns/teapot-spring.toml";
+
+/// A calendar date in YYYY-MM-DD format. Dates compare in calendar order.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(try_from = "String")]
+pub struct FlightDate {
+    pub year: u32,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl FlightDate {
+    pub fn parse(text: &str) -> Result<FlightDate> {
+        let invalid = || Error::ContextError(format!("Invalid date {:?}. Use YYYY-MM-DD.", text));
+        let parts: Vec<&str> = text.trim().split('-').collect();
+        if parts.len() != 3 {
+            return Err(invalid());
+        }
+        let year = parts[0].parse::<u32>().map_err(|_| invalid())?;
+        let month = parts[1].parse::<u32>().map_err(|_| invalid())?;
+        let day = parts[2].parse::<u32>().map_err(|_| invalid())?;
+        if month < 1 || month > 12 || day < 1 || day > FlightDate::days_in_month(year, month) {
+            return Err(invalid());
+        }
+        Ok(FlightDate { year, month, day })
+    }
+
+    fn days_in_month(year: u32, month: u32) -> u32 {
+        match month {
+            2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        }
+    }
+}
+
+impl TryFrom<String> for FlightDate {
+    type Error = String;
+
+    fn try_from(text: String) -> std::result::Result<Self, Self::Error> {
+        FlightDate::parse(&text).map_err(|_| format!("invalid date {:?}, expected YYYY-MM-DD", text))
+    }
+}
+
+impl fmt::Display for FlightDate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// Who a line item is shown to. Empty lists target everyone.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Targeting {
+    #[serde(default)]
+    pub geo: Vec<String>,
+    #[serde(default)]
+    pub devices: Vec<String>,
+    /// Placement ids, as in the mock ad server
+    #[serde(default)]
+    pub placements: Vec<String>,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+}
+
+impl Targeting {
+    /// A short description, e.g. "geo=US,CA devices=desktop"
+    pub fn summary(&self) -> String {
+        let fields = [
+            ("geo", &self.geo),
+            ("devices", &self.devices),
+            ("placements", &self.placements),
+            ("keywords", &self.keywords),
+        ];
+        let parts: Vec<String> = fields
+            .iter()
+            .filter(|(_, values)| !values.is_empty())
+            .map(|(name, values)| format!("{}={}", name, values.join(",")))
+            .collect();
+        if parts.is_empty() {
+            "untargeted".to_string()
+        } else {
+            parts.join(" ")
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CampaignCreative {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    pub kind: CreativeKind,
+    /// The asset root or file, relative to the campaign file
+    pub path: String,
+}
+
+/// A creative in a line item's rotation
+#[derive(Clone, Debug, Deserialize)]
+pub struct CreativeAssignment {
+    /// The id of a campaign creative
+    pub id: String,
+    /// The share of impressions relative to the other creatives in the line item
+    #[serde(default = "CreativeAssignment::default_weight")]
+    pub weight: u32,
+    /// The most times that one user sees the creative in this line item
+    pub frequency_cap: Option<u32>,
+}
+
+impl CreativeAssignment {
+    fn default_weight() -> u32 {
+        1
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct LineItem {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    pub start: FlightDate,
+    pub end: FlightDate,
+    #[serde(default)]
+    pub targeting: Targeting,
+    pub sizes: Vec<(u32, u32)>,
+    pub creatives: Vec<CreativeAssignment>,
+    /// How the creatives take turns
+    #[serde(default)]
+    pub rotation: RotationRule,
+    /// The most times that one user sees any creative from this line item
+    pub frequency_cap: Option<u32>,
+}
+
+impl LineItem {
+    /// True when the date is within the flight, including the start and end dates
+    pub fn is_live(&self, date: FlightDate) -> bool {
+        self.start <= date && date <= self.end
+    }
+
+    pub fn flight(&self) -> String {
+        format!("{} to {}", self.start, self.end)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Campaign {
+    pub id: String,
+    pub name: String,
+    pub advertiser: String,
+    pub creatives: Vec<CampaignCreative>,
+    pub line_items: Vec<LineItem>,
+    /// The folder of the campaign file, for resolving creative paths
+    #[serde(skip)]
+    pub base_dir: PathBuf,
+}
+
+/// A creative at one of its trafficked sizes, with the line items that traffic it at that size
+#[derive(Clone, Debug, PartialEq)]
+pub struct CreativePreview {
+    pub creative_id: String,
+    pub size: (u32, u32),
+    pub line_items: Vec<String>,
+}
+
+impl Campaign {
+    pub fn from_toml<P: AsRef<Path>>(text: &str, base_dir: P) -> Result<Campaign> {
+        let mut campaign: Campaign =
+            toml::from_str(text).map_err(|e| Error::ContextError(format!("Invalid campaign: {}", e)))?;
+        campaign.base_dir = base_dir.as_ref().to_path_buf();
+        campaign.validate()?;
+        Ok(campaign)
+    }
+
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Campaign> {
+        let text = fs::read_to_string(path.as_ref())?;
+        let base_dir = path.as_ref().parent().map(|dir| dir.to_path_buf()).unwrap_or_default();
+        Campaign::from_toml(&text, base_dir)
+    }
+
+    /// Check the references and values that serde cannot check
+    fn validate(&self) -> Result<()> {
+        let mut ids = BTreeSet::new();
+        for creative in self.creatives.iter() {
+            if !ids.insert(creative.id.as_str()) {
+                return Err(Error::ContextError(format!("Duplicate creative id {}", creative.id)));
+            }
+        }
+        for line_item in self.line_items.iter() {
+            let context = |message: String| Error::ContextError(format!("Line item {}: {}", line_item.id, message));
+            if line_item.end < line_item.start {
+                return Err(context(format!("the flight ends before it starts ({})", line_item.flight())));
+            }
+            if line_item.sizes.is_empty() {
+                return Err(context("no sizes".to_string()));
+            }
+            if line_item.creatives.is_empty() {
+                return Err(context("no creatives".to_string()));
+            }
+            for assignment in line_item.creatives.iter() {
+                if self.creative(&assignment.id).is_none() {
+                    return Err(context(format!("unknown creative {}", assignment.id)));
+                }
+                if assignment.weight == 0 {
+                    return Err(context(format!("creative {} has a weight of 0", assignment.id)));
+                }
+                if assignment.frequency_cap == Some(0) {
+                    return Err(context(format!("creative {} has a frequency cap of 0", assignment.id)));
+                }
+            }
+            if line_item.frequency_cap == Some(0) {
+                return Err(context("a frequency cap of 0".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn creative(&self, id: &str) -> Option<&CampaignCreative> {
+        self.creatives.iter().find(|creative| creative.id == id)
+    }
+
+    pub fn line_item(&self, id: &str) -> Option<&LineItem> {
+        self.line_items.iter().find(|line_item| line_item.id == id)
+    }
+
+    /// The path of a creative relative to the working directory
+    pub fn creative_path(&self, creative: &CampaignCreative) -> PathBuf {
+        normalize_path(&self.base_dir.join(&creative.path))
+    }
+
+    /// The line items that are live on the date
+    pub fn live_line_items(&self, date: FlightDate) -> Vec<&LineItem> {
+        self.line_items.iter().filter(|line_item| line_item.is_live(date)).collect()
+    }
+
+    /// Each creative at each size that it is trafficked at, in the order of the campaign's creatives. Creatives
+    /// that are not in any line item are left out.
+    pub fn previews(&self) -> Vec<CreativePreview> {
+        let mut previews: Vec<CreativePreview> = Vec::new();
+        for creative in self.creatives.iter() {
+            for line_item in self.line_items.iter() {
+                if !line_item.creatives.iter().any(|assignment| assignment.id == creative.id) {
+                    continue;
+                }
+                for size in line_item.sizes.iter() {
+                    let existing = previews.iter_mut().find(|p| p.creative_id == creative.id && p.size == *size);
+                    match existing {
+                        Some(preview) => preview.line_items.push(line_item.id.clone()),
+                        None => previews.push(CreativePreview {
+                            creative_id: creative.id.clone(),
+                            size: *size,
+                            line_items: vec![line_item.id.clone()],
+                        }),
+                    }
+                }
+            }
+        }
+        previews
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAMPAIGN: &str = r#"
+id = "spring"
+name = "Spring"
+advertiser = "Teapot Co."
+
+[[creatives]]
+id = "teapot"
+kind = "display"
+path = ".."
+
+[[creatives]]
+id = "native"
+kind = "native"
+path = "../teapot/native.toml"
+
+[[creatives]]
+id = "unused"
+kind = "vast"
+path = "../vast/teapot-vast3.xml"
+
+[[line_items]]
+id = "homepage"
+start = "2026-03-01"
+end = "2026-03-31"
+sizes = [[728, 90], [300, 250]]
+creatives = [{ id = "teapot", weight = 3 }, { id = "native" }]
+
+[[line_items]]
+id = "sidebar"
+start = "2026-03-15"
+end = "2026-04-15"
+sizes = [[300, 250]]
+creatives = [{ id = "teapot" }]
+"#;
+
+    fn date(text: &str) -> FlightDate {
+        FlightDate::parse(text).unwrap()
+    }
+
+    #[test]
+    fn flight_dates_are_checked_calendar_dates() {
+        assert_eq!(date(" 2026-03-01 "), FlightDate { year: 2026, month: 3, day: 1 });
+        assert_eq!(date("2024-02-29").to_string(), "2024-02-29");
+        assert!(date("2026-02-28") < date("2026-03-01"));
+        for text in ["2026-02-29", "1900-02-29", "2026-04-31", "2026-13-01", "2026-00-10", "2026-3", "March 1"].iter() {
+            assert!(FlightDate::parse(text).is_err(), "{} parsed", text);
+        }
+        assert!(FlightDate::parse("2000-02-29").is_ok());
+    }
+
+    #[test]
+    fn line_items_are_validated() {
+        let campaign = Campaign::from_toml(CAMPAIGN, "static/campaigns").unwrap();
+        assert_eq!(campaign.line_item("homepage").unwrap().creatives[1].weight, 1);
+        assert_eq!(campaign.creative_path(campaign.creative("teapot").unwrap()), PathBuf::from("static"));
+        let live: Vec<&str> = campaign.live_line_items(date("2026-03-31")).iter().map(|l| l.id.as_str()).collect();
+        assert_eq!(live, vec!["homepage", "sidebar"]);
+        assert!(campaign.live_line_items(date("2026-04-16")).is_empty());
+
+        let invalid = [
+            ("end = \"2026-04-15\"", "end = \"2026-03-01\""),
+            ("sizes = [[300, 250]]", "sizes = []"),
+            ("creatives = [{ id = \"teapot\" }]", "creatives = [{ id = \"missing\" }]"),
+            ("{ id = \"native\" }", "{ id = \"native\", weight = 0 }"),
+            ("{ id = \"native\" }", "{ id = \"native\", frequency_cap = 0 }"),
+            ("id = \"unused\"", "id = \"teapot\""),
+            ("start = \"2026-03-15\"", "start = \"2026-03-32\""),
+        ];
+        for (from, to) in invalid.iter() {
+            assert!(Campaign::from_toml(&CAMPAIGN.replace(from, to), "static/campaigns").is_err(), "{}", to);
+        }
+    }
+
+    #[test]
+    fn previews_list_each_creative_at_each_trafficked_size() {
+        let campaign = Campaign::from_toml(CAMPAIGN, "static/campaigns").unwrap();
+        let preview = |creative_id: &str, size, line_items: &[&str]| CreativePreview {
+            creative_id: creative_id.to_string(),
+            size,
+            line_items: line_items.iter().map(|id| id.to_string()).collect(),
+        };
+        assert_eq!(
+            campaign.previews(),
+            vec![
+                preview("teapot", (728, 90), &["homepage"]),
+                preview("teapot", (300, 250), &["homepage", "sidebar"]),
+                preview("native", (728, 90), &["homepage"]),
+                preview("native", (300, 250), &["homepage"]),
+            ]
+        );
+    }
+}
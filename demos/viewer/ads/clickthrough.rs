@@ -0,0 +1,338 @@
+/// Click-through handling with clickTags and ad server macros
+///
+/// A creative names its exits (e.g. "cta" and "logo") and each exit reads a clickTag variable. The clickTag
+/// values come from the ad server when the ad is trafficked and usually contain macros, such as a click
+/// tracking prefix and a cachebuster. The ClickConfig holds a trafficking setup that can be loaded from a
+/// TOML file so that the final URLs can be checked offline.
+///
+/// Example:
+///
+/// ```toml
+/// [exits]
+/// cta = "clickTag"
+/// logo = "clickTag2"
+///
+/// [click_tags]
+/// clickTag = "%%CLICK_URL_UNESC%%https://example.com/teapot?cb=%%CACHEBUSTER%%"
+/// clickTag2 = "%%CLICK_URL_ESC%%https%3A%2F%2Fexample.com%2F"
+///
+/// [macros]
+/// CLICK_URL_UNESC = "https://adclick.example.net/click?id=123&adurl="
+/// ```
+///
+/// Macros can be written as %%NAME%%, ${NAME} or [NAME], where a name in brackets starts with a letter.
+/// CACHEBUSTER, TIMESTAMP and timestamp are always available. If NAME_ESC is not defined, it is the
+/// percent-encoded value of NAME or NAME_UNESC.
+///
+use quicksilver::{Error, Result};
+
+use serde::Deserialize;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The clickTag used by exits that are not listed in the config
+pub const DEFAULT_CLICK_TAG: &str = "clickTag";
+
+/// Percent-encode a string for use as a URL query parameter value
+pub fn url_encode(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => result.push(byte as char),
+            _ => result.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    result
+}
+
+/// Decode a percent-encoded string. Invalid escapes are kept as they are.
+pub fn url_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                result.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                result.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&result).to_string()
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ClickConfig {
+    /// Exit name to clickTag variable name
+    #[serde(default)]
+    pub exits: BTreeMap<String, String>,
+    /// clickTag variable name to URL, as provided by the ad server
+    #[serde(default)]
+    pub click_tags: BTreeMap<String, String>,
+    /// Macro values that the ad server would substitute
+    #[serde(default)]
+    pub macros: BTreeMap<String, String>,
+}
+
+impl ClickConfig {
+    pub fn from_toml(text: &str) -> Result<ClickConfig> {
+        toml::from_str(text).map_err(|e| Error::ContextError(format!("Invalid click config: {}", e)))
+    }
+
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<ClickConfig> {
+        let text = fs::read_to_string(path.as_ref())?;
+        ClickConfig::from_toml(&text)
+    }
+
+    /// The clickTag variable that an exit reads
+    pub fn click_tag_for(&self, exit: &str) -> &str {
+        self.exits.get(exit).map(|s| s.as_str()).unwrap_or(DEFAULT_CLICK_TAG)
+    }
+
+    /// Get the value of a macro, including the built-in ones. The cachebuster is passed in so that every
+    /// macro in one click uses the same value.
+    fn macro_value(&self, name: &str, cachebuster: u64) -> Option<String> {
+        if let Some(value) = self.macros.get(name) {
+            return Some(value.clone());
+        }
+        match name {
+            "CACHEBUSTER" | "TIMESTAMP" | "timestamp" => return Some(cachebuster.to_string()),
+            _ => (),
+        }
+        if name.ends_with("_ESC") {
+            let base = &name[..name.len() - "_ESC".len()];
+            let unesc = format!("{}_UNESC", base);
+            return self.macros.get(base).or_else(|| self.macros.get(&unesc)).map(|value| url_encode(value));
+        }
+        None
+    }
+
+    /// Replace all macros in the text. Unknown macros are left in place and returned in the list of warnings.
+    pub fn expand_macros(&self, text: &str, cachebuster: u64) -> (String, Vec<String>) {
+        replace_macros(text, |name| self.macro_value(name, cachebuster))
+    }
+
+    /// The clickTag's URL without any macros, which is the landing page. The ad server adds its own click
+    /// tracking when the ad is trafficked. A query parameter whose value was only macros, like a cachebuster, is
+    /// removed along with its name. The URL after an _ESC macro is percent-encoded for it, so it is decoded.
+    pub fn landing_url(&self, click_tag: &str) -> Result<String> {
+        let url = self
+            .click_tags
+            .get(click_tag)
+            .ok_or_else(|| Error::ContextError(format!("Undefined clickTag {}", click_tag)))?;
+        let (mut url, _) = replace_macros(url, |name| {
+            let mark = if name.ends_with("_ESC") { ESC_MACRO_MARK } else { MACRO_MARK };
+            Some(mark.to_string())
+        });
+        if let Some(start) = url.find(ESC_MACRO_MARK) {
+            url = format!("{}{}", &url[..start], url_decode(&url[start..]));
+        }
+        Ok(remove_marked_macros(&url.replace(ESC_MACRO_MARK, &MACRO_MARK.to_string())))
+    }
+
+    /// Build the final URL for a click on the exit, as the ad server would redirect to it
+    pub fn expand_exit(&self, exit: &str) -> Result<String> {
+        let click_tag = self.click_tag_for(exit);
+        let url = self
+            .click_tags
+            .get(click_tag)
+            .ok_or_else(|| Error::ContextError(format!("Exit {:?} uses undefined {}", exit, click_tag)))?;
+        let cachebuster = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+        let (url, unknown) = self.expand_macros(url, cachebuster);
+        for name in unknown {
+            log::warn!("Unknown macro {} in {} for exit {:?}", name, click_tag, exit);
+        }
+        Ok(url)
+    }
+}
+
+/// Stands in for a removed macro until the query parameters that only held macros are found
+const MACRO_MARK: char = '\u{0}';
+/// Stands in for a removed _ESC macro until the rest of the URL is decoded
+const ESC_MACRO_MARK: char = '\u{1}';
+
+/// Remove the MACRO_MARKs from a URL, and the query parameters whose values were only marks
+fn remove_marked_macros(url: &str) -> String {
+    let (url, fragment) = url.split_at(url.find('#').unwrap_or(url.len()));
+    let mut result = match url.find('?') {
+        Some(start) => {
+            let params: Vec<&str> = url[start + 1..]
+                .split('&')
+                .filter(|param| match param.find('=') {
+                    Some(end) => {
+                        let value = &param[end + 1..];
+                        value.is_empty() || !value.chars().all(|c| c == MACRO_MARK)
+                    }
+                    None => true,
+                })
+                .collect();
+            let query = params.join("&");
+            if query.is_empty() {
+                url[..start].to_string()
+            } else {
+                format!("{}?{}", &url[..start], query)
+            }
+        }
+        None => url.to_string(),
+    };
+    result.push_str(fragment);
+    result.replace(MACRO_MARK, "")
+}
+
+/// Replace the macros in the text with their values. Macros without a value are left in place and returned.
+fn replace_macros<F: Fn(&str) -> Option<String>>(text: &str, value_of: F) -> (String, Vec<String>) {
+    const DELIMITERS: [(&str, &str); 3] = [("%%", "%%"), ("${", "}"), ("[", "]")];
+    let mut result = String::with_capacity(text.len());
+    let mut unknown: Vec<String> = Vec::new();
+    let mut rest = text;
+    'outer: while !rest.is_empty() {
+        for (open, close) in DELIMITERS.iter() {
+            if !rest.starts_with(open) {
+                continue;
+            }
+            let after = &rest[open.len()..];
+            if let Some(end) = after.find(close) {
+                let name = &after[..end];
+                let is_name = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+                // Brackets are also used in query strings, e.g. ids[0]=1, so those macros start with a letter
+                let is_name = is_name && (*open != "[" || name.starts_with(|c: char| c.is_ascii_alphabetic()));
+                if is_name {
+                    match value_of(name) {
+                        Some(value) => result.push_str(&value),
+                        None => {
+                            result.push_str(&rest[..open.len() + end + close.len()]);
+                            unknown.push(name.to_string());
+                        }
+                    }
+                    rest = &after[end + close.len()..];
+                    continue 'outer;
+                }
+            }
+        }
+        let c = rest.chars().next().unwrap();
+        result.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    (result, unknown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> ClickConfig {
+        ClickConfig::from_toml(
+            r#"
+            [exits]
+            logo = "clickTag2"
+
+            [click_tags]
+            clickTag = "%%CLICK_URL_UNESC%%https://example.com/teapot?cb=%%CACHEBUSTER%%"
+            clickTag2 = "%%CLICK_URL_ESC%%https%3A%2F%2Fexample.com%2F"
+
+            [macros]
+            CLICK_URL_UNESC = "https://ads.example.net/click?adurl="
+            SITE = "news"
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn all_delimiters_are_expanded() {
+        let (text, unknown) = config().expand_macros("%%SITE%%/${SITE}/[SITE]", 7);
+        assert_eq!(text, "news/news/news");
+        assert!(unknown.is_empty());
+    }
+
+    #[test]
+    fn built_in_macros_use_the_cachebuster() {
+        let (text, _) = config().expand_macros("cb=%%CACHEBUSTER%%&t=${TIMESTAMP}&ts=[timestamp]", 42);
+        assert_eq!(text, "cb=42&t=42&ts=42");
+    }
+
+    #[test]
+    fn esc_macros_are_encoded_from_the_unescaped_value() {
+        let (text, _) = config().expand_macros("%%CLICK_URL_ESC%%", 0);
+        assert_eq!(text, "https%3A%2F%2Fads.example.net%2Fclick%3Fadurl%3D");
+        let (text, _) = config().expand_macros("%%SITE_ESC%%", 0);
+        assert_eq!(text, "news");
+    }
+
+    #[test]
+    fn unknown_macros_are_kept_and_reported() {
+        let (text, unknown) = config().expand_macros("a=%%MISSING%%&b=${OTHER}", 0);
+        assert_eq!(text, "a=%%MISSING%%&b=${OTHER}");
+        assert_eq!(unknown, vec!["MISSING".to_string(), "OTHER".to_string()]);
+    }
+
+    #[test]
+    fn brackets_in_query_strings_are_not_macros() {
+        let (text, unknown) = config().expand_macros("https://example.com/?ids[0]=1&ids[1]=2&x=[_]", 0);
+        assert_eq!(text, "https://example.com/?ids[0]=1&ids[1]=2&x=[_]");
+        assert!(unknown.is_empty());
+    }
+
+    #[test]
+    fn unterminated_and_non_name_delimiters_are_text() {
+        let (text, unknown) = config().expand_macros("100%% off ${not a name} [", 0);
+        assert_eq!(text, "100%% off ${not a name} [");
+        assert!(unknown.is_empty());
+    }
+
+    #[test]
+    fn exits_use_their_click_tag() {
+        let config = config();
+        assert_eq!(config.click_tag_for("cta"), DEFAULT_CLICK_TAG);
+        assert_eq!(config.click_tag_for("logo"), "clickTag2");
+        let url = config.expand_exit("cta").unwrap();
+        assert!(url.starts_with("https://ads.example.net/click?adurl=https://example.com/teapot?cb="));
+        assert!(config.expand_exit("missing").is_ok());
+        let config = ClickConfig::from_toml("[exits]\ncta = \"clickTag9\"").unwrap();
+        assert!(config.expand_exit("cta").is_err());
+    }
+
+    #[test]
+    fn landing_urls_leave_out_macros_and_their_parameters() {
+        let config = ClickConfig::from_toml(
+            r#"
+            [click_tags]
+            clickTag = "%%CLICK_URL_UNESC%%https://example.com/teapot?utm_source=display&cb=%%CACHEBUSTER%%"
+            clickTag2 = "%%CLICK_URL_ESC%%https%3A%2F%2Fexample.com%2F"
+            middle = "https://example.com/?cb=[CACHEBUSTER]&ids[0]=1&empty=&site=%%SITE%%-x#top"
+            only = "https://example.com/?cb=${CACHEBUSTER}${TIMESTAMP}"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.landing_url("clickTag").unwrap(), "https://example.com/teapot?utm_source=display");
+        assert_eq!(config.landing_url("clickTag2").unwrap(), "https://example.com/");
+        assert_eq!(config.landing_url("middle").unwrap(), "https://example.com/?ids[0]=1&empty=&site=-x#top");
+        assert_eq!(config.landing_url("only").unwrap(), "https://example.com/");
+        assert!(config.landing_url("clickTag3").is_err());
+    }
+
+    #[test]
+    fn url_encode_keeps_unreserved_characters() {
+        assert_eq!(url_encode("a-z_0.9~ /?&="), "a-z_0.9~%20%2F%3F%26%3D");
+    }
+
+    #[test]
+    fn url_decode_reverses_url_encode() {
+        assert_eq!(
+            url_decode(&url_encode("https://example.com/?q=tea pot&l=\u{e9}")),
+            "https://example.com/?q=tea pot&l=\u{e9}"
+        );
+        assert_eq!(url_decode("100%+5%2x%4"), "100%+5%2x%4");
+    }
+}
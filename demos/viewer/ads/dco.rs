@@ -0,0 +1,375 @@
+/// Dynamic creative optimization from a product feed
+///
+/// A DCO template is one creative design with slots for a product's name, price, image and discount. The slots
+/// are filled from the rows of a product feed, which is a local CSV file with a header row or a JSON array of
+/// objects. Each row becomes one variant at each of the template's sizes.
+///
+/// Example:
+///
+/// ```toml
+/// name = "Teapot Shop"
+/// feed = "products.csv"
+/// sizes = [[300, 250], [728, 90], [160, 600]]
+/// currency = "$"
+/// cta_text = "Shop Now"
+///
+/// [columns]
+/// product_name = "title"
+/// price = "sale_price"
+/// image = "image_link"
+/// discount = "discount"
+/// ```
+///
+/// The feed path is relative to the template file and image paths are relative to the feed file. Columns that
+/// are not in the mapping use the slot name, e.g. a "price" column for the price slot.
+///
+use super::*;
+
+use quicksilver::{Error, Result};
+
+use serde::Deserialize;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The sample DCO template, relative to the asset root
+pub const DEFAULT_DCO_TEMPLATE: &str = "dco/template.toml";
+
+/// The parts of a DCO template that are filled from the feed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DcoSlot {
+    ProductName,
+    Price,
+    Image,
+    Discount,
+}
+
+impl DcoSlot {
+    pub const ALL: [DcoSlot; 4] = [DcoSlot::ProductName, DcoSlot::Price, DcoSlot::Image, DcoSlot::Discount];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DcoSlot::ProductName => "product_name",
+            DcoSlot::Price => "price",
+            DcoSlot::Image => "image",
+            DcoSlot::Discount => "discount",
+        }
+    }
+
+    /// The recommended maximum number of characters, since the smaller sizes cut off longer text
+    pub fn max_chars(&self) -> Option<usize> {
+        match self {
+            DcoSlot::ProductName => Some(40),
+            DcoSlot::Price | DcoSlot::Discount => Some(12),
+            DcoSlot::Image => None,
+        }
+    }
+}
+
+/// The feed column for each slot
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct SlotColumns {
+    pub product_name: Option<String>,
+    pub price: Option<String>,
+    pub image: Option<String>,
+    pub discount: Option<String>,
+}
+
+impl SlotColumns {
+    pub fn column(&self, slot: DcoSlot) -> &str {
+        let column = match slot {
+            DcoSlot::ProductName => &self.product_name,
+            DcoSlot::Price => &self.price,
+            DcoSlot::Image => &self.image,
+            DcoSlot::Discount => &self.discount,
+        };
+        column.as_ref().map_or(slot.name(), |column| column.as_str())
+    }
+}
+
+/// The rows of a product feed. Each row maps a column name to its value.
+#[derive(Clone, Debug)]
+pub struct ProductFeed {
+    pub columns: Vec<String>,
+    pub rows: Vec<BTreeMap<String, String>>,
+    /// The folder of the feed file, for resolving image paths
+    pub base_dir: PathBuf,
+}
+
+impl ProductFeed {
+    /// Parse a CSV feed, where the first row has the column names
+    pub fn from_csv<P: AsRef<Path>>(text: &str, base_dir: P) -> Result<ProductFeed> {
+        let invalid = |e: csv::Error| Error::ContextError(format!("Invalid CSV feed: {}", e));
+        let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(text.as_bytes());
+        let columns: Vec<String> = reader.headers().map_err(invalid)?.iter().map(|name| name.to_string()).collect();
+        let mut rows = Vec::new();
+        for record in reader.records() {
+            let record = record.map_err(invalid)?;
+            rows.push(columns.iter().cloned().zip(record.iter().map(|value| value.to_string())).collect());
+        }
+        Ok(ProductFeed { columns, rows, base_dir: base_dir.as_ref().to_path_buf() })
+    }
+
+    /// Parse a JSON feed, which is an array of objects. Numbers and booleans are converted to text.
+    pub fn from_json<P: AsRef<Path>>(text: &str, base_dir: P) -> Result<ProductFeed> {
+        let items: Vec<BTreeMap<String, serde_json::Value>> =
+            serde_json::from_str(text).map_err(|e| Error::ContextError(format!("Invalid JSON feed: {}", e)))?;
+        let mut columns: Vec<String> = Vec::new();
+        let mut rows = Vec::new();
+        for item in items {
+            let mut row = BTreeMap::new();
+            for (name, value) in item {
+                if !columns.contains(&name) {
+                    columns.push(name.clone());
+                }
+                let value = match value {
+                    serde_json::Value::String(text) => text,
+                    serde_json::Value::Null => String::new(),
+                    value => value.to_string(),
+                };
+                row.insert(name, value);
+            }
+            rows.push(row);
+        }
+        Ok(ProductFeed { columns, rows, base_dir: base_dir.as_ref().to_path_buf() })
+    }
+
+    /// Load a feed file. Files with a .json extension are parsed as JSON and anything else as CSV.
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<ProductFeed> {
+        let text = fs::read_to_string(path.as_ref())?;
+        let base_dir = path.as_ref().parent().map(|dir| dir.to_path_buf()).unwrap_or_default();
+        let is_json = path.as_ref().extension().map_or(false, |ext| ext.eq_ignore_ascii_case("json"));
+        if is_json {
+            ProductFeed::from_json(&text, base_dir)
+        } else {
+            ProductFeed::from_csv(&text, base_dir)
+        }
+    }
+}
+
+/// A template filled with one feed row at one size
+#[derive(Clone, Debug, PartialEq)]
+pub struct DcoVariant {
+    /// The feed row, starting at 1
+    pub row: usize,
+    pub size: (u32, u32),
+    pub product_name: String,
+    /// The price with the currency symbol
+    pub price: String,
+    /// The image path relative to the working directory, or None when the row has no local image
+    pub image: Option<PathBuf>,
+    /// The text of the discount badge, or None when the product is not discounted
+    pub discount: Option<String>,
+    /// Missing values and text that is longer than recommended
+    pub warnings: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct DcoTemplate {
+    pub name: String,
+    /// The product feed, relative to the template file
+    pub feed: String,
+    pub sizes: Vec<(u32, u32)>,
+    #[serde(default)]
+    pub columns: SlotColumns,
+    /// The symbol that is put before prices
+    #[serde(default = "DcoTemplate::default_currency")]
+    pub currency: String,
+    #[serde(default = "DcoTemplate::default_cta_text")]
+    pub cta_text: String,
+    /// The click-through exit for the whole ad
+    #[serde(default = "DcoTemplate::default_exit")]
+    pub exit: String,
+    /// The folder of the template file, for resolving the feed path
+    #[serde(skip)]
+    pub base_dir: PathBuf,
+}
+
+impl DcoTemplate {
+    fn default_currency() -> String {
+        "$".to_string()
+    }
+
+    fn default_cta_text() -> String {
+        "Shop Now".to_string()
+    }
+
+    fn default_exit() -> String {
+        "cta".to_string()
+    }
+
+    pub fn from_toml<P: AsRef<Path>>(text: &str, base_dir: P) -> Result<DcoTemplate> {
+        let mut template: DcoTemplate =
+            toml::from_str(text).map_err(|e| Error::ContextError(format!("Invalid DCO template: {}", e)))?;
+        if template.sizes.is_empty() {
+            return Err(Error::ContextError(format!("DCO template {} has no sizes", template.name)));
+        }
+        template.base_dir = base_dir.as_ref().to_path_buf();
+        Ok(template)
+    }
+
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<DcoTemplate> {
+        let text = fs::read_to_string(path.as_ref())?;
+        let base_dir = path.as_ref().parent().map(|dir| dir.to_path_buf()).unwrap_or_default();
+        DcoTemplate::from_toml(&text, base_dir)
+    }
+
+    /// The path of the feed relative to the working directory
+    pub fn feed_path(&self) -> PathBuf {
+        normalize_path(&self.base_dir.join(&self.feed))
+    }
+
+    /// The mapped columns that are missing from the feed. The discount column is optional.
+    pub fn missing_columns(&self, feed: &ProductFeed) -> Vec<String> {
+        DcoSlot::ALL
+            .iter()
+            .filter(|slot| **slot != DcoSlot::Discount)
+            .map(|slot| self.columns.column(*slot))
+            .filter(|column| !feed.columns.iter().any(|name| name == column))
+            .map(|column| column.to_string())
+            .collect()
+    }
+
+    /// One variant for each row at each size, ordered by size and then by row
+    pub fn variants(&self, feed: &ProductFeed) -> Vec<DcoVariant> {
+        let mut variants = Vec::new();
+        for size in self.sizes.iter() {
+            for (index, row) in feed.rows.iter().enumerate() {
+                variants.push(self.fill(index + 1, row, *size, &feed.base_dir));
+            }
+        }
+        variants
+    }
+
+    /// Fill the slots with the values of a row
+    fn fill(&self, row: usize, values: &BTreeMap<String, String>, size: (u32, u32), feed_dir: &Path) -> DcoVariant {
+        let mut warnings = Vec::new();
+        let value = |slot: DcoSlot| values.get(self.columns.column(slot)).map_or("", |value| value.trim());
+        for slot in DcoSlot::ALL.iter() {
+            let chars = value(*slot).chars().count();
+            if value(*slot).is_empty() && *slot != DcoSlot::Discount {
+                warnings.push(format!("{} is empty", slot.name()));
+            } else if let Some(max) = slot.max_chars().filter(|max| chars > *max) {
+                warnings.push(format!("{} has {} characters, more than the recommended {}", slot.name(), chars, max));
+            }
+        }
+
+        let price = match value(DcoSlot::Price).trim_start_matches(self.currency.as_str()).parse::<f64>() {
+            Ok(amount) => format!("{}{:.2}", self.currency, amount),
+            Err(_) => {
+                if !value(DcoSlot::Price).is_empty() {
+                    warnings.push(format!("price {:?} is not a number", value(DcoSlot::Price)));
+                }
+                value(DcoSlot::Price).to_string()
+            }
+        };
+
+        // A number is a percentage off, and other text like "Sale" is shown as it is
+        let discount = match value(DcoSlot::Discount).trim_end_matches('%').parse::<f64>() {
+            Ok(percent) if percent > 0.0 => Some(format!("{:.0}% off", percent)),
+            Ok(_) => None,
+            Err(_) if value(DcoSlot::Discount).is_empty() => None,
+            Err(_) => Some(value(DcoSlot::Discount).to_string()),
+        };
+
+        let image = match value(DcoSlot::Image) {
+            "" => None,
+            src if src.starts_with("http://") || src.starts_with("https://") || src.starts_with("//") => {
+                warnings.push(format!("image {} is remote and is not loaded", src));
+                None
+            }
+            src => Some(normalize_path(&feed_dir.join(src.trim_start_matches("file://")))),
+        };
+
+        DcoVariant {
+            row,
+            size,
+            product_name: value(DcoSlot::ProductName).to_string(),
+            price,
+            image,
+            discount,
+            warnings,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEMPLATE: &str = r#"
+name = "Teapot Shop"
+feed = "products.csv"
+sizes = [[300, 250], [728, 90]]
+currency = "$"
+
+[columns]
+product_name = "title"
+price = "sale_price"
+"#;
+
+    fn template() -> DcoTemplate {
+        DcoTemplate::from_toml(TEMPLATE, "static/dco").unwrap()
+    }
+
+    #[test]
+    fn csv_and_json_feeds_have_the_same_rows() {
+        let csv = "title, sale_price, image, discount\nKettle, 24.5, img/kettle.png, 20%\n\"Cup, blue\",$8,,\n";
+        let json = r#"[
+            {"title": "Kettle", "sale_price": 24.5, "image": "img/kettle.png", "discount": "20%"},
+            {"title": "Cup, blue", "sale_price": "$8", "image": null, "discount": ""}
+        ]"#;
+        let csv = ProductFeed::from_csv(csv, "static/dco").unwrap();
+        let json = ProductFeed::from_json(json, "static/dco").unwrap();
+        assert_eq!(csv.columns, vec!["title", "sale_price", "image", "discount"]);
+        assert_eq!(csv.rows, json.rows);
+        assert_eq!(csv.rows[1]["title"], "Cup, blue");
+        assert_eq!(json.rows[0]["sale_price"], "24.5");
+        assert!(template().missing_columns(&csv).is_empty());
+
+        assert!(ProductFeed::from_json("{\"title\": \"Kettle\"}", "static/dco").is_err());
+        assert!(ProductFeed::from_csv("title,price\nKettle,1,extra\n", "static/dco").is_err());
+    }
+
+    #[test]
+    fn variants_format_prices_and_discounts() {
+        let feed = ProductFeed::from_csv(
+            "title,sale_price,image,discount\n\
+             Kettle,24.5,img/kettle.png,20%\n\
+             Cup,$8,https://example.com/cup.png,Sale\n\
+             Tray,about 9,,0\n",
+            "static/dco",
+        )
+        .unwrap();
+        let variants = template().variants(&feed);
+        assert_eq!(variants.len(), 6);
+        assert_eq!((variants[0].row, variants[0].size), (1, (300, 250)));
+        assert_eq!((variants[3].row, variants[3].size), (1, (728, 90)));
+
+        assert_eq!(variants[0].price, "$24.50");
+        assert_eq!(variants[0].discount, Some("20% off".to_string()));
+        assert_eq!(variants[0].image, Some(PathBuf::from("static/dco/img/kettle.png")));
+        assert!(variants[0].warnings.is_empty());
+
+        assert_eq!(variants[1].price, "$8.00");
+        assert_eq!(variants[1].discount, Some("Sale".to_string()));
+        assert_eq!(variants[1].image, None);
+        assert_eq!(variants[1].warnings, vec!["image https://example.com/cup.png is remote and is not loaded"]);
+
+        assert_eq!(variants[2].price, "about 9");
+        assert_eq!(variants[2].discount, None);
+        assert_eq!(variants[2].warnings, vec!["image is empty", "price \"about 9\" is not a number"]);
+    }
+
+    #[test]
+    fn missing_columns_and_long_text_are_reported() {
+        let feed = ProductFeed::from_csv(&format!("title,image\n{},a.png\n", "x".repeat(41)), "static/dco").unwrap();
+        assert_eq!(template().missing_columns(&feed), vec!["sale_price"]);
+        assert_eq!(
+            template().variants(&feed)[0].warnings,
+            vec!["product_name has 41 characters, more than the recommended 40", "price is empty"]
+        );
+        assert!(DcoTemplate::from_toml("name = \"Empty\"\nfeed = \"feed.csv\"\nsizes = []\n", "static/dco").is_err());
+    }
+}
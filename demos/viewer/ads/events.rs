@@ -0,0 +1,62 @@
+/// Events that are raised by ad creatives and the viewer. These travel through the EventBus and are
+/// forwarded to the Tracker, so the names match the tracking events used by ad servers.
+///
+use tweek::events::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AdEvent {
+    /// The ad was rendered
+    Impression,
+    /// The ad met the viewability threshold
+    Viewable,
+    /// The ad animation started
+    Start,
+    FirstQuartile,
+    Midpoint,
+    ThirdQuartile,
+    /// The ad animation finished
+    Complete,
+    /// A click on the named exit, e.g. "cta"
+    Click(String),
+    /// The mouse entered the named area
+    Hover(String),
+    /// The ad animation was restarted by the user
+    Replay,
+    /// An expandable ad was expanded by the user
+    Expand,
+    /// An expandable ad was collapsed by the user
+    Collapse,
+    /// The user closed the ad, e.g. an interstitial or a sticky anchor
+    Close,
+}
+
+impl AdEvent {
+    /// The tracking event name
+    pub fn name(&self) -> &'static str {
+        match self {
+            AdEvent::Impression => "impression",
+            AdEvent::Viewable => "viewable",
+            AdEvent::Start => "start",
+            AdEvent::FirstQuartile => "firstQuartile",
+            AdEvent::Midpoint => "midpoint",
+            AdEvent::ThirdQuartile => "thirdQuartile",
+            AdEvent::Complete => "complete",
+            AdEvent::Click(_) => "click",
+            AdEvent::Hover(_) => "hover",
+            AdEvent::Replay => "replay",
+            AdEvent::Expand => "expand",
+            AdEvent::Collapse => "collapse",
+            AdEvent::Close => "close",
+        }
+    }
+
+    /// The exit or area name for interaction events
+    pub fn target(&self) -> Option<&str> {
+        match self {
+            AdEvent::Click(target) | AdEvent::Hover(target) => Some(target),
+            _ => None,
+        }
+    }
+}
+
+impl AnyEvent for AdEvent {}
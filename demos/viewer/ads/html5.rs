@@ -0,0 +1,167 @@
+/// The index.html of an HTML5 ad bundle
+///
+/// Ad servers ingest an HTML5 creative as a zip file with an index.html at the top level. The page declares the
+/// ad size in an ad.size meta tag and each clickTag as a global variable, which the ad server replaces with its
+/// click tracking URL when the ad is trafficked. The creative opens the clickTag when an exit is clicked.
+///
+/// Example:
+///
+/// ```html
+/// <meta name="ad.size" content="width=300,height=250">
+/// <script type="text/javascript">
+/// var clickTag = "https://www.example.com/teapot";
+/// </script>
+/// ```
+///
+use quicksilver::{Error, Result};
+
+use std::collections::BTreeMap;
+
+/// The name of the page that ad servers open in a bundle
+pub const HTML5_INDEX: &str = "index.html";
+
+#[derive(Clone, Debug)]
+pub struct Html5Page {
+    pub title: String,
+    pub width: u32,
+    pub height: u32,
+    /// The script that starts the creative, relative to the page
+    pub script: String,
+    /// clickTag variable name to landing page URL
+    pub click_tags: BTreeMap<String, String>,
+}
+
+impl Html5Page {
+    pub fn new(title: &str, size: (u32, u32), script: &str) -> Self {
+        Html5Page {
+            title: title.to_string(),
+            width: size.0,
+            height: size.1,
+            script: script.to_string(),
+            click_tags: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_click_tag(mut self, name: &str, url: &str) -> Self {
+        self.click_tags.insert(name.to_string(), url.to_string());
+        self
+    }
+
+    /// The page's HTML. Returns an error if a clickTag name cannot be used as a JavaScript variable.
+    pub fn to_html(&self) -> Result<String> {
+        let mut variables = String::new();
+        for (name, url) in self.click_tags.iter() {
+            let mut chars = name.chars();
+            let is_identifier = matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '$')
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+            if !is_identifier {
+                return Err(Error::ContextError(format!("Invalid clickTag name {:?}", name)));
+            }
+            variables.push_str(&format!("var {} = {};\n", name, js_string(url)));
+        }
+        Ok(format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="ad.size" content="width={width},height={height}">
+<title>{title}</title>
+<script type="text/javascript">
+{variables}</script>
+<style>
+html, body {{ margin: 0; padding: 0; overflow: hidden; }}
+canvas {{ display: block; width: {width}px; height: {height}px; }}
+</style>
+</head>
+<body>
+<script src="{script}"></script>
+</body>
+</html>
+"#,
+            width = self.width,
+            height = self.height,
+            title = html_escape(&self.title),
+            variables = variables,
+            script = html_escape(&self.script),
+        ))
+    }
+}
+
+/// Parse the content of an ad.size meta tag, e.g. "width=300,height=250"
+pub fn parse_ad_size(content: &str) -> Option<(u32, u32)> {
+    let (mut width, mut height) = (None, None);
+    for pair in content.split(',') {
+        let mut parts = pair.splitn(2, '=').map(|part| part.trim());
+        match (parts.next(), parts.next().and_then(|value| value.parse::<u32>().ok())) {
+            (Some("width"), Some(value)) => width = Some(value),
+            (Some("height"), Some(value)) => height = Some(value),
+            _ => return None,
+        }
+    }
+    match (width, height) {
+        (Some(width), Some(height)) if width > 0 && height > 0 => Some((width, height)),
+        _ => None,
+    }
+}
+
+/// Escape text for use in HTML content and attribute values
+pub fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// A JavaScript string literal that is also safe inside a script element
+fn js_string(text: &str) -> String {
+    let mut result = String::with_capacity(text.len() + 2);
+    result.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '<' => result.push_str("\\u003C"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04X}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_have_the_size_and_click_tags() {
+        let page = Html5Page::new("Teapot <300x250>", (300, 250), "teapot.js")
+            .with_click_tag("clickTag", "https://example.com/?a=1&b=\"</script>\"")
+            .with_click_tag("clickTag2", "https://example.com/");
+        let html = page.to_html().unwrap();
+        assert!(html.contains(r#"<meta name="ad.size" content="width=300,height=250">"#));
+        assert!(html.contains("<title>Teapot &lt;300x250&gt;</title>"));
+        assert!(html.contains(r#"var clickTag = "https://example.com/?a=1&b=\"\u003C/script>\"";"#));
+        assert!(html.contains(r#"var clickTag2 = "https://example.com/";"#));
+        assert!(html.contains("canvas { display: block; width: 300px; height: 250px; }"));
+        assert!(html.contains(r#"<script src="teapot.js"></script>"#));
+        assert_eq!(html.matches("</script>").count(), 2);
+    }
+
+    #[test]
+    fn click_tags_must_be_variable_names() {
+        for name in ["clickTag", "_tag", "$tag2"].iter() {
+            assert!(Html5Page::new("", (1, 1), "").with_click_tag(name, "").to_html().is_ok());
+        }
+        for name in ["2tag", "click-tag", "click tag", ""].iter() {
+            assert!(Html5Page::new("", (1, 1), "").with_click_tag(name, "").to_html().is_err());
+        }
+    }
+
+    #[test]
+    fn parses_the_ad_size() {
+        assert_eq!(parse_ad_size("width=300,height=250"), Some((300, 250)));
+        assert_eq!(parse_ad_size(" height = 90 , width = 728 "), Some((728, 90)));
+        assert_eq!(parse_ad_size("width=300"), None);
+        assert_eq!(parse_ad_size("width=0,height=250"), None);
+        assert_eq!(parse_ad_size("width=300,height=250,depth=1"), None);
+        assert_eq!(parse_ad_size(""), None);
+    }
+}
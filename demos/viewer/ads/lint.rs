@@ -0,0 +1,454 @@
+/// IAB compliance checks for creatives
+///
+/// A rules file sets the limits that a creative is checked against. Every rule has a default from the IAB display
+/// guidelines, and ad sizes can override any of them by their AdSpec id:
+///
+/// ```toml
+/// [rules]
+/// max_animation_secs = 15.0
+/// max_loops = 3
+/// max_initial_load_kb = 150.0
+/// max_polite_load_kb = 1024.0
+/// max_frame_rate = 24.0
+/// require_cta = true
+/// border_on_white = true
+/// min_font_size = 10.0
+///
+/// [specs."728x90"]
+/// min_font_size = 8.0
+/// ```
+///
+/// The ad builder describes the creative at one size with CreativeFacts, and lint() checks the facts against the
+/// rules for that size. The report has one LintCheck per rule, and facts that are not known are skipped.
+///
+use quicksilver::{Error, Result};
+
+use serde::Deserialize;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// The rules that the viewer and the lint command use, relative to the asset root
+pub const DEFAULT_LINT_RULES: &str = "lint.toml";
+
+/// Backgrounds at or above this relative luminance count as white
+const WHITE_LUMINANCE: f32 = 0.9;
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct LintRules {
+    /// Seconds of animation over all loops
+    #[serde(default = "LintRules::default_max_animation_secs")]
+    pub max_animation_secs: f64,
+    /// Times that the animation plays, including the first
+    #[serde(default = "LintRules::default_max_loops")]
+    pub max_loops: u32,
+    /// Compressed kilobytes of files that are loaded before the ad is shown
+    #[serde(default = "LintRules::default_max_initial_load_kb")]
+    pub max_initial_load_kb: f64,
+    /// Compressed kilobytes of files that are loaded after the publisher's page
+    #[serde(default = "LintRules::default_max_polite_load_kb")]
+    pub max_polite_load_kb: f64,
+    #[serde(default = "LintRules::default_max_frame_rate")]
+    pub max_frame_rate: f64,
+    #[serde(default = "LintRules::default_true")]
+    pub require_cta: bool,
+    /// Ads with a white background must have a border in another color, so that they stand out from the page
+    #[serde(default = "LintRules::default_true")]
+    pub border_on_white: bool,
+    /// Pixels, at the size that the text is drawn in the ad
+    #[serde(default = "LintRules::default_min_font_size")]
+    pub min_font_size: f32,
+}
+
+impl LintRules {
+    fn default_max_animation_secs() -> f64 {
+        15.0
+    }
+
+    fn default_max_loops() -> u32 {
+        3
+    }
+
+    fn default_max_initial_load_kb() -> f64 {
+        150.0
+    }
+
+    fn default_max_polite_load_kb() -> f64 {
+        1024.0
+    }
+
+    fn default_max_frame_rate() -> f64 {
+        24.0
+    }
+
+    fn default_true() -> bool {
+        true
+    }
+
+    fn default_min_font_size() -> f32 {
+        10.0
+    }
+
+    /// A copy of the rules with the values that the overrides set
+    fn merged(&self, overrides: &RuleOverrides) -> LintRules {
+        LintRules {
+            max_animation_secs: overrides.max_animation_secs.unwrap_or(self.max_animation_secs),
+            max_loops: overrides.max_loops.unwrap_or(self.max_loops),
+            max_initial_load_kb: overrides.max_initial_load_kb.unwrap_or(self.max_initial_load_kb),
+            max_polite_load_kb: overrides.max_polite_load_kb.unwrap_or(self.max_polite_load_kb),
+            max_frame_rate: overrides.max_frame_rate.unwrap_or(self.max_frame_rate),
+            require_cta: overrides.require_cta.unwrap_or(self.require_cta),
+            border_on_white: overrides.border_on_white.unwrap_or(self.border_on_white),
+            min_font_size: overrides.min_font_size.unwrap_or(self.min_font_size),
+        }
+    }
+}
+
+impl Default for LintRules {
+    fn default() -> Self {
+        LintRules {
+            max_animation_secs: LintRules::default_max_animation_secs(),
+            max_loops: LintRules::default_max_loops(),
+            max_initial_load_kb: LintRules::default_max_initial_load_kb(),
+            max_polite_load_kb: LintRules::default_max_polite_load_kb(),
+            max_frame_rate: LintRules::default_max_frame_rate(),
+            require_cta: true,
+            border_on_white: true,
+            min_font_size: LintRules::default_min_font_size(),
+        }
+    }
+}
+
+/// Rules for one ad size. Rules that are left out use the value in [rules].
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct RuleOverrides {
+    pub max_animation_secs: Option<f64>,
+    pub max_loops: Option<u32>,
+    pub max_initial_load_kb: Option<f64>,
+    pub max_polite_load_kb: Option<f64>,
+    pub max_frame_rate: Option<f64>,
+    pub require_cta: Option<bool>,
+    pub border_on_white: Option<bool>,
+    pub min_font_size: Option<f32>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct LintConfig {
+    #[serde(default)]
+    pub rules: LintRules,
+    /// Overrides by AdSpec id, e.g. "728x90"
+    #[serde(default)]
+    pub specs: BTreeMap<String, RuleOverrides>,
+}
+
+impl LintConfig {
+    pub fn from_toml(text: &str) -> Result<LintConfig> {
+        let config: LintConfig =
+            toml::from_str(text).map_err(|e| Error::ContextError(format!("Invalid lint rules: {}", e)))?;
+        let mut all_rules = vec![("rules".to_string(), config.rules.clone())];
+        all_rules.extend(config.specs.keys().map(|id| (format!("specs.{}", id), config.rules_for(id))));
+        for (name, rules) in all_rules {
+            let limits =
+                [rules.max_animation_secs, rules.max_frame_rate, rules.max_initial_load_kb, rules.max_polite_load_kb];
+            if limits.iter().any(|limit| *limit <= 0.0) {
+                return Err(Error::ContextError(format!("Lint rules in {} must be positive", name)));
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<LintConfig> {
+        let text = fs::read_to_string(path.as_ref())?;
+        LintConfig::from_toml(&text)
+    }
+
+    /// The rules for the AdSpec with this id
+    pub fn rules_for(&self, spec_id: &str) -> LintRules {
+        match self.specs.get(spec_id) {
+            Some(overrides) => self.rules.merged(overrides),
+            None => self.rules.clone(),
+        }
+    }
+}
+
+/// What the lint rules need to know about a creative at one size
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CreativeFacts {
+    pub name: String,
+    pub spec_id: String,
+    /// Seconds for one play of the animation
+    pub duration: f64,
+    /// Times that the animation plays, including the first
+    pub loops: u32,
+    /// Compressed bytes of files that are loaded before the ad is shown, if they were measured
+    pub initial_load_bytes: Option<u64>,
+    /// Compressed bytes of files that are loaded after the publisher's page, if they were measured
+    pub polite_load_bytes: Option<u64>,
+    /// Frames per second that the creative caps its animation at, if it has a cap. The display's refresh rate is
+    /// not the creative's frame rate, so it is not used here.
+    pub frame_rate: Option<f64>,
+    pub has_cta: bool,
+    /// Relative luminance of the background, from 0.0 for black to 1.0 for white
+    pub background_luminance: f32,
+    /// Width of the border, or 0.0 when the ad has no border or it is the same color as the background
+    pub border_width: f32,
+    /// Each piece of copy and the size in pixels that it is drawn at
+    pub text_sizes: Vec<(String, f32)>,
+}
+
+/// Relative luminance of an sRGB color with components from 0.0 to 1.0
+pub fn relative_luminance(r: f32, g: f32, b: f32) -> f32 {
+    let linear = |c: f32| if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) };
+    0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LintStatus {
+    Pass,
+    Fail,
+    /// The facts for the rule are not known, e.g. the frame rate of an ad without a frame cap
+    Skipped,
+}
+
+impl LintStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            LintStatus::Pass => "PASS",
+            LintStatus::Fail => "FAIL",
+            LintStatus::Skipped => "SKIP",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LintCheck {
+    pub rule: &'static str,
+    pub status: LintStatus,
+    pub detail: String,
+}
+
+impl LintCheck {
+    fn new(rule: &'static str, passed: bool, detail: String) -> Self {
+        let status = if passed { LintStatus::Pass } else { LintStatus::Fail };
+        LintCheck { rule, status, detail }
+    }
+
+    fn skipped(rule: &'static str, detail: &str) -> Self {
+        LintCheck { rule, status: LintStatus::Skipped, detail: detail.to_string() }
+    }
+}
+
+/// The result of every rule for one creative at one size
+#[derive(Clone, Debug, PartialEq)]
+pub struct LintReport {
+    pub creative: String,
+    pub spec_id: String,
+    pub checks: Vec<LintCheck>,
+}
+
+impl LintReport {
+    pub fn failures(&self) -> Vec<&LintCheck> {
+        self.checks.iter().filter(|check| check.status == LintStatus::Fail).collect()
+    }
+
+    pub fn is_compliant(&self) -> bool {
+        self.failures().is_empty()
+    }
+}
+
+impl fmt::Display for LintReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let failed = self.failures().len();
+        writeln!(f, "{} at {}: {} of {} checks failed", self.creative, self.spec_id, failed, self.checks.len())?;
+        for check in self.checks.iter() {
+            writeln!(f, "  {} {:<16} {}", check.status.label(), check.rule, check.detail)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check the facts against the rules
+pub fn lint(facts: &CreativeFacts, rules: &LintRules) -> LintReport {
+    let mut checks = Vec::new();
+
+    let total = facts.duration * facts.loops as f64;
+    let detail = format!("{:.1}s in total, max {:.1}s", total, rules.max_animation_secs);
+    checks.push(LintCheck::new("animation length", total <= rules.max_animation_secs, detail));
+
+    let detail = format!("{}, max {}", facts.loops, rules.max_loops);
+    checks.push(LintCheck::new("loops", facts.loops <= rules.max_loops, detail));
+
+    let loads = [
+        ("initial load", facts.initial_load_bytes, rules.max_initial_load_kb),
+        ("polite load", facts.polite_load_bytes, rules.max_polite_load_kb),
+    ];
+    for (rule, bytes, max_kb) in loads.iter() {
+        checks.push(match bytes {
+            Some(bytes) => {
+                let kb = *bytes as f64 / 1024.0;
+                LintCheck::new(rule, kb <= *max_kb, format!("{:.1}KB, max {:.1}KB", kb, max_kb))
+            }
+            None => LintCheck::skipped(rule, "not measured"),
+        });
+    }
+
+    checks.push(match facts.frame_rate {
+        Some(frame_rate) => {
+            let detail = format!("{:.0}fps, max {:.0}fps", frame_rate, rules.max_frame_rate);
+            LintCheck::new("frame rate", frame_rate <= rules.max_frame_rate, detail)
+        }
+        None => LintCheck::skipped("frame rate", "no frame cap"),
+    });
+
+    checks.push(if rules.require_cta {
+        let detail = if facts.has_cta { "found" } else { "missing" };
+        LintCheck::new("cta", facts.has_cta, detail.to_string())
+    } else {
+        LintCheck::skipped("cta", "not required")
+    });
+
+    let is_white = facts.background_luminance >= WHITE_LUMINANCE;
+    checks.push(if !rules.border_on_white {
+        LintCheck::skipped("border", "not required")
+    } else if !is_white {
+        LintCheck::skipped("border", "background is not white")
+    } else if facts.border_width > 0.0 {
+        LintCheck::new("border", true, format!("{:.0}px on a white background", facts.border_width))
+    } else {
+        LintCheck::new("border", false, "missing on a white background".to_string())
+    });
+
+    let too_small: Vec<String> = facts
+        .text_sizes
+        .iter()
+        .filter(|(_, size)| *size < rules.min_font_size)
+        .map(|(name, size)| format!("{} is {:.1}px", name, size))
+        .collect();
+    let detail = if facts.text_sizes.is_empty() {
+        "no text".to_string()
+    } else if too_small.is_empty() {
+        let smallest = facts.text_sizes.iter().map(|(_, size)| *size).fold(f32::MAX, f32::min);
+        format!("smallest is {:.1}px, min {:.1}px", smallest, rules.min_font_size)
+    } else {
+        format!("{}, min {:.1}px", too_small.join(", "), rules.min_font_size)
+    };
+    checks.push(LintCheck::new("font size", too_small.is_empty(), detail));
+
+    LintReport { creative: facts.name.clone(), spec_id: facts.spec_id.clone(), checks }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn facts() -> CreativeFacts {
+        CreativeFacts {
+            name: "Teapot".to_string(),
+            spec_id: "300x250".to_string(),
+            duration: 5.8,
+            loops: 1,
+            initial_load_bytes: Some(100 * 1024),
+            polite_load_bytes: None,
+            frame_rate: None,
+            has_cta: true,
+            background_luminance: 1.0,
+            border_width: 1.0,
+            text_sizes: vec![("intro".to_string(), 20.0), ("tagline".to_string(), 12.0)],
+        }
+    }
+
+    fn status(report: &LintReport, rule: &str) -> LintStatus {
+        report.checks.iter().find(|check| check.rule == rule).unwrap().status
+    }
+
+    #[test]
+    fn compliant_creatives_pass() {
+        let report = lint(&facts(), &LintRules::default());
+        assert!(report.is_compliant(), "{}", report);
+        assert_eq!(report.checks.len(), 8);
+        assert_eq!(status(&report, "animation length"), LintStatus::Pass);
+        assert_eq!(status(&report, "initial load"), LintStatus::Pass);
+        assert_eq!(status(&report, "border"), LintStatus::Pass);
+        assert_eq!(report.checks.last().unwrap().detail, "smallest is 12.0px, min 10.0px");
+    }
+
+    #[test]
+    fn reports_each_failure() {
+        let facts = CreativeFacts {
+            loops: 3,
+            initial_load_bytes: Some(200 * 1024),
+            frame_rate: Some(60.0),
+            has_cta: false,
+            border_width: 0.0,
+            text_sizes: vec![("tagline".to_string(), 8.0)],
+            ..facts()
+        };
+        let report = lint(&facts, &LintRules::default());
+        let failed: Vec<&str> = report.failures().iter().map(|check| check.rule).collect();
+        assert_eq!(failed, vec!["animation length", "initial load", "frame rate", "cta", "border", "font size"]);
+        assert_eq!(status(&report, "loops"), LintStatus::Pass);
+        assert_eq!(report.checks.last().unwrap().detail, "tagline is 8.0px, min 10.0px");
+        assert!(!report.is_compliant());
+    }
+
+    #[test]
+    fn skips_unknown_facts_and_rules_that_are_off() {
+        let rules = LintRules { require_cta: false, border_on_white: false, ..LintRules::default() };
+        let report = lint(&CreativeFacts { has_cta: false, border_width: 0.0, ..facts() }, &rules);
+        assert!(report.is_compliant());
+        assert_eq!(status(&report, "polite load"), LintStatus::Skipped);
+        assert_eq!(status(&report, "frame rate"), LintStatus::Skipped);
+        assert_eq!(status(&report, "cta"), LintStatus::Skipped);
+        assert_eq!(status(&report, "border"), LintStatus::Skipped);
+
+        let dark = CreativeFacts { background_luminance: 0.2, border_width: 0.0, ..facts() };
+        let report = lint(&dark, &LintRules::default());
+        assert_eq!(status(&report, "border"), LintStatus::Skipped);
+    }
+
+    #[test]
+    fn relative_luminance_of_srgb_colors() {
+        assert_eq!(relative_luminance(0.0, 0.0, 0.0), 0.0);
+        assert!((relative_luminance(1.0, 1.0, 1.0) - 1.0).abs() < 1e-6);
+        assert!((relative_luminance(1.0, 0.0, 0.0) - 0.2126).abs() < 1e-6);
+        // Mid gray is much darker than 0.5 once it is linearized
+        assert!((relative_luminance(0.5, 0.5, 0.5) - 0.214).abs() < 1e-3);
+        let cream = 0xEE as f32 / 255.0;
+        assert!(relative_luminance(1.0, 1.0, cream) >= WHITE_LUMINANCE);
+    }
+
+    #[test]
+    fn spec_overrides_merge_with_the_rules() {
+        let config = LintConfig::from_toml(
+            r#"
+            [rules]
+            max_loops = 2
+            min_font_size = 12.0
+
+            [specs."728x90"]
+            min_font_size = 8.0
+            require_cta = false
+            "#,
+        )
+        .unwrap();
+        let rules = config.rules_for("300x250");
+        assert_eq!(rules.max_loops, 2);
+        assert_eq!(rules.min_font_size, 12.0);
+        assert_eq!(rules.max_initial_load_kb, 150.0);
+
+        let banner = config.rules_for("728x90");
+        assert_eq!(banner.min_font_size, 8.0);
+        assert!(!banner.require_cta);
+        assert_eq!(banner.max_loops, 2);
+        assert_eq!(LintRules { min_font_size: 12.0, require_cta: true, ..banner }, rules);
+    }
+
+    #[test]
+    fn limits_must_be_positive() {
+        assert!(LintConfig::from_toml("[rules]\nmax_frame_rate = 0.0").is_err());
+        assert!(LintConfig::from_toml("[specs.\"300x250\"]\nmax_initial_load_kb = -1.0").is_err());
+        assert_eq!(LintConfig::from_toml("").unwrap().rules, LintRules::default());
+    }
+}
@@ -0,0 +1,33 @@
+pub use self::ad_server::*;
+pub use self::campaign::*;
+pub use self::clickthrough::*;
+pub use self::dco::*;
+pub use self::events::*;
+pub use self::html5::*;
+pub use self::lint::*;
+pub use self::mraid::*;
+pub use self::native::*;
+pub use self::openrtb::*;
+pub use self::rotation::*;
+pub use self::strings::*;
+pub use self::tracking::*;
+pub use self::variants::*;
+pub use self::vast::*;
+pub use self::viewability::*;
+
+mod ad_server;
+mod campaign;
+mod clickthrough;
+mod dco;
+mod events;
+mod html5;
+mod lint;
+mod mraid;
+mod native;
+mod openrtb;
+mod rotation;
+mod strings;
+mod tracking;
+mod variants;
+mod vast;
+mod viewability;
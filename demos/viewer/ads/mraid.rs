@@ -0,0 +1,448 @@
+/// MRAID container emulation
+///
+/// Mobile rich-media creatives talk to the app through the MRAID API. Here a creative sends MraidCommands on the
+/// EventBus, e.g. from a button's onclick, and the viewer passes them to the MraidController. The controller
+/// applies the state rules from the MRAID 3.0 spec and returns the MraidEvents that the creative receives. The
+/// getters match the MRAID getters, so getState() is state() and so on.
+///
+/// States: loading -> default on ready. An inline ad can expand or resize from default, and close returns
+/// it to default. Close in the default state hides the ad. Interstitials are full screen in the default state
+/// and can only close.
+///
+use quicksilver::geom::{Rectangle, Vector};
+
+use tweek::events::*;
+
+pub const MRAID_VERSION: &str = "3.0";
+
+/// The smallest size allowed for resize
+pub const MIN_RESIZE_SIZE: f32 = 50.0;
+/// The size of the close region that the container adds to expanded and resized ads
+pub const CLOSE_REGION_SIZE: f32 = 50.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MraidState {
+    Loading,
+    Default,
+    Expanded,
+    Resized,
+    Hidden,
+}
+
+impl MraidState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MraidState::Loading => "loading",
+            MraidState::Default => "default",
+            MraidState::Expanded => "expanded",
+            MraidState::Resized => "resized",
+            MraidState::Hidden => "hidden",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlacementType {
+    Inline,
+    Interstitial,
+}
+
+impl PlacementType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlacementType::Inline => "inline",
+            PlacementType::Interstitial => "interstitial",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExpandProperties {
+    pub width: f32,
+    pub height: f32,
+    /// When true, the creative draws its own close control
+    pub use_custom_close: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResizeProperties {
+    pub width: f32,
+    pub height: f32,
+    /// Offset from the default position
+    pub offset_x: f32,
+    pub offset_y: f32,
+    pub allow_offscreen: bool,
+}
+
+/// Calls from the creative to the container
+#[derive(Clone, Debug, PartialEq)]
+pub enum MraidCommand {
+    Expand,
+    Resize,
+    Close,
+    SetExpandProperties(ExpandProperties),
+    SetResizeProperties(ResizeProperties),
+    UseCustomClose(bool),
+    /// Open a URL in the browser
+    Open(String),
+}
+
+impl AnyEvent for MraidCommand {}
+
+/// Events from the container to the creative
+#[derive(Clone, Debug, PartialEq)]
+pub enum MraidEvent {
+    Ready,
+    Error { message: String, action: String },
+    StateChange(MraidState),
+    ViewableChange(bool),
+    SizeChange(f32, f32),
+    /// Percent of the ad in view, from 0.0 to 100.0
+    ExposureChange(f32),
+}
+
+impl AnyEvent for MraidEvent {}
+
+pub struct MraidController {
+    state: MraidState,
+    placement: PlacementType,
+    expand_properties: Option<ExpandProperties>,
+    resize_properties: Option<ResizeProperties>,
+    use_custom_close: bool,
+    is_viewable: bool,
+    exposure: f32,
+    /// The largest area the ad can use, i.e. the app's content area
+    max_size: Rectangle,
+    default_position: Rectangle,
+}
+
+impl MraidController {
+    pub fn new(placement: PlacementType, max_size: Rectangle) -> Self {
+        MraidController {
+            state: MraidState::Loading,
+            placement,
+            expand_properties: None,
+            resize_properties: None,
+            use_custom_close: false,
+            is_viewable: false,
+            exposure: 0.0,
+            default_position: max_size.clone(),
+            max_size,
+        }
+    }
+
+    pub fn version(&self) -> &'static str {
+        MRAID_VERSION
+    }
+
+    pub fn state(&self) -> MraidState {
+        self.state
+    }
+
+    pub fn placement_type(&self) -> PlacementType {
+        self.placement
+    }
+
+    pub fn is_viewable(&self) -> bool {
+        self.is_viewable
+    }
+
+    pub fn max_size(&self) -> Vector {
+        self.max_size.size
+    }
+
+    pub fn screen_size(&self) -> Vector {
+        self.max_size.size
+    }
+
+    pub fn default_position(&self) -> Rectangle {
+        self.default_position.clone()
+    }
+
+    /// The viewer sets this when the ad is laid out in the default state
+    pub fn set_default_position(&mut self, frame: Rectangle) {
+        self.default_position = frame;
+    }
+
+    /// The expand properties, which default to the max size
+    pub fn expand_properties(&self) -> ExpandProperties {
+        self.expand_properties.clone().unwrap_or(ExpandProperties {
+            width: self.max_size.width(),
+            height: self.max_size.height(),
+            use_custom_close: self.use_custom_close,
+        })
+    }
+
+    pub fn resize_properties(&self) -> Option<ResizeProperties> {
+        self.resize_properties.clone()
+    }
+
+    /// True if the container must draw a close control over the ad
+    pub fn needs_close_control(&self) -> bool {
+        match self.state {
+            MraidState::Expanded => !self.expand_properties().use_custom_close,
+            MraidState::Default => self.placement == PlacementType::Interstitial && !self.use_custom_close,
+            _ => false,
+        }
+    }
+
+    /// The current position of the ad
+    pub fn current_position(&self) -> Rectangle {
+        match self.state {
+            MraidState::Expanded => {
+                let props = self.expand_properties();
+                let max = self.max_size.size;
+                let size = Vector::new(props.width.min(max.x), props.height.min(max.y));
+                let pos = self.max_size.pos + (self.max_size.size - size) * 0.5;
+                Rectangle::new(pos, size)
+            }
+            MraidState::Resized => match &self.resize_properties {
+                Some(props) => MraidController::resized_frame(&self.default_position, props),
+                None => self.default_position.clone(),
+            },
+            MraidState::Default if self.placement == PlacementType::Interstitial => self.max_size.clone(),
+            _ => self.default_position.clone(),
+        }
+    }
+
+    /// The area where the container draws its close control, at the top right of the ad
+    pub fn close_region(&self) -> Rectangle {
+        MraidController::close_region_for(&self.current_position())
+    }
+
+    fn close_region_for(frame: &Rectangle) -> Rectangle {
+        let size = Vector::new(CLOSE_REGION_SIZE, CLOSE_REGION_SIZE);
+        Rectangle::new((frame.x() + frame.width() - size.x, frame.y()), size)
+    }
+
+    fn resized_frame(default_position: &Rectangle, props: &ResizeProperties) -> Rectangle {
+        Rectangle::new(
+            (default_position.x() + props.offset_x, default_position.y() + props.offset_y),
+            (props.width, props.height),
+        )
+    }
+
+    fn error(message: &str, action: &str) -> Vec<MraidEvent> {
+        log::warn!("MRAID {} failed: {}", action, message);
+        vec![MraidEvent::Error { message: message.to_string(), action: action.to_string() }]
+    }
+
+    fn change_state(&mut self, state: MraidState) -> Vec<MraidEvent> {
+        self.state = state;
+        if state == MraidState::Hidden {
+            return vec![MraidEvent::StateChange(state)];
+        }
+        let size = self.current_position().size;
+        vec![MraidEvent::SizeChange(size.x, size.y), MraidEvent::StateChange(state)]
+    }
+
+    /// Return to the loading state, e.g. when a hidden ad is shown again. Properties are kept.
+    pub fn reset(&mut self) {
+        self.state = MraidState::Loading;
+        self.is_viewable = false;
+        self.exposure = 0.0;
+    }
+
+    /// The ad finished loading
+    pub fn set_ready(&mut self) -> Vec<MraidEvent> {
+        if self.state != MraidState::Loading {
+            return Vec::new();
+        }
+        self.state = MraidState::Default;
+        vec![MraidEvent::Ready, MraidEvent::StateChange(MraidState::Default)]
+    }
+
+    /// Update the fraction of the ad in view, from 0.0 to 1.0
+    pub fn set_exposure(&mut self, fraction: f32) -> Vec<MraidEvent> {
+        let mut events = Vec::new();
+        if self.state == MraidState::Loading {
+            return events;
+        }
+        let fraction = if self.state == MraidState::Hidden { 0.0 } else { fraction };
+        let exposure = (fraction * 100.0).round();
+        if exposure != self.exposure {
+            self.exposure = exposure;
+            events.push(MraidEvent::ExposureChange(exposure));
+        }
+        let is_viewable = exposure > 0.0 && self.state != MraidState::Hidden;
+        if is_viewable != self.is_viewable {
+            self.is_viewable = is_viewable;
+            events.push(MraidEvent::ViewableChange(is_viewable));
+        }
+        events
+    }
+
+    /// Apply a command from the creative and return the events for the creative
+    pub fn handle(&mut self, command: &MraidCommand) -> Vec<MraidEvent> {
+        match command {
+            MraidCommand::Expand => self.expand(),
+            MraidCommand::Resize => self.resize(),
+            MraidCommand::Close => self.close(),
+            MraidCommand::SetExpandProperties(props) => {
+                self.expand_properties = Some(props.clone());
+                Vec::new()
+            }
+            MraidCommand::SetResizeProperties(props) => {
+                if props.width < MIN_RESIZE_SIZE || props.height < MIN_RESIZE_SIZE {
+                    return MraidController::error("Resize properties must be at least 50x50", "setResizeProperties");
+                }
+                self.resize_properties = Some(props.clone());
+                Vec::new()
+            }
+            MraidCommand::UseCustomClose(value) => {
+                self.use_custom_close = *value;
+                if let Some(props) = &mut self.expand_properties {
+                    props.use_custom_close = *value;
+                }
+                Vec::new()
+            }
+            MraidCommand::Open(url) => {
+                log::info!("MRAID open url={}", url);
+                Vec::new()
+            }
+        }
+    }
+
+    fn expand(&mut self) -> Vec<MraidEvent> {
+        if self.placement == PlacementType::Interstitial {
+            return MraidController::error("Interstitial ads cannot expand", "expand");
+        }
+        match self.state {
+            MraidState::Default | MraidState::Resized => self.change_state(MraidState::Expanded),
+            state => MraidController::error(&format!("Cannot expand in the {} state", state.name()), "expand"),
+        }
+    }
+
+    fn resize(&mut self) -> Vec<MraidEvent> {
+        if self.placement == PlacementType::Interstitial {
+            return MraidController::error("Interstitial ads cannot resize", "resize");
+        }
+        match self.state {
+            MraidState::Default | MraidState::Resized => (),
+            state => return MraidController::error(&format!("Cannot resize in the {} state", state.name()), "resize"),
+        }
+        let props = match &self.resize_properties {
+            Some(props) => props,
+            None => return MraidController::error("setResizeProperties must be called before resize", "resize"),
+        };
+        let frame = MraidController::resized_frame(&self.default_position, props);
+        let close = MraidController::close_region_for(&frame);
+        let fits = |rect: &Rectangle| {
+            rect.x() >= self.max_size.x()
+                && rect.y() >= self.max_size.y()
+                && rect.x() + rect.width() <= self.max_size.x() + self.max_size.width()
+                && rect.y() + rect.height() <= self.max_size.y() + self.max_size.height()
+        };
+        if !props.allow_offscreen && !fits(&frame) {
+            return MraidController::error("Resized ad does not fit in the max size", "resize");
+        }
+        if !fits(&close) {
+            return MraidController::error("Close region of the resized ad is offscreen", "resize");
+        }
+        self.change_state(MraidState::Resized)
+    }
+
+    fn close(&mut self) -> Vec<MraidEvent> {
+        match self.state {
+            MraidState::Expanded | MraidState::Resized => self.change_state(MraidState::Default),
+            MraidState::Default => {
+                let mut events = self.change_state(MraidState::Hidden);
+                events.extend(self.set_exposure(0.0));
+                events
+            }
+            state => MraidController::error(&format!("Cannot close in the {} state", state.name()), "close"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inline_controller() -> MraidController {
+        let mut controller = MraidController::new(PlacementType::Inline, Rectangle::new((0.0, 0.0), (320.0, 480.0)));
+        controller.set_default_position(Rectangle::new((0.0, 100.0), (320.0, 50.0)));
+        controller
+    }
+
+    fn resize_properties(width: f32, height: f32) -> ResizeProperties {
+        ResizeProperties { width, height, offset_x: 0.0, offset_y: 0.0, allow_offscreen: false }
+    }
+
+    fn is_error(events: &[MraidEvent]) -> bool {
+        matches!(events, [MraidEvent::Error { .. }])
+    }
+
+    #[test]
+    fn inline_ads_expand_and_close_back_to_default() {
+        let mut controller = inline_controller();
+        assert!(is_error(&controller.handle(&MraidCommand::Expand)));
+        assert_eq!(controller.set_ready(), vec![MraidEvent::Ready, MraidEvent::StateChange(MraidState::Default)]);
+        assert!(controller.set_ready().is_empty());
+
+        assert_eq!(
+            controller.handle(&MraidCommand::Expand),
+            vec![MraidEvent::SizeChange(320.0, 480.0), MraidEvent::StateChange(MraidState::Expanded)]
+        );
+        assert!(controller.needs_close_control());
+        assert!(is_error(&controller.handle(&MraidCommand::Resize)));
+        assert_eq!(
+            controller.handle(&MraidCommand::Close),
+            vec![MraidEvent::SizeChange(320.0, 50.0), MraidEvent::StateChange(MraidState::Default)]
+        );
+        assert_eq!(controller.state(), MraidState::Default);
+    }
+
+    #[test]
+    fn resize_needs_properties_that_fit() {
+        let mut controller = inline_controller();
+        controller.set_ready();
+        assert!(is_error(&controller.handle(&MraidCommand::Resize)));
+        assert!(is_error(&controller.handle(&MraidCommand::SetResizeProperties(resize_properties(40.0, 250.0)))));
+        controller.handle(&MraidCommand::SetResizeProperties(resize_properties(320.0, 500.0)));
+        assert!(is_error(&controller.handle(&MraidCommand::Resize)));
+
+        controller.handle(&MraidCommand::SetResizeProperties(resize_properties(320.0, 250.0)));
+        assert_eq!(
+            controller.handle(&MraidCommand::Resize),
+            vec![MraidEvent::SizeChange(320.0, 250.0), MraidEvent::StateChange(MraidState::Resized)]
+        );
+        assert_eq!(controller.current_position(), Rectangle::new((0.0, 100.0), (320.0, 250.0)));
+        assert_eq!(controller.handle(&MraidCommand::Expand)[1], MraidEvent::StateChange(MraidState::Expanded));
+    }
+
+    #[test]
+    fn close_in_the_default_state_hides_the_ad() {
+        let mut controller = inline_controller();
+        controller.set_ready();
+        assert_eq!(
+            controller.set_exposure(0.5),
+            vec![MraidEvent::ExposureChange(50.0), MraidEvent::ViewableChange(true)]
+        );
+        assert_eq!(
+            controller.handle(&MraidCommand::Close),
+            vec![
+                MraidEvent::StateChange(MraidState::Hidden),
+                MraidEvent::ExposureChange(0.0),
+                MraidEvent::ViewableChange(false)
+            ]
+        );
+        assert!(is_error(&controller.handle(&MraidCommand::Close)));
+
+        controller.reset();
+        assert_eq!(controller.state(), MraidState::Loading);
+        assert_eq!(controller.set_ready()[1], MraidEvent::StateChange(MraidState::Default));
+    }
+
+    #[test]
+    fn interstitials_can_only_close() {
+        let mut controller =
+            MraidController::new(PlacementType::Interstitial, Rectangle::new((0.0, 0.0), (320.0, 480.0)));
+        controller.set_ready();
+        assert_eq!(controller.current_position(), Rectangle::new((0.0, 0.0), (320.0, 480.0)));
+        assert!(controller.needs_close_control());
+        assert!(is_error(&controller.handle(&MraidCommand::Expand)));
+        assert!(is_error(&controller.handle(&MraidCommand::Resize)));
+        assert_eq!(controller.handle(&MraidCommand::Close)[0], MraidEvent::StateChange(MraidState::Hidden));
+    }
+}
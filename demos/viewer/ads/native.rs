@@ -0,0 +1,175 @@
+/// Native ad components
+///
+/// A native ad is delivered as structured components instead of a finished creative, and the publisher lays
+/// them out to match the feed. The components are described in a TOML file. Images are asset ids from the
+/// ad's manifest and can be either an svg or an image.
+///
+/// Example:
+///
+/// ```toml
+/// headline = "Brew something beautiful"
+/// body = "The Teapot pours a perfect cup every time."
+/// icon = "logo"
+/// main_image = "teapot"
+/// main_image_size = [462, 548]
+/// cta_text = "Shop Now"
+/// sponsor = "Teapot Co."
+/// ```
+///
+/// Text lengths follow the recommendations in the IAB Native Ads spec. Longer text is allowed, but the
+/// templates may cut it off, so the viewer logs a warning.
+///
+use quicksilver::{Error, Result};
+
+use serde::Deserialize;
+
+use std::fs;
+use std::path::Path;
+
+/// The native components of the Teapot sample ad, relative to the asset root
+pub const DEFAULT_NATIVE_AD: &str = "teapot/native.toml";
+
+/// The components of a native ad
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NativeComponent {
+    Headline,
+    Body,
+    Icon,
+    MainImage,
+    Cta,
+    Sponsor,
+}
+
+impl NativeComponent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            NativeComponent::Headline => "headline",
+            NativeComponent::Body => "body",
+            NativeComponent::Icon => "icon",
+            NativeComponent::MainImage => "main_image",
+            NativeComponent::Cta => "cta_text",
+            NativeComponent::Sponsor => "sponsor",
+        }
+    }
+
+    /// The recommended maximum number of characters for text components
+    pub fn max_chars(&self) -> Option<usize> {
+        match self {
+            NativeComponent::Headline => Some(90),
+            NativeComponent::Body => Some(140),
+            NativeComponent::Cta => Some(15),
+            NativeComponent::Sponsor => Some(25),
+            NativeComponent::Icon | NativeComponent::MainImage => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct NativeAd {
+    pub headline: String,
+    pub body: String,
+    /// Asset id of the square icon or logo
+    pub icon: String,
+    /// Asset id of the main image
+    pub main_image: String,
+    /// Natural size of the main image when it is not an svg, so that it can be fitted without stretching
+    pub main_image_size: Option<(f32, f32)>,
+    pub cta_text: String,
+    /// The advertiser name that is shown with the "Sponsored" disclosure
+    pub sponsor: String,
+    /// The click-through exit for the whole ad
+    #[serde(default = "NativeAd::default_exit")]
+    pub exit: String,
+}
+
+impl NativeAd {
+    fn default_exit() -> String {
+        "cta".to_string()
+    }
+
+    pub fn from_toml(text: &str) -> Result<NativeAd> {
+        toml::from_str(text).map_err(|e| Error::ContextError(format!("Invalid native ad: {}", e)))
+    }
+
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<NativeAd> {
+        let text = fs::read_to_string(path.as_ref())?;
+        NativeAd::from_toml(&text)
+    }
+
+    /// The text or asset id of a component
+    pub fn component(&self, component: NativeComponent) -> &str {
+        match component {
+            NativeComponent::Headline => &self.headline,
+            NativeComponent::Body => &self.body,
+            NativeComponent::Icon => &self.icon,
+            NativeComponent::MainImage => &self.main_image,
+            NativeComponent::Cta => &self.cta_text,
+            NativeComponent::Sponsor => &self.sponsor,
+        }
+    }
+
+    /// Empty components and text that is longer than recommended
+    pub fn warnings(&self) -> Vec<String> {
+        let components = [
+            NativeComponent::Headline,
+            NativeComponent::Body,
+            NativeComponent::Icon,
+            NativeComponent::MainImage,
+            NativeComponent::Cta,
+            NativeComponent::Sponsor,
+        ];
+        let mut warnings = Vec::new();
+        for component in components.iter() {
+            let value = self.component(*component);
+            let chars = value.chars().count();
+            if value.trim().is_empty() {
+                warnings.push(format!("{} is empty", component.name()));
+            } else if let Some(max) = component.max_chars().filter(|max| chars > *max) {
+                let name = component.name();
+                warnings.push(format!("{} has {} characters, more than the recommended {}", name, chars, max));
+            }
+        }
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NATIVE_AD: &str = r#"
+headline = "Brew something beautiful"
+body = "The Teapot pours a perfect cup every time."
+icon = "logo"
+main_image = "teapot"
+cta_text = "Shop Now"
+sponsor = "Teapot Co."
+"#;
+
+    #[test]
+    fn complete_components_have_no_warnings() {
+        let ad = NativeAd::from_toml(NATIVE_AD).unwrap();
+        assert_eq!(ad.exit, "cta");
+        assert_eq!(ad.main_image_size, None);
+        assert_eq!(ad.component(NativeComponent::Icon), "logo");
+        assert!(ad.warnings().is_empty());
+        assert!(NativeAd::from_toml("headline = \"Teapot\"").is_err());
+    }
+
+    #[test]
+    fn empty_and_long_components_are_warned_about() {
+        let text = NATIVE_AD
+            .replace("Shop Now", "Shop the Spring Collection")
+            .replace("icon = \"logo\"", "icon = \" \"")
+            .replace("Teapot Co.", "Teapot Co. – Fine Kettles & Cups");
+        let ad = NativeAd::from_toml(&text).unwrap();
+        assert_eq!(
+            ad.warnings(),
+            vec![
+                "icon is empty",
+                "cta_text has 26 characters, more than the recommended 15",
+                "sponsor has 32 characters, more than the recommended 25",
+            ]
+        );
+    }
+}
@@ -0,0 +1,418 @@
+/// OpenRTB bid responses from captured JSON files
+///
+/// An exchange sends a bid request for one or more impressions and each bidder answers with a bid response. Each
+/// Bid has a price, the markup (adm) and notice URLs: nurl for the win notice, burl for billing when the ad is
+/// shown and lurl for the loss notice. The auction picks the highest bid for the impression. The markup and URLs
+/// contain auction macros like ${AUCTION_PRICE}, which the exchange substitutes before the creative is served.
+///
+/// Only the fields used for rendering are parsed. Unknown fields are ignored, so responses from OpenRTB 2.0 to 2.6
+/// can be loaded.
+///
+use quicksilver::{Error, Result};
+
+use serde::Deserialize;
+
+use tweek::events::*;
+
+use std::fs;
+use std::path::Path;
+
+/// The sample bid response, relative to the asset root
+pub const DEFAULT_BID_RESPONSE: &str = "openrtb/teapot-response.json";
+
+/// Loss reason for bids that were outbid, from the OpenRTB 2.5 loss reason codes
+pub const LOSS_LOST_TO_HIGHER_BID: u32 = 102;
+
+/// The increment over the second highest bid in a second price auction
+const SECOND_PRICE_INCREMENT: f64 = 0.01;
+
+/// Events from the bid preview
+#[derive(Clone, Debug, PartialEq)]
+pub enum BidEvent {
+    /// The winning creative was clicked
+    Click,
+}
+
+impl AnyEvent for BidEvent {}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Bid {
+    pub id: String,
+    pub impid: String,
+    pub price: f64,
+    #[serde(default)]
+    pub adid: Option<String>,
+    #[serde(default)]
+    pub nurl: Option<String>,
+    #[serde(default)]
+    pub burl: Option<String>,
+    #[serde(default)]
+    pub lurl: Option<String>,
+    #[serde(default)]
+    pub adm: Option<String>,
+    #[serde(default)]
+    pub adomain: Vec<String>,
+    #[serde(default)]
+    pub crid: Option<String>,
+    #[serde(default)]
+    pub w: Option<u32>,
+    #[serde(default)]
+    pub h: Option<u32>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct SeatBid {
+    #[serde(default)]
+    pub seat: Option<String>,
+    pub bid: Vec<Bid>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct BidResponse {
+    /// The id of the bid request
+    pub id: String,
+    #[serde(default)]
+    pub seatbid: Vec<SeatBid>,
+    #[serde(default)]
+    pub bidid: Option<String>,
+    #[serde(default = "default_currency")]
+    pub cur: String,
+}
+
+fn default_currency() -> String {
+    "USD".to_string()
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct BannerFormat {
+    pub w: u32,
+    pub h: u32,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Banner {
+    #[serde(default)]
+    pub w: Option<u32>,
+    #[serde(default)]
+    pub h: Option<u32>,
+    #[serde(default)]
+    pub format: Vec<BannerFormat>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Imp {
+    pub id: String,
+    #[serde(default)]
+    pub banner: Option<Banner>,
+}
+
+impl Imp {
+    /// The banner size, or the first of the allowed formats
+    pub fn size(&self) -> Option<(u32, u32)> {
+        let banner = self.banner.as_ref()?;
+        match (banner.w, banner.h) {
+            (Some(w), Some(h)) => Some((w, h)),
+            _ => banner.format.first().map(|format| (format.w, format.h)),
+        }
+    }
+}
+
+/// The parts of the bid request that decide the impression size and the auction type
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct BidRequest {
+    pub id: String,
+    #[serde(default)]
+    pub imp: Vec<Imp>,
+    /// 1 for first price and 2 for second price plus
+    #[serde(default = "default_auction_type")]
+    pub at: u32,
+}
+
+fn default_auction_type() -> u32 {
+    2
+}
+
+impl BidRequest {
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<BidRequest> {
+        let text = fs::read_to_string(path.as_ref())?;
+        serde_json::from_str(&text).map_err(|e| Error::ContextError(format!("Invalid bid request: {}", e)))
+    }
+}
+
+impl BidResponse {
+    pub fn parse(text: &str) -> Result<BidResponse> {
+        serde_json::from_str(text).map_err(|e| Error::ContextError(format!("Invalid bid response: {}", e)))
+    }
+
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<BidResponse> {
+        let text = fs::read_to_string(path.as_ref())?;
+        BidResponse::parse(&text)
+    }
+
+    /// Run the auction for the impression of the highest bid. Without a request, the auction is second price
+    /// and the size comes from the bid.
+    pub fn auction(&self, request: Option<&BidRequest>) -> Result<AuctionResult> {
+        let bids: Vec<(&SeatBid, &Bid)> =
+            self.seatbid.iter().flat_map(|seat| seat.bid.iter().map(move |bid| (seat, bid))).collect();
+        let (seat, winner) = bids
+            .iter()
+            .max_by(|a, b| a.1.price.partial_cmp(&b.1.price).unwrap_or(std::cmp::Ordering::Equal))
+            .cloned()
+            .ok_or_else(|| Error::ContextError(format!("Bid response {} has no bids", self.id)))?;
+        let losers: Vec<(Option<String>, Bid)> = bids
+            .iter()
+            .filter(|(_, bid)| bid.impid == winner.impid && bid.id != winner.id)
+            .map(|(seat, bid)| (seat.seat.clone(), (*bid).clone()))
+            .collect();
+
+        let first_price = request.map_or(false, |request| request.at == 1);
+        let second_price = losers.iter().map(|(_, bid)| bid.price).fold(None, |max: Option<f64>, price| {
+            Some(max.map_or(price, |max| max.max(price)))
+        });
+        let clearing_price = match second_price {
+            Some(second) if !first_price => (second + SECOND_PRICE_INCREMENT).min(winner.price),
+            _ => winner.price,
+        };
+
+        let imp_size = request.and_then(|request| request.imp.iter().find(|imp| imp.id == winner.impid)?.size());
+        let size = match (winner.w, winner.h, imp_size) {
+            (_, _, Some(size)) => size,
+            (Some(w), Some(h), None) => (w, h),
+            _ => {
+                let message = format!("No size for bid {}. Add w and h to the bid or load the bid request.", winner.id);
+                return Err(Error::ContextError(message));
+            }
+        };
+        Ok(AuctionResult {
+            auction_id: request.map_or(self.id.clone(), |request| request.id.clone()),
+            bid_id: self.bidid.clone().unwrap_or_else(|| winner.id.clone()),
+            currency: self.cur.clone(),
+            seat: seat.seat.clone(),
+            winner: winner.clone(),
+            losers,
+            clearing_price,
+            size,
+        })
+    }
+}
+
+/// The creative types that can be found in the adm field
+#[derive(Clone, Debug, PartialEq)]
+pub enum BidMarkup {
+    /// A VAST document for a video ad
+    Vast(String),
+    /// An image banner, usually an img in a link
+    Image { src: String, href: Option<String> },
+    /// Any other HTML, which the viewer cannot render
+    Html(String),
+}
+
+impl BidMarkup {
+    pub fn parse(adm: &str) -> BidMarkup {
+        let trimmed = adm.trim_start();
+        if trimmed.starts_with("<VAST") || (trimmed.starts_with("<?xml") && trimmed.contains("<VAST")) {
+            return BidMarkup::Vast(adm.to_string());
+        }
+        match html_attribute(adm, "img", "src") {
+            Some(src) => BidMarkup::Image { src, href: html_attribute(adm, "a", "href") },
+            None => BidMarkup::Html(adm.to_string()),
+        }
+    }
+}
+
+/// Find the value of an attribute in the first tag with this name. This is a simple search for typical banner
+/// markup rather than an HTML parser.
+fn html_attribute(html: &str, tag: &str, attribute: &str) -> Option<String> {
+    // ASCII lowercase keeps the byte offsets of the original text
+    let lower = html.to_ascii_lowercase();
+    let open = format!("<{}", tag);
+    let start = lower
+        .match_indices(&open)
+        .map(|(index, _)| index)
+        .find(|index| lower[index + open.len()..].starts_with(|c: char| c.is_ascii_whitespace()))?;
+    let end = start + lower[start..].find('>')?;
+    let tag_text = &html[start..end];
+    let tag_lower = &lower[start..end];
+    // The name must start after whitespace, so that src does not match data-src
+    let name = format!("{}=", attribute);
+    let value_start = tag_lower
+        .match_indices(&name)
+        .map(|(index, _)| index)
+        .find(|index| tag_lower[..*index].ends_with(|c: char| c.is_ascii_whitespace()))?
+        + name.len();
+    let rest = &tag_text[value_start..];
+    let value = match rest.chars().next()? {
+        quote @ '"' | quote @ '\'' => rest[1..].split(quote).next()?,
+        _ => rest.split(|c: char| c.is_whitespace() || c == '/').next()?,
+    };
+    Some(value.to_string())
+}
+
+/// The winning bid with the values for the auction macros
+#[derive(Clone, Debug)]
+pub struct AuctionResult {
+    pub auction_id: String,
+    pub bid_id: String,
+    pub currency: String,
+    pub seat: Option<String>,
+    pub winner: Bid,
+    /// Other bids for the same impression, with their seats
+    pub losers: Vec<(Option<String>, Bid)>,
+    pub clearing_price: f64,
+    /// The size of the impression
+    pub size: (u32, u32),
+}
+
+impl AuctionResult {
+    /// Substitute the auction macros for a bid. The loss code is only known for loss notices.
+    fn expand_for(&self, seat: &Option<String>, bid: &Bid, text: &str, loss: Option<u32>) -> String {
+        let macros = [
+            ("${AUCTION_ID}", self.auction_id.clone()),
+            ("${AUCTION_BID_ID}", self.bid_id.clone()),
+            ("${AUCTION_IMP_ID}", bid.impid.clone()),
+            ("${AUCTION_SEAT_ID}", seat.clone().unwrap_or_default()),
+            ("${AUCTION_AD_ID}", bid.adid.clone().unwrap_or_default()),
+            ("${AUCTION_PRICE}", format!("{:.2}", self.clearing_price)),
+            ("${AUCTION_CURRENCY}", self.currency.clone()),
+            ("${AUCTION_MBR}", String::new()),
+            ("${AUCTION_LOSS}", loss.map(|code| code.to_string()).unwrap_or_default()),
+        ];
+        macros.iter().fold(text.to_string(), |text, (name, value)| text.replace(name, value))
+    }
+
+    /// Substitute the auction macros for the winning bid
+    pub fn expand(&self, text: &str) -> String {
+        self.expand_for(&self.seat, &self.winner, text, None)
+    }
+
+    /// The winning markup with the macros substituted
+    pub fn markup(&self) -> Option<BidMarkup> {
+        self.winner.adm.as_ref().map(|adm| BidMarkup::parse(&self.expand(adm)))
+    }
+
+    /// The win notice and billing URLs for the winner, then the loss notices for the other bids
+    pub fn notices(&self) -> Vec<(&'static str, String)> {
+        let mut notices = Vec::new();
+        if let Some(nurl) = &self.winner.nurl {
+            notices.push(("win", self.expand(nurl)));
+        }
+        if let Some(burl) = &self.winner.burl {
+            notices.push(("billing", self.expand(burl)));
+        }
+        for (seat, bid) in self.losers.iter() {
+            if let Some(lurl) = &bid.lurl {
+                notices.push(("loss", self.expand_for(seat, bid, lurl, Some(LOSS_LOST_TO_HIGHER_BID))));
+            }
+        }
+        notices
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bid(id: &str, price: f64) -> Bid {
+        Bid { id: id.to_string(), impid: "1".to_string(), price, w: Some(300), h: Some(250), ..Default::default() }
+    }
+
+    fn response(bids: Vec<Bid>) -> BidResponse {
+        let seatbid = bids.into_iter().map(|bid| SeatBid { seat: Some(format!("{}-seat", bid.id)), bid: vec![bid] });
+        BidResponse { id: "auction".to_string(), seatbid: seatbid.collect(), cur: "USD".to_string(), bidid: None }
+    }
+
+    #[test]
+    fn the_highest_bid_wins_at_the_second_price() {
+        let result = response(vec![bid("low", 1.0), bid("high", 2.5), bid("mid", 1.75)]).auction(None).unwrap();
+        assert_eq!(result.winner.id, "high");
+        assert_eq!(result.seat.as_deref(), Some("high-seat"));
+        assert_eq!(result.losers.len(), 2);
+        assert!((result.clearing_price - 1.76).abs() < 1e-9);
+        assert_eq!(result.size, (300, 250));
+    }
+
+    #[test]
+    fn the_clearing_price_is_at_most_the_winning_bid() {
+        let result = response(vec![bid("a", 2.0), bid("b", 2.0)]).auction(None).unwrap();
+        assert!((result.clearing_price - 2.0).abs() < 1e-9);
+        let result = response(vec![bid("only", 3.0)]).auction(None).unwrap();
+        assert!((result.clearing_price - 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn first_price_auctions_clear_at_the_winning_bid() {
+        let request = BidRequest { id: "request".to_string(), imp: Vec::new(), at: 1 };
+        let result = response(vec![bid("low", 1.0), bid("high", 2.5)]).auction(Some(&request)).unwrap();
+        assert!((result.clearing_price - 2.5).abs() < 1e-9);
+        assert_eq!(result.auction_id, "request");
+    }
+
+    #[test]
+    fn the_request_decides_the_size() {
+        let format = vec![BannerFormat { w: 336, h: 280 }, BannerFormat { w: 300, h: 250 }];
+        let imp = Imp { id: "1".to_string(), banner: Some(Banner { w: None, h: None, format }) };
+        let request = BidRequest { id: "request".to_string(), imp: vec![imp], at: 2 };
+        let result = response(vec![bid("a", 1.0)]).auction(Some(&request)).unwrap();
+        assert_eq!(result.size, (336, 280));
+
+        let mut unsized_bid = bid("a", 1.0);
+        unsized_bid.w = None;
+        assert!(response(vec![unsized_bid]).auction(None).is_err());
+        assert!(response(Vec::new()).auction(None).is_err());
+    }
+
+    #[test]
+    fn substitutes_auction_macros() {
+        let response = BidResponse::load_file("static/openrtb/teapot-response.json").unwrap();
+        let request = BidRequest::load_file("static/openrtb/teapot-request.json").unwrap();
+        let result = response.auction(Some(&request)).unwrap();
+        assert_eq!(result.winner.id, "bid-teapot");
+        assert_eq!(
+            result.expand("${AUCTION_ID} ${AUCTION_BID_ID} ${AUCTION_IMP_ID} ${AUCTION_SEAT_ID} ${AUCTION_AD_ID}"),
+            "teapot-auction-1 teapot-response-1 1 teapot-dsp teapot-300x250"
+        );
+        assert_eq!(result.expand("${AUCTION_PRICE} ${AUCTION_CURRENCY}${AUCTION_MBR}"), "1.76 USD");
+
+        let notices = result.notices();
+        let kinds: Vec<&str> = notices.iter().map(|(kind, _)| *kind).collect();
+        assert_eq!(kinds, vec!["win", "billing", "loss"]);
+        assert_eq!(notices[0].1, "https://dsp.example.com/win?auction=teapot-auction-1&imp=1&price=1.76&cur=USD");
+        assert_eq!(
+            notices[2].1,
+            "https://kettle.example.com/loss?auction=teapot-auction-1&seat=kettle-dsp&reason=102&price=1.76"
+        );
+        let markup = BidMarkup::Image {
+            src: "../teapot/img-teapot.png".to_string(),
+            href: Some("https://www.example.com/teapot?auction=teapot-auction-1&imp=1".to_string()),
+        };
+        assert_eq!(result.markup(), Some(markup));
+    }
+
+    #[test]
+    fn finds_html_attributes() {
+        assert_eq!(html_attribute("<IMG SRC=\"a.png\">", "img", "src").as_deref(), Some("a.png"));
+        assert_eq!(html_attribute("<img\n\tsrc='a.png'>", "img", "src").as_deref(), Some("a.png"));
+        assert_eq!(html_attribute("<img width=1 src=a.png/>", "img", "src").as_deref(), Some("a.png"));
+        assert_eq!(html_attribute("<imgs src=a.png><img src=b.png>", "img", "src").as_deref(), Some("b.png"));
+        assert_eq!(html_attribute("<img data-src=\"a.png\" src=\"b.png\">", "img", "src").as_deref(), Some("b.png"));
+        assert_eq!(html_attribute("<img data-src=\"a.png\">", "img", "src"), None);
+        assert_eq!(html_attribute("<img src=\"a.png\"", "img", "src"), None);
+    }
+
+    #[test]
+    fn keeps_offsets_for_text_that_changes_length_in_lowercase() {
+        // 'İ' is two bytes, but its full lowercase form is three
+        let html = "<p>İİİ</p><img alt=\"İ\" src=\"teapot.png\">";
+        assert_eq!(html_attribute(html, "img", "src").as_deref(), Some("teapot.png"));
+        assert_eq!(html_attribute(html, "img", "alt").as_deref(), Some("İ"));
+    }
+
+    #[test]
+    fn parses_markup() {
+        assert!(matches!(BidMarkup::parse("  <VAST version=\"3.0\"/>"), BidMarkup::Vast(_)));
+        assert!(matches!(BidMarkup::parse("<?xml version=\"1.0\"?><VAST/>"), BidMarkup::Vast(_)));
+        assert!(matches!(BidMarkup::parse("<div><script src=\"ad.js\"></script></div>"), BidMarkup::Html(_)));
+        let markup = BidMarkup::parse("<img src=\"a.png\">");
+        assert_eq!(markup, BidMarkup::Image { src: "a.png".to_string(), href: None });
+    }
+}
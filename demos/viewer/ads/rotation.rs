@@ -0,0 +1,366 @@
+/// Creative rotation and frequency capping for a single user
+///
+/// The simulator decides which ad one user sees at each of N impressions. At each impression, the line items are
+/// tried in the campaign's order and the first one that can serve picks a creative with its rotation rule:
+///
+/// * even: the creatives take turns in order, so each one gets the same share
+/// * weighted: a creative is picked at random in proportion to its weight. The random numbers come from a seed,
+///   so a simulation can be repeated.
+/// * sequential: the creatives are a story that the user sees in order, one step per impression from the line
+///   item. After the last step, the story starts again.
+///
+/// A line item is skipped when the user reached its frequency cap or it does not buy the requested size.
+/// Creatives that reached their own cap are left out of the rotation. In a sequence, a capped step is skipped.
+///
+use super::*;
+
+use serde::Deserialize;
+
+use std::collections::HashMap;
+
+/// The number of impressions that are simulated by default
+pub const DEFAULT_IMPRESSIONS: usize = 10;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RotationRule {
+    Even,
+    Weighted,
+    Sequential,
+}
+
+impl Default for RotationRule {
+    fn default() -> Self {
+        RotationRule::Weighted
+    }
+}
+
+/// One impression in a simulation. The creative is None when no line item could serve.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimulatedImpression {
+    /// Starts at 1
+    pub number: usize,
+    pub line_item: Option<String>,
+    pub creative_id: Option<String>,
+    pub size: (u32, u32),
+    /// Why this creative was shown, or why there was no ad
+    pub reason: String,
+}
+
+/// A small xorshift generator, so that weighted rotation is repeatable without a dependency
+struct SeededRandom(u64);
+
+impl SeededRandom {
+    fn new(seed: u64) -> Self {
+        // Spread the bits of small seeds, and avoid zero, which would stay zero forever
+        SeededRandom(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    /// A number from 0 up to, but not including, max
+    fn next_below(&mut self, max: u32) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % max as u64) as u32
+    }
+}
+
+pub struct RotationSimulator<'a> {
+    campaign: &'a Campaign,
+    /// Only line items that buy this size can serve. Otherwise each line item serves at its first size.
+    size: Option<(u32, u32)>,
+    /// Only line items that are live on this date can serve
+    date: Option<FlightDate>,
+    seed: u64,
+}
+
+impl<'a> RotationSimulator<'a> {
+    pub fn new(campaign: &'a Campaign) -> Self {
+        RotationSimulator { campaign, size: None, date: None, seed: 1 }
+    }
+
+    pub fn with_size(mut self, size: (u32, u32)) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    pub fn with_date(mut self, date: FlightDate) -> Self {
+        self.date = Some(date);
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// The ads that one user sees across the number of impressions
+    pub fn run(&self, impressions: usize) -> Vec<SimulatedImpression> {
+        let mut random = SeededRandom::new(self.seed);
+        // Impressions per line item and per (line item, creative) for this user
+        let mut line_item_counts: HashMap<&str, u32> = HashMap::new();
+        let mut creative_counts: HashMap<(&str, &str), u32> = HashMap::new();
+        // The next turn for even and sequential rotation
+        let mut turns: HashMap<&str, usize> = HashMap::new();
+
+        let mut results = Vec::new();
+        for number in 1..=impressions {
+            let mut skipped = Vec::new();
+            let mut served = None;
+            for line_item in self.campaign.line_items.iter() {
+                let id = line_item.id.as_str();
+                if let Some(date) = self.date.filter(|date| !line_item.is_live(*date)) {
+                    skipped.push(format!("{} is not live on {}", id, date));
+                    continue;
+                }
+                let size = match self.size {
+                    Some(size) if !line_item.sizes.contains(&size) => {
+                        skipped.push(format!("{} does not buy {}x{}", id, size.0, size.1));
+                        continue;
+                    }
+                    Some(size) => size,
+                    None => line_item.sizes[0],
+                };
+                let count = line_item_counts.get(id).cloned().unwrap_or(0);
+                if line_item.frequency_cap.map_or(false, |cap| count >= cap) {
+                    skipped.push(format!("{} reached its frequency cap", id));
+                    continue;
+                }
+                // The creatives that the user has not seen too often
+                let under_cap = |a: &&CreativeAssignment| {
+                    let seen = creative_counts.get(&(id, a.id.as_str())).cloned().unwrap_or(0);
+                    a.frequency_cap.map_or(true, |cap| seen < cap)
+                };
+                let available: Vec<&CreativeAssignment> = line_item.creatives.iter().filter(under_cap).collect();
+                if available.is_empty() {
+                    skipped.push(format!("{} has no creatives under their frequency caps", id));
+                    continue;
+                }
+                let turn = turns.entry(id).or_insert(0);
+                let (assignment, reason) = match line_item.rotation {
+                    RotationRule::Even => {
+                        let assignment = available[*turn % available.len()];
+                        *turn += 1;
+                        (assignment, "even rotation".to_string())
+                    }
+                    RotationRule::Weighted => {
+                        let total: u32 = available.iter().map(|a| a.weight).sum();
+                        let mut pick = random.next_below(total);
+                        let assignment = available
+                            .iter()
+                            .find(|a| {
+                                if pick < a.weight {
+                                    return true;
+                                }
+                                pick -= a.weight;
+                                false
+                            })
+                            .cloned()
+                            .unwrap_or(available[0]);
+                        (assignment, format!("weight {} of {}", assignment.weight, total))
+                    }
+                    RotationRule::Sequential => {
+                        // Steps are counted over all creatives, so that capped steps are skipped in the story
+                        let steps = line_item.creatives.len();
+                        let mut step = *turn % steps;
+                        while !available.iter().any(|a| a.id == line_item.creatives[step].id) {
+                            step = (step + 1) % steps;
+                        }
+                        *turn = step + 1;
+                        let assignment = &line_item.creatives[step];
+                        (assignment, format!("step {} of {}", step + 1, steps))
+                    }
+                };
+                *line_item_counts.entry(id).or_insert(0) += 1;
+                *creative_counts.entry((id, assignment.id.as_str())).or_insert(0) += 1;
+                served = Some(SimulatedImpression {
+                    number,
+                    line_item: Some(line_item.id.clone()),
+                    creative_id: Some(assignment.id.clone()),
+                    size,
+                    reason,
+                });
+                break;
+            }
+            let impression = served.unwrap_or_else(|| {
+                let first_size = self.campaign.line_items.first().map(|line_item| line_item.sizes[0]);
+                let size = self.size.or(first_size).unwrap_or_default();
+                let reason = if skipped.is_empty() { "no line items".to_string() } else { skipped.join(", ") };
+                SimulatedImpression { number, line_item: None, creative_id: None, size, reason }
+            });
+            results.push(impression);
+        }
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CREATIVES: &str = r#"
+        id = "test"
+        name = "Test"
+        advertiser = "Teapot Co."
+
+        [[creatives]]
+        id = "a"
+        kind = "display"
+        path = "."
+
+        [[creatives]]
+        id = "b"
+        kind = "display"
+        path = "."
+
+        [[creatives]]
+        id = "c"
+        kind = "display"
+        path = "."
+    "#;
+
+    fn campaign(line_items: &str) -> Campaign {
+        Campaign::from_toml(&format!("{}\n{}", CREATIVES, line_items), ".").unwrap()
+    }
+
+    fn creative_ids(impressions: &[SimulatedImpression]) -> Vec<&str> {
+        impressions.iter().map(|impression| impression.creative_id.as_deref().unwrap_or("-")).collect()
+    }
+
+    #[test]
+    fn line_items_stop_serving_at_their_frequency_cap() {
+        let campaign = campaign(
+            r#"
+            [[line_items]]
+            id = "capped"
+            start = "2026-03-01"
+            end = "2026-03-31"
+            sizes = [[300, 250]]
+            creatives = [{ id = "a" }]
+            frequency_cap = 2
+
+            [[line_items]]
+            id = "fallback"
+            start = "2026-03-01"
+            end = "2026-03-31"
+            sizes = [[300, 250]]
+            creatives = [{ id = "b" }]
+            "#,
+        );
+        let impressions = RotationSimulator::new(&campaign).run(4);
+        assert_eq!(creative_ids(&impressions), vec!["a", "a", "b", "b"]);
+        assert_eq!(impressions[0].number, 1);
+        assert_eq!(impressions[2].line_item.as_deref(), Some("fallback"));
+    }
+
+    #[test]
+    fn creatives_leave_the_rotation_at_their_frequency_cap() {
+        let campaign = campaign(
+            r#"
+            [[line_items]]
+            id = "even"
+            start = "2026-03-01"
+            end = "2026-03-31"
+            sizes = [[300, 250]]
+            creatives = [{ id = "a", frequency_cap = 1 }, { id = "b" }]
+            rotation = "even"
+            "#,
+        );
+        let impressions = RotationSimulator::new(&campaign).run(4);
+        assert_eq!(creative_ids(&impressions), vec!["a", "b", "b", "b"]);
+        assert!(impressions.iter().all(|impression| impression.reason == "even rotation"));
+    }
+
+    #[test]
+    fn sequences_skip_capped_steps() {
+        let campaign = campaign(
+            r#"
+            [[line_items]]
+            id = "story"
+            start = "2026-03-01"
+            end = "2026-03-31"
+            sizes = [[300, 250]]
+            creatives = [{ id = "a" }, { id = "b", frequency_cap = 1 }, { id = "c" }]
+            rotation = "sequential"
+            "#,
+        );
+        let impressions = RotationSimulator::new(&campaign).run(6);
+        assert_eq!(creative_ids(&impressions), vec!["a", "b", "c", "a", "c", "a"]);
+        assert_eq!(impressions[2].reason, "step 3 of 3");
+    }
+
+    #[test]
+    fn weighted_rotation_follows_the_weights_and_repeats_with_the_seed() {
+        let campaign = campaign(
+            r#"
+            [[line_items]]
+            id = "weighted"
+            start = "2026-03-01"
+            end = "2026-03-31"
+            sizes = [[300, 250]]
+            creatives = [{ id = "a", weight = 3 }, { id = "b", weight = 1 }]
+            "#,
+        );
+        let simulator = RotationSimulator::new(&campaign).with_seed(42);
+        let impressions = simulator.run(4000);
+        let share = impressions.iter().filter(|i| i.creative_id.as_deref() == Some("a")).count() as f64 / 4000.0;
+        assert!((share - 0.75).abs() < 0.03, "share of a: {}", share);
+        assert_eq!(impressions, simulator.run(4000));
+        assert!(impressions[0].reason.ends_with(" of 4"));
+    }
+
+    #[test]
+    fn no_ad_when_every_line_item_is_exhausted() {
+        let campaign = campaign(
+            r#"
+            [[line_items]]
+            id = "capped"
+            start = "2026-03-01"
+            end = "2026-03-31"
+            sizes = [[300, 250]]
+            creatives = [{ id = "a" }]
+            frequency_cap = 1
+
+            [[line_items]]
+            id = "creative-capped"
+            start = "2026-03-01"
+            end = "2026-03-31"
+            sizes = [[300, 250]]
+            creatives = [{ id = "b", frequency_cap = 1 }]
+            "#,
+        );
+        let impressions = RotationSimulator::new(&campaign).run(3);
+        assert_eq!(creative_ids(&impressions), vec!["a", "b", "-"]);
+        assert_eq!(impressions[2].line_item, None);
+        assert_eq!(impressions[2].size, (300, 250));
+        assert_eq!(
+            impressions[2].reason,
+            "capped reached its frequency cap, creative-capped has no creatives under their frequency caps"
+        );
+    }
+
+    #[test]
+    fn line_items_serve_only_their_sizes_and_flights() {
+        let campaign = campaign(
+            r#"
+            [[line_items]]
+            id = "march"
+            start = "2026-03-01"
+            end = "2026-03-31"
+            sizes = [[728, 90]]
+            creatives = [{ id = "a" }]
+            "#,
+        );
+        let impressions = RotationSimulator::new(&campaign).with_size((300, 250)).run(1);
+        assert_eq!(impressions[0].creative_id, None);
+        assert_eq!(impressions[0].reason, "march does not buy 300x250");
+
+        let date = FlightDate::parse("2026-04-01").unwrap();
+        let impressions = RotationSimulator::new(&campaign).with_date(date).run(1);
+        assert_eq!(impressions[0].reason, "march is not live on 2026-04-01");
+
+        let date = FlightDate::parse("2026-03-31").unwrap();
+        let impressions = RotationSimulator::new(&campaign).with_date(date).with_size((728, 90)).run(1);
+        assert_eq!(creative_ids(&impressions), vec!["a"]);
+    }
+}
@@ -0,0 +1,251 @@
+/// String tables for localized ad copy
+///
+/// A creative's copy is kept in one TOML file per locale, named by the locale code, e.g. "fr.toml". Each file is
+/// a flat table of keys and text, where "\n" starts a new line:
+///
+/// ```toml
+/// intro = "Découvrez"
+/// headline = "Une pub animée\ntrès simple"
+/// headline_accent = "animée"
+/// ```
+///
+/// The DEFAULT_LOCALE table is the reference. Keys that another locale leaves out use the default text, and are
+/// reported when the tables are loaded.
+///
+/// An optional [script] section at the end of a file describes how the locale's text is written:
+///
+/// ```toml
+/// [script]
+/// direction = "rtl"
+/// font_family = "DejaVuSans"
+/// font_folder = "fonts/arabic"
+/// ```
+///
+/// The direction is "ltr" or "rtl", and is "rtl" by default for RTL_LANGUAGES. The font family is used for text
+/// that the brand fonts have no glyphs for, and is loaded from the font folder, relative to the asset root.
+///
+use quicksilver::{Error, Result};
+
+use serde::Deserialize;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// The locale that other locales fall back to
+pub const DEFAULT_LOCALE: &str = "en";
+/// The string tables of the Teapot sample ad, relative to the asset root
+pub const DEFAULT_STRINGS_DIR: &str = "teapot/strings";
+
+/// Languages that are written right to left, by the language part of the locale code
+pub const RTL_LANGUAGES: [&str; 5] = ["ar", "fa", "he", "ur", "yi"];
+
+/// How a locale's text is written
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct ScriptSettings {
+    pub direction: Option<String>,
+    pub font_family: Option<String>,
+    pub font_folder: Option<String>,
+}
+
+/// The layout of a string table file, where every key outside of [script] is a string
+#[derive(Deserialize)]
+struct StringTableFile {
+    #[serde(default)]
+    script: ScriptSettings,
+    #[serde(flatten)]
+    strings: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StringTable {
+    pub locale: String,
+    pub strings: BTreeMap<String, String>,
+    pub script: ScriptSettings,
+}
+
+impl StringTable {
+    pub fn from_toml(locale: &str, text: &str) -> Result<StringTable> {
+        let file: StringTableFile = toml::from_str(text)
+            .map_err(|e| Error::ContextError(format!("Invalid string table {}: {}", locale, e)))?;
+        match file.script.direction.as_deref() {
+            None | Some("ltr") | Some("rtl") => (),
+            Some(direction) => {
+                return Err(Error::ContextError(format!("String table {} has direction {:?}", locale, direction)))
+            }
+        }
+        Ok(StringTable { locale: locale.to_string(), strings: file.strings, script: file.script })
+    }
+
+    /// Whether the locale's text is written right to left
+    pub fn is_rtl(&self) -> bool {
+        match self.script.direction.as_deref() {
+            Some(direction) => direction == "rtl",
+            None => {
+                let language = self.locale.split(&['-', '_'][..]).next().unwrap_or_default();
+                RTL_LANGUAGES.contains(&language.to_lowercase().as_str())
+            }
+        }
+    }
+
+    /// Load a string table. The locale is the file name without the extension.
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<StringTable> {
+        let text = fs::read_to_string(path.as_ref())?;
+        let locale = path.as_ref().file_stem().and_then(|stem| stem.to_str()).unwrap_or_default();
+        StringTable::from_toml(locale, &text)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.strings.get(key).map(|text| text.as_str())
+    }
+}
+
+/// The string tables of one creative, with the DEFAULT_LOCALE first and the others in order of locale
+#[derive(Clone, Debug, Default)]
+pub struct StringTables {
+    tables: Vec<StringTable>,
+}
+
+impl StringTables {
+    /// Load every .toml file in the folder. The DEFAULT_LOCALE is required.
+    pub fn load_folder<P: AsRef<Path>>(dir: P) -> Result<StringTables> {
+        let mut tables = Vec::new();
+        for entry in fs::read_dir(dir.as_ref())? {
+            let path = entry?.path();
+            if path.extension().map_or(false, |ext| ext.eq_ignore_ascii_case("toml")) {
+                tables.push(StringTable::load_file(&path)?);
+            }
+        }
+        StringTables::new(tables)
+    }
+
+    pub fn new(mut tables: Vec<StringTable>) -> Result<StringTables> {
+        if !tables.iter().any(|table| table.locale == DEFAULT_LOCALE) {
+            return Err(Error::ContextError(format!("No string table for the default locale {}", DEFAULT_LOCALE)));
+        }
+        tables.sort_by(|a, b| (a.locale != DEFAULT_LOCALE, &a.locale).cmp(&(b.locale != DEFAULT_LOCALE, &b.locale)));
+        let tables = StringTables { tables };
+        for table in tables.tables.iter() {
+            let missing = tables.missing_keys(&table.locale);
+            if !missing.is_empty() {
+                log::warn!(
+                    "String table {} is missing {}, which use the default text",
+                    table.locale,
+                    missing.join(", ")
+                );
+            }
+        }
+        Ok(tables)
+    }
+
+    pub fn locales(&self) -> Vec<&str> {
+        self.tables.iter().map(|table| table.locale.as_str()).collect()
+    }
+
+    pub fn table(&self, locale: &str) -> Option<&StringTable> {
+        self.tables.iter().find(|table| table.locale == locale)
+    }
+
+    /// The table for the locale with the default text filled in for missing keys. Unknown locales get the default
+    /// table.
+    pub fn resolve(&self, locale: &str) -> StringTable {
+        let table = self.table(locale).unwrap_or(&self.tables[0]);
+        let mut strings = self.tables[0].strings.clone();
+        strings.extend(table.strings.iter().map(|(key, text)| (key.clone(), text.clone())));
+        StringTable { locale: table.locale.clone(), strings, script: table.script.clone() }
+    }
+
+    /// The keys of the default table that the locale leaves out
+    pub fn missing_keys(&self, locale: &str) -> Vec<String> {
+        let table = match self.table(locale) {
+            Some(table) => table,
+            None => return Vec::new(),
+        };
+        self.tables[0].strings.keys().filter(|key| !table.strings.contains_key(*key)).cloned().collect()
+    }
+
+    /// The locale after this one, wrapping around to the first
+    pub fn next_locale(&self, locale: &str) -> &str {
+        let index = self.tables.iter().position(|table| table.locale == locale).map_or(0, |index| index + 1);
+        &self.tables[index % self.tables.len()].locale
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(locale: &str, text: &str) -> StringTable {
+        StringTable::from_toml(locale, text).unwrap()
+    }
+
+    const AR: &str = r#"
+        intro = "اكتشف"
+        headline = "إعلان"
+        cta_text = "تسوق الآن"
+
+        [script]
+        font_family = "DejaVuSans"
+    "#;
+
+    fn tables() -> StringTables {
+        StringTables::new(vec![
+            table("fr", "intro = \"Découvrez\""),
+            table("en", "intro = \"Discover\"\nheadline = \"A Very Basic\\nAnimated Ad\"\ncta_text = \"Shop Now\""),
+            table("ar", AR),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn the_default_locale_comes_first() {
+        assert_eq!(tables().locales(), vec!["en", "ar", "fr"]);
+        assert!(StringTables::new(vec![table("fr", "")]).is_err());
+    }
+
+    #[test]
+    fn resolves_missing_keys_to_the_default_text() {
+        let tables = tables();
+        let fr = tables.resolve("fr");
+        assert_eq!(fr.locale, "fr");
+        assert_eq!(fr.get("intro"), Some("Découvrez"));
+        assert_eq!(fr.get("headline"), Some("A Very Basic\nAnimated Ad"));
+        assert_eq!(fr.get("cta_text"), Some("Shop Now"));
+
+        let ar = tables.resolve("ar");
+        assert_eq!(ar.get("cta_text"), Some("تسوق الآن"));
+        assert_eq!(ar.script.font_family.as_deref(), Some("DejaVuSans"));
+
+        let unknown = tables.resolve("de");
+        assert_eq!(unknown.locale, "en");
+        assert_eq!(unknown.get("intro"), Some("Discover"));
+    }
+
+    #[test]
+    fn lists_the_keys_that_a_locale_leaves_out() {
+        let tables = tables();
+        assert_eq!(tables.missing_keys("fr"), vec!["cta_text", "headline"]);
+        assert!(tables.missing_keys("ar").is_empty());
+        assert!(tables.missing_keys("en").is_empty());
+        assert!(tables.missing_keys("de").is_empty());
+    }
+
+    #[test]
+    fn cycles_through_the_locales() {
+        let tables = tables();
+        assert_eq!(tables.next_locale("en"), "ar");
+        assert_eq!(tables.next_locale("ar"), "fr");
+        assert_eq!(tables.next_locale("fr"), "en");
+        assert_eq!(tables.next_locale("de"), "en");
+    }
+
+    #[test]
+    fn detects_the_direction() {
+        assert!(table("ar", "").is_rtl());
+        assert!(table("he-IL", "").is_rtl());
+        assert!(!table("fr", "").is_rtl());
+        assert!(table("en", "[script]\ndirection = \"rtl\"").is_rtl());
+        assert!(!table("ar", "[script]\ndirection = \"ltr\"").is_rtl());
+        assert!(StringTable::from_toml("en", "[script]\ndirection = \"up\"").is_err());
+    }
+}
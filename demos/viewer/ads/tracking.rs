@@ -0,0 +1,428 @@
+/// Impression and interaction tracking
+///
+/// The Tracker turns AdEvents into TrackingEvents and sends them to one or more sinks. Sinks are configured
+/// with a comma-separated list, usually from the AD_TRACKING env var:
+///
+/// * console - log each event (the default)
+/// * log:<path> - append readable lines to a file
+/// * jsonl:<path> - append one JSON object per line to a file
+/// * http:<host:port> - send each event as a GET beacon to a local HTTP server (not in the wasm build)
+///
+/// For example: AD_TRACKING=console,jsonl:tracking.jsonl,http:127.0.0.1:8099
+///
+use super::*;
+
+use quicksilver::{Error, Result};
+
+use serde::Serialize;
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::net::{TcpStream, ToSocketAddrs};
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::mpsc::{channel, Sender};
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Timeout for connecting to, writing to and reading from the HTTP beacon server
+#[cfg(not(target_arch = "wasm32"))]
+const BEACON_TIMEOUT_MS: u64 = 250;
+
+/// A single tracking record
+#[derive(Clone, Debug, Serialize)]
+pub struct TrackingEvent {
+    pub event: String,
+    pub creative: String,
+    pub ad_size: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// Seconds since the ad was rendered
+    pub elapsed: f64,
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+impl TrackingEvent {
+    /// Query string parameters for a beacon URL
+    pub fn query_string(&self) -> String {
+        let mut params = vec![
+            ("event", self.event.clone()),
+            ("creative", self.creative.clone()),
+            ("size", self.ad_size.clone()),
+            ("elapsed", format!("{:.3}", self.elapsed)),
+            ("ts", self.timestamp.to_string()),
+        ];
+        if let Some(target) = &self.target {
+            params.push(("target", target.clone()));
+        }
+        params.iter().map(|(key, value)| format!("{}={}", key, url_encode(value))).collect::<Vec<_>>().join("&")
+    }
+}
+
+/// A destination for tracking events
+pub trait TrackingSink {
+    fn name(&self) -> String;
+    fn send(&mut self, event: &TrackingEvent) -> Result<()>;
+}
+
+/// Logs each event with the log crate
+pub struct ConsoleSink {}
+
+impl TrackingSink for ConsoleSink {
+    fn name(&self) -> String {
+        "console".to_string()
+    }
+
+    fn send(&mut self, event: &TrackingEvent) -> Result<()> {
+        log::info!("Tracking {} {}", event.event, event.query_string());
+        Ok(())
+    }
+}
+
+fn open_append(path: &str) -> Result<File> {
+    Ok(OpenOptions::new().create(true).append(true).open(path)?)
+}
+
+/// Appends readable lines to a file
+pub struct LogFileSink {
+    path: String,
+    file: File,
+}
+
+impl LogFileSink {
+    pub fn new(path: &str) -> Result<Self> {
+        Ok(LogFileSink { path: path.to_string(), file: open_append(path)? })
+    }
+}
+
+impl TrackingSink for LogFileSink {
+    fn name(&self) -> String {
+        format!("log:{}", self.path)
+    }
+
+    fn send(&mut self, event: &TrackingEvent) -> Result<()> {
+        let target = event.target.as_ref().map(|t| format!(" target={}", t)).unwrap_or_default();
+        writeln!(
+            self.file,
+            "{} {:>8.3}s {:<14} creative={} size={}{}",
+            event.timestamp, event.elapsed, event.event, event.creative, event.ad_size, target
+        )?;
+        Ok(())
+    }
+}
+
+/// Appends one JSON object per line to a file
+pub struct JsonLinesSink {
+    path: String,
+    file: File,
+}
+
+impl JsonLinesSink {
+    pub fn new(path: &str) -> Result<Self> {
+        Ok(JsonLinesSink { path: path.to_string(), file: open_append(path)? })
+    }
+}
+
+impl TrackingSink for JsonLinesSink {
+    fn name(&self) -> String {
+        format!("jsonl:{}", self.path)
+    }
+
+    fn send(&mut self, event: &TrackingEvent) -> Result<()> {
+        let line = serde_json::to_string(event).map_err(|e| Error::ContextError(e.to_string()))?;
+        writeln!(self.file, "{}", line)?;
+        Ok(())
+    }
+}
+
+/// Sends each event as a GET request to /beacon on a local HTTP server, which stands in for the ad server's
+/// tracking pixel. Any server that accepts GET requests works, e.g. `python3 -m http.server 8099`.
+///
+/// Beacons are sent in order on a worker thread, so that a slow or unreachable server does not hold up the
+/// frame. Failures are logged by the worker.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct HttpBeaconSink {
+    host: String,
+    queue: Sender<TrackingEvent>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl HttpBeaconSink {
+    pub fn new(host: &str) -> Self {
+        let (queue, events) = channel::<TrackingEvent>();
+        let worker_host = host.to_string();
+        thread::spawn(move || {
+            for event in events {
+                match send_beacon(&worker_host, &event) {
+                    Ok(status) => log::trace!("Beacon {} {}", event.event, status),
+                    Err(e) => log::warn!("Beacon {} to {} failed: {:?}", event.event, worker_host, e),
+                }
+            }
+        });
+        HttpBeaconSink { host: host.to_string(), queue }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl TrackingSink for HttpBeaconSink {
+    fn name(&self) -> String {
+        format!("http:{}", self.host)
+    }
+
+    fn send(&mut self, event: &TrackingEvent) -> Result<()> {
+        self.queue
+            .send(event.clone())
+            .map_err(|_| Error::ContextError(format!("The beacon worker for {} has stopped", self.host)))
+    }
+}
+
+/// Send one beacon and return the response's status line
+#[cfg(not(target_arch = "wasm32"))]
+fn send_beacon(host: &str, event: &TrackingEvent) -> Result<String> {
+    let timeout = Duration::from_millis(BEACON_TIMEOUT_MS);
+    let addr = host.to_socket_addrs()?.next().ok_or_else(|| Error::ContextError(format!("Cannot resolve {}", host)))?;
+    let mut stream = TcpStream::connect_timeout(&addr, timeout)?;
+    stream.set_write_timeout(Some(timeout))?;
+    stream.set_read_timeout(Some(timeout))?;
+    write_beacon(&mut stream, host, event)
+}
+
+/// Write the beacon request for an event to a stream and read the response's status line
+fn write_beacon<S: Read + Write>(stream: &mut S, host: &str, event: &TrackingEvent) -> Result<String> {
+    let request =
+        format!("GET /beacon?{} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", event.query_string(), host);
+    stream.write_all(request.as_bytes())?;
+    let mut status = [0u8; 12];
+    stream.read_exact(&mut status)?;
+    let status = String::from_utf8_lossy(&status).trim().to_string();
+    if !status.starts_with("HTTP/1.") {
+        return Err(Error::ContextError(format!("Unexpected beacon response: {}", status)));
+    }
+    Ok(status)
+}
+
+/// Create a sink from a config entry like "jsonl:tracking.jsonl"
+pub fn parse_sink(entry: &str) -> Result<Box<dyn TrackingSink>> {
+    let entry = entry.trim();
+    let (kind, arg) = match entry.find(':') {
+        Some(idx) => (&entry[..idx], &entry[idx + 1..]),
+        None => (entry, ""),
+    };
+    match (kind, arg) {
+        ("console", _) => Ok(Box::new(ConsoleSink {})),
+        ("log", path) if !path.is_empty() => Ok(Box::new(LogFileSink::new(path)?)),
+        ("jsonl", path) if !path.is_empty() => Ok(Box::new(JsonLinesSink::new(path)?)),
+        #[cfg(not(target_arch = "wasm32"))]
+        ("http", host) if !host.is_empty() => Ok(Box::new(HttpBeaconSink::new(host))),
+        _ => Err(Error::ContextError(format!("Invalid tracking sink: {:?}", entry))),
+    }
+}
+
+/// Receives AdEvents for one creative and forwards them to the sinks
+pub struct Tracker {
+    creative: String,
+    ad_size: String,
+    sinks: Vec<Box<dyn TrackingSink>>,
+    /// When the impression was tracked, in seconds since the epoch
+    started_at: Option<f64>,
+}
+
+impl Tracker {
+    pub fn new(creative: &str, ad_size: &str) -> Self {
+        Tracker { creative: creative.to_string(), ad_size: ad_size.to_string(), sinks: Vec::new(), started_at: None }
+    }
+
+    /// Add the sinks in a comma-separated config. Invalid entries are logged and skipped.
+    pub fn with_sinks(mut self, config: &str) -> Self {
+        for entry in config.split(',').filter(|s| !s.trim().is_empty()) {
+            match parse_sink(entry) {
+                Ok(sink) => self.add_sink(sink),
+                Err(e) => log::error!("{:?}", e),
+            }
+        }
+        self
+    }
+
+    pub fn add_sink(&mut self, sink: Box<dyn TrackingSink>) {
+        log::debug!("Tracking sink: {}", sink.name());
+        self.sinks.push(sink);
+    }
+
+    /// Change the size that is reported, e.g. when the viewer switches to another AdSpec
+    pub fn set_ad_size(&mut self, ad_size: &str) {
+        self.ad_size = ad_size.to_string();
+        self.started_at = None;
+    }
+
+    /// Milliseconds and seconds since the epoch. SystemTime is not available in the browser, so the wasm build
+    /// uses the JS clock.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn now() -> (u64, f64) {
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        (since_epoch.as_millis() as u64, since_epoch.as_secs_f64())
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn now() -> (u64, f64) {
+        let millis = stdweb::web::Date::now();
+        (millis as u64, millis / 1000.0)
+    }
+
+    pub fn track(&mut self, event: &AdEvent) {
+        let (timestamp, now) = Tracker::now();
+        if *event == AdEvent::Impression {
+            self.started_at = Some(now);
+        }
+        let record = TrackingEvent {
+            event: event.name().to_string(),
+            creative: self.creative.clone(),
+            ad_size: self.ad_size.clone(),
+            target: event.target().map(|s| s.to_string()),
+            elapsed: self.started_at.map_or(0.0, |start| now - start),
+            timestamp,
+        };
+        for sink in self.sinks.iter_mut() {
+            if let Err(e) = sink.send(&record) {
+                log::warn!("Tracking sink {} failed for {}: {:?}", sink.name(), record.event, e);
+            }
+        }
+    }
+}
+
+/// Tracks progress through an ad animation and reports the start, quartile and complete events once each
+pub struct PlaybackProgress {
+    duration: f64,
+    elapsed: f64,
+    next: usize,
+}
+
+impl PlaybackProgress {
+    const MILESTONES: [(f64, AdEvent); 5] = [
+        (0.0, AdEvent::Start),
+        (0.25, AdEvent::FirstQuartile),
+        (0.5, AdEvent::Midpoint),
+        (0.75, AdEvent::ThirdQuartile),
+        (1.0, AdEvent::Complete),
+    ];
+
+    pub fn new(duration: f64) -> Self {
+        PlaybackProgress { duration, elapsed: 0.0, next: 0 }
+    }
+
+    pub fn reset(&mut self) {
+        self.elapsed = 0.0;
+        self.next = 0;
+    }
+
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+
+    /// Advance the clock and return any milestones that were passed
+    pub fn advance(&mut self, seconds: f64) -> Vec<AdEvent> {
+        self.elapsed += seconds;
+        let mut events = Vec::new();
+        while self.next < PlaybackProgress::MILESTONES.len() {
+            let (fraction, event) = &PlaybackProgress::MILESTONES[self.next];
+            if self.elapsed < fraction * self.duration {
+                break;
+            }
+            events.push(event.clone());
+            self.next += 1;
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    /// A stream that records what is written and reads back a canned response
+    struct MemoryStream {
+        written: Vec<u8>,
+        response: Cursor<Vec<u8>>,
+    }
+
+    impl MemoryStream {
+        fn new(response: &[u8]) -> Self {
+            MemoryStream { written: Vec::new(), response: Cursor::new(response.to_vec()) }
+        }
+    }
+
+    impl Read for MemoryStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.response.read(buf)
+        }
+    }
+
+    impl Write for MemoryStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.written.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn click_event() -> TrackingEvent {
+        TrackingEvent {
+            event: "click".to_string(),
+            creative: "Teapot".to_string(),
+            ad_size: "300x250".to_string(),
+            target: Some("cta button".to_string()),
+            elapsed: 1.5,
+            timestamp: 1000,
+        }
+    }
+
+    #[test]
+    fn query_string_encodes_values() {
+        let query = click_event().query_string();
+        assert_eq!(query, "event=click&creative=Teapot&size=300x250&elapsed=1.500&ts=1000&target=cta%20button");
+    }
+
+    #[test]
+    fn beacons_are_get_requests_with_the_query_string() {
+        let mut stream = MemoryStream::new(b"HTTP/1.0 200 OK\r\n\r\n");
+        let status = write_beacon(&mut stream, "127.0.0.1:8099", &click_event()).unwrap();
+        assert_eq!(status, "HTTP/1.0 200");
+        assert_eq!(
+            String::from_utf8(stream.written).unwrap(),
+            format!(
+                "GET /beacon?{} HTTP/1.1\r\nHost: 127.0.0.1:8099\r\nConnection: close\r\n\r\n",
+                click_event().query_string()
+            )
+        );
+
+        let mut stream = MemoryStream::new(b"SMTP ready\r\n\r\n");
+        assert!(write_beacon(&mut stream, "127.0.0.1:8099", &click_event()).is_err());
+    }
+
+    #[test]
+    fn sinks_are_parsed_from_config_entries() {
+        assert_eq!(parse_sink(" console ").unwrap().name(), "console");
+        assert_eq!(parse_sink("http:127.0.0.1:8099").unwrap().name(), "http:127.0.0.1:8099");
+        assert!(parse_sink("jsonl:").is_err());
+        assert!(parse_sink("smoke").is_err());
+    }
+
+    #[test]
+    fn playback_reports_each_milestone_once() {
+        let mut progress = PlaybackProgress::new(4.0);
+        assert_eq!(progress.advance(0.0), vec![AdEvent::Start]);
+        assert_eq!(progress.advance(2.5), vec![AdEvent::FirstQuartile, AdEvent::Midpoint]);
+        assert!(progress.advance(0.1).is_empty());
+        assert_eq!(progress.advance(10.0), vec![AdEvent::ThirdQuartile, AdEvent::Complete]);
+        assert!(progress.advance(1.0).is_empty());
+        progress.reset();
+        assert_eq!(progress.advance(0.0), vec![AdEvent::Start]);
+    }
+}
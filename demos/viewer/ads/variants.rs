@@ -0,0 +1,238 @@
+/// A/B variants of a creative
+///
+/// A variants file lists options for the same creative so that they can be reviewed side by side. Each variant
+/// can change the copy, replace colors in the brand kit and change the timing of the animation. Anything that a
+/// variant leaves out stays the same as in the creative.
+///
+/// Example:
+///
+/// ```toml
+/// [[variants]]
+/// id = "control"
+/// name = "A: Control"
+///
+/// [[variants]]
+/// id = "bold-copy"
+/// name = "B: Bold copy"
+/// copy = { headline = "Tea Time,\nReinvented", headline_accent = "Reinvented", cta_text = "Buy Now" }
+/// colors = { primary = "#7A1F1F", accent = "#C0392B" }
+/// timing = { intro_hold = 0.6, slide = 0.4 }
+/// ```
+///
+/// Colors are hex values or other tokens of the brand kit. An accent is the part of a headline or tagline that
+/// is drawn in the accent color, and must appear in the text.
+///
+use quicksilver::{Error, Result};
+
+use serde::Deserialize;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+/// The variants of the Teapot sample ad, relative to the asset root
+pub const DEFAULT_VARIANTS: &str = "teapot/variants.toml";
+
+/// Text that replaces the creative's copy. "\n" starts a new line.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct VariantCopy {
+    /// The first line of the intro
+    pub intro: Option<String>,
+    /// The block that follows the intro
+    pub headline: Option<String>,
+    pub headline_accent: Option<String>,
+    /// The text on the end card
+    pub tagline: Option<String>,
+    pub tagline_accent: Option<String>,
+    /// The CTA is drawn as a text button with the brand kit's cta style instead of the CTA image
+    pub cta_text: Option<String>,
+}
+
+impl VariantCopy {
+    /// The text for a key of a string table, which are the same as the field names
+    pub fn get(&self, key: &str) -> Option<&str> {
+        let text = match key {
+            "intro" => &self.intro,
+            "headline" => &self.headline,
+            "headline_accent" => &self.headline_accent,
+            "tagline" => &self.tagline,
+            "tagline_accent" => &self.tagline_accent,
+            "cta_text" => &self.cta_text,
+            _ => return None,
+        };
+        text.as_ref().map(|text| text.as_str())
+    }
+}
+
+/// Seconds for the parts of the animation
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub struct VariantTiming {
+    /// How long each intro text stays in the middle of the ad
+    #[serde(default = "VariantTiming::default_intro_hold")]
+    pub intro_hold: f64,
+    /// How long each part of the end card takes to slide in
+    #[serde(default = "VariantTiming::default_slide")]
+    pub slide: f64,
+    /// The time between the start of one end card slide and the next
+    #[serde(default = "VariantTiming::default_stagger")]
+    pub stagger: f64,
+}
+
+impl VariantTiming {
+    fn default_intro_hold() -> f64 {
+        1.0
+    }
+
+    fn default_slide() -> f64 {
+        0.6
+    }
+
+    fn default_stagger() -> f64 {
+        0.6
+    }
+}
+
+impl Default for VariantTiming {
+    fn default() -> Self {
+        VariantTiming {
+            intro_hold: VariantTiming::default_intro_hold(),
+            slide: VariantTiming::default_slide(),
+            stagger: VariantTiming::default_stagger(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct CreativeVariant {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub copy: VariantCopy,
+    /// Brand kit color tokens and the values that replace them
+    #[serde(default)]
+    pub colors: BTreeMap<String, String>,
+    #[serde(default)]
+    pub timing: VariantTiming,
+}
+
+impl CreativeVariant {
+    /// The name for captions, or the id when there is no name
+    pub fn title(&self) -> &str {
+        if self.name.is_empty() {
+            &self.id
+        } else {
+            &self.name
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct VariantSet {
+    pub variants: Vec<CreativeVariant>,
+}
+
+impl VariantSet {
+    pub fn from_toml(text: &str) -> Result<VariantSet> {
+        let set: VariantSet =
+            toml::from_str(text).map_err(|e| Error::ContextError(format!("Invalid variants: {}", e)))?;
+        set.validate()?;
+        Ok(set)
+    }
+
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<VariantSet> {
+        let text = fs::read_to_string(path.as_ref())?;
+        VariantSet::from_toml(&text)
+    }
+
+    /// Check the values that serde cannot check
+    fn validate(&self) -> Result<()> {
+        if self.variants.is_empty() {
+            return Err(Error::ContextError("No variants".to_string()));
+        }
+        let mut ids = BTreeSet::new();
+        for variant in self.variants.iter() {
+            let context = |message: String| Error::ContextError(format!("Variant {}: {}", variant.id, message));
+            if !ids.insert(variant.id.as_str()) {
+                return Err(context("duplicate id".to_string()));
+            }
+            let copy = &variant.copy;
+            let accents = [
+                ("headline", &copy.headline, &copy.headline_accent),
+                ("tagline", &copy.tagline, &copy.tagline_accent),
+            ];
+            for (name, text, accent) in accents.iter() {
+                if let (Some(text), Some(accent)) = (text, accent) {
+                    if !text.contains(accent.as_str()) {
+                        return Err(context(format!("the {} accent {:?} is not in the {}", name, accent, name)));
+                    }
+                }
+            }
+            let timing = &variant.timing;
+            if timing.intro_hold < 0.0 || timing.stagger < 0.0 {
+                return Err(context("intro_hold and stagger must be 0 or more".to_string()));
+            }
+            if timing.slide <= 0.0 {
+                return Err(context("slide must be more than 0".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// The variants with these ids, in the order of the ids. An empty list selects every variant.
+    pub fn select(&self, ids: &[&str]) -> Result<Vec<CreativeVariant>> {
+        if ids.is_empty() {
+            return Ok(self.variants.clone());
+        }
+        ids.iter()
+            .map(|id| {
+                let variant = self.variants.iter().find(|variant| variant.id == *id);
+                variant.cloned().ok_or_else(|| Error::ContextError(format!("Unknown variant {}", id)))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variant(fields: &str) -> Result<VariantSet> {
+        VariantSet::from_toml(&format!("[[variants]]\nid = \"test\"\n{}", fields))
+    }
+
+    #[test]
+    fn timing_defaults() {
+        let set = variant("").unwrap();
+        assert_eq!(set.variants[0].timing, VariantTiming::default());
+        assert_eq!(set.variants[0].timing, VariantTiming { intro_hold: 1.0, slide: 0.6, stagger: 0.6 });
+    }
+
+    #[test]
+    fn timing_can_be_zero_except_for_slides() {
+        let set = variant("timing = { intro_hold = 0.0, stagger = 0.0 }").unwrap();
+        assert_eq!(set.variants[0].timing.intro_hold, 0.0);
+        assert_eq!(set.variants[0].timing.slide, 0.6);
+        for timing in ["{ intro_hold = -0.5 }", "{ stagger = -0.1 }", "{ slide = 0.0 }"].iter() {
+            assert!(variant(&format!("timing = {}", timing)).is_err(), "{}", timing);
+        }
+    }
+
+    #[test]
+    fn accents_must_be_in_their_text() {
+        assert!(variant("copy = { headline = \"Tea Time\", headline_accent = \"Time\" }").is_ok());
+        assert!(variant("copy = { tagline = \"Tea Time\", tagline_accent = \"Coffee\" }").is_err());
+    }
+
+    #[test]
+    fn selects_variants_by_id() {
+        let set = VariantSet::from_toml("[[variants]]\nid = \"a\"\n[[variants]]\nid = \"b\"\nname = \"B\"").unwrap();
+        let ids = |variants: Vec<CreativeVariant>| variants.iter().map(|v| v.id.clone()).collect::<Vec<String>>();
+        assert_eq!(ids(set.select(&[]).unwrap()), vec!["a", "b"]);
+        assert_eq!(ids(set.select(&["b", "a"]).unwrap()), vec!["b", "a"]);
+        assert!(set.select(&["c"]).is_err());
+        assert_eq!(set.variants[0].title(), "a");
+        assert_eq!(set.variants[1].title(), "B");
+        assert!(VariantSet::from_toml("[[variants]]\nid = \"a\"\n[[variants]]\nid = \"a\"").is_err());
+    }
+}
@@ -0,0 +1,460 @@
+/// VAST parsing for video ads and their companion banners
+///
+/// VAST (Video Ad Serving Template) is the XML that an ad server returns for a video ad. Versions 2, 3 and 4 share
+/// the same structure for the parts used here:
+///
+/// * VAST > Ad > InLine or Wrapper
+/// * Impression and Error URLs
+/// * Creatives > Creative > Linear with the Duration, TrackingEvents, VideoClicks and MediaFiles
+/// * Creatives > Creative > CompanionAds > Companion with a StaticResource, IFrameResource or HTMLResource, its
+///   TrackingEvents, CompanionClickThrough and CompanionClickTracking (VAST 3 and later)
+///
+/// A Wrapper points to another VAST document with VASTAdTagURI. Wrappers are parsed but not followed.
+/// Ads and companions that cannot be used are skipped and reported in the document's warnings.
+///
+use quicksilver::{Error, Result};
+
+use roxmltree::{Document, Node};
+
+use tweek::events::*;
+
+use std::fs;
+use std::path::Path;
+
+/// The major versions of VAST that can be parsed
+pub const SUPPORTED_VAST_VERSIONS: &[u32] = &[2, 3, 4];
+
+/// The sample VAST response, relative to the asset root
+pub const DEFAULT_VAST_FILE: &str = "vast/teapot-vast3.xml";
+
+/// Events from the companion preview
+#[derive(Clone, Debug, PartialEq)]
+pub enum CompanionEvent {
+    /// A companion was clicked. The value is its index in VastDocument::companions().
+    Click(usize),
+}
+
+impl AnyEvent for CompanionEvent {}
+
+/// A Tracking element: the event name, an optional offset for progress events and the URL to ping
+#[derive(Clone, Debug, PartialEq)]
+pub struct VastTracking {
+    pub event: String,
+    pub offset: Option<String>,
+    pub uri: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VastMediaFile {
+    pub uri: String,
+    pub mime_type: String,
+    pub width: u32,
+    pub height: u32,
+    pub delivery: String,
+}
+
+/// The video part of the ad
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VastLinear {
+    /// Length of the video in seconds
+    pub duration: Option<f64>,
+    pub tracking_events: Vec<VastTracking>,
+    pub click_through: Option<String>,
+    pub click_tracking: Vec<String>,
+    pub media_files: Vec<VastMediaFile>,
+}
+
+/// The creative of a companion
+#[derive(Clone, Debug, PartialEq)]
+pub enum CompanionResource {
+    /// An image or script with its MIME type
+    Static { creative_type: String, uri: String },
+    IFrame(String),
+    Html(String),
+}
+
+impl CompanionResource {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CompanionResource::Static { .. } => "StaticResource",
+            CompanionResource::IFrame(_) => "IFrameResource",
+            CompanionResource::Html(_) => "HTMLResource",
+        }
+    }
+}
+
+/// A display ad that is shown next to the video player
+#[derive(Clone, Debug, PartialEq)]
+pub struct VastCompanion {
+    pub id: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    pub resources: Vec<CompanionResource>,
+    pub alt_text: Option<String>,
+    /// Usually a single creativeView event, which is pinged when the companion is shown
+    pub tracking_events: Vec<VastTracking>,
+    pub click_through: Option<String>,
+    pub click_tracking: Vec<String>,
+}
+
+impl VastCompanion {
+    /// The id if there is one, otherwise the size
+    pub fn label(&self) -> String {
+        match &self.id {
+            Some(id) => format!("{} {}x{}", id, self.width, self.height),
+            None => format!("{}x{}", self.width, self.height),
+        }
+    }
+
+    /// The URLs for an event, e.g. creativeView
+    pub fn tracking_uris(&self, event: &str) -> Vec<&str> {
+        self.tracking_events.iter().filter(|t| t.event == event).map(|t| t.uri.as_str()).collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VastAd {
+    pub id: Option<String>,
+    pub ad_system: Option<String>,
+    pub ad_title: Option<String>,
+    /// The URL of the next VAST document when the ad is a Wrapper
+    pub wrapper_uri: Option<String>,
+    pub impressions: Vec<String>,
+    pub errors: Vec<String>,
+    pub linear: Option<VastLinear>,
+    /// The required attribute of CompanionAds: all, any or none (VAST 3 and later). When several creatives have
+    /// companions, this is the strictest of their values.
+    pub companions_required: Option<String>,
+    pub companions: Vec<VastCompanion>,
+}
+
+impl VastAd {
+    pub fn is_wrapper(&self) -> bool {
+        self.wrapper_uri.is_some()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VastDocument {
+    pub version: String,
+    pub ads: Vec<VastAd>,
+    /// Problems that did not prevent parsing, e.g. a companion without a size
+    pub warnings: Vec<String>,
+}
+
+impl VastDocument {
+    pub fn parse(text: &str) -> Result<VastDocument> {
+        let doc = Document::parse(text).map_err(|e| Error::ContextError(format!("Invalid VAST XML: {}", e)))?;
+        let root = doc.root_element();
+        if root.tag_name().name() != "VAST" {
+            return Err(Error::ContextError(format!("Expected a VAST root element, found {}", root.tag_name().name())));
+        }
+        let version = root.attribute("version").unwrap_or_default().to_string();
+        let major = version.split('.').next().and_then(|v| v.parse::<u32>().ok());
+        if !major.map_or(false, |major| SUPPORTED_VAST_VERSIONS.contains(&major)) {
+            return Err(Error::ContextError(format!("Unsupported VAST version: {:?}", version)));
+        }
+        let mut warnings = Vec::new();
+        let ads = children(root, "Ad").filter_map(|node| VastDocument::parse_ad(node, &mut warnings)).collect();
+        Ok(VastDocument { version, ads, warnings })
+    }
+
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<VastDocument> {
+        let text = fs::read_to_string(path.as_ref())?;
+        VastDocument::parse(&text)
+    }
+
+    /// All companions in the document
+    pub fn companions(&self) -> Vec<&VastCompanion> {
+        self.ads.iter().flat_map(|ad| ad.companions.iter()).collect()
+    }
+
+    fn parse_ad(node: Node, warnings: &mut Vec<String>) -> Option<VastAd> {
+        let id = node.attribute("id").map(|id| id.to_string());
+        // An Ad contains either an InLine or a Wrapper, which have the same children for the parts used here
+        let body = child(node, "InLine").or_else(|| child(node, "Wrapper"));
+        let mut ad = VastAd {
+            id,
+            ad_system: None,
+            ad_title: None,
+            wrapper_uri: None,
+            impressions: Vec::new(),
+            errors: Vec::new(),
+            linear: None,
+            companions_required: None,
+            companions: Vec::new(),
+        };
+        let body = match body {
+            Some(body) => body,
+            None => {
+                let id = ad.id.as_ref().map_or("without an id", |id| id.as_str());
+                warnings.push(format!("Ad {} was skipped because it has no InLine or Wrapper", id));
+                return None;
+            }
+        };
+        ad.ad_system = child_text(body, "AdSystem");
+        ad.ad_title = child_text(body, "AdTitle");
+        ad.wrapper_uri = child_text(body, "VASTAdTagURI");
+        ad.impressions = children(body, "Impression").map(text).filter(|uri| !uri.is_empty()).collect();
+        ad.errors = children(body, "Error").map(text).filter(|uri| !uri.is_empty()).collect();
+
+        for creative in child(body, "Creatives").into_iter().flat_map(|node| children(node, "Creative")) {
+            if let Some(linear) = child(creative, "Linear") {
+                ad.linear = Some(VastDocument::parse_linear(linear));
+            }
+            if let Some(companion_ads) = child(creative, "CompanionAds") {
+                if let Some(required) = companion_ads.attribute("required") {
+                    let is_stricter = match &ad.companions_required {
+                        Some(current) => requirement_rank(required) > requirement_rank(current),
+                        None => true,
+                    };
+                    if is_stricter {
+                        ad.companions_required = Some(required.to_string());
+                    }
+                }
+                for companion in children(companion_ads, "Companion") {
+                    if let Some(companion) = VastDocument::parse_companion(companion, warnings) {
+                        ad.companions.push(companion);
+                    }
+                }
+            }
+        }
+        Some(ad)
+    }
+
+    fn parse_linear(node: Node) -> VastLinear {
+        let mut linear = VastLinear::default();
+        linear.duration = child_text(node, "Duration").and_then(|value| parse_duration(&value));
+        linear.tracking_events = parse_tracking_events(node);
+        if let Some(clicks) = child(node, "VideoClicks") {
+            linear.click_through = child_text(clicks, "ClickThrough");
+            linear.click_tracking = children(clicks, "ClickTracking").map(text).collect();
+        }
+        for media in child(node, "MediaFiles").into_iter().flat_map(|node| children(node, "MediaFile")) {
+            linear.media_files.push(VastMediaFile {
+                uri: text(media),
+                mime_type: media.attribute("type").unwrap_or_default().to_string(),
+                width: media.attribute("width").and_then(|v| v.parse().ok()).unwrap_or(0),
+                height: media.attribute("height").and_then(|v| v.parse().ok()).unwrap_or(0),
+                delivery: media.attribute("delivery").unwrap_or_default().to_string(),
+            });
+        }
+        linear
+    }
+
+    fn parse_companion(node: Node, warnings: &mut Vec<String>) -> Option<VastCompanion> {
+        let id = node.attribute("id").map(|id| id.to_string());
+        let size = |name: &str| node.attribute(name).and_then(|v| v.parse::<u32>().ok()).filter(|v| *v > 0);
+        let (width, height) = match (size("width"), size("height")) {
+            (Some(width), Some(height)) => (width, height),
+            _ => {
+                let id = id.as_ref().map_or("without an id", |id| id.as_str());
+                warnings.push(format!("Companion {} was skipped because it has no width and height", id));
+                return None;
+            }
+        };
+        let mut resources = Vec::new();
+        for resource in node.children().filter(|n| n.is_element()) {
+            match resource.tag_name().name() {
+                "StaticResource" => {
+                    let creative_type = resource.attribute("creativeType").unwrap_or_default().to_string();
+                    resources.push(CompanionResource::Static { creative_type, uri: text(resource) });
+                }
+                "IFrameResource" => resources.push(CompanionResource::IFrame(text(resource))),
+                "HTMLResource" => resources.push(CompanionResource::Html(text(resource))),
+                _ => (),
+            }
+        }
+        Some(VastCompanion {
+            id,
+            width,
+            height,
+            resources,
+            alt_text: child_text(node, "AltText"),
+            tracking_events: parse_tracking_events(node),
+            click_through: child_text(node, "CompanionClickThrough"),
+            click_tracking: children(node, "CompanionClickTracking").map(text).collect(),
+        })
+    }
+}
+
+/// Parse a VAST time in HH:MM:SS or HH:MM:SS.mmm format to seconds
+pub fn parse_duration(value: &str) -> Option<f64> {
+    let parts: Vec<&str> = value.trim().split(':').collect();
+    if parts.len() != 3 {
+        return None;
+    }
+    let hours = parts[0].parse::<f64>().ok()?;
+    let minutes = parts[1].parse::<f64>().ok()?;
+    let seconds = parts[2].parse::<f64>().ok()?;
+    Some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+/// Orders the values of the CompanionAds required attribute from none to all. Unknown values rank lowest.
+fn requirement_rank(required: &str) -> u8 {
+    match required {
+        "all" => 3,
+        "any" => 2,
+        "none" => 1,
+        _ => 0,
+    }
+}
+
+fn parse_tracking_events(node: Node) -> Vec<VastTracking> {
+    child(node, "TrackingEvents")
+        .into_iter()
+        .flat_map(|node| children(node, "Tracking"))
+        .map(|tracking| VastTracking {
+            event: tracking.attribute("event").unwrap_or_default().to_string(),
+            offset: tracking.attribute("offset").map(|offset| offset.to_string()),
+            uri: text(tracking),
+        })
+        .collect()
+}
+
+fn children<'a, 'input: 'a>(node: Node<'a, 'input>, name: &'a str) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children().filter(move |n| n.is_element() && n.tag_name().name() == name)
+}
+
+fn child<'a, 'input: 'a>(node: Node<'a, 'input>, name: &'a str) -> Option<Node<'a, 'input>> {
+    children(node, name).next()
+}
+
+/// The trimmed text of an element. URLs are usually wrapped in CDATA with whitespace around it.
+fn text(node: Node) -> String {
+    node.descendants().filter(|n| n.is_text()).filter_map(|n| n.text()).collect::<String>().trim().to_string()
+}
+
+fn child_text(node: Node, name: &str) -> Option<String> {
+    child(node, name).map(text).filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vast(version: &str, ads: &str) -> String {
+        format!(r#"<?xml version="1.0" encoding="UTF-8"?><VAST version="{}">{}</VAST>"#, version, ads)
+    }
+
+    fn companion_ads(required: &str, id: &str) -> String {
+        format!(
+            r#"<Creative><CompanionAds required="{}">
+                <Companion id="{}" width="300" height="250">
+                  <StaticResource creativeType="image/png"><![CDATA[ {}.png ]]></StaticResource>
+                </Companion>
+              </CompanionAds></Creative>"#,
+            required, id, id
+        )
+    }
+
+    #[test]
+    fn parses_the_sample_documents() {
+        for version in ["2", "3", "4"].iter() {
+            let path = format!("static/vast/teapot-vast{}.xml", version);
+            let document = VastDocument::load_file(&path).unwrap();
+            assert!(document.version.starts_with(*version), "{}", path);
+            assert_eq!(document.ads.len(), 1, "{}", path);
+            assert!(document.ads[0].linear.as_ref().and_then(|linear| linear.duration).is_some(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn parses_inline_ads() {
+        let document = VastDocument::load_file("static/vast/teapot-vast3.xml").unwrap();
+        let ad = &document.ads[0];
+        assert_eq!(ad.id.as_deref(), Some("teapot-video-1"));
+        assert_eq!(ad.ad_title.as_deref(), Some("Teapot 15s"));
+        assert_eq!(ad.impressions, vec!["https://track.example.com/impression?ad=teapot-video-1"]);
+        assert!(!ad.is_wrapper());
+        let linear = ad.linear.as_ref().unwrap();
+        assert_eq!(linear.tracking_events.len(), 6);
+        assert_eq!(linear.tracking_events[5].offset.as_deref(), Some("00:00:05"));
+        assert_eq!(linear.click_tracking, vec!["https://track.example.com/click"]);
+        assert_eq!(linear.media_files[0].width, 1280);
+        assert_eq!(linear.media_files[0].uri, "https://cdn.example.com/teapot-720p.mp4");
+
+        assert_eq!(ad.companions_required.as_deref(), Some("any"));
+        let companions = document.companions();
+        assert_eq!(companions.len(), 3);
+        assert_eq!(companions[0].label(), "teapot-300x250 300x250");
+        let view_uris = companions[0].tracking_uris("creativeView");
+        assert_eq!(view_uris, vec!["https://track.example.com/companion/view?id=300x250"]);
+        assert_eq!(companions[1].resources[0].name(), "StaticResource");
+        assert_eq!(companions[2].resources[0].name(), "HTMLResource");
+    }
+
+    #[test]
+    fn parses_wrappers() {
+        let text = vast(
+            "3.0",
+            r#"<Ad id="w"><Wrapper><AdSystem>Network</AdSystem>
+                <VASTAdTagURI><![CDATA[https://ads.example.com/vast.xml]]></VASTAdTagURI>
+                <Impression><![CDATA[https://track.example.com/wrapper]]></Impression>
+              </Wrapper></Ad>"#,
+        );
+        let document = VastDocument::parse(&text).unwrap();
+        let ad = &document.ads[0];
+        assert!(ad.is_wrapper());
+        assert_eq!(ad.wrapper_uri.as_deref(), Some("https://ads.example.com/vast.xml"));
+        assert_eq!(ad.impressions.len(), 1);
+        assert!(ad.linear.is_none());
+    }
+
+    #[test]
+    fn rejects_unsupported_documents() {
+        assert!(VastDocument::parse("<VAST version=\"3.0\"><Ad></VAST>").is_err());
+        assert!(VastDocument::parse("<VMAP version=\"1.0\"/>").is_err());
+        assert!(VastDocument::parse(&vast("1.0", "")).is_err());
+        assert!(VastDocument::parse(&vast("", "")).is_err());
+        assert!(VastDocument::parse(&vast("4.2", "")).is_ok());
+    }
+
+    #[test]
+    fn skips_unusable_ads_and_companions_with_warnings() {
+        let text = vast(
+            "3.0",
+            r#"<Ad id="empty"/>
+              <Ad><InLine><Creatives><Creative><CompanionAds>
+                <Companion id="no-size"><HTMLResource>Teapot</HTMLResource></Companion>
+                <Companion width="0" height="90"><HTMLResource>Teapot</HTMLResource></Companion>
+              </CompanionAds></Creative></Creatives></InLine></Ad>"#,
+        );
+        let document = VastDocument::parse(&text).unwrap();
+        assert_eq!(document.ads.len(), 1);
+        assert!(document.companions().is_empty());
+        assert_eq!(document.warnings.len(), 3);
+        assert!(document.warnings[0].contains("Ad empty"));
+        assert!(document.warnings[1].contains("Companion no-size"));
+        assert!(document.warnings[2].contains("without an id"));
+    }
+
+    #[test]
+    fn keeps_the_strictest_companion_requirement() {
+        let cases = [("all", "none", "all"), ("none", "any", "any"), ("any", "all", "all")];
+        for (first, second, expected) in cases.iter() {
+            let creatives = companion_ads(first, "a") + &companion_ads(second, "b");
+            let text = vast("3.0", &format!("<Ad><InLine><Creatives>{}</Creatives></InLine></Ad>", creatives));
+            let document = VastDocument::parse(&text).unwrap();
+            assert_eq!(document.ads[0].companions_required.as_deref(), Some(*expected));
+            assert_eq!(document.companions().len(), 2);
+        }
+    }
+
+    #[test]
+    fn trims_resource_uris() {
+        let creatives = companion_ads("none", "a");
+        let text = vast("3.0", &format!("<Ad><InLine><Creatives>{}</Creatives></InLine></Ad>", creatives));
+        let document = VastDocument::parse(&text).unwrap();
+        let expected = CompanionResource::Static { creative_type: "image/png".to_string(), uri: "a.png".to_string() };
+        assert_eq!(document.companions()[0].resources, vec![expected]);
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("00:00:15"), Some(15.0));
+        assert_eq!(parse_duration(" 01:02:03.500 "), Some(3723.5));
+        assert_eq!(parse_duration("00:15"), None);
+        assert_eq!(parse_duration("aa:00:15"), None);
+    }
+}
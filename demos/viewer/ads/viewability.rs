@@ -0,0 +1,189 @@
+/// Viewability measurement
+///
+/// An ad is viewable when a minimum fraction of its pixels has been in view for a minimum continuous time.
+/// The MRC (Media Rating Council) guidelines define the common standards:
+///
+/// * Display: 50% of pixels for 1 second
+/// * Large display (242,500 pixels or more): 30% of pixels for 1 second
+/// * Video: 50% of pixels for 2 seconds
+///
+/// The ViewabilityMeter is updated every frame with the fraction in view and reports each standard once
+/// when it is met. Time only counts while the fraction stays at or above the threshold.
+///
+use quicksilver::geom::Rectangle;
+
+/// Ads with at least this many pixels use the large display standard
+pub const LARGE_AD_PIXELS: f32 = 242_500.0;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ViewabilityStandard {
+    pub name: &'static str,
+    /// Fraction of the ad that must be in view, from 0.0 to 1.0
+    pub min_fraction: f32,
+    /// Continuous seconds that the fraction must be met
+    pub min_seconds: f64,
+}
+
+pub const MRC_DISPLAY: ViewabilityStandard =
+    ViewabilityStandard { name: "MRC display", min_fraction: 0.5, min_seconds: 1.0 };
+pub const MRC_LARGE_DISPLAY: ViewabilityStandard =
+    ViewabilityStandard { name: "MRC large display", min_fraction: 0.3, min_seconds: 1.0 };
+pub const MRC_VIDEO: ViewabilityStandard =
+    ViewabilityStandard { name: "MRC video", min_fraction: 0.5, min_seconds: 2.0 };
+pub const FULL_VIEW: ViewabilityStandard =
+    ViewabilityStandard { name: "100% in view", min_fraction: 1.0, min_seconds: 1.0 };
+
+/// The fraction of the ad frame that is inside the viewport, from 0.0 to 1.0
+pub fn fraction_in_view(ad_frame: &Rectangle, viewport: &Rectangle) -> f32 {
+    let ad_area = ad_frame.width() * ad_frame.height();
+    if ad_area <= 0.0 {
+        return 0.0;
+    }
+    let left = ad_frame.x().max(viewport.x());
+    let top = ad_frame.y().max(viewport.y());
+    let right = (ad_frame.x() + ad_frame.width()).min(viewport.x() + viewport.width());
+    let bottom = (ad_frame.y() + ad_frame.height()).min(viewport.y() + viewport.height());
+    if right <= left || bottom <= top {
+        return 0.0;
+    }
+    ((right - left) * (bottom - top) / ad_area).min(1.0)
+}
+
+struct StandardProgress {
+    standard: ViewabilityStandard,
+    /// Continuous seconds at or above the threshold
+    seconds: f64,
+    is_met: bool,
+}
+
+/// Measures viewability over time for one ad
+pub struct ViewabilityMeter {
+    standards: Vec<StandardProgress>,
+    fraction: f32,
+    max_fraction: f32,
+    seconds_in_view: f64,
+}
+
+impl ViewabilityMeter {
+    pub fn new(standards: &[ViewabilityStandard]) -> Self {
+        let standards = standards
+            .iter()
+            .map(|standard| StandardProgress { standard: standard.clone(), seconds: 0.0, is_met: false })
+            .collect();
+        ViewabilityMeter { standards, fraction: 0.0, max_fraction: 0.0, seconds_in_view: 0.0 }
+    }
+
+    /// The primary MRC standard for an ad of this size, followed by the video and 100% standards
+    pub fn for_ad_size(width: f32, height: f32) -> Self {
+        ViewabilityMeter::new(&[ViewabilityMeter::primary_standard(width, height), MRC_VIDEO, FULL_VIEW])
+    }
+
+    pub fn primary_standard(width: f32, height: f32) -> ViewabilityStandard {
+        if width * height >= LARGE_AD_PIXELS {
+            MRC_LARGE_DISPLAY
+        } else {
+            MRC_DISPLAY
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for progress in self.standards.iter_mut() {
+            progress.seconds = 0.0;
+            progress.is_met = false;
+        }
+        self.fraction = 0.0;
+        self.max_fraction = 0.0;
+        self.seconds_in_view = 0.0;
+    }
+
+    /// Record that the given fraction was in view for the last interval. Returns the standards that were
+    /// met during this update.
+    pub fn update(&mut self, fraction: f32, seconds: f64) -> Vec<ViewabilityStandard> {
+        self.fraction = fraction;
+        self.max_fraction = self.max_fraction.max(fraction);
+        if fraction > 0.0 {
+            self.seconds_in_view += seconds;
+        }
+        let mut met = Vec::new();
+        for progress in self.standards.iter_mut().filter(|p| !p.is_met) {
+            if fraction >= progress.standard.min_fraction {
+                progress.seconds += seconds;
+            } else {
+                progress.seconds = 0.0;
+            }
+            if progress.seconds >= progress.standard.min_seconds {
+                progress.is_met = true;
+                met.push(progress.standard.clone());
+            }
+        }
+        met
+    }
+
+    /// The fraction in view on the last update
+    pub fn fraction(&self) -> f32 {
+        self.fraction
+    }
+
+    pub fn max_fraction(&self) -> f32 {
+        self.max_fraction
+    }
+
+    /// Total seconds with any part of the ad in view
+    pub fn seconds_in_view(&self) -> f64 {
+        self.seconds_in_view
+    }
+
+    /// True if the first standard, which is the one used for the viewable event, has been met
+    pub fn is_viewable(&self) -> bool {
+        self.standards.first().map_or(false, |p| p.is_met)
+    }
+
+    /// Names of the standards met so far
+    pub fn met_standards(&self) -> Vec<&'static str> {
+        self.standards.iter().filter(|p| p.is_met).map(|p| p.standard.name).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fraction_in_view_is_the_visible_share_of_the_ad() {
+        let viewport = Rectangle::new((0.0, 0.0), (800.0, 600.0));
+        assert_eq!(fraction_in_view(&Rectangle::new((100.0, 100.0), (300.0, 250.0)), &viewport), 1.0);
+        assert_eq!(fraction_in_view(&Rectangle::new((100.0, 475.0), (300.0, 250.0)), &viewport), 0.5);
+        assert_eq!(fraction_in_view(&Rectangle::new((-150.0, 0.0), (300.0, 250.0)), &viewport), 0.5);
+        assert_eq!(fraction_in_view(&Rectangle::new((100.0, 600.0), (300.0, 250.0)), &viewport), 0.0);
+        assert_eq!(fraction_in_view(&Rectangle::new((100.0, 100.0), (0.0, 250.0)), &viewport), 0.0);
+    }
+
+    #[test]
+    fn display_standard_needs_half_the_ad_for_one_continuous_second() {
+        let mut meter = ViewabilityMeter::for_ad_size(300.0, 250.0);
+        assert!(meter.update(0.49, 2.0).is_empty());
+        assert!(meter.update(0.5, 0.6).is_empty());
+        // Dropping below the threshold restarts the clock
+        assert!(meter.update(0.2, 0.1).is_empty());
+        assert!(meter.update(0.8, 0.6).is_empty());
+        assert!(!meter.is_viewable());
+        assert_eq!(meter.update(0.8, 0.4), vec![MRC_DISPLAY]);
+        assert!(meter.is_viewable());
+        assert!(meter.update(0.8, 0.5).is_empty());
+        assert_eq!(meter.met_standards(), vec!["MRC display"]);
+        assert_eq!(meter.max_fraction(), 0.8);
+        assert!((meter.seconds_in_view() - 4.2).abs() < 1e-9);
+
+        meter.reset();
+        assert!(!meter.is_viewable());
+        assert_eq!(meter.update(1.0, 2.0), vec![MRC_DISPLAY, MRC_VIDEO, FULL_VIEW]);
+    }
+
+    #[test]
+    fn large_ads_use_the_large_display_standard() {
+        assert_eq!(ViewabilityMeter::primary_standard(970.0, 250.0), MRC_LARGE_DISPLAY);
+        assert_eq!(ViewabilityMeter::primary_standard(300.0, 600.0), MRC_DISPLAY);
+        let mut meter = ViewabilityMeter::for_ad_size(970.0, 250.0);
+        assert_eq!(meter.update(0.3, 1.0), vec![MRC_LARGE_DISPLAY]);
+    }
+}
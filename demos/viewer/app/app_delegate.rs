@@ -3,6 +3,7 @@
 ///
 ///
 use super::*;
+use crate::ads::*;
 use crate::samples::*;
 
 use tweek::{
@@ -25,6 +26,14 @@ use std::any::TypeId;
 const FPS_INTERVAL: usize = 40;
 pub const FPS_TAG: u32 = 901;
 
+/// The Theme that the viewer starts with, before the brand kit's theme tokens are applied
+pub fn base_theme() -> Theme {
+    let mut theme = Theme::default();
+    theme.font_size = 18.0;
+    theme.bg_color = Color::from_hex("#FFFFEE");
+    theme
+}
+
 //-- Main -----------------------------------------------------------------------
 
 /// AppDelegate serves as a layer between the backend runloop and Tweek UI.
@@ -32,6 +41,7 @@ pub const FPS_TAG: u32 = 901;
 pub struct AppDelegate {
     controller: Box<dyn Controller>,
     theme: Theme,
+    brand_kit: BrandKit,
     app_state: AppState,
     data_scene: Scene,
     frames: usize,
@@ -42,9 +52,17 @@ impl AppDelegate {
     /// Constructor
     pub fn new(screen: Vector) -> Self {
 
-        let mut theme = Theme::default();
-        theme.font_size = 18.0;
-        theme.bg_color = Color::from_hex("#FFFFEE");
+        // The AD_BRAND_KIT env var can be used to re-skin the ad with a different kit
+        let kit_path = env_asset_path("AD_BRAND_KIT", DEFAULT_BRAND_KIT);
+        let brand_kit = match BrandKit::load_file(&kit_path) {
+            Ok(kit) => kit,
+            Err(e) => {
+                log::error!("Failed to load brand kit {}: {:?}", kit_path, e);
+                BrandKit::default()
+            }
+        };
+        let mut theme = base_theme();
+        brand_kit.apply_to_theme(&mut theme);
 
         // Add data scene for displaying FPS and other info
         let frame = Rectangle::new_sized(screen);
@@ -67,6 +85,7 @@ impl AppDelegate {
         let app = AppDelegate {
             controller,
             theme,
+            brand_kit,
             app_state,
             data_scene,
             frames: 0,
@@ -78,13 +97,187 @@ impl AppDelegate {
     /// Application lifecycle event called before runloop starts
     pub fn application_ready(&mut self, screen: Vector) {
 
-        let mut controller = AdViewer::new(Rectangle::new_sized(screen));
+        // The AD_ASSET_ROOT env var can be used to load creatives from another folder
+        let asset_root = asset_root();
+        let mut controller = AdViewer::new(Rectangle::new_sized(screen))
+            .with_brand_kit(self.brand_kit.clone())
+            .with_asset_root(&asset_root);
+
+        // AD_STRINGS is the folder of string tables for the ad's copy and AD_LOCALE the locale to start with
+        let strings_dir = env_asset_path("AD_STRINGS", DEFAULT_STRINGS_DIR);
+        match StringTables::load_folder(&strings_dir) {
+            Ok(strings) => {
+                let locale = std::env::var("AD_LOCALE").unwrap_or(DEFAULT_LOCALE.to_string());
+                controller = controller.with_strings(strings, &locale);
+            }
+            Err(e) => log::error!("Failed to load string tables in {}: {:?}", strings_dir, e),
+        }
+
+        // AD_LINT_RULES is the rules file that the ad is checked against each time it is built
+        let rules_path = env_asset_path("AD_LINT_RULES", DEFAULT_LINT_RULES);
+        match LintConfig::load_file(&rules_path) {
+            Ok(config) => controller = controller.with_lint(config),
+            Err(e) => log::error!("Failed to load lint rules {}: {:?}", rules_path, e),
+        }
+
+        // AD_VIEWER_MODE=page shows the ad inside a mock publisher page, which AD_MOCK_PAGE can override.
+        // AD_VIEWER_MODE=expandable shows the expandable formats.
+        // AD_VIEWER_MODE=interstitial, anchor or sidebar shows the ad in that placement.
+        // AD_VIEWER_MODE=native builds a native ad from components, which AD_NATIVE_AD can override.
+        // AD_VIEWER_MODE=vast shows the companion banners in a VAST file, which AD_VAST_FILE can override.
+        // AD_VIEWER_MODE=openrtb shows the winner of a bid response, which AD_BID_RESPONSE can override.
+        // AD_VIEWER_MODE=adserver requests the ad for AD_PLACEMENT from the mock ad server in AD_SERVER_FILE.
+        // AD_VIEWER_MODE=campaign previews the creatives of a campaign, which AD_CAMPAIGN can override.
+        // AD_VIEWER_MODE=rotation plays back the ads that one user sees in the campaign. See load_rotation.
+        // AD_VIEWER_MODE=dco fills a DCO template from its product feed, which AD_DCO_TEMPLATE can override.
+        // AD_VIEWER_MODE=variants compares the variants in AD_VARIANTS side by side. AD_VARIANT_IDS (e.g. a,b)
+        // selects some of them.
+        let mode = std::env::var("AD_VIEWER_MODE").unwrap_or_default();
+        if let Some(placement) = Placement::from_name(&mode) {
+            controller = controller.with_placement(placement);
+        } else {
+            match mode.as_str() {
+                "page" => {
+                    let page_path = env_asset_path("AD_MOCK_PAGE", DEFAULT_MOCK_PAGE);
+                    match MockPage::load_file(&page_path) {
+                        Ok(page) => controller = controller.with_mock_page(page),
+                        Err(e) => log::error!("Failed to load mock page {}: {:?}", page_path, e),
+                    }
+                }
+                "native" => {
+                    let native_path = env_asset_path("AD_NATIVE_AD", DEFAULT_NATIVE_AD);
+                    match NativeAd::load_file(&native_path) {
+                        Ok(ad) => controller = controller.with_native(ad),
+                        Err(e) => log::error!("Failed to load native ad {}: {:?}", native_path, e),
+                    }
+                }
+                "vast" => {
+                    let vast_path = env_asset_path("AD_VAST_FILE", DEFAULT_VAST_FILE);
+                    match VastDocument::load_file(&vast_path) {
+                        Ok(document) => controller = controller.with_vast(document, &vast_path),
+                        Err(e) => log::error!("Failed to load VAST file {}: {:?}", vast_path, e),
+                    }
+                }
+                "adserver" => {
+                    let server_path = env_asset_path("AD_SERVER_FILE", DEFAULT_AD_SERVER);
+                    let placement_id = std::env::var("AD_PLACEMENT").unwrap_or(DEFAULT_PLACEMENT.to_string());
+                    match MockAdServer::load_file(&server_path) {
+                        Ok(server) => controller = controller.with_ad_server(server, &placement_id),
+                        Err(e) => log::error!("Failed to load ad server {}: {:?}", server_path, e),
+                    }
+                }
+                "campaign" => {
+                    let campaign_path = env_asset_path("AD_CAMPAIGN", DEFAULT_CAMPAIGN);
+                    match Campaign::load_file(&campaign_path) {
+                        Ok(campaign) => controller = controller.with_campaign(campaign),
+                        Err(e) => log::error!("Failed to load campaign {}: {:?}", campaign_path, e),
+                    }
+                }
+                "dco" => {
+                    let template_path = env_asset_path("AD_DCO_TEMPLATE", DEFAULT_DCO_TEMPLATE);
+                    match load_dco_variants(&template_path) {
+                        Ok((template, variants)) => controller = controller.with_dco(template, variants),
+                        Err(e) => log::error!("Failed to load DCO template {}: {:?}", template_path, e),
+                    }
+                }
+                "variants" => {
+                    let variants_path = env_asset_path("AD_VARIANTS", DEFAULT_VARIANTS);
+                    let ids = std::env::var("AD_VARIANT_IDS").unwrap_or_default();
+                    let ids: Vec<&str> = ids.split(',').map(|id| id.trim()).filter(|id| !id.is_empty()).collect();
+                    match load_variant_builders(&variants_path, &ids, &self.brand_kit) {
+                        Ok(variants) => controller = controller.with_variants(variants),
+                        Err(e) => log::error!("Failed to load variants {}: {:?}", variants_path, e),
+                    }
+                }
+                "rotation" => controller = AppDelegate::load_rotation(controller),
+                "openrtb" => controller = AppDelegate::load_bid_response(controller),
+                "expandable" => controller = controller.with_expandable(EXPANDABLE_SIZES_MAP["728x90"].clone()),
+                "" => (),
+                mode => log::error!("Unknown viewer mode: {}", mode),
+            }
+        }
         // Load stage here
         controller.view_will_load(&mut self.theme);
         controller.set_theme(&mut self.theme);
         self.controller = Box::new(controller);
     }
 
+    /// Simulate the campaign's rotation for one user. AD_IMPRESSIONS sets the number of impressions and AD_SEED
+    /// the seed for weighted rotation. AD_SIZE (e.g. 300x250) and AD_DATE (YYYY-MM-DD) limit the line items.
+    fn load_rotation(controller: AdViewer) -> AdViewer {
+        let campaign_path = env_asset_path("AD_CAMPAIGN", DEFAULT_CAMPAIGN);
+        let campaign = match Campaign::load_file(&campaign_path) {
+            Ok(campaign) => campaign,
+            Err(e) => {
+                log::error!("Failed to load campaign {}: {:?}", campaign_path, e);
+                return controller;
+            }
+        };
+        let impressions = std::env::var("AD_IMPRESSIONS").ok().and_then(|value| value.parse().ok());
+        let seed = std::env::var("AD_SEED").ok().and_then(|value| value.parse().ok());
+        let mut simulator = RotationSimulator::new(&campaign).with_seed(seed.unwrap_or(1));
+        if let Ok(size) = std::env::var("AD_SIZE") {
+            let mut parts = size.split('x').map(|value| value.parse::<u32>());
+            match (parts.next(), parts.next()) {
+                (Some(Ok(width)), Some(Ok(height))) => simulator = simulator.with_size((width, height)),
+                _ => log::error!("Invalid AD_SIZE {}. Use WIDTHxHEIGHT.", size),
+            }
+        }
+        if let Ok(date) = std::env::var("AD_DATE") {
+            match FlightDate::parse(&date) {
+                Ok(date) => simulator = simulator.with_date(date),
+                Err(e) => log::error!("{:?}", e),
+            }
+        }
+        let impressions = simulator.run(impressions.unwrap_or(DEFAULT_IMPRESSIONS));
+        controller.with_rotation(campaign, impressions)
+    }
+
+    /// Run the auction for a captured bid response. AD_BID_REQUEST can point to the bid request, which sets the
+    /// impression size and the auction type. A winner with VAST markup shows its companions, like vast mode.
+    fn load_bid_response(controller: AdViewer) -> AdViewer {
+        let response_path = env_asset_path("AD_BID_RESPONSE", DEFAULT_BID_RESPONSE);
+        let response = match BidResponse::load_file(&response_path) {
+            Ok(response) => response,
+            Err(e) => {
+                log::error!("Failed to load bid response {}: {:?}", response_path, e);
+                return controller;
+            }
+        };
+        let request = match std::env::var("AD_BID_REQUEST") {
+            Ok(request_path) => match BidRequest::load_file(&request_path) {
+                Ok(request) => Some(request),
+                Err(e) => {
+                    log::error!("Failed to load bid request {}: {:?}", request_path, e);
+                    None
+                }
+            },
+            Err(_) => None,
+        };
+        let result = match response.auction(request.as_ref()) {
+            Ok(result) => result,
+            Err(e) => {
+                log::error!("No winner in bid response {}: {:?}", response_path, e);
+                return controller;
+            }
+        };
+        match result.markup() {
+            Some(BidMarkup::Vast(xml)) => match VastDocument::parse(&xml) {
+                Ok(document) => {
+                    for (kind, url) in result.notices() {
+                        log::info!("OpenRTB {} notice url={}", kind, url);
+                    }
+                    controller.with_vast(document, &response_path)
+                }
+                Err(e) => {
+                    log::error!("Failed to parse the VAST markup of bid {}: {:?}", result.winner.id, e);
+                    controller
+                }
+            },
+            _ => controller.with_bid(result, &response_path),
+        }
+    }
+
 }
 
 // ************************************************************************************
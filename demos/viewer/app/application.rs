@@ -3,10 +3,30 @@ use super::*;
 use quicksilver::{
     geom::{Rectangle, Vector},
     graphics::Color,
-    lifecycle::{Event, State, Window},
+    lifecycle::{Event, Settings, State, Window},
     Error, Result,
 };
 
+/// The frame rate that the AD_FRAME_RATE env var caps drawing at, or None when drawing is not capped
+pub fn env_frame_rate() -> Option<f64> {
+    let value = std::env::var("AD_FRAME_RATE").ok()?;
+    match value.trim().parse::<f64>() {
+        Ok(frame_rate) if frame_rate > 0.0 => Some(frame_rate),
+        _ => {
+            log::warn!("Ignoring AD_FRAME_RATE={:?}, which is not a positive number", value);
+            None
+        }
+    }
+}
+
+/// The window settings, with the draw rate capped at the frame rate if there is one
+pub fn app_settings(frame_rate: Option<f64>) -> Settings {
+    match frame_rate {
+        Some(frame_rate) => Settings { draw_rate: 1000.0 / frame_rate, ..Settings::default() },
+        None => Settings::default(),
+    }
+}
+
 #[allow(dead_code)]
 #[allow(unused_variables)]
 pub struct Application {
@@ -0,0 +1,621 @@
+/// Asset manifests and preloading for creatives
+///
+/// Each creative has a manifest.toml that lists the images, SVGs and fonts it uses. Paths in the manifest
+/// are relative to the asset root, which is the static folder by default. The AssetLoader requests every file
+/// with quicksilver's file loading, so that the same code loads a creative on desktop and in an HTML5 bundle,
+/// and then decodes one asset per call to load_next() so that the viewer can show progress between frames.
+///
+/// Example:
+///
+/// ```toml
+/// name = "Teapot"
+///
+/// [[images]]
+/// id = "teapot"
+/// path = "teapot/img-teapot.png"
+///
+/// [[svgs]]
+/// id = "logo"
+/// path = "teapot/logo-MK.svg"
+///
+/// [[fonts]]
+/// family = "Montserrat"
+/// weight = "bold"
+/// path = "teapot/fonts/Montserrat-Bold.ttf"
+///
+/// [[code]]
+/// path = "teapot/teapot.wasm"
+/// ```
+///
+/// Any entry can set `load = "polite"` for a file that a served ad loads after the page, and `specs` to the AdSpec
+/// ids that it is used at. The AssetLoader loads the polite files after the initial ones, so the HTML5 build can show
+/// the ad before they arrive, while the viewer waits for every image, SVG and font before the ad is built. Code is
+/// the creative's compiled build, which the viewer does not load.
+///
+use super::*;
+
+use tweek::tools::*;
+
+use quicksilver::{lifecycle::Asset, load_file, Error, Result};
+
+use serde::Deserialize;
+
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The default asset root, relative to the working directory
+pub const DEFAULT_ASSET_ROOT: &str = "static";
+
+/// The asset root, which the AD_ASSET_ROOT env var can override
+pub fn asset_root() -> String {
+    std::env::var("AD_ASSET_ROOT").unwrap_or(DEFAULT_ASSET_ROOT.to_string())
+}
+
+/// The path in the env var if it is set, or else the default path resolved against the asset root
+pub fn env_asset_path(var: &str, default_path: &str) -> String {
+    std::env::var(var).unwrap_or_else(|_| Path::new(&asset_root()).join(default_path).to_string_lossy().to_string())
+}
+
+/// When a served ad loads a file. Initial files are loaded before the ad is shown, and polite files after the
+/// publisher's page has finished loading.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LoadPhase {
+    Initial,
+    Polite,
+}
+
+impl LoadPhase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LoadPhase::Initial => "initial",
+            LoadPhase::Polite => "polite",
+        }
+    }
+}
+
+impl Default for LoadPhase {
+    fn default() -> Self {
+        LoadPhase::Initial
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ImageAsset {
+    pub id: String,
+    pub path: String,
+    #[serde(default)]
+    pub load: LoadPhase,
+    /// The AdSpec ids that use the file. Empty means every size.
+    #[serde(default)]
+    pub specs: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SvgAsset {
+    pub id: String,
+    pub path: String,
+    #[serde(default)]
+    pub load: LoadPhase,
+    #[serde(default)]
+    pub specs: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct FontAsset {
+    pub family: String,
+    #[serde(default = "FontAsset::default_weight")]
+    pub weight: FontWeight,
+    pub path: String,
+    #[serde(default)]
+    pub load: LoadPhase,
+    #[serde(default)]
+    pub specs: Vec<String>,
+}
+
+impl FontAsset {
+    fn default_weight() -> FontWeight {
+        FontWeight::Regular
+    }
+
+    /// The entry as a [[fonts]] table, which can be appended to a manifest's TOML
+    pub fn to_toml(&self) -> String {
+        let mut text = String::from("\n[[fonts]]\n");
+        text.push_str(&format!("family = {}\n", toml::Value::from(self.family.as_str())));
+        text.push_str(&format!("weight = \"{}\"\n", self.weight.name()));
+        text.push_str(&format!("path = {}\n", toml::Value::from(self.path.as_str())));
+        text.push_str(&format!("load = \"{}\"\n", self.load.name()));
+        if !self.specs.is_empty() {
+            let specs: Vec<toml::Value> = self.specs.iter().map(|spec| toml::Value::from(spec.as_str())).collect();
+            text.push_str(&format!("specs = {}\n", toml::Value::from(specs)));
+        }
+        text
+    }
+}
+
+/// A compiled build of the creative, e.g. a wasm file
+#[derive(Clone, Debug, Deserialize)]
+pub struct CodeAsset {
+    pub path: String,
+    #[serde(default)]
+    pub load: LoadPhase,
+    #[serde(default)]
+    pub specs: Vec<String>,
+}
+
+/// The list of files used by a creative
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct AssetManifest {
+    pub name: String,
+    #[serde(default)]
+    pub images: Vec<ImageAsset>,
+    #[serde(default)]
+    pub svgs: Vec<SvgAsset>,
+    #[serde(default)]
+    pub fonts: Vec<FontAsset>,
+    #[serde(default)]
+    pub code: Vec<CodeAsset>,
+}
+
+impl AssetManifest {
+    pub fn from_toml(text: &str) -> Result<AssetManifest> {
+        toml::from_str(text).map_err(|e| Error::ContextError(format!("Invalid asset manifest: {}", e)))
+    }
+
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<AssetManifest> {
+        let text = fs::read_to_string(path.as_ref())?;
+        AssetManifest::from_toml(&text)
+    }
+
+    /// The paths of the files that the AssetLoader loads, which are all but the code
+    pub fn paths(&self) -> Vec<&str> {
+        let images = self.images.iter().map(|a| a.path.as_str());
+        let svgs = self.svgs.iter().map(|a| a.path.as_str());
+        let fonts = self.fonts.iter().map(|a| a.path.as_str());
+        images.chain(svgs).chain(fonts).collect()
+    }
+
+    /// Every file with its category and load phase, leaving out files that are not used at the AdSpec
+    pub fn files_for_spec(&self, spec_id: &str) -> Vec<(&str, AssetCategory, LoadPhase)> {
+        let images = self.images.iter().map(|a| (&a.path, AssetCategory::Image, a.load, &a.specs));
+        let svgs = self.svgs.iter().map(|a| (&a.path, AssetCategory::Image, a.load, &a.specs));
+        let fonts = self.fonts.iter().map(|a| (&a.path, AssetCategory::Font, a.load, &a.specs));
+        let code = self.code.iter().map(|a| (&a.path, AssetCategory::Code, a.load, &a.specs));
+        images
+            .chain(svgs)
+            .chain(fonts)
+            .chain(code)
+            .filter(|(_, _, _, specs)| specs.is_empty() || specs.iter().any(|id| id == spec_id))
+            .map(|(path, category, load, _)| (path.as_str(), category, load))
+            .collect()
+    }
+}
+
+/// Loads files with quicksilver, which reads them from disk on desktop and requests them from the server in a
+/// browser. Every requested file loads at the same time.
+pub struct FileLoader {
+    root: PathBuf,
+    requests: Vec<(String, Asset<Vec<u8>>)>,
+    files: HashMap<String, Result<Vec<u8>>>,
+}
+
+impl FileLoader {
+    /// The root is the folder that requested paths are relative to
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        FileLoader { root: root.as_ref().to_path_buf(), requests: Vec::new(), files: HashMap::new() }
+    }
+
+    /// Start loading the file at the path. A file that was already requested is only loaded once.
+    pub fn request(&mut self, path: &str) {
+        if self.files.contains_key(path) || self.requests.iter().any(|(requested, _)| requested == path) {
+            return;
+        }
+        let asset = Asset::new(load_file(self.root.join(path)));
+        self.requests.push((path.to_string(), asset));
+    }
+
+    /// Collect the files that have finished loading, or failed to. Call this once per frame.
+    pub fn poll(&mut self) {
+        for (path, mut asset) in std::mem::take(&mut self.requests) {
+            let mut data = None;
+            let result = asset.execute(|loaded| {
+                data = Some(std::mem::take(loaded));
+                Ok(())
+            });
+            match (result, data) {
+                (Ok(()), None) => self.requests.push((path, asset)),
+                (Ok(()), Some(data)) => {
+                    self.files.insert(path, Ok(data));
+                }
+                (Err(e), _) => {
+                    let e = Error::ContextError(format!("Failed to load {}: {:?}", path, e));
+                    self.files.insert(path, Err(e));
+                }
+            }
+        }
+    }
+
+    /// Whether every requested file has finished loading or failed to
+    pub fn is_complete(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    /// Take the data of a file that has finished loading. Returns None while the file is loading.
+    pub fn take(&mut self, path: &str) -> Option<Result<Vec<u8>>> {
+        self.files.remove(path)
+    }
+
+    /// Copy the data of a file that has finished loading, leaving it for the next caller
+    pub fn copy(&self, path: &str) -> Option<Result<Vec<u8>>> {
+        self.files.get(path).map(|data| match data {
+            Ok(data) => Ok(data.clone()),
+            Err(e) => Err(Error::ContextError(format!("{:?}", e))),
+        })
+    }
+
+    /// Take the text of a file that has finished loading
+    pub fn take_text(&mut self, path: &str) -> Option<Result<String>> {
+        self.take(path).map(|data| {
+            data.and_then(|data| {
+                String::from_utf8(data).map_err(|e| Error::ContextError(format!("{} is not UTF-8: {}", path, e)))
+            })
+        })
+    }
+}
+
+/// The loaded assets for a creative, which are handed to an ad builder
+pub struct AdAssets {
+    root: PathBuf,
+    images: HashMap<String, DrawImage>,
+    /// The data of each SVG by id
+    svgs: HashMap<String, Vec<u8>>,
+    /// The rasterized SVGs by id and size in pixels
+    svg_images: HashMap<(String, (u32, u32)), DrawImage>,
+    pub fonts: FontRegistry,
+    pub svg_cache: SvgCache,
+}
+
+impl AdAssets {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        AdAssets {
+            root: root.as_ref().to_path_buf(),
+            images: HashMap::new(),
+            svgs: HashMap::new(),
+            svg_images: HashMap::new(),
+            fonts: FontRegistry::default(),
+            svg_cache: SvgCache::new(root),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Get a copy of a preloaded image
+    pub fn image(&self, id: &str) -> Option<DrawImage> {
+        let image = self.images.get(id).cloned();
+        if image.is_none() {
+            log::warn!("No image asset with id {:?}", id);
+        }
+        image
+    }
+
+    /// Rasterize an SVG asset so that it fits within max_size. Returns the image with its size in pixels. Each SVG
+    /// is only rasterized once per size.
+    pub fn svg_image(&mut self, id: &str, max_size: (f32, f32)) -> Result<(DrawImage, (f32, f32))> {
+        let data = self.svgs.get(id).ok_or_else(|| Error::ContextError(format!("No SVG asset with id {:?}", id)))?;
+        let size = fit_size(svg_data_size(data)?, max_size);
+        let key = (id.to_string(), size);
+        let image = match self.svg_images.get(&key) {
+            Some(image) => image.clone(),
+            None => {
+                let png = rasterize_svg_data(data, size.0, size.1)?
+                    .encode_png()
+                    .map_err(|e| Error::ContextError(format!("Failed to encode SVG asset {:?}: {}", id, e)))?;
+                let image = DrawImage::from_bytes(&png)?;
+                self.svg_images.insert(key, image.clone());
+                image
+            }
+        };
+        Ok((image, (size.0 as f32, size.1 as f32)))
+    }
+}
+
+enum AssetRequest {
+    Image(ImageAsset),
+    Svg(SvgAsset),
+    Font(FontAsset),
+}
+
+impl AssetRequest {
+    fn path(&self) -> &str {
+        match self {
+            AssetRequest::Image(asset) => &asset.path,
+            AssetRequest::Svg(asset) => &asset.path,
+            AssetRequest::Font(asset) => &asset.path,
+        }
+    }
+
+    fn load(&self) -> LoadPhase {
+        match self {
+            AssetRequest::Image(asset) => asset.load,
+            AssetRequest::Svg(asset) => asset.load,
+            AssetRequest::Font(asset) => asset.load,
+        }
+    }
+}
+
+/// Loads the assets in a manifest incrementally. Initial assets are loaded first, and the files of polite assets are
+/// only requested once the initial assets have loaded.
+pub struct AssetLoader {
+    queue: VecDeque<AssetRequest>,
+    files: FileLoader,
+    is_polite_requested: bool,
+    total: usize,
+    errors: Vec<String>,
+    assets: AdAssets,
+}
+
+impl AssetLoader {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        AssetLoader {
+            queue: VecDeque::new(),
+            files: FileLoader::new(root.as_ref()),
+            is_polite_requested: false,
+            total: 0,
+            errors: Vec::new(),
+            assets: AdAssets::new(root),
+        }
+    }
+
+    /// Resolve a manifest path against the asset root
+    pub fn resolve(&self, path: &str) -> PathBuf {
+        self.assets.root.join(path)
+    }
+
+    /// Queue every asset in the manifest, with the initial assets before the polite ones, and start loading the
+    /// files of the initial assets
+    pub fn enqueue(&mut self, manifest: &AssetManifest) {
+        let images = manifest.images.iter().cloned().map(AssetRequest::Image);
+        let svgs = manifest.svgs.iter().cloned().map(AssetRequest::Svg);
+        let fonts = manifest.fonts.iter().cloned().map(AssetRequest::Font);
+        let (initial, polite): (Vec<AssetRequest>, Vec<AssetRequest>) =
+            images.chain(svgs).chain(fonts).partition(|request| request.load() == LoadPhase::Initial);
+        for request in initial.iter() {
+            self.files.request(request.path());
+        }
+        self.queue.extend(initial);
+        self.queue.extend(polite);
+        self.total = self.queue.len();
+    }
+
+    /// Decode the next asset in the queue once its file has loaded. Failures are recorded and loading continues
+    /// with the next asset. Entries with the same path share the file.
+    pub fn load_next(&mut self) {
+        if !self.is_polite_requested && self.is_initial_complete() {
+            self.is_polite_requested = true;
+            for request in self.queue.iter() {
+                self.files.request(request.path());
+            }
+        }
+        self.files.poll();
+        let path = match self.queue.front() {
+            Some(request) => request.path(),
+            None => return,
+        };
+        let is_shared = self.queue.iter().skip(1).any(|request| request.path() == path);
+        let data = match if is_shared { self.files.copy(path) } else { self.files.take(path) } {
+            Some(data) => data,
+            None => return,
+        };
+        let request = match self.queue.pop_front() {
+            Some(request) => request,
+            None => return,
+        };
+        let result = data.and_then(|data| match request {
+            AssetRequest::Image(asset) => DrawImage::from_bytes(&data).map(|image| {
+                self.assets.images.insert(asset.id.clone(), image);
+            }),
+            AssetRequest::Svg(asset) => {
+                // SVGs are rasterized per AdSpec when the ad is built, so only validate the data here
+                svg_data_size(&data).map(|_| {
+                    self.assets.svgs.insert(asset.id.clone(), data);
+                })
+            }
+            AssetRequest::Font(asset) => self.assets.fonts.register_bytes(&asset.family, asset.weight, data),
+        });
+        if let Err(e) = result {
+            log::error!("Asset load failed: {:?}", e);
+            self.errors.push(format!("{:?}", e));
+        }
+    }
+
+    /// Fraction of assets loaded, from 0.0 to 1.0
+    pub fn progress(&self) -> f32 {
+        if self.total == 0 {
+            return 1.0;
+        }
+        (self.total - self.queue.len()) as f32 / self.total as f32
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Whether every initial asset has loaded, so that the ad can be shown while the polite assets load
+    pub fn is_initial_complete(&self) -> bool {
+        self.queue.iter().all(|request| request.load() == LoadPhase::Polite)
+    }
+
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    /// Record an error that happened outside of the loader, which prevents loading from finishing
+    pub fn add_error(&mut self, message: String) {
+        log::error!("{}", message);
+        self.errors.push(message);
+    }
+
+    /// Mutable access to the assets, e.g. for loading brand kit fonts into the registry or building the ad with the
+    /// initial assets
+    pub fn assets_mut(&mut self) -> &mut AdAssets {
+        &mut self.assets
+    }
+
+    /// Finish loading and return the assets. Returns an error if any asset failed to load.
+    pub fn finish(self) -> Result<AdAssets> {
+        if !self.errors.is_empty() {
+            return Err(Error::ContextError(self.errors.join("\n")));
+        }
+        Ok(self.assets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = r#"
+        name = "Test"
+
+        [[images]]
+        id = "hero"
+        path = "hero.png"
+        load = "polite"
+
+        [[images]]
+        id = "wide"
+        path = "wide.png"
+        specs = ["728x90"]
+
+        [[svgs]]
+        id = "logo"
+        path = "logo.svg"
+        specs = ["300x250", "728x90"]
+
+        [[fonts]]
+        family = "Brand"
+        path = "fonts/Brand.ttf"
+
+        [[code]]
+        path = "ad.wasm"
+        specs = ["300x250"]
+    "#;
+
+    #[test]
+    fn font_entries_can_be_appended_to_a_manifest() {
+        let font = FontAsset {
+            family: "Brand \"Display\"".to_string(),
+            weight: FontWeight::Bold,
+            path: "fonts/Brand Display-Bold.otf".to_string(),
+            load: LoadPhase::Polite,
+            specs: vec!["300x250".to_string()],
+        };
+        let text = format!("{}{}", MANIFEST, font.to_toml());
+        let manifest = AssetManifest::from_toml(&text).unwrap();
+        assert_eq!(manifest.fonts.len(), 2);
+        let parsed = &manifest.fonts[1];
+        assert_eq!(parsed.family, font.family);
+        assert_eq!(parsed.weight, FontWeight::Bold);
+        assert_eq!(parsed.path, font.path);
+        assert_eq!(parsed.load, LoadPhase::Polite);
+        assert_eq!(parsed.specs, font.specs);
+    }
+
+    #[test]
+    fn files_for_spec_leaves_out_other_sizes() {
+        let manifest = AssetManifest::from_toml(MANIFEST).unwrap();
+        assert_eq!(
+            manifest.files_for_spec("300x250"),
+            vec![
+                ("hero.png", AssetCategory::Image, LoadPhase::Polite),
+                ("logo.svg", AssetCategory::Image, LoadPhase::Initial),
+                ("fonts/Brand.ttf", AssetCategory::Font, LoadPhase::Initial),
+                ("ad.wasm", AssetCategory::Code, LoadPhase::Initial),
+            ]
+        );
+        let paths: Vec<&str> = manifest.files_for_spec("728x90").iter().map(|(path, _, _)| *path).collect();
+        assert_eq!(paths, vec!["hero.png", "wide.png", "logo.svg", "fonts/Brand.ttf"]);
+        let paths: Vec<&str> = manifest.files_for_spec("160x600").iter().map(|(path, _, _)| *path).collect();
+        assert_eq!(paths, vec!["hero.png", "fonts/Brand.ttf"]);
+    }
+
+    #[test]
+    fn paths_leave_out_the_code() {
+        let manifest = AssetManifest::from_toml(MANIFEST).unwrap();
+        assert_eq!(manifest.paths(), vec!["hero.png", "wide.png", "logo.svg", "fonts/Brand.ttf"]);
+    }
+
+    #[test]
+    fn entries_with_the_same_path_share_the_file() {
+        let dir = std::env::temp_dir().join(format!("demo-ads-shared-assets-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("Brand.ttf"), bundled_fonts()[1].1).unwrap();
+        let manifest = AssetManifest::from_toml(
+            r#"
+            name = "Shared"
+
+            [[fonts]]
+            family = "Brand"
+            weight = "bold"
+            path = "Brand.ttf"
+
+            [[fonts]]
+            family = "Headline"
+            path = "Brand.ttf"
+        "#,
+        )
+        .unwrap();
+
+        let mut loader = AssetLoader::new(&dir);
+        loader.enqueue(&manifest);
+        for _ in 0..4 {
+            loader.load_next();
+        }
+        assert!(loader.is_complete());
+        let assets = loader.finish().unwrap();
+        assert!(assets.fonts.contains("Brand", FontWeight::Bold));
+        assert!(assets.fonts.contains("Headline", FontWeight::Regular));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn polite_assets_load_after_the_initial_ones() {
+        let dir = std::env::temp_dir().join(format!("demo-ads-polite-assets-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("Polite.ttf"), bundled_fonts()[0].1).unwrap();
+        fs::write(dir.join("Brand.ttf"), bundled_fonts()[1].1).unwrap();
+        let manifest = AssetManifest::from_toml(
+            r#"
+            name = "Polite"
+
+            [[fonts]]
+            family = "Polite"
+            path = "Polite.ttf"
+            load = "polite"
+
+            [[fonts]]
+            family = "Brand"
+            path = "Brand.ttf"
+        "#,
+        )
+        .unwrap();
+
+        let mut loader = AssetLoader::new(&dir);
+        loader.enqueue(&manifest);
+        assert!(!loader.is_initial_complete());
+        loader.load_next();
+        assert!(loader.is_initial_complete());
+        assert!(!loader.is_complete());
+        assert!(loader.assets_mut().fonts.contains("Brand", FontWeight::Regular));
+        assert!(!loader.assets_mut().fonts.contains("Polite", FontWeight::Regular));
+        loader.load_next();
+        assert!(loader.is_complete());
+        assert!(loader.finish().unwrap().fonts.contains("Polite", FontWeight::Regular));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,277 @@
+/// Right-to-left text for Arabic and Hebrew ad copy
+///
+/// Fonts are drawn one glyph after another from left to right, without shaping. So before a word is measured or
+/// drawn, Arabic letters are replaced with the presentation form for their position in the word, and words that
+/// contain right-to-left letters are reversed into the order that they are seen in. Numbers and Latin text inside
+/// a word keep their order, and brackets are mirrored.
+///
+/// The order of the words on a line is decided by the TextBlock, using is_rtl_word(). This is a simplified form of
+/// the Unicode bidi algorithm, which is enough for short ad copy but not for nested embeddings.
+///
+use std::char;
+
+/// Arabic letters and the first of their presentation forms. Letters that join on both sides have four forms in
+/// the order isolated, final, initial and medial. Letters that only join to the letter before them have isolated
+/// and final forms.
+const ARABIC_FORMS: [(char, u32, Joining); 36] = [
+    ('\u{0621}', 0xFE80, Joining::None),
+    ('\u{0622}', 0xFE81, Joining::Right),
+    ('\u{0623}', 0xFE83, Joining::Right),
+    ('\u{0624}', 0xFE85, Joining::Right),
+    ('\u{0625}', 0xFE87, Joining::Right),
+    ('\u{0626}', 0xFE89, Joining::Dual),
+    ('\u{0627}', 0xFE8D, Joining::Right),
+    ('\u{0628}', 0xFE8F, Joining::Dual),
+    ('\u{0629}', 0xFE93, Joining::Right),
+    ('\u{062A}', 0xFE95, Joining::Dual),
+    ('\u{062B}', 0xFE99, Joining::Dual),
+    ('\u{062C}', 0xFE9D, Joining::Dual),
+    ('\u{062D}', 0xFEA1, Joining::Dual),
+    ('\u{062E}', 0xFEA5, Joining::Dual),
+    ('\u{062F}', 0xFEA9, Joining::Right),
+    ('\u{0630}', 0xFEAB, Joining::Right),
+    ('\u{0631}', 0xFEAD, Joining::Right),
+    ('\u{0632}', 0xFEAF, Joining::Right),
+    ('\u{0633}', 0xFEB1, Joining::Dual),
+    ('\u{0634}', 0xFEB5, Joining::Dual),
+    ('\u{0635}', 0xFEB9, Joining::Dual),
+    ('\u{0636}', 0xFEBD, Joining::Dual),
+    ('\u{0637}', 0xFEC1, Joining::Dual),
+    ('\u{0638}', 0xFEC5, Joining::Dual),
+    ('\u{0639}', 0xFEC9, Joining::Dual),
+    ('\u{063A}', 0xFECD, Joining::Dual),
+    ('\u{0641}', 0xFED1, Joining::Dual),
+    ('\u{0642}', 0xFED5, Joining::Dual),
+    ('\u{0643}', 0xFED9, Joining::Dual),
+    ('\u{0644}', 0xFEDD, Joining::Dual),
+    ('\u{0645}', 0xFEE1, Joining::Dual),
+    ('\u{0646}', 0xFEE5, Joining::Dual),
+    ('\u{0647}', 0xFEE9, Joining::Dual),
+    ('\u{0648}', 0xFEED, Joining::Right),
+    ('\u{0649}', 0xFEEF, Joining::Right),
+    ('\u{064A}', 0xFEF1, Joining::Dual),
+];
+
+/// Lam followed by one of these alefs is drawn as a ligature, which has isolated and final forms
+const LAM: char = '\u{0644}';
+const LAM_ALEF_FORMS: [(char, u32); 4] =
+    [('\u{0622}', 0xFEF5), ('\u{0623}', 0xFEF7), ('\u{0625}', 0xFEF9), ('\u{0627}', 0xFEFB)];
+
+/// The kashida that stretches a joined word. It joins on both sides and has no forms.
+const TATWEEL: char = '\u{0640}';
+
+const MIRRORED: [(char, char); 6] = [('(', ')'), (')', '('), ('[', ']'), (']', '['), ('«', '»'), ('»', '«')];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Joining {
+    None,
+    /// Joins to the letter before it only
+    Right,
+    Dual,
+}
+
+pub fn is_rtl_char(c: char) -> bool {
+    matches!(c as u32, 0x0590..=0x08FF | 0xFB1D..=0xFDFF | 0xFE70..=0xFEFF)
+}
+
+pub fn is_rtl_word(word: &str) -> bool {
+    word.chars().any(is_rtl_char)
+}
+
+/// Vowel marks and other characters that sit on a letter and do not affect joining
+fn is_transparent(c: char) -> bool {
+    matches!(c as u32, 0x0610..=0x061A | 0x064B..=0x065F | 0x0670 | 0x06D6..=0x06ED)
+}
+
+fn is_digit(c: char) -> bool {
+    c.is_ascii_digit() || ('\u{0660}'..='\u{0669}').contains(&c)
+}
+
+fn joining(c: char) -> Option<(u32, Joining)> {
+    if c == TATWEEL {
+        return Some((TATWEEL as u32, Joining::Dual));
+    }
+    ARABIC_FORMS.iter().find(|(letter, _, _)| *letter == c).map(|(_, base, joining)| (*base, *joining))
+}
+
+/// Replace Arabic letters with the presentation forms for their position, in logical order
+pub fn shape_arabic(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    // The letters that a letter can join to, skipping over vowel marks
+    let prev_letter = |i: usize| chars[..i].iter().rev().find(|c| !is_transparent(**c)).copied();
+    let next_letter = |i: usize| chars[i + 1..].iter().find(|c| !is_transparent(**c)).copied();
+    let joins_next = |c: Option<char>| matches!(c.and_then(joining), Some((_, Joining::Dual)));
+    let joins_prev = |c: Option<char>| !matches!(c.and_then(joining), None | Some((_, Joining::None)));
+
+    let mut shaped = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let joined_before = joins_next(prev_letter(i));
+        if c == LAM {
+            // Vowel marks between the lam and the alef are kept after the ligature
+            let alef = (i + 1..chars.len()).find(|j| !is_transparent(chars[*j]));
+            let ligature = alef.and_then(|j| {
+                let (_, base) = LAM_ALEF_FORMS.iter().find(|(alef, _)| *alef == chars[j])?;
+                Some((j, base))
+            });
+            if let Some((j, base)) = ligature {
+                shaped.extend(char::from_u32(base + joined_before as u32));
+                shaped.extend(chars[i + 1..j].iter());
+                i = j + 1;
+                continue;
+            }
+        }
+        let form = match joining(c) {
+            Some((_, _)) if c == TATWEEL => c as u32,
+            Some((base, Joining::None)) => base,
+            Some((base, Joining::Right)) => base + joined_before as u32,
+            Some((base, Joining::Dual)) => match (joined_before, joins_prev(next_letter(i))) {
+                (false, false) => base,
+                (true, false) => base + 1,
+                (false, true) => base + 2,
+                (true, true) => base + 3,
+            },
+            None => c as u32,
+        };
+        shaped.extend(char::from_u32(form));
+        i += 1;
+    }
+    shaped
+}
+
+/// The word as it is drawn from left to right. Words without right-to-left letters are returned unchanged.
+pub fn visual_word(word: &str) -> String {
+    if !is_rtl_word(word) {
+        return word.to_string();
+    }
+    // Reverse the letters, keeping vowel marks after the letter that they sit on
+    let mut clusters: Vec<Vec<char>> = Vec::new();
+    for c in shape_arabic(word).chars() {
+        match clusters.last_mut() {
+            Some(cluster) if is_transparent(c) => cluster.push(c),
+            _ => clusters.push(vec![c]),
+        }
+    }
+    let mut chars: Vec<char> = clusters.into_iter().rev().flatten().collect();
+    // Put numbers and Latin text back in reading order
+    let is_ltr = |c: char| is_digit(c) || c.is_ascii_alphabetic();
+    let mut start = 0;
+    while start < chars.len() {
+        if !is_ltr(chars[start]) {
+            start += 1;
+            continue;
+        }
+        let end = chars[start..].iter().position(|c| !is_ltr(*c)).map_or(chars.len(), |n| start + n);
+        chars[start..end].reverse();
+        start = end;
+    }
+    chars.into_iter().map(|c| MIRRORED.iter().find(|(from, _)| *from == c).map_or(c, |(_, to)| *to)).collect()
+}
+
+/// Put the words of a right-to-left line in the order that they are drawn from left to right. The line is
+/// reversed, and then each run of left-to-right words is reversed again so that it reads in its own order.
+pub fn reorder_rtl_line<T, F: Fn(&T) -> bool>(words: &mut [T], is_rtl: F) {
+    words.reverse();
+    let mut start = 0;
+    while start < words.len() {
+        let end = words[start..].iter().position(&is_rtl).map_or(words.len(), |n| start + n);
+        words[start..end].reverse();
+        start = end + 1;
+    }
+}
+
+/// A single line of right-to-left text as it is drawn, for controls that draw text without a TextBlock
+pub fn visual_rtl_line(line: &str) -> String {
+    let mut words: Vec<&str> = line.split_whitespace().collect();
+    reorder_rtl_line(&mut words, |word| is_rtl_word(word));
+    words.iter().map(|word| visual_word(word)).collect::<Vec<String>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BEH: char = '\u{0628}';
+    const ALEF: char = '\u{0627}';
+    const FATHA: char = '\u{064E}';
+
+    fn codes(text: &str) -> Vec<u32> {
+        text.chars().map(|c| c as u32).collect()
+    }
+
+    #[test]
+    fn shapes_each_position_of_a_dual_joining_letter() {
+        // Beh alone, then at the start, middle and end of a word
+        assert_eq!(codes(&shape_arabic("\u{0628}")), vec![0xFE8F]);
+        assert_eq!(codes(&shape_arabic("\u{0628}\u{0628}")), vec![0xFE91, 0xFE90]);
+        assert_eq!(codes(&shape_arabic("\u{0628}\u{0628}\u{0628}")), vec![0xFE91, 0xFE92, 0xFE90]);
+    }
+
+    #[test]
+    fn right_joining_letters_break_the_word() {
+        // Alef joins to the beh before it, but the beh after it starts again
+        let text: String = [BEH, ALEF, BEH].iter().collect();
+        assert_eq!(codes(&shape_arabic(&text)), vec![0xFE91, 0xFE8E, 0xFE8F]);
+        // Hamza does not join at all
+        assert_eq!(codes(&shape_arabic("\u{0628}\u{0621}")), vec![0xFE8F, 0xFE80]);
+    }
+
+    #[test]
+    fn lam_alef_is_a_ligature() {
+        assert_eq!(codes(&shape_arabic("\u{0644}\u{0627}")), vec![0xFEFB]);
+        assert_eq!(codes(&shape_arabic("\u{0628}\u{0644}\u{0627}")), vec![0xFE91, 0xFEFC]);
+        assert_eq!(codes(&shape_arabic("\u{0644}\u{0623}")), vec![0xFEF7]);
+    }
+
+    #[test]
+    fn lam_alef_skips_vowel_marks() {
+        let text: String = [LAM, FATHA, ALEF].iter().collect();
+        assert_eq!(codes(&shape_arabic(&text)), vec![0xFEFB, FATHA as u32]);
+        let text: String = [BEH, LAM, FATHA, ALEF].iter().collect();
+        assert_eq!(codes(&shape_arabic(&text)), vec![0xFE91, 0xFEFC, FATHA as u32]);
+    }
+
+    #[test]
+    fn vowel_marks_do_not_break_joining() {
+        let text: String = [BEH, FATHA, BEH].iter().collect();
+        assert_eq!(codes(&shape_arabic(&text)), vec![0xFE91, FATHA as u32, 0xFE90]);
+        // In the visual order, the mark stays after the letter that it sits on
+        assert_eq!(codes(&visual_word(&text)), vec![0xFE90, 0xFE91, FATHA as u32]);
+    }
+
+    #[test]
+    fn tatweel_joins_on_both_sides() {
+        let text: String = [BEH, TATWEEL, BEH].iter().collect();
+        assert_eq!(codes(&shape_arabic(&text)), vec![0xFE91, TATWEEL as u32, 0xFE90]);
+    }
+
+    #[test]
+    fn visual_words_keep_numbers_and_latin_in_reading_order() {
+        assert_eq!(visual_word("Teapot"), "Teapot");
+        let text: String = [BEH, '1', '2', 'a', 'b', BEH].iter().collect();
+        let expected: String = ['\u{FE8F}', '1', '2', 'a', 'b', '\u{FE8F}'].iter().collect();
+        assert_eq!(visual_word(&text), expected);
+        let text: String = ['(', BEH, ')'].iter().collect();
+        assert_eq!(visual_word(&text), "(\u{FE8F})");
+    }
+
+    #[test]
+    fn reorders_mixed_lines() {
+        let beh = BEH.to_string();
+        let line = format!("{} Teapot 2026 {}{}", beh, beh, beh);
+        assert_eq!(visual_rtl_line(&line), "\u{FE90}\u{FE91} Teapot 2026 \u{FE8F}");
+        assert_eq!(visual_rtl_line("Teapot  Co"), "Teapot Co");
+
+        let mut words = vec!["a", "B", "c", "d", "E"];
+        reorder_rtl_line(&mut words, |word| word.chars().all(|c| c.is_uppercase()));
+        assert_eq!(words, vec!["E", "c", "d", "B", "a"]);
+    }
+
+    #[test]
+    fn detects_rtl_text() {
+        assert!(is_rtl_word("\u{05E9}\u{05DC}\u{05D5}\u{05DD}"));
+        assert!(is_rtl_word("a\u{0628}"));
+        assert!(!is_rtl_word("Teapot 2026"));
+    }
+}
@@ -0,0 +1,375 @@
+/// Brand kits for re-skinning a creative per advertiser
+///
+/// A brand kit is a TOML file with named tokens for colors, fonts, corner radii and button styles. Ad builders
+/// should ask the kit for a token like "primary" instead of hardcoding a color, so that the same creative can
+/// be rendered with a different kit. The [theme] section of a kit is applied to the Tweek Theme at startup.
+///
+/// Example:
+///
+/// ```toml
+/// name = "Teapot"
+/// font_folder = "teapot/fonts"
+///
+/// [theme]
+/// bg_color = "#FFFFEE"
+/// font_size = 18.0
+///
+/// [palette]
+/// primary = "#003300"
+///
+/// [fonts]
+/// headline = { family = "Roboto", weight = "bold", size = 68.0 }
+///
+/// [radii]
+/// button = 3.0
+///
+/// [buttons]
+/// cta = { fill = "primary", text_color = "#FFFFFF", font = "headline", radius = "button" }
+/// ```
+///
+use super::*;
+
+use tweek::gui::*;
+
+use quicksilver::{geom::Rectangle, graphics::Color, Error, Result};
+
+use serde::Deserialize;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// The kit loaded at startup when no other kit is specified, relative to the asset root
+pub const DEFAULT_BRAND_KIT: &str = "teapot/brand.toml";
+
+/// Color used for tokens that are not in the palette, so that they stand out in the viewer
+const MISSING_TOKEN_COLOR: &str = "#FF00FF";
+
+/// Parse a color in #RRGGBB or #RRGGBBAA format
+pub fn parse_hex_color(hex: &str) -> Result<Color> {
+    let digits = hex.trim_start_matches('#');
+    let valid = (digits.len() == 6 || digits.len() == 8) && digits.chars().all(|c| c.is_ascii_hexdigit());
+    if !hex.starts_with('#') || !valid {
+        return Err(Error::ContextError(format!("Invalid color: {}", hex)));
+    }
+    Ok(Color::from_hex(hex))
+}
+
+/// Values that are copied into the Tweek Theme
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ThemeTokens {
+    pub bg_color: Option<String>,
+    pub font_size: Option<f32>,
+}
+
+/// A named font definition
+#[derive(Clone, Debug, Deserialize)]
+pub struct FontToken {
+    #[serde(default = "default_family")]
+    pub family: String,
+    #[serde(default = "default_weight")]
+    pub weight: FontWeight,
+    pub size: f32,
+}
+
+fn default_family() -> String {
+    DEFAULT_FAMILY.to_string()
+}
+
+fn default_weight() -> FontWeight {
+    FontWeight::Regular
+}
+
+/// A named button style. Color, font and radius values can either be literal values or names of other tokens.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ButtonStyle {
+    pub fill: Option<String>,
+    pub text_color: Option<String>,
+    pub border_color: Option<String>,
+    pub border_width: Option<f32>,
+    pub font: Option<String>,
+    pub radius: Option<String>,
+}
+
+/// A button style with its tokens looked up, for drawing a button the same way with or without tweek
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedButtonStyle {
+    pub fill: Option<Color>,
+    /// The border color and width
+    pub border: Option<(Color, f32)>,
+    pub text_color: Color,
+    pub font_size: f32,
+    pub radius: Option<f32>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct BrandKit {
+    pub name: String,
+    /// Folder of brand fonts, relative to the static folder
+    pub font_folder: Option<String>,
+    #[serde(default)]
+    pub theme: ThemeTokens,
+    #[serde(default)]
+    pub palette: BTreeMap<String, String>,
+    #[serde(default)]
+    pub fonts: BTreeMap<String, FontToken>,
+    #[serde(default)]
+    pub radii: BTreeMap<String, f32>,
+    #[serde(default)]
+    pub buttons: BTreeMap<String, ButtonStyle>,
+}
+
+impl BrandKit {
+    /// Parse a kit from a TOML string and validate all colors
+    pub fn from_toml(text: &str) -> Result<BrandKit> {
+        let kit: BrandKit =
+            toml::from_str(text).map_err(|e| Error::ContextError(format!("Invalid brand kit: {}", e)))?;
+        for (token, value) in &kit.palette {
+            parse_hex_color(value).map_err(|e| Error::ContextError(format!("Palette token {}: {:?}", token, e)))?;
+        }
+        if let Some(bg_color) = &kit.theme.bg_color {
+            kit.resolve_color(bg_color)?;
+        }
+        Ok(kit)
+    }
+
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<BrandKit> {
+        let text = fs::read_to_string(path.as_ref())?;
+        let kit = BrandKit::from_toml(&text)?;
+        log::debug!("Loaded brand kit {} from {:?}", kit.name, path.as_ref());
+        Ok(kit)
+    }
+
+    /// A copy of the kit with palette tokens replaced or added. Values are hex colors or tokens of this palette.
+    pub fn with_palette(&self, colors: &BTreeMap<String, String>) -> Result<BrandKit> {
+        let mut kit = self.clone();
+        for (token, value) in colors {
+            let hex = match self.palette.get(value) {
+                Some(hex) => hex.clone(),
+                None => value.clone(),
+            };
+            parse_hex_color(&hex).map_err(|e| Error::ContextError(format!("Palette token {}: {:?}", token, e)))?;
+            kit.palette.insert(token.clone(), hex);
+        }
+        Ok(kit)
+    }
+
+    /// Copy the theme tokens into the Theme
+    pub fn apply_to_theme(&self, theme: &mut Theme) {
+        if let Some(font_size) = self.theme.font_size {
+            theme.font_size = font_size;
+        }
+        if let Some(bg_color) = &self.theme.bg_color {
+            theme.bg_color = self.color(bg_color);
+        }
+    }
+
+    /// Register the fonts in the kit's font folder. The static_root is the folder that font_folder is
+    /// relative to.
+    pub fn load_fonts<P: AsRef<Path>>(&self, static_root: P, registry: &mut FontRegistry) -> Result<usize> {
+        match &self.font_folder {
+            Some(folder) => registry.load_folder(static_root.as_ref().join(folder)),
+            None => Ok(0),
+        }
+    }
+
+    /// The .ttf and .otf files in the kit's font folder by name, relative to the static folder
+    pub fn font_files<P: AsRef<Path>>(&self, static_root: P) -> Result<Vec<String>> {
+        let folder = match &self.font_folder {
+            Some(folder) => folder.trim_end_matches('/'),
+            None => return Ok(Vec::new()),
+        };
+        let mut files = Vec::new();
+        for entry in fs::read_dir(static_root.as_ref().join(folder))? {
+            let name = entry?.file_name().to_string_lossy().to_string();
+            let ext = Path::new(&name).extension().and_then(|s| s.to_str()).unwrap_or("").to_lowercase();
+            if ext == "ttf" || ext == "otf" {
+                files.push(format!("{}/{}", folder, name));
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// Manifest entries for the fonts in the kit's font folder, with the family and weight taken from the file names
+    /// like in load_fonts
+    pub fn font_assets<P: AsRef<Path>>(&self, static_root: P) -> Result<Vec<FontAsset>> {
+        let mut assets = Vec::new();
+        for path in self.font_files(static_root)? {
+            if let Some((family, weight)) = font_file_style(&path) {
+                assets.push(FontAsset { family, weight, path, load: LoadPhase::Initial, specs: Vec::new() });
+            }
+        }
+        Ok(assets)
+    }
+
+    /// A value is either a palette token or a literal hex color
+    fn resolve_color(&self, value: &str) -> Result<Color> {
+        match self.palette.get(value) {
+            Some(hex) => parse_hex_color(hex),
+            None => parse_hex_color(value),
+        }
+    }
+
+    /// Get a color by token name, or a literal hex color. Unknown tokens are logged and drawn in magenta.
+    pub fn color(&self, token: &str) -> Color {
+        self.resolve_color(token).unwrap_or_else(|_| {
+            log::warn!("Brand kit {} has no color token {:?}", self.name, token);
+            Color::from_hex(MISSING_TOKEN_COLOR)
+        })
+    }
+
+    /// Get a font by token name. Unknown tokens use the default family at the theme font size.
+    pub fn font(&self, token: &str) -> FontToken {
+        self.fonts.get(token).cloned().unwrap_or_else(|| {
+            log::warn!("Brand kit {} has no font token {:?}", self.name, token);
+            FontToken { family: default_family(), weight: default_weight(), size: self.theme.font_size.unwrap_or(18.0) }
+        })
+    }
+
+    /// Get a corner radius by token name, or a literal number. Unknown tokens are 0.0
+    pub fn radius(&self, token: &str) -> f32 {
+        match self.radii.get(token) {
+            Some(radius) => *radius,
+            None => token.parse::<f32>().unwrap_or_else(|_| {
+                log::warn!("Brand kit {} has no radius token {:?}", self.name, token);
+                0.0
+            }),
+        }
+    }
+
+    /// The font of text on a button with the named style. A style without a font uses the default family at the
+    /// theme font size, like a Button.
+    pub fn button_font(&self, token: &str) -> FontToken {
+        match self.buttons.get(token).and_then(|style| style.font.as_ref()) {
+            Some(font) => self.font(font),
+            None => FontToken {
+                family: default_family(),
+                weight: default_weight(),
+                size: self.theme.font_size.unwrap_or(18.0),
+            },
+        }
+    }
+
+    /// The font size of text on a button with the named style
+    pub fn button_font_size(&self, token: &str) -> f32 {
+        self.button_font(token).size
+    }
+
+    /// Look up the tokens of the named button style. Returns None and logs a warning if there is no such style.
+    pub fn button_style(&self, token: &str) -> Option<ResolvedButtonStyle> {
+        let style = match self.buttons.get(token) {
+            Some(style) => style,
+            None => {
+                log::warn!("Brand kit {} has no button token {:?}", self.name, token);
+                return None;
+            }
+        };
+        let font_size = self.button_font_size(token);
+        Some(ResolvedButtonStyle {
+            fill: style.fill.as_ref().map(|fill| self.color(fill)),
+            border: style.border_color.as_ref().map(|color| (self.color(color), style.border_width.unwrap_or(1.0))),
+            text_color: style.text_color.as_ref().map(|c| self.color(c)).unwrap_or(Color::BLACK),
+            font_size,
+            radius: style.radius.as_ref().map(|radius| self.radius(radius)),
+        })
+    }
+
+    /// Style a Button using the named button style, with the text drawn in the style's font
+    pub fn apply_button_style(&self, token: &str, text: &str, button: &mut Button, fonts: &mut FontRegistry) {
+        if let Some(style) = self.button_style(token) {
+            if let Some(fill) = style.fill {
+                button.layer.bg_style = BackgroundStyle::Solid(fill);
+            }
+            if let Some((color, width)) = style.border {
+                button.layer.border_style = BorderStyle::SolidLine(color, width);
+            }
+            button.layer.font_style = FontStyle::new(style.font_size, style.text_color);
+            if let Some(radius) = style.radius {
+                button.layer.corner_radius = radius;
+            }
+        }
+        self.set_button_text(token, text, self.button_font_size(token), button, fonts);
+    }
+
+    /// Put the text on a Button, centered, in the family, weight and text color of the named button style. The font
+    /// is resolved through the FontRegistry like the copy in a TextBlock.
+    pub fn set_button_text(
+        &self,
+        token: &str,
+        text: &str,
+        font_size: f32,
+        button: &mut Button,
+        fonts: &mut FontRegistry,
+    ) {
+        let font = self.button_font(token);
+        let color = match self.buttons.get(token).and_then(|style| style.text_color.as_ref()) {
+            Some(color) => self.color(color),
+            None => Color::BLACK,
+        };
+        let key = fonts.resolve(&font.family, font.weight, text);
+        let (width, height) = fonts.font(&key).measure_text(text, font_size);
+        let frame = &button.layer.frame;
+        let origin = (frame.x() + (frame.width() - width) / 2.0, frame.y() + (frame.height() - height) / 2.0);
+        let mut label = Label::new(Rectangle::new(origin, (width, height)));
+        label.set_text(text);
+        label.display = LabelDisplay::Text;
+        label.set_font(fonts.font(&key).clone());
+        label.layer.font_style = FontStyle::new(font_size, color);
+        label.layer.lock_style = true;
+        button.set_label(label);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KIT: &str = r##"
+        name = "Test"
+
+        [theme]
+        font_size = 16.0
+
+        [palette]
+        primary = "#003300"
+
+        [fonts]
+        headline = { family = "Roboto", weight = "bold", size = 24.0 }
+
+        [buttons]
+        cta = { fill = "primary", text_color = "#FFFFFF", border_color = "#000000", font = "headline", radius = "6" }
+        plain = { border_color = "primary", border_width = 2.0 }
+    "##;
+
+    #[test]
+    fn resolves_button_styles() {
+        let kit = BrandKit::from_toml(KIT).unwrap();
+        let cta = kit.button_style("cta").unwrap();
+        assert_eq!(cta.fill, Some(Color::from_hex("#003300")));
+        assert_eq!(cta.border, Some((Color::from_hex("#000000"), 1.0)));
+        assert_eq!(cta.text_color, Color::from_hex("#FFFFFF"));
+        assert_eq!(cta.font_size, 24.0);
+        assert_eq!(cta.radius, Some(6.0));
+
+        let plain = kit.button_style("plain").unwrap();
+        assert_eq!(plain.fill, None);
+        assert_eq!(plain.border, Some((Color::from_hex("#003300"), 2.0)));
+        assert_eq!(plain.text_color, Color::BLACK);
+        assert_eq!(plain.font_size, 16.0);
+        assert_eq!(plain.radius, None);
+
+        assert_eq!(kit.button_style("missing"), None);
+    }
+
+    #[test]
+    fn button_fonts_fall_back_to_the_theme_size() {
+        let kit = BrandKit::from_toml(KIT).unwrap();
+        assert_eq!(kit.button_font_size("cta"), 24.0);
+        assert_eq!(kit.button_font("cta").family, "Roboto");
+        assert_eq!(kit.button_font_size("plain"), 16.0);
+        assert_eq!(kit.button_font("plain").family, DEFAULT_FAMILY);
+        assert_eq!(kit.button_font_size("missing"), 16.0);
+        assert_eq!(BrandKit::default().button_font_size("cta"), 18.0);
+    }
+}
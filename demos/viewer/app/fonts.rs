@@ -0,0 +1,282 @@
+/// A registry of fonts keyed by family and weight
+///
+/// The Roboto fonts in /static are always registered as the last resort. Brand fonts can be added from
+/// a creative's asset folder, where the family and weight are taken from the file name. For example,
+/// "Montserrat-Bold.ttf" is registered as family "Montserrat" and weight Bold.
+///
+/// Each family can have a fallback chain of other families. When resolving a font for a piece of text,
+/// the chain is searched for the first font that has glyphs for every character. If no font covers the
+/// text, a warning is logged once per missing character and the first available font is used anyway.
+///
+use tweek::tools::*;
+
+use quicksilver::{Error, Result};
+
+use rusttype::Font;
+
+use serde::Deserialize;
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+static ROBOTO_REGULAR: &[u8] = include_bytes!("../../static/Roboto-Regular.ttf");
+static ROBOTO_BOLD: &[u8] = include_bytes!("../../static/Roboto-Bold.ttf");
+
+/// The font family that is always available
+pub const DEFAULT_FAMILY: &str = "Roboto";
+
+/// The bundled fonts and their file names in /static. They are compiled into the build instead of being loaded.
+pub fn bundled_fonts() -> [(&'static str, &'static [u8]); 2] {
+    [("Roboto-Regular.ttf", ROBOTO_REGULAR), ("Roboto-Bold.ttf", ROBOTO_BOLD)]
+}
+
+/// The family and weight of a .ttf or .otf file, parsed from a file name like Family-Weight. The weight is optional
+/// and defaults to Regular. Returns None for other files.
+pub fn font_file_style<P: AsRef<Path>>(path: P) -> Option<(String, FontWeight)> {
+    let path = path.as_ref();
+    let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("").to_lowercase();
+    if ext != "ttf" && ext != "otf" {
+        return None;
+    }
+    let stem = path.file_stem().and_then(|s| s.to_str())?;
+    match stem.rfind('-') {
+        Some(idx) => match FontWeight::from_name(&stem[idx + 1..]) {
+            Some(weight) => Some((stem[..idx].to_string(), weight)),
+            None => Some((stem.to_string(), FontWeight::Regular)),
+        },
+        None => Some((stem.to_string(), FontWeight::Regular)),
+    }
+}
+
+/// Supported font weights. When a weight is missing from a family, Regular is used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FontWeight {
+    Light,
+    Regular,
+    Medium,
+    Bold,
+}
+
+impl FontWeight {
+    /// Parse the weight names commonly used in font file names
+    pub fn from_name(name: &str) -> Option<FontWeight> {
+        match name.to_lowercase().as_str() {
+            "light" | "300" => Some(FontWeight::Light),
+            "regular" | "normal" | "book" | "400" => Some(FontWeight::Regular),
+            "medium" | "500" => Some(FontWeight::Medium),
+            "bold" | "700" => Some(FontWeight::Bold),
+            _ => None,
+        }
+    }
+
+    /// The name used in TOML files
+    pub fn name(&self) -> &'static str {
+        match self {
+            FontWeight::Light => "light",
+            FontWeight::Regular => "regular",
+            FontWeight::Medium => "medium",
+            FontWeight::Bold => "bold",
+        }
+    }
+}
+
+/// Lookup key for a registered font. Family names are matched case-insensitively.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FontKey {
+    pub family: String,
+    pub weight: FontWeight,
+}
+
+impl FontKey {
+    pub fn new(family: &str, weight: FontWeight) -> Self {
+        FontKey { family: family.to_lowercase(), weight }
+    }
+}
+
+/// A font used for drawing along with the parsed font that is used for checking glyph coverage
+struct FontEntry {
+    draw_font: DrawFont,
+    glyphs: Font<'static>,
+}
+
+impl FontEntry {
+    fn has_glyph(&self, c: char) -> bool {
+        c.is_whitespace() || self.glyphs.glyph(c).id().0 != 0
+    }
+}
+
+pub struct FontRegistry {
+    fonts: HashMap<FontKey, FontEntry>,
+    fallbacks: HashMap<String, Vec<String>>,
+    /// Characters that have already been reported as missing, so that each is only logged once.
+    warned: HashSet<(String, char)>,
+}
+
+impl FontRegistry {
+    /// Constructor with only the bundled fonts registered
+    pub fn new() -> Self {
+        let mut registry = FontRegistry { fonts: HashMap::new(), fallbacks: HashMap::new(), warned: HashSet::new() };
+        registry
+            .register_bytes(DEFAULT_FAMILY, FontWeight::Regular, ROBOTO_REGULAR.to_vec())
+            .expect("Bundled regular font is invalid");
+        registry
+            .register_bytes(DEFAULT_FAMILY, FontWeight::Bold, ROBOTO_BOLD.to_vec())
+            .expect("Bundled bold font is invalid");
+        registry
+    }
+
+    /// Register a font from TTF or OTF data
+    pub fn register_bytes(&mut self, family: &str, weight: FontWeight, data: Vec<u8>) -> Result<()> {
+        let glyphs = Font::from_bytes(data.clone())
+            .map_err(|e| Error::ContextError(format!("Invalid font data for {} {:?}: {}", family, weight, e)))?;
+        let draw_font = DrawFont::from_bytes(data, None);
+        self.fonts.insert(FontKey::new(family, weight), FontEntry { draw_font, glyphs });
+        Ok(())
+    }
+
+    /// Register a font from a TTF or OTF file
+    pub fn register_file<P: AsRef<Path>>(&mut self, family: &str, weight: FontWeight, path: P) -> Result<()> {
+        let data = fs::read(path.as_ref())?;
+        log::debug!("Loaded font {} {:?} from {:?}", family, weight, path.as_ref());
+        self.register_bytes(family, weight, data)
+    }
+
+    /// Register every .ttf and .otf file in the folder. The file name is parsed as Family-Weight, where the
+    /// weight is optional and defaults to Regular. Files that cannot be read or parsed are logged and skipped.
+    /// Returns the number of fonts registered.
+    pub fn load_folder<P: AsRef<Path>>(&mut self, dir: P) -> Result<usize> {
+        let mut count = 0;
+        for entry in fs::read_dir(dir.as_ref())? {
+            let path = match entry {
+                Ok(entry) => entry.path(),
+                Err(e) => {
+                    log::warn!("Skipped an entry in font folder {:?}: {}", dir.as_ref(), e);
+                    continue;
+                }
+            };
+            let (family, weight) = match font_file_style(&path) {
+                Some(style) => style,
+                None => continue,
+            };
+            match self.register_file(&family, weight, &path) {
+                Ok(()) => count += 1,
+                Err(e) => log::warn!("Skipped font file {:?}: {:?}", path, e),
+            }
+        }
+        Ok(count)
+    }
+
+    /// Set the families to try, in order, when the specified family is missing a font or glyph.
+    /// The DEFAULT_FAMILY is always tried last and does not need to be included.
+    pub fn set_fallbacks(&mut self, family: &str, chain: &[&str]) {
+        let chain = chain.iter().map(|s| s.to_lowercase()).collect();
+        self.fallbacks.insert(family.to_lowercase(), chain);
+    }
+
+    pub fn contains(&self, family: &str, weight: FontWeight) -> bool {
+        self.fonts.contains_key(&FontKey::new(family, weight))
+    }
+
+    /// The keys to try for the family and weight, in order of preference
+    fn candidates(&self, family: &str, weight: FontWeight) -> Vec<FontKey> {
+        let family = family.to_lowercase();
+        let mut families = vec![family.clone()];
+        if let Some(chain) = self.fallbacks.get(&family) {
+            families.extend(chain.iter().cloned());
+        }
+        families.push(DEFAULT_FAMILY.to_lowercase());
+
+        let mut keys: Vec<FontKey> = Vec::new();
+        for family in families {
+            for weight in &[weight, FontWeight::Regular] {
+                let key = FontKey { family: family.clone(), weight: *weight };
+                if self.fonts.contains_key(&key) && !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+        }
+        keys
+    }
+
+    /// Find the best font for drawing the text in the requested family and weight
+    pub fn resolve(&mut self, family: &str, weight: FontWeight, text: &str) -> FontKey {
+        let keys = self.candidates(family, weight);
+        for key in &keys {
+            let entry = &self.fonts[key];
+            if text.chars().all(|c| entry.has_glyph(c)) {
+                return key.clone();
+            }
+        }
+        let key = keys[0].clone();
+        let entry = &self.fonts[&key];
+        for c in text.chars().filter(|c| !entry.has_glyph(*c)) {
+            if self.warned.insert((family.to_lowercase(), c)) {
+                log::warn!("Missing glyph {:?} (U+{:04X}) in font family {} and its fallbacks", c, c as u32, family);
+            }
+        }
+        key
+    }
+
+    /// Get the DrawFont for a key returned by resolve(). Unknown keys return the default font.
+    pub fn font(&mut self, key: &FontKey) -> &mut DrawFont {
+        if !self.fonts.contains_key(key) {
+            let key = FontKey::new(DEFAULT_FAMILY, FontWeight::Regular);
+            return &mut self.fonts.get_mut(&key).unwrap().draw_font;
+        }
+        &mut self.fonts.get_mut(key).unwrap().draw_font
+    }
+
+    /// Get the parsed font for a key returned by resolve(), for drawing glyphs without a window. Unknown keys
+    /// return the default font.
+    pub fn glyphs(&self, key: &FontKey) -> &Font<'static> {
+        match self.fonts.get(key) {
+            Some(entry) => &entry.glyphs,
+            None => &self.fonts[&FontKey::new(DEFAULT_FAMILY, FontWeight::Regular)].glyphs,
+        }
+    }
+}
+
+impl Default for FontRegistry {
+    fn default() -> Self {
+        FontRegistry::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("demo-ads-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn load_folder_skips_invalid_fonts() {
+        let dir = temp_dir("fonts");
+        fs::write(dir.join("Broken-Bold.ttf"), b"not a font").unwrap();
+        fs::write(dir.join("Brand-Bold.ttf"), ROBOTO_BOLD).unwrap();
+        fs::write(dir.join("Brand.otf"), ROBOTO_REGULAR).unwrap();
+        fs::write(dir.join("notes.txt"), b"ignored").unwrap();
+
+        let mut fonts = FontRegistry::new();
+        assert_eq!(fonts.load_folder(&dir).unwrap(), 2);
+        assert!(fonts.contains("brand", FontWeight::Bold));
+        assert!(fonts.contains("Brand", FontWeight::Regular));
+        assert!(!fonts.contains("Broken", FontWeight::Bold));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn weight_names() {
+        assert_eq!(FontWeight::from_name("Bold"), Some(FontWeight::Bold));
+        assert_eq!(FontWeight::from_name("400"), Some(FontWeight::Regular));
+        assert_eq!(FontWeight::from_name("Italic"), None);
+    }
+}
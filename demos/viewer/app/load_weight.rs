@@ -0,0 +1,262 @@
+/// Initial and polite load weight of a creative
+///
+/// Ad servers cap the bytes that an ad loads before it is shown, which is the initial load, and allow more to be
+/// loaded politely after the publisher's page has finished loading. Each file in an asset manifest is weighed as
+/// it is served, compressed with gzip, and counted in the phase that its `load` value sets. Files that list
+/// `specs` only count at those sizes, so the weight is measured per AdSpec. The brand kit's fonts are added to the
+/// initial load.
+///
+use super::*;
+
+use flate2::{write::GzEncoder, Compression};
+
+use quicksilver::{Error, Result};
+
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AssetCategory {
+    /// Images and SVGs
+    Image,
+    Font,
+    Code,
+}
+
+impl AssetCategory {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AssetCategory::Image => "image",
+            AssetCategory::Font => "font",
+            AssetCategory::Code => "code",
+        }
+    }
+}
+
+const CATEGORIES: [AssetCategory; 3] = [AssetCategory::Image, AssetCategory::Font, AssetCategory::Code];
+
+/// The size of data after gzip compression, which is how servers usually send it
+pub fn gzip_size(data: &[u8]) -> Result<u64> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?.len() as u64)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AssetWeight {
+    pub path: String,
+    pub category: AssetCategory,
+    pub phase: LoadPhase,
+    pub bytes: u64,
+    pub compressed_bytes: u64,
+}
+
+/// The weight of every file that a creative loads at one size
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoadWeight {
+    pub name: String,
+    pub spec_id: String,
+    pub assets: Vec<AssetWeight>,
+}
+
+impl LoadWeight {
+    pub fn new(name: &str, spec_id: &str) -> Self {
+        LoadWeight { name: name.to_string(), spec_id: spec_id.to_string(), assets: Vec::new() }
+    }
+
+    /// Weigh the files in the manifest that are used at the AdSpec. Paths are relative to the asset root.
+    pub fn measure<P: AsRef<Path>>(manifest: &AssetManifest, root: P, spec_id: &str) -> Result<LoadWeight> {
+        let mut weight = LoadWeight::new(&manifest.name, spec_id);
+        for (path, category, phase) in manifest.files_for_spec(spec_id) {
+            weight.add_file(root.as_ref(), path, category, phase)?;
+        }
+        Ok(weight)
+    }
+
+    /// Weigh a file. The path is relative to the root.
+    pub fn add_file<P: AsRef<Path>>(
+        &mut self,
+        root: P,
+        path: &str,
+        category: AssetCategory,
+        phase: LoadPhase,
+    ) -> Result<()> {
+        let data = fs::read(root.as_ref().join(path))
+            .map_err(|e| Error::ContextError(format!("Cannot weigh {}: {}", path, e)))?;
+        self.add_data(path, category, phase, &data)
+    }
+
+    /// Weigh data that is served at the path
+    pub fn add_data(&mut self, path: &str, category: AssetCategory, phase: LoadPhase, data: &[u8]) -> Result<()> {
+        let (bytes, compressed_bytes) = (data.len() as u64, gzip_size(data)?);
+        self.assets.push(AssetWeight { path: path.to_string(), category, phase, bytes, compressed_bytes });
+        Ok(())
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.assets.iter().any(|asset| asset.path == path)
+    }
+
+    /// Add the fonts that the brand kit draws text with, which are the bundled fonts and the files in its font
+    /// folder. Text is drawn before the ad is shown, so they are in the initial load. Fonts that the manifest
+    /// already lists are not weighed twice. The root is the folder that the font folder is relative to.
+    pub fn with_brand_fonts<P: AsRef<Path>>(mut self, kit: &BrandKit, root: P) -> Result<LoadWeight> {
+        for (path, data) in bundled_fonts().iter() {
+            if !self.contains(path) {
+                self.add_data(path, AssetCategory::Font, LoadPhase::Initial, data)?;
+            }
+        }
+        for path in kit.font_files(root.as_ref())? {
+            if !self.contains(&path) {
+                self.add_file(root.as_ref(), &path, AssetCategory::Font, LoadPhase::Initial)?;
+            }
+        }
+        Ok(self)
+    }
+
+    /// Compressed bytes of the files in the phase
+    pub fn total(&self, phase: LoadPhase) -> u64 {
+        self.assets.iter().filter(|asset| asset.phase == phase).map(|asset| asset.compressed_bytes).sum()
+    }
+
+    /// Compressed bytes of the files in the phase and category
+    pub fn category_total(&self, phase: LoadPhase, category: AssetCategory) -> u64 {
+        let assets = self.assets.iter().filter(|asset| asset.phase == phase && asset.category == category);
+        assets.map(|asset| asset.compressed_bytes).sum()
+    }
+}
+
+/// A LoadWeight compared to the budgets for its size, in kilobytes
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoadWeightReport {
+    pub weight: LoadWeight,
+    pub initial_budget_kb: f64,
+    pub polite_budget_kb: f64,
+}
+
+impl LoadWeightReport {
+    pub fn new(weight: LoadWeight, initial_budget_kb: f64, polite_budget_kb: f64) -> Self {
+        LoadWeightReport { weight, initial_budget_kb, polite_budget_kb }
+    }
+
+    fn budget_kb(&self, phase: LoadPhase) -> f64 {
+        match phase {
+            LoadPhase::Initial => self.initial_budget_kb,
+            LoadPhase::Polite => self.polite_budget_kb,
+        }
+    }
+
+    pub fn is_over_budget(&self, phase: LoadPhase) -> bool {
+        kb(self.weight.total(phase)) > self.budget_kb(phase)
+    }
+
+    pub fn is_within_budgets(&self) -> bool {
+        !self.is_over_budget(LoadPhase::Initial) && !self.is_over_budget(LoadPhase::Polite)
+    }
+}
+
+impl fmt::Display for LoadWeightReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let weight = &self.weight;
+        writeln!(f, "{} at {}", weight.name, weight.spec_id)?;
+        for asset in weight.assets.iter() {
+            writeln!(
+                f,
+                "  {:<8} {:<6} {:>8.1}KB {:>8.1}KB gz  {}",
+                asset.phase.name(),
+                asset.category.name(),
+                kb(asset.bytes),
+                kb(asset.compressed_bytes),
+                asset.path
+            )?;
+        }
+        for phase in [LoadPhase::Initial, LoadPhase::Polite].iter() {
+            let categories: Vec<String> = CATEGORIES
+                .iter()
+                .map(|category| format!("{} {:.1}KB", category.name(), kb(weight.category_total(*phase, *category))))
+                .collect();
+            let status = if self.is_over_budget(*phase) { "OVER" } else { "OK" };
+            writeln!(
+                f,
+                "  {:<4} {:<8} {:.1}KB of {:.1}KB ({})",
+                status,
+                phase.name(),
+                kb(weight.total(*phase)),
+                self.budget_kb(*phase),
+                categories.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn kb(bytes: u64) -> f64 {
+    bytes as f64 / 1024.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weight() -> LoadWeight {
+        let mut weight = LoadWeight::new("Test", "300x250");
+        weight.add_data("hero.png", AssetCategory::Image, LoadPhase::Polite, &[7; 2000]).unwrap();
+        weight.add_data("logo.svg", AssetCategory::Image, LoadPhase::Initial, b"<svg></svg>").unwrap();
+        weight.add_data("ad.wasm", AssetCategory::Code, LoadPhase::Initial, &[0; 5000]).unwrap();
+        weight
+    }
+
+    fn compressed(path: &str, weight: &LoadWeight) -> u64 {
+        weight.assets.iter().find(|asset| asset.path == path).unwrap().compressed_bytes
+    }
+
+    #[test]
+    fn totals_are_compressed_bytes_by_phase_and_category() {
+        let weight = weight();
+        let hero = compressed("hero.png", &weight);
+        let logo = compressed("logo.svg", &weight);
+        let wasm = compressed("ad.wasm", &weight);
+        assert!(wasm < 5000);
+        assert_eq!(weight.total(LoadPhase::Initial), logo + wasm);
+        assert_eq!(weight.total(LoadPhase::Polite), hero);
+        assert_eq!(weight.category_total(LoadPhase::Initial, AssetCategory::Image), logo);
+        assert_eq!(weight.category_total(LoadPhase::Initial, AssetCategory::Code), wasm);
+        assert_eq!(weight.category_total(LoadPhase::Polite, AssetCategory::Image), hero);
+        assert_eq!(weight.category_total(LoadPhase::Polite, AssetCategory::Font), 0);
+    }
+
+    #[test]
+    fn reports_the_phases_over_budget() {
+        let wasm = compressed("ad.wasm", &weight());
+        let report = LoadWeightReport::new(weight(), 1024.0, 0.01);
+        assert!(!report.is_over_budget(LoadPhase::Initial));
+        assert!(report.is_over_budget(LoadPhase::Polite));
+        assert!(!report.is_within_budgets());
+        assert!(LoadWeightReport::new(weight(), kb(wasm), 1024.0).is_over_budget(LoadPhase::Initial));
+    }
+
+    #[test]
+    fn weighs_the_brand_fonts_once() {
+        let dir = std::env::temp_dir().join(format!("demo-ads-load-weight-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("fonts")).unwrap();
+        fs::write(dir.join("fonts/Brand-Bold.ttf"), [1; 300]).unwrap();
+        fs::write(dir.join("fonts/notes.txt"), b"ignored").unwrap();
+        let kit = BrandKit { font_folder: Some("fonts".to_string()), ..BrandKit::default() };
+
+        let mut weight = LoadWeight::new("Test", "300x250");
+        weight.add_data("Roboto-Bold.ttf", AssetCategory::Font, LoadPhase::Polite, &[2; 100]).unwrap();
+        let weight = weight.with_brand_fonts(&kit, &dir).unwrap();
+        let paths: Vec<&str> = weight.assets.iter().map(|asset| asset.path.as_str()).collect();
+        assert_eq!(paths, vec!["Roboto-Bold.ttf", "Roboto-Regular.ttf", "fonts/Brand-Bold.ttf"]);
+        assert_eq!(weight.assets[0].phase, LoadPhase::Polite);
+        assert!(weight.assets[1..].iter().all(|asset| asset.category == AssetCategory::Font));
+        assert!(weight.category_total(LoadPhase::Initial, AssetCategory::Font) > 0);
+
+        let plain = LoadWeight::new("Test", "300x250").with_brand_fonts(&BrandKit::default(), &dir).unwrap();
+        assert_eq!(plain.assets.len(), bundled_fonts().len());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,161 @@
+/// Mock publisher pages for testing ads in page context
+///
+/// A page is a column of blocks that is described in a TOML file. Blocks are stacked from the top with
+/// fixed spacing and centered horizontally. One block is the ad slot, which sets the size of the ad.
+/// The viewport_height is the height of the simulated browser window that scrolls over the page.
+///
+/// Example:
+///
+/// ```toml
+/// name = "Fuzzy Dice"
+/// width = 640
+/// viewport_height = 300
+/// bg_color = "#252627"
+/// text_color = "#A9A9B3"
+///
+/// [[blocks]]
+/// kind = "header"
+/// text = "Fuzzy Dice"
+///
+/// [[blocks]]
+/// kind = "text"
+/// lines = 6
+///
+/// [[blocks]]
+/// kind = "ad_slot"
+/// width = 300
+/// height = 250
+/// ```
+///
+use quicksilver::{
+    geom::{Rectangle, Vector},
+    Error, Result,
+};
+
+use serde::Deserialize;
+
+use std::fs;
+use std::path::Path;
+
+/// The default mock page, relative to the asset root
+pub const DEFAULT_MOCK_PAGE: &str = "pages/publisher.toml";
+
+/// Height of one line in a text block
+pub const PAGE_LINE_H: f32 = 16.0;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PageBlockKind {
+    /// A colored bar with the site name
+    Header,
+    /// Placeholder lines of article text
+    Text,
+    /// A placeholder image
+    Image,
+    /// The slot where the ad is placed
+    AdSlot,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct PageBlock {
+    pub kind: PageBlockKind,
+    #[serde(default)]
+    pub text: String,
+    /// Defaults to the page width
+    pub width: Option<f32>,
+    /// Defaults to a height for the kind of block
+    pub height: Option<f32>,
+    /// Number of lines in a text block
+    pub lines: Option<u32>,
+    pub color: Option<String>,
+}
+
+impl PageBlock {
+    fn size(&self, page_width: f32) -> (f32, f32) {
+        let height = match (self.height, self.kind) {
+            (Some(height), _) => height,
+            (None, PageBlockKind::Header) => 60.0,
+            (None, PageBlockKind::Text) => self.lines.unwrap_or(4) as f32 * PAGE_LINE_H,
+            (None, PageBlockKind::Image) => 160.0,
+            (None, PageBlockKind::AdSlot) => 250.0,
+        };
+        (self.width.unwrap_or(page_width).min(page_width), height)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct MockPage {
+    pub name: String,
+    pub width: f32,
+    pub viewport_height: f32,
+    #[serde(default = "MockPage::default_bg_color")]
+    pub bg_color: String,
+    #[serde(default = "MockPage::default_text_color")]
+    pub text_color: String,
+    #[serde(default = "MockPage::default_spacing")]
+    pub spacing: f32,
+    pub blocks: Vec<PageBlock>,
+}
+
+impl MockPage {
+    fn default_bg_color() -> String {
+        "#FFFFFF".to_string()
+    }
+
+    fn default_text_color() -> String {
+        "#333333".to_string()
+    }
+
+    fn default_spacing() -> f32 {
+        12.0
+    }
+
+    /// Parse a page description. The page must have exactly one ad slot.
+    pub fn from_toml(text: &str) -> Result<MockPage> {
+        let page: MockPage =
+            toml::from_str(text).map_err(|e| Error::ContextError(format!("Invalid mock page: {}", e)))?;
+        let slots = page.blocks.iter().filter(|b| b.kind == PageBlockKind::AdSlot).count();
+        if slots != 1 {
+            return Err(Error::ContextError(format!("Mock page {} has {} ad slots, expected 1", page.name, slots)));
+        }
+        Ok(page)
+    }
+
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<MockPage> {
+        let text = fs::read_to_string(path.as_ref())?;
+        MockPage::from_toml(&text)
+    }
+
+    /// The frame of each block when the top left of the page is at origin
+    pub fn block_frames(&self, origin: Vector) -> Vec<(&PageBlock, Rectangle)> {
+        let mut ypos = origin.y + self.spacing;
+        self.blocks
+            .iter()
+            .map(|block| {
+                let (width, height) = block.size(self.width);
+                let frame = Rectangle::new((origin.x + (self.width - width) / 2.0, ypos), (width, height));
+                ypos += height + self.spacing;
+                (block, frame)
+            })
+            .collect()
+    }
+
+    /// The total height of the page
+    pub fn height(&self) -> f32 {
+        self.blocks.iter().fold(self.spacing, |height, block| height + block.size(self.width).1 + self.spacing)
+    }
+
+    /// The frame of the ad slot when the top left of the page is at origin
+    pub fn ad_slot_frame(&self, origin: Vector) -> Rectangle {
+        self.block_frames(origin)
+            .into_iter()
+            .find(|(block, _)| block.kind == PageBlockKind::AdSlot)
+            .map(|(_, frame)| frame)
+            .unwrap_or_else(|| Rectangle::new(origin, (0.0, 0.0)))
+    }
+
+    /// The largest scroll offset, when the bottom of the page is at the bottom of the viewport
+    pub fn max_scroll(&self) -> f32 {
+        (self.height() - self.viewport_height).max(0.0)
+    }
+}
@@ -1,9 +1,29 @@
 pub use self::application::*;
 pub use self::app_delegate::*;
+pub use self::assets::*;
+pub use self::bidi::*;
+pub use self::brand_kit::*;
 pub use self::controller::*;
+pub use self::fonts::*;
 pub use self::layout::*;
+pub use self::load_weight::*;
+pub use self::mock_page::*;
+pub use self::raster::*;
+pub use self::rich_text::*;
+pub use self::svg::*;
+pub use self::zip::*;
 
 mod application;
 mod app_delegate;
+mod assets;
+mod bidi;
+mod brand_kit;
 mod controller;
+mod fonts;
 mod layout;
+mod load_weight;
+mod mock_page;
+mod raster;
+mod rich_text;
+mod svg;
+mod zip;
@@ -0,0 +1,244 @@
+/// Offline drawing into a PNG file
+///
+/// The viewer draws with tweek, which needs a window. To export a creative as an image, the same frames are drawn
+/// into a tiny-skia Pixmap instead. Text is laid out with a TextBlock, so that lines wrap the same way as in the
+/// viewer, and the glyphs are rasterized with rusttype from the fonts in the FontRegistry.
+///
+use super::*;
+
+use quicksilver::{geom::Rectangle, graphics::Color, Error, Result};
+
+use tiny_skia::{
+    FillRule, FilterQuality, Paint, PathBuilder, Pixmap, PixmapPaint, PremultipliedColorU8, Rect, Stroke, Transform,
+};
+
+use std::path::Path;
+
+pub struct Canvas {
+    pixmap: Pixmap,
+}
+
+impl Canvas {
+    pub fn new(width: u32, height: u32) -> Result<Canvas> {
+        let pixmap = Pixmap::new(width, height)
+            .ok_or_else(|| Error::ContextError(format!("Invalid canvas size {}x{}", width, height)))?;
+        Ok(Canvas { pixmap })
+    }
+
+    pub fn fill(&mut self, color: Color) {
+        self.pixmap.fill(skia_color(color));
+    }
+
+    pub fn fill_rect(&mut self, rect: &Rectangle, color: Color) {
+        let rect = match Rect::from_xywh(rect.x(), rect.y(), rect.width(), rect.height()) {
+            Some(rect) => rect,
+            None => return,
+        };
+        let mut paint = Paint::default();
+        paint.set_color(skia_color(color));
+        paint.anti_alias = true;
+        self.pixmap.fill_rect(rect, &paint, Transform::identity(), None);
+    }
+
+    /// A border inside the edges of the rect
+    pub fn stroke_rect(&mut self, rect: &Rectangle, color: Color, width: f32) {
+        let (x, y, w, h) = (rect.x(), rect.y(), rect.width(), rect.height());
+        self.fill_rect(&Rectangle::new((x, y), (w, width)), color);
+        self.fill_rect(&Rectangle::new((x, y + h - width), (w, width)), color);
+        self.fill_rect(&Rectangle::new((x, y), (width, h)), color);
+        self.fill_rect(&Rectangle::new((x + w - width, y), (width, h)), color);
+    }
+
+    /// Fill a rect with rounded corners. The radius is limited to half of the shorter side.
+    pub fn fill_rounded_rect(&mut self, rect: &Rectangle, color: Color, radius: f32) {
+        let path = match rounded_rect_path(rect.x(), rect.y(), rect.width(), rect.height(), radius) {
+            Some(path) => path,
+            None => return,
+        };
+        let mut paint = Paint::default();
+        paint.set_color(skia_color(color));
+        paint.anti_alias = true;
+        self.pixmap.fill_path(&path, &paint, FillRule::Winding, Transform::identity(), None);
+    }
+
+    /// A border inside the edges of a rect with rounded corners
+    pub fn stroke_rounded_rect(&mut self, rect: &Rectangle, color: Color, width: f32, radius: f32) {
+        // The stroke is centered on the path, so the path is inset by half of the width
+        let inset = width / 2.0;
+        let (w, h) = (rect.width() - width, rect.height() - width);
+        let path = match rounded_rect_path(rect.x() + inset, rect.y() + inset, w, h, radius - inset) {
+            Some(path) => path,
+            None => return,
+        };
+        let mut paint = Paint::default();
+        paint.set_color(skia_color(color));
+        paint.anti_alias = true;
+        let stroke = Stroke { width, ..Default::default() };
+        self.pixmap.stroke_path(&path, &paint, &stroke, Transform::identity(), None);
+    }
+
+    /// Draw a PNG or SVG file centered in the frame at the largest size that keeps its aspect ratio. SVGs are
+    /// rasterized at that size, and PNGs are scaled.
+    pub fn draw_image<P: AsRef<Path>>(&mut self, path: P, frame: &Rectangle) -> Result<()> {
+        let path = path.as_ref();
+        let max_size = (frame.width(), frame.height());
+        let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or("").to_lowercase();
+        let (image, size) = match ext.as_str() {
+            "svg" => {
+                let (width, height) = fit_size(svg_size(path)?, max_size);
+                (rasterize_svg(path, width, height)?, (width as f32, height as f32))
+            }
+            "png" => {
+                let image = Pixmap::load_png(path)
+                    .map_err(|e| Error::ContextError(format!("Failed to load {:?}: {}", path, e)))?;
+                let (width, height) = fit_size((image.width() as f32, image.height() as f32), max_size);
+                (image, (width as f32, height as f32))
+            }
+            _ => return Err(Error::ContextError(format!("Only PNG and SVG images can be exported: {:?}", path))),
+        };
+        let x = frame.x() + (frame.width() - size.0) / 2.0;
+        let y = frame.y() + (frame.height() - size.1) / 2.0;
+        let scale = (size.0 / image.width() as f32, size.1 / image.height() as f32);
+        let transform = Transform::from_row(scale.0, 0.0, 0.0, scale.1, x, y);
+        let paint = PixmapPaint { quality: FilterQuality::Bicubic, ..Default::default() };
+        self.pixmap.draw_pixmap(0, 0, image.as_ref(), &paint, transform, None);
+        Ok(())
+    }
+
+    /// Draw the text block wrapped within the frame. Lines that do not fit the height of the frame are dropped,
+    /// like in the viewer's templates. Returns false if any were dropped.
+    pub fn draw_text(&mut self, block: &TextBlock, frame: &Rectangle, fonts: &mut FontRegistry) -> bool {
+        let bottom = frame.y() + frame.height() + 0.5;
+        let mut fits = true;
+        for run in block.layout(frame, fonts) {
+            if run.frame.y() + run.frame.height() > bottom {
+                fits = false;
+                continue;
+            }
+            self.draw_run(&run, fonts);
+        }
+        fits
+    }
+
+    /// Rasterize the glyphs of a run into a pixmap of the text color, where the alpha is the glyph coverage, and
+    /// blend it onto the canvas
+    fn draw_run(&mut self, run: &TextRun, fonts: &mut FontRegistry) {
+        let font = fonts.glyphs(&run.font);
+        let scale = rusttype::Scale::uniform(run.font_size);
+        let ascent = font.v_metrics(scale).ascent;
+        let start = rusttype::point(run.frame.x(), run.frame.y() + ascent);
+        let glyphs: Vec<rusttype::PositionedGlyph> = font.layout(&run.text, scale, start).collect();
+        let color = skia_color(run.color);
+        for glyph in glyphs.iter() {
+            let bounds = match glyph.pixel_bounding_box() {
+                Some(bounds) => bounds,
+                None => continue,
+            };
+            let (width, height) = (bounds.width() as u32, bounds.height() as u32);
+            let mut mask = match Pixmap::new(width, height) {
+                Some(mask) => mask,
+                None => continue,
+            };
+            let pixels = mask.pixels_mut();
+            glyph.draw(|x, y, coverage| {
+                let alpha = coverage.min(1.0) * color.alpha();
+                let channel = |value: f32| (value * alpha * 255.0).round() as u8;
+                let pixel = PremultipliedColorU8::from_rgba(
+                    channel(color.red()),
+                    channel(color.green()),
+                    channel(color.blue()),
+                    (alpha * 255.0).round() as u8,
+                );
+                if let Some(pixel) = pixel {
+                    pixels[(y * width + x) as usize] = pixel;
+                }
+            });
+            let paint = PixmapPaint::default();
+            self.pixmap.draw_pixmap(bounds.min.x, bounds.min.y, mask.as_ref(), &paint, Transform::identity(), None);
+        }
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.pixmap
+            .save_png(path.as_ref())
+            .map_err(|e| Error::ContextError(format!("Failed to save {:?}: {}", path.as_ref(), e)))
+    }
+}
+
+/// The natural size of a PNG or SVG file
+pub fn image_size<P: AsRef<Path>>(path: P) -> Result<(f32, f32)> {
+    let path = path.as_ref();
+    if path.extension().map_or(false, |ext| ext.eq_ignore_ascii_case("svg")) {
+        return svg_size(path);
+    }
+    let image =
+        Pixmap::load_png(path).map_err(|e| Error::ContextError(format!("Failed to load {:?}: {}", path, e)))?;
+    Ok((image.width() as f32, image.height() as f32))
+}
+
+/// The outline of a rect with rounded corners, or None if it is empty. Each corner is a cubic curve.
+fn rounded_rect_path(x: f32, y: f32, w: f32, h: f32, radius: f32) -> Option<tiny_skia::Path> {
+    if w <= 0.0 || h <= 0.0 {
+        return None;
+    }
+    let r = radius.max(0.0).min(w / 2.0).min(h / 2.0);
+    // The distance of the control points from the corner's ends that best approximates a quarter circle
+    let k = r * 0.552_284_8;
+    let mut builder = PathBuilder::new();
+    builder.move_to(x + r, y);
+    builder.line_to(x + w - r, y);
+    builder.cubic_to(x + w - r + k, y, x + w, y + r - k, x + w, y + r);
+    builder.line_to(x + w, y + h - r);
+    builder.cubic_to(x + w, y + h - r + k, x + w - r + k, y + h, x + w - r, y + h);
+    builder.line_to(x + r, y + h);
+    builder.cubic_to(x + r - k, y + h, x, y + h - r + k, x, y + h - r);
+    builder.line_to(x, y + r);
+    builder.cubic_to(x, y + r - k, x + r - k, y, x + r, y);
+    builder.close();
+    builder.finish()
+}
+
+fn skia_color(color: Color) -> tiny_skia::Color {
+    tiny_skia::Color::from_rgba(color.r, color.g, color.b, color.a).unwrap_or(tiny_skia::Color::BLACK)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alpha(canvas: &Canvas, x: u32, y: u32) -> u8 {
+        canvas.pixmap.pixel(x, y).unwrap().alpha()
+    }
+
+    #[test]
+    fn rounded_rects_leave_the_corners_empty() {
+        let mut canvas = Canvas::new(40, 20).unwrap();
+        canvas.fill_rounded_rect(&Rectangle::new((0.0, 0.0), (40.0, 20.0)), Color::BLACK, 8.0);
+        assert_eq!(alpha(&canvas, 0, 0), 0);
+        assert_eq!(alpha(&canvas, 39, 19), 0);
+        assert_eq!(alpha(&canvas, 20, 0), 255);
+        assert_eq!(alpha(&canvas, 0, 10), 255);
+        assert_eq!(alpha(&canvas, 20, 10), 255);
+    }
+
+    #[test]
+    fn rounded_borders_stay_inside_the_rect() {
+        let mut canvas = Canvas::new(40, 20).unwrap();
+        canvas.stroke_rounded_rect(&Rectangle::new((10.0, 5.0), (20.0, 10.0)), Color::BLACK, 2.0, 5.0);
+        assert_eq!(alpha(&canvas, 20, 4), 0);
+        assert_eq!(alpha(&canvas, 20, 5), 255);
+        assert_eq!(alpha(&canvas, 20, 6), 255);
+        assert_eq!(alpha(&canvas, 20, 7), 0);
+        assert_eq!(alpha(&canvas, 10, 5), 0);
+    }
+
+    #[test]
+    fn large_radii_are_limited_to_half_of_the_shorter_side() {
+        assert!(rounded_rect_path(0.0, 0.0, 40.0, 20.0, 100.0).is_some());
+        assert!(rounded_rect_path(0.0, 0.0, 0.0, 20.0, 4.0).is_none());
+        let mut canvas = Canvas::new(40, 20).unwrap();
+        canvas.fill_rounded_rect(&Rectangle::new((0.0, 0.0), (40.0, 20.0)), Color::BLACK, 100.0);
+        assert_eq!(alpha(&canvas, 20, 10), 255);
+        assert_eq!(alpha(&canvas, 1, 1), 0);
+    }
+}
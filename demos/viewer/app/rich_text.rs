@@ -0,0 +1,410 @@
+/// Multi-line and styled text for ad copy
+///
+/// A TextBlock is a list of TextSpans that share a base font family, size and color. Each span can override
+/// the family, weight, size and color for its own words. Fonts come from a FontRegistry. Line breaks happen
+/// at "\n" and wherever the next word no longer fits the available width. The result of a layout is a list
+/// of TextRuns with absolute frames, which can be turned into Labels for adding to a Scene or Timeline.
+///
+/// Words with Arabic or Hebrew letters are shaped and reversed for drawing. In a RightToLeft block, the words on
+/// each line are also placed from right to left, except that runs of left-to-right words keep their order.
+///
+use super::*;
+
+use tweek::gui::*;
+
+use quicksilver::{
+    geom::{Rectangle, Vector},
+    graphics::Color,
+};
+
+/// Horizontal alignment of each line within the block width
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+/// The direction that the words on a line are read in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextDirection {
+    LeftToRight,
+    RightToLeft,
+}
+
+/// A string of text with optional style overrides
+#[derive(Clone, Debug)]
+pub struct TextSpan {
+    pub text: String,
+    pub family: Option<String>,
+    pub weight: FontWeight,
+    pub font_size: Option<f32>,
+    pub color: Option<Color>,
+}
+
+impl TextSpan {
+    pub fn new(text: &str) -> Self {
+        TextSpan { text: text.to_string(), family: None, weight: FontWeight::Regular, font_size: None, color: None }
+    }
+
+    pub fn bold(mut self) -> Self {
+        self.weight = FontWeight::Bold;
+        self
+    }
+
+    pub fn with_family(mut self, family: &str) -> Self {
+        self.family = Some(family.to_string());
+        self
+    }
+
+    pub fn with_weight(mut self, weight: FontWeight) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    pub fn with_size(mut self, font_size: f32) -> Self {
+        self.font_size = Some(font_size);
+        self
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = Some(color);
+        self
+    }
+}
+
+/// A single styled piece of text on one line, positioned in absolute coordinates
+#[derive(Clone, Debug)]
+pub struct TextRun {
+    pub text: String,
+    pub font: FontKey,
+    pub font_size: f32,
+    pub color: Color,
+    pub frame: Rectangle,
+}
+
+/// A paragraph of styled text that wraps within a given width
+#[derive(Clone, Debug)]
+pub struct TextBlock {
+    pub spans: Vec<TextSpan>,
+    pub family: String,
+    pub font_size: f32,
+    pub color: Color,
+    pub align: TextAlign,
+    pub direction: TextDirection,
+    /// Multiplier of the largest font size on each line
+    pub line_height: f32,
+}
+
+/// Internal: a measured word waiting to be placed on a line
+struct Word {
+    /// The text in drawing order
+    text: String,
+    span: usize,
+    font: FontKey,
+    width: f32,
+    /// The space before the word on its line, which is 0.0 for the first word
+    space: f32,
+    /// The width of a space in the word's font
+    space_w: f32,
+    rtl: bool,
+}
+
+impl TextBlock {
+    pub fn new(font_size: f32, color: Color) -> Self {
+        TextBlock {
+            spans: Vec::new(),
+            family: DEFAULT_FAMILY.to_string(),
+            font_size,
+            color,
+            align: TextAlign::Left,
+            direction: TextDirection::LeftToRight,
+            line_height: 1.2,
+        }
+    }
+
+    pub fn with_family(mut self, family: &str) -> Self {
+        self.family = family.to_string();
+        self
+    }
+
+    pub fn with_align(mut self, align: TextAlign) -> Self {
+        self.align = align;
+        self
+    }
+
+    pub fn with_direction(mut self, direction: TextDirection) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    pub fn with_line_height(mut self, line_height: f32) -> Self {
+        self.line_height = line_height;
+        self
+    }
+
+    /// Append plain text using the block's base style
+    pub fn text(self, text: &str) -> Self {
+        self.span(TextSpan::new(text))
+    }
+
+    pub fn span(mut self, span: TextSpan) -> Self {
+        self.spans.push(span);
+        self
+    }
+
+    /// The full text of the block without styling
+    pub fn plain_text(&self) -> String {
+        self.spans.iter().map(|s| s.text.as_str()).collect::<Vec<_>>().concat()
+    }
+
+    fn span_size(&self, span: &TextSpan) -> f32 {
+        span.font_size.unwrap_or(self.font_size)
+    }
+
+    fn span_family<'a>(&'a self, span: &'a TextSpan) -> &'a str {
+        span.family.as_ref().unwrap_or(&self.family)
+    }
+
+    /// Break the spans into lines of measured words. Words carry the index of the span they came from
+    /// and the resolved font, so that adjacent words with the same style can be merged back into runs.
+    /// A word only gets a space before it when the text has whitespace there, so a word can continue in the
+    /// next span, and it wraps to the next line as a whole.
+    fn wrap_lines(&self, width: f32, fonts: &mut FontRegistry) -> Vec<Vec<Word>> {
+        let mut lines: Vec<Vec<Word>> = vec![Vec::new()];
+        let mut line_w = 0.0;
+        // Whether there is whitespace between the last word and the next one
+        let mut gap = false;
+        for (i, span) in self.spans.iter().enumerate() {
+            let size = self.span_size(span);
+            let family = self.span_family(span);
+            let key = fonts.resolve(family, span.weight, " ");
+            let space_w = fonts.font(&key).measure_text(" ", size).0;
+            for (n, segment) in span.text.split('\n').enumerate() {
+                if n > 0 {
+                    lines.push(Vec::new());
+                    line_w = 0.0;
+                    gap = false;
+                }
+                let mut rest = segment;
+                while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+                    let end = rest[start..].find(char::is_whitespace).map_or(rest.len(), |end| start + end);
+                    let source = &rest[start..end];
+                    let has_space = gap || start > 0;
+                    rest = &rest[end..];
+                    gap = false;
+
+                    let rtl = is_rtl_word(source);
+                    let word = visual_word(source);
+                    let key = fonts.resolve(family, span.weight, &word);
+                    let word_w = fonts.font(&key).measure_text(&word, size).0;
+                    let line = lines.last_mut().unwrap();
+                    let lead = if line.is_empty() || !has_space { 0.0 } else { space_w };
+                    if !line.is_empty() && line_w + lead + word_w > width {
+                        // The start of a word that continues from the previous span moves down with it
+                        let start =
+                            if has_space { line.len() } else { line.iter().rposition(|w| w.space > 0.0).unwrap_or(0) };
+                        if start > 0 {
+                            let mut carried: Vec<Word> = line.drain(start..).collect();
+                            if let Some(first) = carried.first_mut() {
+                                first.space = 0.0;
+                            }
+                            line_w = carried.iter().map(|w| w.space + w.width).sum();
+                            lines.push(carried);
+                        }
+                    }
+                    let line = lines.last_mut().unwrap();
+                    let space = if line.is_empty() || !has_space { 0.0 } else { space_w };
+                    line_w += space + word_w;
+                    line.push(Word { text: word, span: i, font: key, width: word_w, space, space_w, rtl });
+                }
+                gap = gap || !rest.is_empty();
+            }
+        }
+        lines
+    }
+
+    /// Put the words of a right-to-left line in drawing order, with the spaces between them in the new order.
+    /// A word that continues in the next span stays together, with its right-to-left parts in reverse order.
+    fn visual_order(&self, line: Vec<Word>) -> Vec<Word> {
+        if self.direction == TextDirection::LeftToRight {
+            return line;
+        }
+        let mut joined: Vec<Vec<Word>> = Vec::new();
+        for word in line {
+            match joined.last_mut() {
+                Some(parts) if word.space == 0.0 => parts.push(word),
+                _ => joined.push(vec![word]),
+            }
+        }
+        reorder_rtl_line(&mut joined, |parts| parts[0].rtl);
+        let mut line = Vec::new();
+        for (i, mut parts) in joined.into_iter().enumerate() {
+            if parts[0].rtl {
+                parts.reverse();
+            }
+            for (n, mut word) in parts.into_iter().enumerate() {
+                word.space = if i > 0 && n == 0 { word.space_w } else { 0.0 };
+                line.push(word);
+            }
+        }
+        line
+    }
+
+    /// Calculate the runs of text for this block inside the specified frame. Only the width of the frame
+    /// is used for wrapping; lines continue below the frame if the text does not fit.
+    pub fn layout(&self, frame: &Rectangle, fonts: &mut FontRegistry) -> Vec<TextRun> {
+        let mut runs: Vec<TextRun> = Vec::new();
+        let mut ypos = frame.y();
+        for line in self.wrap_lines(frame.width(), fonts) {
+            let line = self.visual_order(line);
+            let max_size = line.iter().map(|w| self.span_size(&self.spans[w.span])).fold(0.0, f32::max);
+            let line_h = if max_size > 0.0 { max_size } else { self.font_size } * self.line_height;
+            let line_w: f32 = line.iter().map(|w| w.space + w.width).sum();
+            let mut xpos = match self.align {
+                TextAlign::Left => frame.x(),
+                TextAlign::Center => frame.x() + (frame.width() - line_w) / 2.0,
+                TextAlign::Right => frame.x() + frame.width() - line_w,
+            };
+
+            let mut current: Option<(usize, FontKey)> = None;
+            for word in line {
+                let span = &self.spans[word.span];
+                let size = self.span_size(span);
+                let style = (word.span, word.font.clone());
+                if current.as_ref() == Some(&style) {
+                    let run = runs.last_mut().unwrap();
+                    if word.space > 0.0 {
+                        run.text.push(' ');
+                    }
+                    run.text.push_str(&word.text);
+                    run.frame.size.x += word.space + word.width;
+                } else {
+                    // Baseline align each run to the bottom of the line
+                    let run_y = ypos + (line_h - size * self.line_height);
+                    let frame = Rectangle::new((xpos + word.space, run_y), (word.width, size));
+                    runs.push(TextRun {
+                        text: word.text.clone(),
+                        font: word.font.clone(),
+                        font_size: size,
+                        color: span.color.unwrap_or(self.color),
+                        frame,
+                    });
+                    current = Some(style);
+                }
+                xpos += word.space + word.width;
+            }
+            ypos += line_h;
+        }
+        runs
+    }
+
+    /// The size of the area covered by the laid out text
+    pub fn measure(&self, width: f32, fonts: &mut FontRegistry) -> Vector {
+        let runs = self.layout(&Rectangle::new((0.0, 0.0), (width, 0.0)), fonts);
+        runs.iter().fold(Vector::ZERO, |acc, run| {
+            let right = run.frame.x() + run.frame.width();
+            let bottom = run.frame.y() + run.frame.height() * self.line_height;
+            Vector::new(acc.x.max(right), acc.y.max(bottom))
+        })
+    }
+
+    /// How far the laid out text reaches past the sides and the bottom of the frame. Both are 0.0 when it fits.
+    pub fn overflow(&self, frame: &Rectangle, fonts: &mut FontRegistry) -> Vector {
+        let right = frame.x() + frame.width();
+        let bottom = frame.y() + frame.height();
+        self.layout(frame, fonts).iter().fold(Vector::ZERO, |acc, run| {
+            let x = (frame.x() - run.frame.x()).max(run.frame.x() + run.frame.width() - right);
+            let y = run.frame.y() + run.frame.height() * self.line_height - bottom;
+            Vector::new(acc.x.max(x), acc.y.max(y))
+        })
+    }
+
+    /// Create one Label for each TextRun. The caller is responsible for setting ids and animations.
+    pub fn make_labels(&self, frame: &Rectangle, fonts: &mut FontRegistry) -> Vec<Label> {
+        self.layout(frame, fonts)
+            .into_iter()
+            .map(|run| {
+                let mut label = Label::new(run.frame.clone());
+                label.set_text(&run.text);
+                label.display = LabelDisplay::Text;
+                label.set_font(fonts.font(&run.font).clone());
+                label.layer.font_style = FontStyle::new(run.font_size, run.color);
+                label.layer.lock_style = true;
+                label
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wide_frame() -> Rectangle {
+        Rectangle::new((0.0, 0.0), (1000.0, 100.0))
+    }
+
+    #[test]
+    fn spans_without_whitespace_join_into_one_word() {
+        let mut fonts = FontRegistry::new();
+        let block = TextBlock::new(20.0, Color::BLACK).text("Brew").span(TextSpan::new("ing tea").bold());
+        let runs = block.layout(&wide_frame(), &mut fonts);
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].text, "Brew");
+        assert_eq!(runs[1].text, "ing tea");
+        assert_eq!(runs[1].frame.x(), runs[0].frame.x() + runs[0].frame.width());
+        assert_eq!(block.plain_text(), "Brewing tea");
+    }
+
+    #[test]
+    fn whitespace_at_a_span_boundary_is_kept() {
+        let mut fonts = FontRegistry::new();
+        for block in [
+            TextBlock::new(20.0, Color::BLACK).text("Brew ").span(TextSpan::new("tea").bold()),
+            TextBlock::new(20.0, Color::BLACK).text("Brew").span(TextSpan::new(" tea").bold()),
+        ]
+        .iter()
+        {
+            let runs = block.layout(&wide_frame(), &mut fonts);
+            assert_eq!(runs.len(), 2);
+            assert!(runs[1].frame.x() > runs[0].frame.x() + runs[0].frame.width());
+        }
+    }
+
+    #[test]
+    fn a_joined_word_wraps_as_a_whole() {
+        let mut fonts = FontRegistry::new();
+        let block = TextBlock::new(20.0, Color::BLACK).text("Hot Brew").span(TextSpan::new("ing").bold());
+        let hot_w = TextBlock::new(20.0, Color::BLACK).text("Hot Brew").measure(1000.0, &mut fonts).x;
+        let runs = block.layout(&Rectangle::new((0.0, 0.0), (hot_w, 100.0)), &mut fonts);
+        let texts: Vec<&str> = runs.iter().map(|run| run.text.as_str()).collect();
+        assert_eq!(texts, vec!["Hot", "Brew", "ing"]);
+        assert_eq!(runs[1].frame.x(), 0.0);
+        assert!(runs[1].frame.y() > runs[0].frame.y());
+        assert_eq!(runs[2].frame.y(), runs[1].frame.y());
+    }
+
+    #[test]
+    fn a_joined_word_keeps_its_parts_together_right_to_left() {
+        let mut fonts = FontRegistry::new();
+        // "שלום עולם" with the end of the first word in bold
+        let block = TextBlock::new(20.0, Color::BLACK)
+            .with_direction(TextDirection::RightToLeft)
+            .text("\u{05E9}\u{05DC}")
+            .span(TextSpan::new("\u{05D5}\u{05DD} \u{05E2}\u{05D5}\u{05DC}\u{05DD}").bold());
+        let runs = block.layout(&wide_frame(), &mut fonts);
+        let texts: Vec<&str> = runs.iter().map(|run| run.text.as_str()).collect();
+        assert_eq!(texts, vec!["\u{05DD}\u{05DC}\u{05D5}\u{05E2} \u{05DD}\u{05D5}", "\u{05DC}\u{05E9}"]);
+        assert_eq!(runs[1].frame.x(), runs[0].frame.x() + runs[0].frame.width());
+    }
+
+    #[test]
+    fn words_in_a_span_are_merged_into_one_run() {
+        let mut fonts = FontRegistry::new();
+        let block = TextBlock::new(20.0, Color::BLACK).text("Brew a  cup");
+        let runs = block.layout(&wide_frame(), &mut fonts);
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].text, "Brew a cup");
+    }
+}
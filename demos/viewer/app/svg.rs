@@ -0,0 +1,198 @@
+/// SVG rasterization for logos, CTAs and icons
+///
+/// Bitmaps look soft when an ad is scaled to a different AdSpec, so vector assets are rasterized at the exact
+/// pixel size needed for each spec. Rendered images are written as PNG files to a cache folder inside the
+/// asset root, which allows them to be loaded with DrawImage::load_image_file like any other image.
+/// A cached PNG is reused as long as it is newer than its SVG source, and each image is only loaded once per size.
+///
+use tweek::tools::*;
+
+use quicksilver::{Error, Result};
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Cache folder for rasterized images, relative to the asset root
+const SVG_CACHE_DIR: &str = ".cache/svg";
+
+/// Parse SVG data. Files that the SVG links to are resolved against the resources_dir, if there is one.
+fn parse_svg(data: &[u8], resources_dir: Option<PathBuf>, name: &str) -> Result<usvg::Tree> {
+    let options = usvg::Options { resources_dir, ..Default::default() };
+    usvg::Tree::from_data(data, &options.to_ref())
+        .map_err(|e| Error::ContextError(format!("Invalid SVG {}: {}", name, e)))
+}
+
+/// Parse an SVG file
+fn load_svg_tree(path: &Path) -> Result<usvg::Tree> {
+    let data = fs::read(path)?;
+    parse_svg(&data, path.parent().map(|p| p.to_path_buf()), &path.to_string_lossy())
+}
+
+fn tree_size(tree: &usvg::Tree) -> (f32, f32) {
+    let size = tree.svg_node().size;
+    (size.width() as f32, size.height() as f32)
+}
+
+fn render_tree(tree: &usvg::Tree, width: u32, height: u32, name: &str) -> Result<tiny_skia::Pixmap> {
+    let mut pixmap = tiny_skia::Pixmap::new(width, height)
+        .ok_or_else(|| Error::ContextError(format!("Invalid raster size {}x{}", width, height)))?;
+    resvg::render(tree, usvg::FitTo::Size(width, height), tiny_skia::Transform::default(), pixmap.as_mut())
+        .ok_or_else(|| Error::ContextError(format!("Failed to render SVG {}", name)))?;
+    Ok(pixmap)
+}
+
+/// The natural size of an SVG document as defined by its width/height or viewBox
+pub fn svg_size<P: AsRef<Path>>(path: P) -> Result<(f32, f32)> {
+    Ok(tree_size(&load_svg_tree(path.as_ref())?))
+}
+
+/// The natural size of an SVG document that was loaded as data
+pub fn svg_data_size(data: &[u8]) -> Result<(f32, f32)> {
+    Ok(tree_size(&parse_svg(data, None, "data")?))
+}
+
+/// Calculate the largest size with the same aspect ratio as the source that fits inside max_size
+pub fn fit_size(source: (f32, f32), max_size: (f32, f32)) -> (u32, u32) {
+    let scale = (max_size.0 / source.0).min(max_size.1 / source.1);
+    let width = (source.0 * scale).round().max(1.0);
+    let height = (source.1 * scale).round().max(1.0);
+    (width as u32, height as u32)
+}
+
+/// Rasterize an SVG file to exactly width x height pixels. The image is stretched if the aspect ratio does not
+/// match, so callers should usually calculate the size with fit_size().
+pub fn rasterize_svg<P: AsRef<Path>>(path: P, width: u32, height: u32) -> Result<tiny_skia::Pixmap> {
+    let tree = load_svg_tree(path.as_ref())?;
+    render_tree(&tree, width, height, &path.as_ref().to_string_lossy())
+}
+
+/// Rasterize SVG data like rasterize_svg(). Files that the SVG links to are not loaded.
+pub fn rasterize_svg_data(data: &[u8], width: u32, height: u32) -> Result<tiny_skia::Pixmap> {
+    render_tree(&parse_svg(data, None, "data")?, width, height, "data")
+}
+
+/// Rasterizes SVG files into a PNG cache under the asset root and loads them as DrawImages
+pub struct SvgCache {
+    static_root: PathBuf,
+    /// The natural size of each SVG by path
+    sizes: HashMap<String, (f32, f32)>,
+    /// The loaded images by SVG path and size
+    images: HashMap<(String, (u32, u32)), DrawImage>,
+}
+
+impl SvgCache {
+    /// The static_root is the asset root that SVG paths are relative to
+    pub fn new<P: AsRef<Path>>(static_root: P) -> Self {
+        SvgCache { static_root: static_root.as_ref().to_path_buf(), sizes: HashMap::new(), images: HashMap::new() }
+    }
+
+    /// The cached file name, relative to the asset root, for the SVG at the specified size. The cache has the same
+    /// folders as the asset root, so that files with the same name in different folders do not collide.
+    fn cache_path(&self, svg_path: &Path, width: u32, height: u32) -> PathBuf {
+        let stem = svg_path.file_stem().and_then(|s| s.to_str()).unwrap_or("image");
+        let folders = svg_path.parent().map(|parent| parent.components()).into_iter().flatten();
+        let mut path = PathBuf::from(SVG_CACHE_DIR);
+        path.extend(folders.filter(|component| matches!(component, Component::Normal(_))));
+        path.join(format!("{}-{}x{}.png", stem, width, height))
+    }
+
+    /// Rasterize the SVG at the path relative to the asset root, if the cached PNG is missing or stale.
+    /// Returns the path of the PNG relative to the asset root.
+    pub fn rasterize(&mut self, svg_path: &str, width: u32, height: u32) -> Result<String> {
+        let source = self.static_root.join(svg_path);
+        let cache_path = self.cache_path(Path::new(svg_path), width, height);
+        let target = self.static_root.join(&cache_path);
+
+        let source_time = fs::metadata(&source).and_then(|m| m.modified());
+        let target_time = fs::metadata(&target).and_then(|m| m.modified());
+        let is_fresh = match (source_time, target_time) {
+            (Ok(source_time), Ok(target_time)) => target_time >= source_time,
+            _ => false,
+        };
+        if !is_fresh {
+            let pixmap = rasterize_svg(&source, width, height)?;
+            if let Some(dir) = target.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            pixmap.save_png(&target).map_err(|e| Error::ContextError(format!("Failed to save {:?}: {}", target, e)))?;
+            log::debug!("Rasterized {} at {}x{}", svg_path, width, height);
+        }
+        Ok(cache_path.to_string_lossy().to_string())
+    }
+
+    /// A path relative to the working directory as a path relative to the asset root, or None when the file
+    /// is outside of it. Both paths are canonicalized, so that ./static, absolute paths and symlinks match.
+    pub fn static_path<P: AsRef<Path>>(&self, path: P) -> Option<String> {
+        let path = path.as_ref();
+        let relative = match (path.canonicalize(), self.static_root.canonicalize()) {
+            (Ok(path), Ok(root)) => path.strip_prefix(&root).ok()?.to_path_buf(),
+            // Files that do not exist yet can only be matched as written
+            _ => path.strip_prefix(&self.static_root).ok()?.to_path_buf(),
+        };
+        Some(relative.to_string_lossy().to_string())
+    }
+
+    /// The size in pixels that fits within max_size and keeps the SVG's aspect ratio
+    pub fn fitted_size(&mut self, svg_path: &str, max_size: (f32, f32)) -> Result<(u32, u32)> {
+        let natural = match self.sizes.get(svg_path) {
+            Some(size) => *size,
+            None => {
+                let size = svg_size(self.static_root.join(svg_path))?;
+                self.sizes.insert(svg_path.to_string(), size);
+                size
+            }
+        };
+        Ok(fit_size(natural, max_size))
+    }
+
+    /// Load the SVG as an image of the specified size. Images that were already loaded at the size are reused.
+    pub fn load_image(&mut self, svg_path: &str, size: (u32, u32)) -> Result<DrawImage> {
+        let key = (svg_path.to_string(), size);
+        if let Some(image) = self.images.get(&key) {
+            return Ok(image.clone());
+        }
+        let png_path = self.rasterize(svg_path, size.0, size.1)?;
+        let image = DrawImage::load_image_file(&self.static_root.join(png_path).to_string_lossy())?;
+        self.images.insert(key, image.clone());
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fits_sizes_inside_the_maximum() {
+        assert_eq!(fit_size((100.0, 50.0), (300.0, 250.0)), (300, 150));
+        assert_eq!(fit_size((100.0, 50.0), (80.0, 10.0)), (20, 10));
+        assert_eq!(fit_size((1000.0, 1.0), (10.0, 10.0)), (10, 1));
+    }
+
+    #[test]
+    fn resolves_static_paths_however_they_are_written() {
+        let cwd = std::env::current_dir().unwrap();
+        let file = "teapot/logo-MK.svg";
+        for root in ["static", "./static"].iter() {
+            let cache = SvgCache::new(root);
+            assert_eq!(cache.static_path(Path::new("static").join(file)).as_deref(), Some(file));
+            assert_eq!(cache.static_path(Path::new("./static/vast/../").join(file)).as_deref(), Some(file));
+            assert_eq!(cache.static_path(cwd.join("static").join(file)).as_deref(), Some(file));
+            assert_eq!(cache.static_path("viewer/main.rs"), None);
+        }
+        let cache = SvgCache::new(cwd.join("static"));
+        assert_eq!(cache.static_path(Path::new("static").join(file)).as_deref(), Some(file));
+    }
+
+    #[test]
+    fn cache_paths_keep_the_folders() {
+        let cache = SvgCache::new("static");
+        let a = cache.cache_path(Path::new("a/icons/x.svg"), 30, 20);
+        let b = cache.cache_path(Path::new("b/icons/x.svg"), 30, 20);
+        assert_eq!(a, Path::new(".cache/svg/a/icons/x-30x20.png"));
+        assert_eq!(b, Path::new(".cache/svg/b/icons/x-30x20.png"));
+        assert_eq!(cache.cache_path(Path::new("x.svg"), 30, 20), Path::new(".cache/svg/x-30x20.png"));
+        assert_eq!(cache.cache_path(Path::new("../x.svg"), 30, 20), Path::new(".cache/svg/x-30x20.png"));
+    }
+}
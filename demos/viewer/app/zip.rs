@@ -0,0 +1,247 @@
+/// Writes zip files in memory
+///
+/// Only what ad servers need to read a bundle is supported: files are deflated unless that makes them larger,
+/// folders are implied by the file names and every file has the same timestamp so that exports are reproducible.
+///
+use flate2::{write::DeflateEncoder, Compression, Crc};
+
+use quicksilver::{Error, Result};
+
+use std::io::Write;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const END_SIGNATURE: u32 = 0x0605_4b50;
+/// Version 2.0, which added deflate and folders
+const ZIP_VERSION: u16 = 20;
+/// The file name is UTF-8
+const UTF8_FLAG: u16 = 0x0800;
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+/// 1980-01-01 00:00 in MS-DOS format, which is the earliest date that zip files can have
+const DOS_TIME: u16 = 0;
+const DOS_DATE: u16 = (1 << 5) | 1;
+
+struct ZipEntry {
+    name: String,
+    method: u16,
+    crc: u32,
+    compressed_size: u32,
+    size: u32,
+    offset: u32,
+}
+
+/// A zip file that files are added to one at a time
+#[derive(Default)]
+pub struct ZipWriter {
+    data: Vec<u8>,
+    entries: Vec<ZipEntry>,
+}
+
+impl ZipWriter {
+    pub fn new() -> Self {
+        ZipWriter::default()
+    }
+
+    /// Add a file. Folders in the name are separated by '/'.
+    pub fn add_file(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        if name.is_empty() || name.len() > u16::MAX as usize || name.starts_with('/') || name.contains('\\') {
+            return Err(Error::ContextError(format!("Invalid file name in zip: {:?}", name)));
+        }
+        if self.entries.iter().any(|entry| entry.name == name) {
+            return Err(Error::ContextError(format!("Duplicate file in zip: {}", name)));
+        }
+        let mut crc = Crc::new();
+        crc.update(data);
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(data)?;
+        let deflated = encoder.finish()?;
+        let (method, contents) = if deflated.len() < data.len() { (DEFLATED, &deflated[..]) } else { (STORED, data) };
+
+        let entry = ZipEntry {
+            name: name.to_string(),
+            method,
+            crc: crc.sum(),
+            compressed_size: to_u32(contents.len(), name)?,
+            size: to_u32(data.len(), name)?,
+            offset: to_u32(self.data.len(), name)?,
+        };
+        let out = &mut self.data;
+        put_u32(out, LOCAL_HEADER_SIGNATURE);
+        put_u16(out, ZIP_VERSION);
+        put_entry_fields(out, &entry);
+        put_u16(out, 0);
+        out.extend_from_slice(entry.name.as_bytes());
+        out.extend_from_slice(contents);
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Write the central directory and return the zip file
+    pub fn finish(self) -> Result<Vec<u8>> {
+        let mut out = self.data;
+        let directory_offset = to_u32(out.len(), "central directory")?;
+        for entry in self.entries.iter() {
+            put_u32(&mut out, CENTRAL_HEADER_SIGNATURE);
+            put_u16(&mut out, ZIP_VERSION);
+            put_u16(&mut out, ZIP_VERSION);
+            put_entry_fields(&mut out, entry);
+            // Extra field, comment, disk number and file attributes
+            for _ in 0..4 {
+                put_u16(&mut out, 0);
+            }
+            put_u32(&mut out, 0);
+            put_u32(&mut out, entry.offset);
+            out.extend_from_slice(entry.name.as_bytes());
+        }
+        let directory_size = to_u32(out.len(), "central directory")? - directory_offset;
+        if self.entries.len() > u16::MAX as usize {
+            return Err(Error::ContextError(format!("Too many files for a zip: {}", self.entries.len())));
+        }
+        put_u32(&mut out, END_SIGNATURE);
+        put_u16(&mut out, 0);
+        put_u16(&mut out, 0);
+        put_u16(&mut out, self.entries.len() as u16);
+        put_u16(&mut out, self.entries.len() as u16);
+        put_u32(&mut out, directory_size);
+        put_u32(&mut out, directory_offset);
+        put_u16(&mut out, 0);
+        Ok(out)
+    }
+}
+
+/// The fields from the flags to the file name length, which are the same in both headers
+fn put_entry_fields(out: &mut Vec<u8>, entry: &ZipEntry) {
+    put_u16(out, UTF8_FLAG);
+    put_u16(out, entry.method);
+    put_u16(out, DOS_TIME);
+    put_u16(out, DOS_DATE);
+    put_u32(out, entry.crc);
+    put_u32(out, entry.compressed_size);
+    put_u32(out, entry.size);
+    put_u16(out, entry.name.len() as u16);
+}
+
+fn put_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Zip64 is not supported, so sizes and offsets must fit in 32 bits
+fn to_u32(value: usize, name: &str) -> Result<u32> {
+    if value > u32::MAX as usize {
+        return Err(Error::ContextError(format!("{} is too large for a zip", name)));
+    }
+    Ok(value as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use flate2::read::DeflateDecoder;
+
+    use std::io::Read;
+
+    fn u16_at(data: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes([data[at], data[at + 1]])
+    }
+
+    fn u32_at(data: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+    }
+
+    /// Read every file through the central directory, checking the local headers and CRCs along the way
+    fn read_zip(zip: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let end = zip.len() - 22;
+        assert_eq!(u32_at(zip, end), END_SIGNATURE);
+        let count = u16_at(zip, end + 10) as usize;
+        let directory_size = u32_at(zip, end + 12) as usize;
+        let mut at = u32_at(zip, end + 16) as usize;
+        assert_eq!(at + directory_size, end);
+
+        let mut files = Vec::new();
+        for _ in 0..count {
+            assert_eq!(u32_at(zip, at), CENTRAL_HEADER_SIGNATURE);
+            let method = u16_at(zip, at + 10);
+            let crc = u32_at(zip, at + 16);
+            let compressed_size = u32_at(zip, at + 20) as usize;
+            let size = u32_at(zip, at + 24) as usize;
+            let name_len = u16_at(zip, at + 28) as usize;
+            let offset = u32_at(zip, at + 42) as usize;
+            let name = String::from_utf8(zip[at + 46..at + 46 + name_len].to_vec()).unwrap();
+
+            // The local header repeats the fields from the flags to the file name length
+            assert_eq!(u32_at(zip, offset), LOCAL_HEADER_SIGNATURE);
+            assert_eq!(zip[offset + 6..offset + 28], zip[at + 8..at + 30]);
+            at += 46 + name_len;
+            let start = offset + 30 + u16_at(zip, offset + 26) as usize + u16_at(zip, offset + 28) as usize;
+            let contents = &zip[start..start + compressed_size];
+            let data = match method {
+                STORED => contents.to_vec(),
+                DEFLATED => {
+                    let mut data = Vec::new();
+                    DeflateDecoder::new(contents).read_to_end(&mut data).unwrap();
+                    data
+                }
+                _ => panic!("Unknown method {} for {}", method, name),
+            };
+            assert_eq!(data.len(), size);
+            let mut check = Crc::new();
+            check.update(&data);
+            assert_eq!(check.sum(), crc, "CRC of {}", name);
+            files.push((name, data));
+        }
+        assert_eq!(at, end);
+        files
+    }
+
+    fn sample_zip() -> Vec<u8> {
+        let mut zip = ZipWriter::new();
+        zip.add_file("index.html", "<html>teapot</html>\n".repeat(50).as_bytes()).unwrap();
+        zip.add_file("static/teapot/brand.toml", b"name = \"Teapot\"").unwrap();
+        zip.add_file("static/empty.txt", b"").unwrap();
+        zip.add_file("static/héllo.txt", &[0, 1, 2, 255]).unwrap();
+        zip.finish().unwrap()
+    }
+
+    #[test]
+    fn files_can_be_read_back() {
+        let files = read_zip(&sample_zip());
+        let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["index.html", "static/teapot/brand.toml", "static/empty.txt", "static/héllo.txt"]);
+        assert_eq!(files[0].1, "<html>teapot</html>\n".repeat(50).into_bytes());
+        assert_eq!(files[1].1, b"name = \"Teapot\"");
+        assert!(files[2].1.is_empty());
+        assert_eq!(files[3].1, vec![0, 1, 2, 255]);
+    }
+
+    #[test]
+    fn deflates_only_when_smaller() {
+        let zip = sample_zip();
+        assert_eq!(u16_at(&zip, 8), DEFLATED);
+        assert!(zip.len() < 1000);
+        let mut small = ZipWriter::new();
+        small.add_file("a", b"a").unwrap();
+        assert_eq!(u16_at(&small.finish().unwrap(), 8), STORED);
+    }
+
+    #[test]
+    fn exports_are_reproducible() {
+        assert_eq!(sample_zip(), sample_zip());
+        assert_eq!(read_zip(&ZipWriter::new().finish().unwrap()), Vec::new());
+    }
+
+    #[test]
+    fn rejects_bad_and_duplicate_names() {
+        let mut zip = ZipWriter::new();
+        assert!(zip.add_file("", b"").is_err());
+        assert!(zip.add_file("/index.html", b"").is_err());
+        assert!(zip.add_file("static\\index.html", b"").is_err());
+        zip.add_file("index.html", b"").unwrap();
+        assert!(zip.add_file("index.html", b"other").is_err());
+    }
+}
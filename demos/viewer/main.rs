@@ -1,3 +1,4 @@
+mod ads;
 mod app;
 mod samples;
 
@@ -6,12 +7,17 @@ use app::*;
 #[macro_use]
 extern crate lazy_static;
 
-use quicksilver::{
-    geom::Vector,
-    lifecycle::{run_with, Settings},
-};
+#[cfg(target_arch = "wasm32")]
+#[macro_use]
+extern crate stdweb;
+
+use quicksilver::{geom::Vector, lifecycle::run_with};
+
+#[cfg(target_arch = "wasm32")]
+use quicksilver::lifecycle::Settings;
 
 fn main() {
+    #[cfg(not(target_arch = "wasm32"))]
     std::env::set_var("RUST_LOG", "trace,quicksilver=info");
 
     #[cfg(not(target_arch = "wasm32"))]
@@ -19,6 +25,96 @@ fn main() {
     #[cfg(not(target_arch = "wasm32"))]
     color_backtrace::install();
 
-    let screen = Vector::new(800, 600);
-    run_with("Ad Viewer", screen, Settings::default(), || Application::new(screen));
+    // Subcommands run instead of opening the viewer
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        match args.first().map(String::as_str) {
+            // `teapot dco-export [template] [out_dir]` renders each DCO variant to a PNG file
+            Some("dco-export") => {
+                let template_path = args
+                    .get(1)
+                    .cloned()
+                    .unwrap_or_else(|| env_asset_path("AD_DCO_TEMPLATE", ads::DEFAULT_DCO_TEMPLATE));
+                let out_dir = args.get(2).cloned().unwrap_or(samples::DEFAULT_DCO_EXPORT_DIR.to_string());
+                match samples::export_dco(&template_path, &out_dir) {
+                    Ok(paths) => log::info!("Exported {} DCO variants to {}", paths.len(), out_dir),
+                    Err(e) => {
+                        log::error!("DCO export of {} failed: {:?}", template_path, e);
+                        std::process::exit(1);
+                    }
+                }
+                return;
+            }
+            // `teapot lint [rules]` prints a report of the Teapot ad against the lint rules at each size
+            Some("lint") => {
+                let rules_path =
+                    args.get(1).cloned().unwrap_or_else(|| env_asset_path("AD_LINT_RULES", ads::DEFAULT_LINT_RULES));
+                match samples::lint_teapot(&rules_path) {
+                    Ok(reports) => {
+                        for report in reports.iter() {
+                            print!("{}", report);
+                        }
+                        if reports.iter().any(|report| !report.is_compliant()) {
+                            std::process::exit(1);
+                        }
+                    }
+                    Err(e) => {
+                        log::error!("Lint with {} failed: {:?}", rules_path, e);
+                        std::process::exit(2);
+                    }
+                }
+                return;
+            }
+            // `teapot html5-export [build_dir] [out_dir]` packages the wasm build as an HTML5 ad bundle for each size
+            Some("html5-export") => {
+                let build_dir = args.get(1).cloned().unwrap_or(samples::DEFAULT_WASM_BUILD_DIR.to_string());
+                let out_dir = args.get(2).cloned().unwrap_or(samples::DEFAULT_HTML5_EXPORT_DIR.to_string());
+                match samples::export_teapot_html5(&build_dir, &out_dir) {
+                    Ok(paths) => log::info!("Exported {} HTML5 bundles to {}", paths.len(), out_dir),
+                    Err(e) => {
+                        log::error!("HTML5 export of {} failed: {:?}", build_dir, e);
+                        std::process::exit(1);
+                    }
+                }
+                return;
+            }
+            // `teapot weight [rules]` prints the initial and polite load of the Teapot ad at each size against the
+            // budgets
+            Some("weight") => {
+                let rules_path =
+                    args.get(1).cloned().unwrap_or_else(|| env_asset_path("AD_LINT_RULES", ads::DEFAULT_LINT_RULES));
+                match samples::weigh_teapot(&rules_path) {
+                    Ok(reports) => {
+                        for report in reports.iter() {
+                            print!("{}", report);
+                        }
+                        if reports.iter().any(|report| !report.is_within_budgets()) {
+                            std::process::exit(1);
+                        }
+                    }
+                    Err(e) => {
+                        log::error!("Load weight with {} failed: {:?}", rules_path, e);
+                        std::process::exit(2);
+                    }
+                }
+                return;
+            }
+            _ => (),
+        }
+    }
+
+    // In a browser, the wasm build is an HTML5 ad that only shows the Teapot ad at the page's ad size
+    #[cfg(target_arch = "wasm32")]
+    {
+        let size = samples::page_ad_size();
+        let screen = Vector::new(size.0 as f32, size.1 as f32);
+        run_with("Teapot", screen, Settings::default(), move || samples::Html5Ad::new(size));
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let screen = Vector::new(800, 600);
+        run_with("Ad Viewer", screen, app_settings(env_frame_rate()), || Application::new(screen));
+    }
 }
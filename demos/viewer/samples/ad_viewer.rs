@@ -2,10 +2,11 @@
 /// formats. A toolbar at the top allows for selection of different ad sizes. In the main viewer area,
 /// the ad sample is centered and surrounded by blank space and off-screen assets.
 use super::*;
+use crate::ads::*;
 use crate::app::*;
 
 use quicksilver::{
-    geom::{Rectangle, Vector},
+    geom::{Rectangle, Shape, Vector},
     graphics::{Color},
     lifecycle::{Window},
 };
@@ -24,61 +25,1123 @@ use stretch::{
     style::*
 };
 
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Instant;
+
 const TOOLBAR_ID: u32 = 100;
 const MAIN_ID: u32 = 200;
 
+const LOADING_ID: u32 = 300;
+const MRAID_CLOSE_ID: u32 = 500;
+const NO_FILL_ID: u32 = 510;
+const CAPTION_ID: u32 = 520;
+const VARIANTS_ID: u32 = 530;
+const COPY_WARNINGS_ID: u32 = 540;
+const LINT_WARNINGS_ID: u32 = 550;
+
+/// Seconds that each impression is shown in rotation playback after the creative's playback ends
+const IMPRESSION_PAUSE: f64 = 1.0;
+/// Seconds of playback for creatives without an animation, e.g. native ads and VAST companions
+const STATIC_CREATIVE_DURATION: f64 = 5.0;
+
 const TOOLBAR_H: f32 = 50.0;
 const TOOLBAR_BTN_W: f32 = 70.0;
 const TOOLBAR_BTN_H: f32 = 32.0;
 
+/// The toolbar's play icon, relative to the asset root
+const PLAY_ICON: &str = "icons/ios-play.svg";
+
+/// Name used for hover tracking on the whole ad
+const AD_HOVER_AREA: &str = "ad";
+
+/// The resize that the toolbar's Resize button requests, relative to the ad's default position
+const TEST_RESIZE: ResizeProperties =
+    ResizeProperties { width: 600.0, height: 300.0, offset_x: -50.0, offset_y: 0.0, allow_offscreen: false };
+
+/// Layout of the panes for comparing variants. The bottom of the body is left for the caption and FPS.
+const PANE_SPACING: f32 = 10.0;
+const PANE_LABEL_H: f32 = 24.0;
+const PANES_BOTTOM_MARGIN: f32 = 40.0;
+
+const PROGRESS_W: f32 = 300.0;
+const PROGRESS_H: f32 = 8.0;
+
+/// The background color of the area around the ad
+const BODY_COLOR: &str = "#555555";
+
+/// Events from the viewer's toolbar
+#[derive(Clone, Debug, PartialEq)]
+pub enum ViewerEvent {
+    /// Show the ad in the size with this key in the sizes map, or in the native template with this name
+    SelectSize(String),
+    /// Move through the campaign previews or DCO variants by this many steps
+    StepPreview(isize),
+    /// Show the ad in the next locale of its string tables
+    NextLocale,
+}
+
+impl AnyEvent for ViewerEvent {}
 
 #[allow(dead_code)]
 pub struct AdViewer {
     frame: Rectangle,
     stage: Stage,
     ad_spec: AdSpec,
+    brand_kit: BrandKit,
+    click_config: ClickConfig,
+    asset_root: String,
+    /// Loads the creative's assets one per frame while the loading scene is displayed
+    loader: Option<AssetLoader>,
+    assets: Option<AdAssets>,
+    ad_frame: Rectangle,
+    tracker: Tracker,
+    playback: PlaybackProgress,
+    /// Time of the previous update, for advancing the playback clock
+    last_update: Option<Instant>,
+    viewability: ViewabilityMeter,
+    /// In page mode, the ad is placed in a mock publisher page
+    page_view: Option<PageView>,
+    /// The MRAID container state, which decides where the ad is displayed
+    mraid: MraidController,
+    /// In expandable mode, the sizes of the ad and the view that clips it
+    expandable: Option<ExpandableSpec>,
+    expandable_view: Option<ExpandableView>,
+    /// In a placement mode, the ad is shown in an interstitial, sticky anchor or sidebar rail
+    placement_view: Option<PlacementView>,
+    /// In native mode, the ad is assembled from components in a native template
+    native: Option<NativeAd>,
+    native_template: NativeTemplate,
+    /// In VAST mode, the companion banners are shown instead of the ad
+    companion_gallery: Option<CompanionGallery>,
+    /// In OpenRTB mode, the winning creative of a bid response is shown instead of the ad
+    bid_view: Option<BidView>,
+    /// In ad server mode, ads are requested for a placement from the mock ad server
+    ad_server: Option<MockAdServer>,
+    placement_id: String,
+    /// The reason when the ad server did not fill the request
+    no_fill: Option<String>,
+    /// In campaign mode, each creative is previewed at each of its trafficked sizes
+    campaign: Option<Campaign>,
+    campaign_previews: Vec<CreativePreview>,
+    /// In rotation mode, the impressions of a simulated user, which are played back in order
+    rotation: Vec<SimulatedImpression>,
+    /// In DCO mode, the template is filled with each row of its product feed at each of its sizes
+    dco: Option<DcoAdBuilder>,
+    dco_variants: Vec<DcoVariant>,
+    /// In variants mode, the variants of the ad are built side by side and play on the same clock
+    variants: Vec<TeapotAdBuilder>,
+    /// The string tables of the ad's copy and the locale that is shown
+    strings: Option<StringTables>,
+    locale: String,
+    /// Copy that does not fit its frame in the current locale and size
+    copy_warnings: Vec<String>,
+    /// The lint rules that the ad is checked against when it is built, and the checks that failed
+    lint_config: Option<LintConfig>,
+    lint_warnings: Vec<String>,
+    /// The creative's manifest, which is weighed at each size for the load rules
+    manifest: Option<AssetManifest>,
+    /// The load weight at each size by spec id, or None if it could not be measured
+    load_weights: HashMap<String, Option<LoadWeight>>,
+    /// The current campaign preview, rotation impression or DCO variant
+    preview_index: usize,
+    is_hovering: bool,
+    /// Set when the ad should be rebuilt on the next render, e.g. for a replay
+    needs_rebuild: bool,
+    /// AdEvents raised outside of update() that are sent to the EventBus on the next update
+    pending_events: Vec<AdEvent>,
+    /// MraidEvents for the creative, which are sent the same way
+    mraid_events: Vec<MraidEvent>,
 }
 
 impl AdViewer {
     pub fn new(frame: Rectangle) -> AdViewer {
         let stage = Stage::new(frame.clone());
         let ad_spec = AdSpec::default();
+        let body_frame = AdViewer::body_frame(&frame);
         let controller = AdViewer {
             frame,
             stage,
-            ad_spec,
+            ad_spec: ad_spec.clone(),
+            brand_kit: BrandKit::default(),
+            click_config: ClickConfig::default(),
+            asset_root: DEFAULT_ASSET_ROOT.to_string(),
+            loader: None,
+            assets: None,
+            ad_frame: Rectangle::new_sized((0.0, 0.0)),
+            tracker: Tracker::new("", ""),
+            playback: PlaybackProgress::new(teapot_duration()),
+            last_update: None,
+            viewability: ViewabilityMeter::for_ad_size(ad_spec.width, ad_spec.height),
+            page_view: None,
+            mraid: MraidController::new(PlacementType::Inline, body_frame),
+            expandable: None,
+            expandable_view: None,
+            placement_view: None,
+            native: None,
+            native_template: NativeTemplate::InFeed,
+            companion_gallery: None,
+            bid_view: None,
+            ad_server: None,
+            placement_id: String::new(),
+            no_fill: None,
+            campaign: None,
+            campaign_previews: Vec::new(),
+            rotation: Vec::new(),
+            dco: None,
+            dco_variants: Vec::new(),
+            variants: Vec::new(),
+            strings: None,
+            locale: DEFAULT_LOCALE.to_string(),
+            copy_warnings: Vec::new(),
+            lint_config: None,
+            lint_warnings: Vec::new(),
+            manifest: None,
+            load_weights: HashMap::new(),
+            preview_index: 0,
+            is_hovering: false,
+            needs_rebuild: false,
+            pending_events: Vec::new(),
+            mraid_events: Vec::new(),
         };
         controller
     }
 
-    fn assemble_stage(&mut self, frame: Rectangle, theme: &mut Theme, spec: AdSpec) -> Stage {
+    pub fn with_brand_kit(mut self, brand_kit: BrandKit) -> Self {
+        self.brand_kit = brand_kit;
+        self
+    }
+
+    pub fn with_asset_root(mut self, asset_root: &str) -> Self {
+        self.asset_root = asset_root.to_string();
+        self
+    }
+
+    /// Show the ad in the slot of a mock publisher page. The ad size is set by the slot.
+    pub fn with_mock_page(mut self, page: MockPage) -> Self {
+        let page_view = PageView::new(page, &AdViewer::body_frame(&self.frame));
+        self.ad_spec = page_view.ad_spec();
+        self.viewability = ViewabilityMeter::for_ad_size(self.ad_spec.width, self.ad_spec.height);
+        self.page_view = Some(page_view);
+        self
+    }
+
+    /// Load the trafficking setup for click-throughs. The AD_CLICK_CONFIG env var can point to another file
+    /// in order to check a different setup.
+    fn load_click_config(&mut self, default_path: &str) {
+        let path = match std::env::var("AD_CLICK_CONFIG") {
+            Ok(path) => path,
+            Err(_) => Path::new(&self.asset_root).join(default_path).to_string_lossy().to_string(),
+        };
+        match ClickConfig::load_file(&path) {
+            Ok(config) => self.click_config = config,
+            Err(e) => log::error!("Failed to load click config {}: {:?}", path, e),
+        }
+    }
+
+    /// The viewer never opens a click-through. It logs the URL that the browser would have gone to. In an HTML5
+    /// bundle, Html5Ad opens the exit's clickTag variable instead.
+    fn handle_click(&self, exit: &str) {
+        match self.click_config.expand_exit(exit) {
+            Ok(url) => log::info!("Click-through exit={} url={}", exit, url),
+            Err(e) => log::error!("Click-through exit={} failed: {:?}", exit, e),
+        }
+    }
+
+    /// Show an expandable ad, which starts at the collapsed size
+    pub fn with_expandable(mut self, spec: ExpandableSpec) -> Self {
+        self.ad_spec = spec.collapsed.clone();
+        self.viewability = ViewabilityMeter::for_ad_size(self.ad_spec.width, self.ad_spec.height);
+        self.expandable = Some(spec);
+        self
+    }
+
+    /// Show the ad in the chrome of a placement. The MRAID placement type and max size follow the placement.
+    pub fn with_placement(mut self, placement: Placement) -> Self {
+        let body_frame = AdViewer::body_frame(&self.frame);
+        let placement_view = PlacementView::new(placement, &body_frame);
+        self.ad_spec = placement.default_spec(&body_frame);
+        self.viewability = ViewabilityMeter::for_ad_size(self.ad_spec.width, self.ad_spec.height);
+        self.mraid = MraidController::new(placement.placement_type(), placement_view.max_size());
+        self.placement_view = Some(placement_view);
+        self
+    }
+
+    /// Build a native ad from its components instead of the Teapot ad. It starts in the in-feed template.
+    pub fn with_native(mut self, ad: NativeAd) -> Self {
+        self.set_native(ad);
+        self
+    }
+
+    fn set_native(&mut self, ad: NativeAd) {
+        for warning in ad.warnings() {
+            log::warn!("Native ad: {}", warning);
+        }
+        self.native_template = NativeTemplate::InFeed;
+        self.ad_spec = self.native_template.ad_spec();
+        self.viewability = ViewabilityMeter::for_ad_size(self.ad_spec.width, self.ad_spec.height);
+        self.native = Some(ad);
+    }
+
+    /// Show the companion banners of a VAST document, which was loaded from vast_path
+    pub fn with_vast<P: AsRef<Path>>(mut self, document: VastDocument, vast_path: P) -> Self {
+        self.set_vast(document, vast_path);
+        self
+    }
+
+    fn set_vast<P: AsRef<Path>>(&mut self, document: VastDocument, vast_path: P) {
+        for warning in document.warnings.iter() {
+            log::warn!("VAST: {}", warning);
+        }
+        for ad in document.ads.iter() {
+            let duration = ad.linear.as_ref().and_then(|linear| linear.duration).unwrap_or_default();
+            log::info!(
+                "VAST {} ad={:?} title={:?} duration={:.1}s companions={}",
+                document.version,
+                ad.id,
+                ad.ad_title,
+                duration,
+                ad.companions.len()
+            );
+            if let Some(uri) = &ad.wrapper_uri {
+                log::warn!("VAST ad {:?} is a wrapper for {}, which is not followed", ad.id, uri);
+            }
+        }
+        // The companions are shown once per VAST response, however often the stage is rebuilt
+        let gallery = CompanionGallery::new(document, vast_path);
+        gallery.track_views();
+        self.companion_gallery = Some(gallery);
+    }
+
+    /// Show the winning creative of a bid response, which was loaded from response_path
+    pub fn with_bid<P: AsRef<Path>>(mut self, result: AuctionResult, response_path: P) -> Self {
+        self.set_bid(result, response_path);
+        self
+    }
+
+    fn set_bid<P: AsRef<Path>>(&mut self, result: AuctionResult, response_path: P) {
+        let bid_view = BidView::new(result, response_path);
+        log::info!("OpenRTB auction={} winner: {}", bid_view.result().auction_id, bid_view.caption());
+        bid_view.log_auction();
+        self.ad_spec = bid_view.ad_spec();
+        self.bid_view = Some(bid_view);
+    }
+
+    /// Request ads for a placement from the mock ad server instead of showing the Teapot ad directly. The first
+    /// request is for the placement's first size, and the toolbar requests the other sizes.
+    pub fn with_ad_server(mut self, server: MockAdServer, placement_id: &str) -> Self {
+        let size = server.placement(placement_id).and_then(|placement| placement.sizes.first().cloned());
+        self.ad_server = Some(server);
+        self.placement_id = placement_id.to_string();
+        self.request_ad(size.unwrap_or(DEFAULT_SIZE));
+        self
+    }
+
+    /// Request an ad from the mock ad server and show the creative that it serves, or the no-fill message
+    fn request_ad(&mut self, size: (u32, u32)) {
+        let response = match &self.ad_server {
+            Some(server) => server.request(&AdRequest::new(&self.placement_id, size)),
+            None => return,
+        };
+        for (id, outcome) in response.attempts.iter() {
+            let placement = &self.placement_id;
+            log::info!("Ad request placement={} size={}x{} line_item={}: {}", placement, size.0, size.1, id, outcome);
+        }
+        match response.decision {
+            AdDecision::Fill(served) => {
+                if served.is_fallback {
+                    log::info!("Serving house ad {} for placement {}", served.creative.id, self.placement_id);
+                }
+                self.show_creative(&served.creative.id, served.creative.kind, &served.path, size);
+            }
+            AdDecision::NoFill(reason) => {
+                log::warn!("No fill: {}", reason);
+                self.clear_creative(AdSpec::new(size.0, size.1, &self.placement_id));
+                self.no_fill = Some(reason);
+                self.finish_creative_change();
+            }
+        }
+    }
+
+    /// Replace the creative with one of any kind at the size. A display creative's path is its asset root.
+    fn show_creative(&mut self, id: &str, kind: CreativeKind, path: &Path, size: (u32, u32)) {
+        self.clear_creative(AdSpec::new(size.0, size.1, id));
+        let path = path.to_string_lossy().to_string();
+        let result = match kind {
+            CreativeKind::Display => {
+                self.asset_root = path.clone();
+                self.load_weights.clear();
+                if self.assets.is_some() {
+                    // The assets are reloaded from the creative's asset root, which counts as a new impression
+                    self.assets = None;
+                    self.load_click_config(TEAPOT_CLICK_CONFIG);
+                    let loader = self.start_loading(TEAPOT_MANIFEST);
+                    self.stage = AdViewer::loading_stage(&self.frame, loader.progress(), loader.errors());
+                    self.loader = Some(loader);
+                }
+                Ok(())
+            }
+            CreativeKind::Native => NativeAd::load_file(&path).map(|ad| {
+                self.set_native(ad);
+                // Use the native template with the requested size, if there is one
+                let size_id = format!("{}x{}", size.0, size.1);
+                if let Some(template) = NativeTemplate::ALL.iter().find(|template| template.ad_spec().id == size_id) {
+                    self.native_template = *template;
+                    self.ad_spec = template.ad_spec();
+                }
+            }),
+            CreativeKind::Vast => VastDocument::load_file(&path).map(|document| self.set_vast(document, &path)),
+            CreativeKind::Openrtb => BidResponse::load_file(&path)
+                .and_then(|response| response.auction(None))
+                .map(|result| self.set_bid(result, &path)),
+        };
+        if let Err(e) = result {
+            log::error!("Failed to load creative {} from {}: {:?}", id, path, e);
+            self.no_fill = Some(format!("Creative {} failed to load", id));
+        }
+        self.finish_creative_change();
+    }
+
+    /// Remove the current creative of any kind before another one is shown at the spec's size
+    fn clear_creative(&mut self, spec: AdSpec) {
+        self.native = None;
+        self.companion_gallery = None;
+        self.bid_view = None;
+        self.no_fill = None;
+        self.ad_spec = spec;
+    }
+
+    /// Rebuild the ad after the creative changed. Before the first load, the stage is built when loading completes.
+    fn finish_creative_change(&mut self) {
+        self.tracker.set_ad_size(&self.ad_spec.id);
+        self.viewability = ViewabilityMeter::for_ad_size(self.ad_spec.width, self.ad_spec.height);
+        if self.assets.is_some() {
+            self.needs_rebuild = true;
+            self.start_playback();
+            if self.no_fill.is_none() {
+                self.pending_events.push(AdEvent::Impression);
+            }
+        }
+    }
+
+    /// Browse the creatives of a campaign at each of their trafficked sizes, starting with the first one
+    pub fn with_campaign(mut self, campaign: Campaign) -> Self {
+        log::info!("Campaign {} ({}) for {}", campaign.name, campaign.id, campaign.advertiser);
+        for line_item in campaign.line_items.iter() {
+            let sizes: Vec<String> = line_item.sizes.iter().map(|(w, h)| format!("{}x{}", w, h)).collect();
+            log::info!(
+                "Line item {} flight={} sizes={} targeting: {}",
+                line_item.id,
+                line_item.flight(),
+                sizes.join(","),
+                line_item.targeting.summary()
+            );
+        }
+        self.campaign_previews = campaign.previews();
+        self.campaign = Some(campaign);
+        self.show_preview(0);
+        self
+    }
+
+    /// Play back the ads that a simulated user sees, one impression at a time
+    pub fn with_rotation(mut self, campaign: Campaign, impressions: Vec<SimulatedImpression>) -> Self {
+        for impression in impressions.iter() {
+            log::info!(
+                "Impression {}: line_item={} creative={} ({})",
+                impression.number,
+                impression.line_item.as_ref().map_or("none", |id| id.as_str()),
+                impression.creative_id.as_ref().map_or("none", |id| id.as_str()),
+                impression.reason
+            );
+        }
+        self.campaign = Some(campaign);
+        self.rotation = impressions;
+        self.show_preview(0);
+        self
+    }
+
+    /// Preview the variants of a DCO template, starting with the first row at the first size
+    pub fn with_dco(mut self, template: DcoTemplate, variants: Vec<DcoVariant>) -> Self {
+        self.dco = Some(DcoAdBuilder::new(self.brand_kit.clone(), template));
+        self.dco_variants = variants;
+        self.show_preview(0);
+        self
+    }
+
+    /// Compare the variants side by side. Playback lasts as long as the longest variant.
+    pub fn with_variants(mut self, variants: Vec<TeapotAdBuilder>) -> Self {
+        for builder in variants.iter() {
+            log::info!("Variant {}: {} ({:.1}s)", builder.variant().id, builder.variant().title(), builder.duration());
+        }
+        let duration = variants.iter().map(|builder| builder.duration()).fold(0.0, f64::max);
+        self.playback = PlaybackProgress::new(duration);
+        self.variants = variants;
+        self
+    }
+
+    /// Localize the ad's copy with the string tables, starting with the locale
+    pub fn with_strings(mut self, strings: StringTables, locale: &str) -> Self {
+        self.locale = match strings.table(locale) {
+            Some(table) => table.locale.clone(),
+            None => {
+                log::warn!("No string table for locale {}, using {}", locale, DEFAULT_LOCALE);
+                DEFAULT_LOCALE.to_string()
+            }
+        };
+        self.strings = Some(strings);
+        self
+    }
+
+    /// Check the ad against the lint rules every time it is built
+    pub fn with_lint(mut self, config: LintConfig) -> Self {
+        self.lint_config = Some(config);
+        self
+    }
+
+    /// The string table of the current locale, with the default text for missing keys
+    fn string_table(&self) -> StringTable {
+        self.strings.as_ref().map_or_else(StringTable::default, |strings| strings.resolve(&self.locale))
+    }
+
+    /// Show the ad in the next locale. This counts as a new impression.
+    fn next_locale(&mut self) {
+        if let Some(strings) = &self.strings {
+            self.locale = strings.next_locale(&self.locale).to_string();
+            log::info!("Locale: {}", self.locale);
+            self.needs_rebuild = true;
+            self.start_playback();
+            self.pending_events.push(AdEvent::Impression);
+        }
+    }
+
+    /// Show the campaign preview, rotation impression or DCO variant at the index, which wraps around at both ends
+    fn show_preview(&mut self, index: isize) {
+        let count = match (self.rotation.len(), self.dco_variants.len()) {
+            (0, 0) => self.campaign_previews.len(),
+            (0, count) => count,
+            (count, _) => count,
+        };
+        let count = count as isize;
+        if count == 0 {
+            return;
+        }
+        self.preview_index = ((index % count + count) % count) as usize;
+        if !self.rotation.is_empty() {
+            self.show_impression(self.rotation[self.preview_index].clone());
+            return;
+        }
+        if let Some(variant) = self.dco_variants.get(self.preview_index) {
+            let (width, height) = variant.size;
+            log::info!("DCO row {} at {}x{}: {} {}", variant.row, width, height, variant.product_name, variant.price);
+            self.clear_creative(AdSpec::new(width, height, "DCO variant"));
+            self.finish_creative_change();
+            return;
+        }
+        let preview = self.campaign_previews[self.preview_index].clone();
+        let creative = self.campaign.as_ref().and_then(|campaign| {
+            let creative = campaign.creative(&preview.creative_id)?;
+            Some((creative.kind, campaign.creative_path(creative)))
+        });
+        if let Some((kind, path)) = creative {
+            log::info!("Preview {} at {}x{}", preview.creative_id, preview.size.0, preview.size.1);
+            self.show_creative(&preview.creative_id, kind, &path, preview.size);
+        }
+    }
+
+    /// Move through the previews by the steps. In DCO mode, the rows of the feed wrap around at the current size.
+    fn step_preview(&mut self, steps: isize) {
+        if let Some(variant) = self.dco_variants.get(self.preview_index) {
+            let size = variant.size;
+            let first = self.dco_variants.iter().position(|v| v.size == size).unwrap_or(0);
+            let rows = self.dco_variants.iter().filter(|v| v.size == size).count() as isize;
+            let offset = (self.preview_index - first) as isize + steps;
+            self.show_preview(first as isize + (offset % rows + rows) % rows);
+            return;
+        }
+        self.show_preview(self.preview_index as isize + steps);
+    }
+
+    /// Show the creative of a simulated impression, or an empty slot with the reason when there was no ad
+    fn show_impression(&mut self, impression: SimulatedImpression) {
+        let creative = self.campaign.as_ref().and_then(|campaign| {
+            let creative = campaign.creative(impression.creative_id.as_ref()?)?;
+            Some((creative.id.clone(), creative.kind, campaign.creative_path(creative)))
+        });
+        match creative {
+            Some((id, kind, path)) => self.show_creative(&id, kind, &path, impression.size),
+            None => {
+                self.clear_creative(AdSpec::new(impression.size.0, impression.size.1, "No ad"));
+                self.no_fill = Some(impression.reason);
+                self.finish_creative_change();
+            }
+        }
+    }
+
+    /// Move to the next impression when the current one has been shown for long enough. Playback stops at the end.
+    fn update_rotation(&mut self) {
+        let has_next = self.preview_index + 1 < self.rotation.len();
+        if has_next && self.playback.elapsed() >= self.creative_duration() + IMPRESSION_PAUSE {
+            self.show_preview(self.preview_index as isize + 1);
+        }
+    }
+
+    /// A caption for the campaign preview, with the line items that traffic the creative at this size. In rotation
+    /// mode, the caption is for the impression.
+    fn preview_caption(&self) -> Option<String> {
+        if !self.variants.is_empty() {
+            return Some(format!("{} variants at {} / Play replays all of them", self.variants.len(), self.ad_spec.id));
+        }
+        if let Some(variant) = self.dco_variants.get(self.preview_index) {
+            let rows = self.dco_variants.iter().filter(|v| v.size == variant.size).count();
+            let mut caption = format!(
+                "{} / row {} of {} / {}x{} / {}",
+                self.dco.as_ref().map_or("DCO", |dco| dco.template().name.as_str()),
+                variant.row,
+                rows,
+                variant.size.0,
+                variant.size.1,
+                variant.product_name
+            );
+            if !variant.warnings.is_empty() {
+                caption = format!("{} / {}", caption, variant.warnings.join(", "));
+            }
+            return Some(caption);
+        }
+        let campaign = self.campaign.as_ref()?;
+        if let Some(impression) = self.rotation.get(self.preview_index) {
+            return Some(format!(
+                "{} / impression {} of {} / {} {} {}x{} / {}",
+                campaign.name,
+                impression.number,
+                self.rotation.len(),
+                impression.line_item.as_ref().map_or("No line item", |id| id.as_str()),
+                impression.creative_id.as_ref().map_or("", |id| id.as_str()),
+                impression.size.0,
+                impression.size.1,
+                impression.reason
+            ));
+        }
+        let preview = self.campaign_previews.get(self.preview_index)?;
+        let creative = campaign.creative(&preview.creative_id)?;
+        let line_items: Vec<String> = preview
+            .line_items
+            .iter()
+            .filter_map(|id| campaign.line_item(id))
+            .map(|line_item| {
+                let weight = line_item.creatives.iter().find(|a| a.id == creative.id).map_or(0, |a| a.weight);
+                format!("{} ({}, weight {})", line_item.id, line_item.flight(), weight)
+            })
+            .collect();
+        Some(format!(
+            "{} / {} {}x{} ({} of {}) / {}",
+            campaign.name,
+            if creative.name.is_empty() { &creative.id } else { &creative.name },
+            preview.size.0,
+            preview.size.1,
+            self.preview_index + 1,
+            self.campaign_previews.len(),
+            line_items.join(", ")
+        ))
+    }
+
+    /// Change the ad size from the toolbar. This counts as a new impression.
+    fn select_size(&mut self, key: &str) {
+        // In DCO mode, a size shows the same feed row at that size
+        if !self.dco_variants.is_empty() {
+            let row = self.dco_variants[self.preview_index].row;
+            let size = parse_size(key);
+            let index = self.dco_variants.iter().position(|v| Some(v.size) == size && v.row == row);
+            if let Some(index) = index {
+                self.show_preview(index as isize);
+            }
+            return;
+        }
+        if self.ad_server.is_some() {
+            if let Some(size) = parse_size(key) {
+                self.request_ad(size);
+            }
+            return;
+        }
+        if self.companion_gallery.is_some() || self.bid_view.is_some() {
+            return;
+        }
+        if self.page_view.is_some() {
+            log::warn!("The ad size is set by the mock page's ad slot");
+            return;
+        }
+        if self.is_interstitial() {
+            log::warn!("Interstitials always fill the screen");
+            return;
+        }
+        if self.native.is_some() {
+            match NativeTemplate::from_name(key) {
+                Some(template) => {
+                    self.native_template = template;
+                    self.ad_spec = template.ad_spec();
+                }
+                None => return,
+            }
+        } else if self.expandable.is_some() {
+            match EXPANDABLE_SIZES_MAP.get(key) {
+                Some(spec) => {
+                    self.ad_spec = spec.collapsed.clone();
+                    self.expandable = Some(spec.clone());
+                }
+                None => return,
+            }
+        } else {
+            match AD_SIZES_MAP.get(key) {
+                Some(spec) => self.ad_spec = spec.clone(),
+                None => return,
+            }
+        }
+        log::info!("Ad size: {} {}", self.ad_spec.id, self.ad_spec.name);
+        self.tracker.set_ad_size(&self.ad_spec.id);
+        self.viewability = ViewabilityMeter::for_ad_size(self.ad_spec.width, self.ad_spec.height);
+        self.needs_rebuild = true;
+        self.start_playback();
+        self.pending_events.push(AdEvent::Impression);
+    }
+
+    /// The area below the toolbar
+    fn body_frame(frame: &Rectangle) -> Rectangle {
+        Rectangle::new((frame.x(), frame.y() + TOOLBAR_H), (frame.width(), frame.height() - TOOLBAR_H))
+    }
+
+    /// Apply a command from the creative to the MRAID container. The ad is rebuilt when its state changes.
+    fn handle_mraid_command(&mut self, command: &MraidCommand) {
+        log::debug!("MRAID command: {:?}", command);
+        let events = self.mraid.handle(command);
+        if events.iter().any(|e| if let MraidEvent::StateChange(_) = e { true } else { false }) {
+            self.needs_rebuild = true;
+        }
+        self.mraid_events.extend(events);
+    }
+
+    /// Restart the playback clock after the ad stage is built. Variants keep the duration of the longest one.
+    fn start_playback(&mut self) {
+        if self.variants.is_empty() {
+            self.playback = PlaybackProgress::new(self.creative_duration());
+        } else {
+            self.playback.reset();
+        }
+        self.last_update = None;
+    }
+
+    /// Seconds of playback for the current creative. Only the Teapot ad is animated.
+    fn creative_duration(&self) -> f64 {
+        let is_static = self.native.is_some()
+            || self.companion_gallery.is_some()
+            || self.bid_view.is_some()
+            || self.dco.is_some()
+            || self.no_fill.is_some();
+        if is_static {
+            STATIC_CREATIVE_DURATION
+        } else {
+            teapot_duration()
+        }
+    }
+
+    /// Seconds since the previous update
+    fn tick(&mut self) -> f64 {
+        let now = Instant::now();
+        let seconds = self.last_update.map_or(0.0, |last| now.duration_since(last).as_secs_f64());
+        self.last_update = Some(now);
+        seconds
+    }
+
+    /// Advance the playback clock and queue the progress events that were reached
+    fn update_playback(&mut self, seconds: f64) {
+        let events = self.playback.advance(seconds);
+        self.pending_events.extend(events);
+    }
+
+    fn is_interstitial(&self) -> bool {
+        self.placement_view.as_ref().map_or(false, |view| view.placement() == Placement::Interstitial)
+    }
+
+    /// A user closed the ad in its placement. MRAID hides the ad.
+    fn close_placement(&mut self) {
+        if let Some(view) = &mut self.placement_view {
+            view.close();
+            self.handle_mraid_command(&MraidCommand::Close);
+        }
+    }
+
+    /// Show a closed or hidden ad again
+    fn reopen(&mut self) {
+        if self.mraid.state() != MraidState::Hidden {
+            return;
+        }
+        if let Some(view) = &mut self.placement_view {
+            view.reopen();
+        }
+        self.mraid.reset();
+        let events = self.mraid.set_ready();
+        self.mraid_events.extend(events);
+    }
+
+    /// Advance the expand animation. The visible part of the ad is used for hover and viewability.
+    fn update_expandable(&mut self, seconds: f64) {
+        if let Some(view) = &mut self.expandable_view {
+            view.update(seconds);
+            self.ad_frame = view.visible_frame();
+        }
+    }
+
+    /// Measure how much of the ad is in view. Outside of page mode, the whole ad is always in view unless
+    /// it is hidden by MRAID.
+    fn update_viewability(&mut self, seconds: f64) {
+        let fraction = match &self.page_view {
+            _ if self.mraid.state() == MraidState::Hidden => 0.0,
+            Some(page_view) => fraction_in_view(&self.ad_frame, &page_view.viewport()),
+            None => 1.0,
+        };
+        let events = self.mraid.set_exposure(fraction);
+        self.mraid_events.extend(events);
+        let was_viewable = self.viewability.is_viewable();
+        for standard in self.viewability.update(fraction, seconds) {
+            log::info!(
+                "Viewability: {} met ({:.0}% for {:.1}s) at {:.1}s",
+                standard.name,
+                standard.min_fraction * 100.0,
+                standard.min_seconds,
+                self.playback.elapsed()
+            );
+        }
+        if self.viewability.is_viewable() && !was_viewable {
+            self.pending_events.push(AdEvent::Viewable);
+        }
+        if let Some(page_view) = &mut self.page_view {
+            let met = self.viewability.met_standards();
+            let met = if met.is_empty() { "not viewable".to_string() } else { met.join(", ") };
+            page_view.set_status(format!("In view: {:.0}% | {}", fraction * 100.0, met));
+        }
+    }
+
+    /// Create the loader for the creative's manifest. Missing files are logged and shown in the loading scene.
+    fn start_loading(&mut self, manifest_path: &str) -> AssetLoader {
+        let mut loader = AssetLoader::new(&self.asset_root);
+        match AssetManifest::load_file(loader.resolve(manifest_path)) {
+            Ok(manifest) => {
+                loader.enqueue(&manifest);
+                self.manifest = Some(manifest);
+                self.load_weights.clear();
+            }
+            Err(e) => loader.add_error(format!("Failed to load manifest {}: {:?}", manifest_path, e)),
+        }
+        if let Err(e) = self.brand_kit.load_fonts(&self.asset_root, &mut loader.assets_mut().fonts) {
+            log::error!("Failed to load fonts for brand kit {}: {:?}", self.brand_kit.name, e);
+        }
+        loader
+    }
+
+    /// A stage with a progress bar, or the list of errors if any assets failed to load
+    fn loading_stage(frame: &Rectangle, progress: f32, errors: &[String]) -> Stage {
+        let mut stage = Stage::new(frame.clone());
+        let mut scene = Scene::new(frame.clone()).with_id(LOADING_ID, "Loading");
+
+        let fill_color = Color::from_hex("#555555");
+        let mut shape = DrawShape::rectangle(&frame, Some(fill_color), None, 0.0, 0.0);
+        let bg = ShapeView::new(*frame, ShapeDef::Rectangle).with_mesh(&mut shape);
+        scene.add_view(Box::new(bg));
+
+        if errors.is_empty() {
+            let track = Rectangle::new((0.0, 0.0), (PROGRESS_W, PROGRESS_H));
+            let track = LayoutHelper::center_frame(frame, &track, Vector::ZERO);
+            let mut shape = DrawShape::rectangle(&track, Some(Color::from_hex("#333333")), None, 0.0, 0.0);
+            let view = ShapeView::new(track.clone(), ShapeDef::Rectangle).with_mesh(&mut shape);
+            scene.add_view(Box::new(view));
+
+            let bar = Rectangle::new(track.pos, (PROGRESS_W * progress, PROGRESS_H));
+            let mut shape = DrawShape::rectangle(&bar, Some(Color::WHITE), None, 0.0, 0.0);
+            let view = ShapeView::new(bar, ShapeDef::Rectangle).with_mesh(&mut shape);
+            scene.add_view(Box::new(view));
+
+            let subframe = Rectangle::new((track.x(), track.y() - 30.0), (PROGRESS_W, 20.0));
+            let mut text = Text::new(subframe, &format!("Loading... {:.0}%", progress * 100.0));
+            text.layer.font_style = FontStyle::new(14.0, Color::WHITE);
+            scene.add_control(Box::new(text));
+        } else {
+            let subframe = Rectangle::new((20.0, 20.0), (frame.width() - 40.0, frame.height() - 40.0));
+            let mut text = Text::new(subframe, &errors.join("\n"));
+            text.layer.font_style = FontStyle::new(14.0, Color::RED);
+            scene.add_control(Box::new(text));
+        }
+        stage.add_scene(scene);
+        stage
+    }
+
+    /// An empty ad slot at the requested size with the no-fill reason
+    fn no_fill_scene(body_frame: &Rectangle, spec: &AdSpec, reason: &str) -> Scene {
+        let mut scene = Scene::new(body_frame.clone()).with_id(NO_FILL_ID, "No Fill");
+        let slot = Rectangle::new_sized((spec.width, spec.height));
+        let slot = LayoutHelper::center_frame(body_frame, &slot, Vector::new(body_frame.x(), body_frame.y()));
+        add_rectangle(&mut scene, &slot, Some(Color::from_hex("#777777")), Some(Color::WHITE));
+        let subframe = Rectangle::new((slot.x() + 10.0, slot.y() + 10.0), (slot.width() - 20.0, 20.0));
+        let mut text = Text::new(subframe, &format!("No fill: {}", reason));
+        text.layer.font_style = FontStyle::new(14.0, Color::WHITE);
+        scene.add_control(Box::new(text));
+        scene
+    }
+
+    /// The copy warnings in a line above the caption
+    fn copy_warnings_scene(frame: &Rectangle, warnings: &[String]) -> Scene {
+        let mut scene = Scene::new(frame.clone()).with_id(COPY_WARNINGS_ID, "Copy Warnings");
+        let subframe = Rectangle::new((20.0, frame.height() - 60.0), (frame.width() - 40.0, 20.0));
+        let mut text = Text::new(subframe, &format!("Copy overflow: {}", warnings.join(", ")));
+        text.layer.font_style = FontStyle::new(12.0, Color::from_hex("#FFB74D"));
+        scene.add_control(Box::new(text));
+        scene
+    }
+
+    /// The failed lint checks in a line above the copy warnings
+    fn lint_warnings_scene(frame: &Rectangle, spec: &AdSpec, warnings: &[String]) -> Scene {
+        let mut scene = Scene::new(frame.clone()).with_id(LINT_WARNINGS_ID, "Lint Warnings");
+        let subframe = Rectangle::new((20.0, frame.height() - 80.0), (frame.width() - 40.0, 20.0));
+        let mut text = Text::new(subframe, &format!("Lint at {}: {}", spec.id, warnings.join(", ")));
+        text.layer.font_style = FontStyle::new(12.0, Color::from_hex("#EF5350"));
+        scene.add_control(Box::new(text));
+        scene
+    }
+
+    /// A line of text at the bottom of the viewer, next to the FPS
+    fn caption_scene(frame: &Rectangle, caption: &str) -> Scene {
+        let mut scene = Scene::new(frame.clone()).with_id(CAPTION_ID, "Caption");
+        let subframe = Rectangle::new((120.0, frame.height() - 40.0), (frame.width() - 140.0, 20.0));
+        let mut text = Text::new(subframe, caption);
+        text.layer.font_style = FontStyle::new(12.0, Color::WHITE);
+        scene.add_control(Box::new(text));
+        scene
+    }
+
+    fn assemble_stage(&mut self, frame: Rectangle, spec: AdSpec) -> Stage {
         let mut stage = Stage::new(frame.clone());
 
         // 1. Set up tools and background
         let tools_bg = self.tools_scene(&frame);
         stage.add_scene(tools_bg);
+        if let Some(caption) = self.preview_caption() {
+            stage.add_scene(AdViewer::caption_scene(&frame, &caption));
+        }
+
+        // In VAST mode, the companions take the place of the ad
+        if let Some(gallery) = &self.companion_gallery {
+            let mut svg_cache = SvgCache::new(&self.asset_root);
+            stage.add_scene(gallery.scene(&AdViewer::body_frame(&frame), &mut svg_cache));
+            return stage;
+        }
+        // When the ad server has no ad for the placement, the slot shows why
+        if let Some(reason) = &self.no_fill {
+            stage.add_scene(AdViewer::no_fill_scene(&AdViewer::body_frame(&frame), &self.ad_spec, reason));
+            return stage;
+        }
+        // In OpenRTB mode, the winning creative takes the place of the ad
+        if let Some(bid_view) = &mut self.bid_view {
+            let mut svg_cache = SvgCache::new(&self.asset_root);
+            stage.add_scene(bid_view.scene(&AdViewer::body_frame(&frame), &mut svg_cache));
+            bid_view.track_render();
+            return stage;
+        }
+
+        // In variants mode, every variant is built into the same stage, so that their timelines start together
+        self.copy_warnings.clear();
+        self.lint_warnings.clear();
+        if !self.variants.is_empty() {
+            self.add_variant_panes(&mut stage, &AdViewer::body_frame(&frame));
+            if !self.copy_warnings.is_empty() {
+                stage.add_scene(AdViewer::copy_warnings_scene(&frame, &self.copy_warnings));
+            }
+            return stage;
+        }
 
         // 2. Build ad
-        let body_frame = Rectangle::new((0.0, TOOLBAR_H), (frame.width(), frame.height() - TOOLBAR_H));
-        let mut ad_frame = Rectangle::new_sized((self.ad_spec.width, self.ad_spec.height));
+        let default_frame = match (&self.page_view, &mut self.placement_view) {
+            (Some(page_view), _) => {
+                // In page mode, the ad goes in the page's ad slot
+                stage.add_scene(page_view.page_scene());
+                page_view.ad_frame()
+            }
+            (None, Some(placement_view)) => {
+                let ad_frame = placement_view.layout(&self.ad_spec);
+                stage.add_scene(placement_view.chrome_scene());
+                ad_frame
+            }
+            (None, None) => {
+                let body_frame = Rectangle::new((0.0, TOOLBAR_H), (frame.width(), frame.height() - TOOLBAR_H));
+                // An expandable ad is centered by its expanded size, so that it has room to expand
+                let (width, height) = match &self.expandable {
+                    Some(expandable) => (expandable.expanded.width, expandable.expanded.height),
+                    None => (self.ad_spec.width, self.ad_spec.height),
+                };
+                let ad_frame = Rectangle::new_sized((width, height));
 
-        // Center the ad_frame inside the body_frame offset by the toolbar height
-        let offset = Vector::new(0.0, TOOLBAR_H);
-        let ad_frame = LayoutHelper::center_frame(&body_frame, &ad_frame, offset);
+                // Center the ad_frame inside the body_frame offset by the toolbar height
+                let offset = Vector::new(0.0, TOOLBAR_H);
+                let ad_frame = LayoutHelper::center_frame(&body_frame, &ad_frame, offset);
+                match &self.expandable {
+                    Some(expandable) => expandable.collapsed_frame(&ad_frame),
+                    None => ad_frame,
+                }
+            }
+        };
+
+        // MRAID moves and sizes the ad when it is expanded, resized or hidden
+        self.mraid.set_default_position(default_frame.clone());
+        let mut ad_frame = self.mraid.current_position();
+        self.ad_frame = ad_frame.clone();
+        self.expandable_view = None;
+        if self.mraid.state() == MraidState::Hidden {
+            return stage;
+        }
+        let mut spec = if ad_frame.size == default_frame.size {
+            spec
+        } else {
+            let name = format!("MRAID {}", self.mraid.state().name());
+            AdSpec::new(ad_frame.width() as u32, ad_frame.height() as u32, &name)
+        };
+
+        // Expandable ads are built at the expanded size and clipped to the collapsed frame until they expand
+        if let (Some(expandable), MraidState::Default) = (&self.expandable, self.mraid.state()) {
+            ad_frame = expandable.expanded_frame(&default_frame);
+            spec = expandable.expanded.clone();
+            let bg_color = match &self.page_view {
+                Some(page_view) => page_view.bg_color(),
+                None => Color::from_hex(BODY_COLOR),
+            };
+            self.expandable_view = Some(ExpandableView::new(expandable.clone(), &ad_frame, bg_color));
+        }
 
         // Finish the Stage using the specified ad
-        let mut builder = TeapotAdBuilder {};
-        builder.build_stage(&mut stage, &ad_frame, theme, &spec);
+        if let (Some(assets), Some(dco)) = (&mut self.assets, &self.dco) {
+            if let Some(variant) = self.dco_variants.get(self.preview_index) {
+                dco.build_stage(&mut stage, &ad_frame, variant, assets);
+            }
+            return stage;
+        }
+        let mut lint_facts = None;
+        match (&mut self.assets, &self.native) {
+            (Some(assets), Some(native)) => {
+                let body_frame = AdViewer::body_frame(&frame);
+                stage.add_scene(native_feed_scene(self.native_template, &body_frame, &ad_frame));
+                let mut builder = NativeAdBuilder::new(self.brand_kit.clone(), native.clone(), self.native_template);
+                builder.build_stage(&mut stage, &ad_frame, assets);
+            }
+            (Some(assets), None) => {
+                let mut builder = TeapotAdBuilder::new(self.brand_kit.clone())
+                    .with_strings(self.string_table())
+                    .with_frame_rate(env_frame_rate());
+                builder.build_stage(&mut stage, &ad_frame, &spec, assets);
+                self.copy_warnings = builder.warnings().to_vec();
+                lint_facts = Some(builder.lint_facts(&spec));
+            }
+            (None, _) => log::error!("Cannot build ad before assets are loaded"),
+        }
+        if let Some(facts) = lint_facts {
+            self.lint_ad(facts, &spec);
+        }
+        if !self.copy_warnings.is_empty() {
+            stage.add_scene(AdViewer::copy_warnings_scene(&frame, &self.copy_warnings));
+        }
+        if !self.lint_warnings.is_empty() {
+            stage.add_scene(AdViewer::lint_warnings_scene(&frame, &spec, &self.lint_warnings));
+        }
+        // Interstitial placements have their own close control with a countdown
+        if self.mraid.needs_close_control() && !self.is_interstitial() {
+            stage.add_scene(self.mraid_close_scene());
+        }
 
         stage
     }
 
+    /// Check the ad that was just built against the lint rules, with the load weight of its manifest, which is
+    /// measured once per size
+    fn lint_ad(&mut self, facts: CreativeFacts, spec: &AdSpec) {
+        let config = match &self.lint_config {
+            Some(config) => config,
+            None => return,
+        };
+        let (manifest, brand_kit, asset_root) = (&self.manifest, &self.brand_kit, &self.asset_root);
+        let weight = self.load_weights.entry(spec.id.clone()).or_insert_with(|| {
+            let manifest = manifest.as_ref()?;
+            LoadWeight::measure(manifest, asset_root, &spec.id)
+                .and_then(|weight| weight.with_brand_fonts(brand_kit, asset_root))
+                .map_err(|e| log::error!("Failed to weigh {}: {:?}", manifest.name, e))
+                .ok()
+        });
+        let facts = CreativeFacts {
+            initial_load_bytes: weight.as_ref().map(|weight| weight.total(LoadPhase::Initial)),
+            polite_load_bytes: weight.as_ref().map(|weight| weight.total(LoadPhase::Polite)),
+            ..facts
+        };
+        let report = lint(&facts, &config.rules_for(&spec.id));
+        log::info!("{}", report);
+        self.lint_warnings = report.failures().iter().map(|check| format!("{} {}", check.rule, check.detail)).collect();
+    }
+
+    /// Build each variant at the current ad size in its own pane, with the variant name above it. The ads are
+    /// scaled down when the panes are too small.
+    fn add_variant_panes(&mut self, stage: &mut Stage, body_frame: &Rectangle) {
+        let area = Rectangle::new(body_frame.pos, (body_frame.width(), body_frame.height() - PANES_BOTTOM_MARGIN));
+        let ad_size = (self.ad_spec.width, self.ad_spec.height);
+        let (panes, scale) = variant_panes(&area, self.variants.len(), ad_size);
+        let (width, height) = (ad_size.0 * scale, ad_size.1 * scale);
+
+        let mut labels = Scene::new(body_frame.clone()).with_id(VARIANTS_ID, "Variants");
+        let mut ad_frames = Vec::new();
+        let strings = self.string_table();
+        for (pane, builder) in panes.iter().zip(self.variants.iter_mut()) {
+            let subframe = Rectangle::new(pane.pos, (pane.width(), PANE_LABEL_H));
+            let title = format!("{} ({:.1}s)", builder.variant().title(), builder.duration());
+            let mut text = Text::new(subframe, &title);
+            text.layer.font_style = FontStyle::new(14.0, Color::WHITE);
+            labels.add_control(Box::new(text));
+
+            let x = pane.x() + (pane.width() - width) / 2.0;
+            let y = pane.y() + PANE_LABEL_H + (pane.height() - PANE_LABEL_H - height) / 2.0;
+            let ad_frame = Rectangle::new((x, y), (width, height));
+            let spec = AdSpec::new(width.round() as u32, height.round() as u32, builder.variant().title());
+            builder.set_strings(strings.clone());
+            match &mut self.assets {
+                Some(assets) => builder.build_stage(stage, &ad_frame, &spec, assets),
+                None => log::error!("Cannot build ad before assets are loaded"),
+            }
+            let id = &builder.variant().id;
+            self.copy_warnings.extend(builder.warnings().iter().map(|warning| format!("{} {}", id, warning)));
+            ad_frames.push(ad_frame);
+        }
+        stage.add_scene(labels);
+
+        // Hover and viewability are measured over all of the panes together
+        if let Some(first) = ad_frames.first() {
+            let max_x = ad_frames.iter().map(|f| f.x() + f.width()).fold(0.0, f32::max);
+            let max_y = ad_frames.iter().map(|f| f.y() + f.height()).fold(0.0, f32::max);
+            self.ad_frame = Rectangle::new(first.pos, (max_x - first.x(), max_y - first.y()));
+        }
+    }
+
+    /// The close control that the MRAID container draws over expanded ads
+    fn mraid_close_scene(&self) -> Scene {
+        let region = self.mraid.close_region();
+        let mut scene = Scene::new(region.clone()).with_id(MRAID_CLOSE_ID, "MRAID Close");
+        let frame = Rectangle::new((region.x() + 10.0, region.y() + 10.0), (30.0, 30.0));
+        let mut button = Button::new(frame).with_text("X");
+        button.layer.font_style = FontStyle::new(16.0, Color::WHITE);
+        button.layer.bg_style = BackgroundStyle::Solid(Color::BLACK.with_alpha(0.6));
+        button.layer.corner_radius = 15.0;
+        button.set_onclick(move |state| {
+            state.event_bus.register_event(MraidCommand::Close);
+        });
+        scene.add_control(Box::new(button));
+        scene
+    }
+
     /// Setup toolbar buttons
     fn tools_scene(&mut self, frame: &Rectangle) -> Scene {
 
         let mut scene = Scene::new(frame.clone()).with_id(TOOLBAR_ID, "Toolbar");
 
-        let fill_color = Color::from_hex("#555555");
+        let fill_color = Color::from_hex(BODY_COLOR);
         let mut shape = DrawShape::rectangle(&frame, Some(fill_color), None, 0.0, 0.0);
         let bg = ShapeView::new(*frame, ShapeDef::Rectangle).with_mesh(&mut shape);
         scene.add_view(Box::new(bg));
@@ -94,24 +1157,84 @@ impl AdViewer {
         let ypos = (rect.height() - TOOLBAR_BTN_H) / 2.0;
 
         // Toolbar buttons to add/remove
+        let placement = self.ad_server.as_ref().and_then(|server| server.placement(&self.placement_id));
+        let keys: Vec<String> = match (&self.native, &self.expandable) {
+            _ if self.campaign.is_some() => Vec::new(),
+            // In DCO mode, the toolbar shows the template's sizes
+            _ if self.dco.is_some() => {
+                let sizes = self.dco.as_ref().map_or(&[][..], |dco| &dco.template().sizes[..]);
+                sizes.iter().map(|(w, h)| format!("{}x{}", w, h)).collect()
+            }
+            // In ad server mode, the toolbar requests the placement's sizes
+            _ if self.ad_server.is_some() => {
+                placement.map_or(Vec::new(), |p| p.sizes.iter().map(|(w, h)| format!("{}x{}", w, h)).collect())
+            }
+            _ if self.companion_gallery.is_some() || self.bid_view.is_some() => Vec::new(),
+            (Some(_), _) => NativeTemplate::ALL.iter().map(|template| template.name().to_string()).collect(),
+            (None, Some(_)) => EXPANDABLE_SIZES_MAP.keys().map(|key| key.to_string()).collect(),
+            (None, None) => AD_SIZES_MAP.keys().map(|key| key.to_string()).collect(),
+        };
+        let mut buttons: Vec<(String, ViewerEvent)> =
+            keys.into_iter().map(|key| (key.clone(), ViewerEvent::SelectSize(key))).collect();
+        if self.campaign.is_some() || self.dco.is_some() {
+            buttons.push(("Prev".to_string(), ViewerEvent::StepPreview(-1)));
+            buttons.push(("Next".to_string(), ViewerEvent::StepPreview(1)));
+        }
+        // The locale button shows the current locale and switches to the next one
+        let is_teapot = self.native.is_none() && self.dco.is_none() && self.no_fill.is_none();
+        if self.strings.is_some() && is_teapot && self.companion_gallery.is_none() && self.bid_view.is_none() {
+            buttons.push((self.locale.to_uppercase(), ViewerEvent::NextLocale));
+        }
+        for (title, event) in buttons {
+            let subframe = scene.sub_frame((xpos, ypos), (TOOLBAR_BTN_W, TOOLBAR_BTN_H));
+            let mut button = Button::new(subframe).with_text(&title);
+            button.layer.font_style = FontStyle::new(14.0, Color::BLACK);
+            button.layer.corner_radius = 3.0;
+            button.set_onclick(move |state| {
+                state.event_bus.register_event(event.clone());
+            });
+            scene.add_control(Box::new(button));
+            xpos += (TOOLBAR_BTN_W + SPACING);
+        }
 
-        for (key, props) in AD_SIZES_MAP.iter() {
+        // MRAID test buttons send the same commands that a creative would
+        let commands = [
+            ("Expand", vec![MraidCommand::Expand]),
+            ("Resize", vec![MraidCommand::SetResizeProperties(TEST_RESIZE), MraidCommand::Resize]),
+            ("Close", vec![MraidCommand::Close]),
+        ];
+        for (title, commands) in commands.iter() {
             let subframe = scene.sub_frame((xpos, ypos), (TOOLBAR_BTN_W, TOOLBAR_BTN_H));
-            let mut button = Button::new(subframe).with_text(key);
+            let mut button = Button::new(subframe).with_text(title);
             button.layer.font_style = FontStyle::new(14.0, Color::BLACK);
             button.layer.corner_radius = 3.0;
+            let commands = commands.clone();
+            button.set_onclick(move |state| {
+                for command in commands.iter() {
+                    state.event_bus.register_event(command.clone());
+                }
+            });
             scene.add_control(Box::new(button));
-            // TODO: add callback
             xpos += (TOOLBAR_BTN_W + SPACING);
         }
 
-        let img = DrawImage::load_image_file("icons/ios-play.png").unwrap();
-        println!(">>> load_image_file {}", img.raw_pixels().len());
+        // Icons are rasterized and cached in the asset root like the ad's SVGs
+        let mut svg_cache = SvgCache::new(&self.asset_root);
+        let icon_size = (TOOLBAR_BTN_H as u32 - 8, TOOLBAR_BTN_H as u32 - 8);
 
         let subframe = scene.sub_frame((frame.width() - 68.0, ypos), (60.0, TOOLBAR_BTN_H));
         let mut label = Label::new(subframe.clone());
-        label.set_image(img);
-        label.display = LabelDisplay::Image;
+        match svg_cache.load_image(PLAY_ICON, icon_size) {
+            Ok(img) => {
+                label.set_image(img);
+                label.display = LabelDisplay::Image;
+            }
+            Err(e) => {
+                log::error!("Failed to load icon {}: {:?}", PLAY_ICON, e);
+                label.set_text("Play");
+                label.display = LabelDisplay::Text;
+            }
+        }
 
         let mut button = Button::new(subframe);
         button.set_label(label);
@@ -207,11 +1330,44 @@ impl AdViewer {
     }
 }
 
+/// Split the area into one pane per variant, as columns or as rows, whichever shows the ad at a larger scale. Each
+/// pane has room for a label above the ad. Returns the panes and the scale of the ad, which is at most 1.0.
+fn variant_panes(area: &Rectangle, count: usize, ad_size: (f32, f32)) -> (Vec<Rectangle>, f32) {
+    let n = count.max(1) as f32;
+    let fit = |pane: (f32, f32)| (pane.0 / ad_size.0).min((pane.1 - PANE_LABEL_H) / ad_size.1).min(1.0).max(0.0);
+    let column = ((area.width() - PANE_SPACING * (n + 1.0)) / n, area.height() - PANE_SPACING * 2.0);
+    let row = (area.width() - PANE_SPACING * 2.0, (area.height() - PANE_SPACING * (n + 1.0)) / n);
+    let use_columns = fit(column) >= fit(row);
+    let pane = if use_columns { column } else { row };
+    let extent = if use_columns { pane.0 } else { pane.1 };
+    let panes = (0..count)
+        .map(|i| {
+            let offset = PANE_SPACING + (extent + PANE_SPACING) * i as f32;
+            let (x, y) = if use_columns { (offset, PANE_SPACING) } else { (PANE_SPACING, offset) };
+            Rectangle::new((area.x() + x, area.y() + y), pane)
+        })
+        .collect();
+    (panes, fit(pane))
+}
+
+/// Parse a toolbar key like 300x250
+fn parse_size(key: &str) -> Option<(u32, u32)> {
+    let mut parts = key.split('x');
+    let width = parts.next()?.parse().ok()?;
+    let height = parts.next()?.parse().ok()?;
+    Some((width, height))
+}
+
 impl Controller for AdViewer {
 
     fn view_will_load(&mut self, theme: &mut Theme) {
-        self.stage = self.assemble_stage(self.frame.clone(), theme, AdSpec::default());
-        self.stage.notify(&DisplayEvent::Ready);
+        self.load_click_config(TEAPOT_CLICK_CONFIG);
+        // The AD_TRACKING env var configures the tracking sinks. See Tracker for the format.
+        let sinks = std::env::var("AD_TRACKING").unwrap_or("console".to_string());
+        self.tracker = Tracker::new("Teapot", &self.ad_spec.id).with_sinks(&sinks);
+        let loader = self.start_loading(TEAPOT_MANIFEST);
+        self.stage = AdViewer::loading_stage(&self.frame, loader.progress(), loader.errors());
+        self.loader = Some(loader);
     }
 
     fn set_theme(&mut self, theme: &mut Theme) {
@@ -223,32 +1379,181 @@ impl Controller for AdViewer {
     }
 
     fn handle_event(&mut self, event: &EventBox) {
+        if let Ok(evt) = event.downcast_ref::<AdEvent>() {
+            self.tracker.track(evt);
+            match evt {
+                AdEvent::Click(exit) => self.handle_click(exit),
+                AdEvent::Expand | AdEvent::Collapse => {
+                    if let Some(view) = &mut self.expandable_view {
+                        view.set_expanded(*evt == AdEvent::Expand);
+                    }
+                }
+                AdEvent::Close => self.close_placement(),
+                _ => (),
+            }
+        }
+        match event.downcast_ref::<ViewerEvent>() {
+            Ok(ViewerEvent::SelectSize(key)) => self.select_size(key),
+            Ok(ViewerEvent::StepPreview(steps)) => self.step_preview(*steps),
+            Ok(ViewerEvent::NextLocale) => self.next_locale(),
+            _ => (),
+        }
+        if let Ok(PlayerEvent::Play) = event.downcast_ref::<PlayerEvent>() {
+            if self.assets.is_some() {
+                self.reopen();
+                self.needs_rebuild = true;
+                self.start_playback();
+                self.pending_events.push(AdEvent::Replay);
+            }
+        }
+        if let Ok(CompanionEvent::Click(index)) = event.downcast_ref::<CompanionEvent>() {
+            if let Some(gallery) = &self.companion_gallery {
+                gallery.handle_click(*index);
+            }
+        }
+        if let Ok(BidEvent::Click) = event.downcast_ref::<BidEvent>() {
+            if let Some(bid_view) = &self.bid_view {
+                bid_view.handle_click();
+            }
+        }
+        if let Ok(command) = event.downcast_ref::<MraidCommand>() {
+            self.handle_mraid_command(command);
+        }
+        if let Ok(evt) = event.downcast_ref::<MraidEvent>() {
+            log::debug!("MRAID event: {:?}", evt);
+        }
         self.stage.handle_event(event);
     }
 
     fn update(&mut self, window: &mut Window, state: &mut AppState) {
+        if let Some(loader) = &mut self.loader {
+            if loader.errors().is_empty() && !loader.is_complete() {
+                loader.load_next();
+                self.stage = AdViewer::loading_stage(&self.frame, loader.progress(), loader.errors());
+            }
+        }
+        if self.assets.is_some() {
+            let seconds = self.tick();
+            self.update_playback(seconds);
+            self.update_rotation();
+            self.update_expandable(seconds);
+            if let Some(view) = &mut self.placement_view {
+                view.update(seconds);
+            }
+            self.update_viewability(seconds);
+        }
+        for event in self.pending_events.drain(..) {
+            state.event_bus.register_event(event);
+        }
+        for event in self.mraid_events.drain(..) {
+            state.event_bus.register_event(event);
+        }
         self.stage.update(window, state);
     }
 
     fn render(&mut self, theme: &mut Theme, window: &mut Window) {
+        // The ad stage needs the theme, so it is assembled here on the first frame after loading completes
+        let is_loaded = self.loader.as_ref().map_or(false, |loader| loader.is_complete() && loader.errors().is_empty());
+        if is_loaded {
+            if let Some(loader) = self.loader.take() {
+                match loader.finish() {
+                    Ok(assets) => {
+                        self.assets = Some(assets);
+                        self.stage = self.assemble_stage(self.frame.clone(), self.ad_spec.clone());
+                        self.stage.set_theme(theme);
+                        self.stage.notify(&DisplayEvent::Ready);
+                        self.start_playback();
+                        self.pending_events.push(AdEvent::Impression);
+                        let events = self.mraid.set_ready();
+                        self.mraid_events.extend(events);
+                    }
+                    Err(e) => log::error!("Failed to load assets: {:?}", e),
+                }
+            }
+        }
+        if self.needs_rebuild {
+            self.needs_rebuild = false;
+            self.stage = self.assemble_stage(self.frame.clone(), self.ad_spec.clone());
+            self.stage.set_theme(theme);
+            self.stage.notify(&DisplayEvent::Ready);
+        }
         self.stage.render(theme, window);
+        if let Some(view) = &mut self.expandable_view {
+            view.render(theme, window);
+        }
+        if let Some(view) = &mut self.placement_view {
+            view.render(theme, window);
+        }
+        if let Some(page_view) = &mut self.page_view {
+            page_view.render(theme, window);
+        }
     }
 
     fn handle_mouse_at(&mut self, pt: &Vector, window: &mut Window) -> bool {
+        let is_hovering = self.assets.is_some() && self.ad_frame.contains(*pt);
+        if is_hovering && !self.is_hovering {
+            self.pending_events.push(AdEvent::Hover(AD_HOVER_AREA.to_string()));
+        }
+        self.is_hovering = is_hovering;
+        if let Some(view) = &mut self.expandable_view {
+            // Hover expandables expand when the mouse enters and collapse when it leaves the expanded ad
+            if view.spec().trigger == ExpandTrigger::Hover {
+                if is_hovering && view.set_expanded(true) {
+                    self.pending_events.push(AdEvent::Expand);
+                } else if !view.expanded_frame().contains(*pt) && view.set_expanded(false) {
+                    self.pending_events.push(AdEvent::Collapse);
+                }
+            }
+            if view.handle_mouse_at(pt, window) {
+                return true;
+            }
+        }
+        if let Some(view) = &mut self.placement_view {
+            if view.handle_mouse_at(pt, window) {
+                return true;
+            }
+        }
         self.stage.handle_mouse_at(pt, window)
 
     }
 
     fn handle_mouse_down(&mut self, pt: &Vector, state: &mut AppState) -> bool {
         println!(">>> handle_mouse_down");
+        if let Some(view) = &mut self.expandable_view {
+            if view.handle_mouse_down(pt, state) {
+                return true;
+            }
+            let is_click = view.spec().trigger == ExpandTrigger::Click;
+            if is_click && self.ad_frame.contains(*pt) && view.set_expanded(true) {
+                self.pending_events.push(AdEvent::Expand);
+            }
+        }
+        if let Some(view) = &mut self.placement_view {
+            if view.handle_mouse_down(pt, state) {
+                return true;
+            }
+        }
         self.stage.handle_mouse_down(pt, state)
     }
 
     fn handle_mouse_up(&mut self, pt: &Vector, state: &mut AppState) -> bool {
+        if let Some(view) = &mut self.expandable_view {
+            if view.handle_mouse_up(pt, state) {
+                return true;
+            }
+        }
+        if let Some(view) = &mut self.placement_view {
+            if view.handle_mouse_up(pt, state) {
+                return true;
+            }
+        }
         self.stage.handle_mouse_up(pt, state)
     }
 
     fn handle_mouse_scroll(&mut self, pt: &Vector, state: &mut AppState) {
+        if let Some(page_view) = &mut self.page_view {
+            page_view.scroll_by(pt.y);
+        }
         self.stage.handle_mouse_scroll(pt, state);
     }
 }
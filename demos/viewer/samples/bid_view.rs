@@ -0,0 +1,192 @@
+/// Displays the winning creative of an OpenRTB auction at the impression size, centered in the body, with a
+/// caption for the bid. Image markup with a local src is loaded from disk, relative to the bid response file, and
+/// other markup is drawn as a placeholder that shows the markup.
+use super::*;
+use crate::ads::*;
+use crate::app::*;
+
+use quicksilver::{
+    geom::{Rectangle, Vector},
+    graphics::Color,
+    Error,
+};
+
+use tweek::{
+    gui::*,
+    tools::*,
+};
+
+use std::path::{Path, PathBuf};
+
+const BID_VIEW_ID: u32 = 450;
+
+const SPACING: f32 = 20.0;
+const CAPTION_H: f32 = 18.0;
+
+pub struct BidView {
+    result: AuctionResult,
+    /// The folder of the bid response, for resolving relative paths
+    base_dir: PathBuf,
+    /// True after the billing notice was logged, since it is only sent once per impression
+    is_rendered: bool,
+}
+
+impl BidView {
+    pub fn new<P: AsRef<Path>>(result: AuctionResult, response_path: P) -> Self {
+        let base_dir = response_path.as_ref().parent().map(|dir| dir.to_path_buf()).unwrap_or_default();
+        BidView { result, base_dir, is_rendered: false }
+    }
+
+    pub fn result(&self) -> &AuctionResult {
+        &self.result
+    }
+
+    /// The size of the impression as an AdSpec
+    pub fn ad_spec(&self) -> AdSpec {
+        let (width, height) = self.result.size;
+        AdSpec::new(width, height, "OpenRTB impression")
+    }
+
+    /// The creative frame, centered in the body below the caption. Creatives larger than the body are scaled down.
+    pub fn layout(&self, body_frame: &Rectangle) -> (Rectangle, f32) {
+        let (width, height) = (self.result.size.0 as f32, self.result.size.1 as f32);
+        let max_w = body_frame.width() - SPACING * 2.0;
+        let max_h = body_frame.height() - SPACING * 2.0 - CAPTION_H * 2.0;
+        let scale = (max_w / width).min(max_h / height).min(1.0);
+        let size = (width * scale, height * scale);
+        // center_frame is relative to the outer frame's origin
+        let offset = Vector::new(body_frame.x(), body_frame.y() + CAPTION_H);
+        let frame = LayoutHelper::center_frame(body_frame, &Rectangle::new_sized(size), offset);
+        (frame, scale)
+    }
+
+    /// The seat, price and advertiser of the winning bid
+    pub fn caption(&self) -> String {
+        let result = &self.result;
+        let mut caption = format!(
+            "{} bid {} at {:.2} {}, cleared at {:.2}",
+            result.seat.as_ref().map_or("Unknown seat", |seat| seat.as_str()),
+            result.winner.id,
+            result.winner.price,
+            result.currency,
+            result.clearing_price
+        );
+        if let Some(crid) = &result.winner.crid {
+            caption = format!("{} crid={}", caption, crid);
+        }
+        if !result.winner.adomain.is_empty() {
+            caption = format!("{} adomain={}", caption, result.winner.adomain.join(","));
+        }
+        caption
+    }
+
+    /// A local path for an image, or None for remote URLs
+    fn local_path(&self, src: &str) -> Option<PathBuf> {
+        if src.starts_with("http://") || src.starts_with("https://") || src.starts_with("//") {
+            return None;
+        }
+        Some(self.base_dir.join(src.trim_start_matches("file://")))
+    }
+
+    /// Load a local image. Like an img tag with a width and height, the image fills the frame, except for SVGs,
+    /// which keep their aspect ratio.
+    fn load_image(&self, src: &str, frame: &Rectangle, svg_cache: &mut SvgCache) -> Option<(DrawImage, (f32, f32))> {
+        let local_path = self.local_path(src)?;
+        let path = local_path.to_string_lossy();
+        let result = if path.ends_with(".svg") {
+            // The svg cache works with paths relative to the static folder
+            let svg_path = svg_cache.static_path(&local_path).ok_or_else(|| {
+                Error::ContextError("SVG creatives must be in the asset root folder".to_string())
+            });
+            svg_path.and_then(|svg_path| {
+                let size = svg_cache.fitted_size(&svg_path, (frame.width(), frame.height()))?;
+                let image = svg_cache.load_image(&svg_path, size)?;
+                Ok((image, (size.0 as f32, size.1 as f32)))
+            })
+        } else {
+            DrawImage::load_image_file(&path).map(|image| (image, (frame.width(), frame.height())))
+        };
+        match result {
+            Ok(result) => Some(result),
+            Err(e) => {
+                log::error!("Failed to load creative {}: {:?}", path, e);
+                None
+            }
+        }
+    }
+
+    /// The creative in a Scene. It is a Button that registers a BidEvent::Click.
+    pub fn scene(&self, body_frame: &Rectangle, svg_cache: &mut SvgCache) -> Scene {
+        let mut scene = Scene::new(body_frame.clone()).with_id(BID_VIEW_ID, "Bid");
+        let (frame, scale) = self.layout(body_frame);
+
+        let mut caption = format!("{}x{} {}", self.result.size.0, self.result.size.1, self.caption());
+        if scale < 1.0 {
+            caption = format!("{} (shown at {:.0}%)", caption, scale * 100.0);
+        }
+        let caption_size = (body_frame.width() - SPACING * 2.0, CAPTION_H);
+        let subframe = Rectangle::new((body_frame.x() + SPACING, frame.y() - CAPTION_H * 2.0), caption_size);
+        let mut text = Text::new(subframe, &caption);
+        text.layer.font_style = FontStyle::new(12.0, Color::WHITE);
+        scene.add_control(Box::new(text));
+
+        let markup = self.result.markup();
+        let image = match &markup {
+            Some(BidMarkup::Image { src, .. }) => self.load_image(src, &frame, svg_cache),
+            _ => None,
+        };
+        let mut button = Button::new(frame.clone());
+        match image {
+            Some((image, size)) => {
+                let mut label = Label::new(Rectangle::new(frame.center_origin(size), size));
+                label.set_image(image);
+                label.display = LabelDisplay::Image;
+                button.set_label(label);
+            }
+            None => {
+                // A placeholder with the markup, since remote images and HTML cannot be rendered here
+                add_rectangle(&mut scene, &frame, Some(Color::from_hex("#777777")), Some(Color::WHITE));
+                let text = match markup {
+                    Some(BidMarkup::Image { src, .. }) => src,
+                    Some(BidMarkup::Html(html)) => html,
+                    Some(BidMarkup::Vast(_)) => "VAST markup".to_string(),
+                    None => "The bid has no adm".to_string(),
+                };
+                button = button.with_text(&text);
+                button.layer.font_style = FontStyle::new(12.0, Color::WHITE);
+            }
+        }
+        button.set_onclick(move |state| {
+            state.event_bus.register_event(BidEvent::Click);
+        });
+        scene.add_control(Box::new(button));
+        scene
+    }
+
+    /// Log the win notice and the loss notices, which the exchange sends when the auction ends
+    pub fn log_auction(&self) {
+        for (kind, url) in self.result.notices().iter().filter(|(kind, _)| *kind != "billing") {
+            log::info!("OpenRTB {} notice url={}", kind, url);
+        }
+    }
+
+    /// Log the billing notice, which is sent the first time the creative is rendered
+    pub fn track_render(&mut self) {
+        if self.is_rendered {
+            return;
+        }
+        self.is_rendered = true;
+        for (kind, url) in self.result.notices().iter().filter(|(kind, _)| *kind == "billing") {
+            log::info!("OpenRTB {} notice url={}", kind, url);
+        }
+    }
+
+    /// The viewer never opens a click-through. It logs the link around the image.
+    pub fn handle_click(&self) {
+        let bid_id = &self.result.winner.id;
+        match self.result.markup() {
+            Some(BidMarkup::Image { href: Some(href), .. }) => log::info!("Bid {} click-through url={}", bid_id, href),
+            _ => log::warn!("Bid {} has no click-through link in its markup", bid_id),
+        }
+    }
+}
@@ -0,0 +1,189 @@
+/// Displays the companion banners from a VAST document at their declared sizes. Companions are placed in rows
+/// with a caption above each one. Local static resources are loaded from disk, relative to the VAST file, and
+/// everything else is drawn as a placeholder that shows the resource type and URL.
+use super::*;
+use crate::ads::*;
+use crate::app::*;
+
+use quicksilver::{
+    geom::Rectangle,
+    graphics::Color,
+    Error,
+};
+
+use tweek::{
+    gui::*,
+    tools::*,
+};
+
+use std::path::{Path, PathBuf};
+
+const GALLERY_ID: u32 = 440;
+
+const SPACING: f32 = 20.0;
+const CAPTION_H: f32 = 18.0;
+
+pub struct CompanionGallery {
+    document: VastDocument,
+    /// The folder of the VAST file, for resolving relative paths
+    base_dir: PathBuf,
+}
+
+impl CompanionGallery {
+    pub fn new<P: AsRef<Path>>(document: VastDocument, vast_path: P) -> Self {
+        let base_dir = vast_path.as_ref().parent().map(|dir| dir.to_path_buf()).unwrap_or_default();
+        CompanionGallery { document, base_dir }
+    }
+
+    pub fn document(&self) -> &VastDocument {
+        &self.document
+    }
+
+    /// The frame of each companion in the body and the scale it is shown at. Companions that do not fit in the
+    /// body are scaled down.
+    pub fn layout(&self, body_frame: &Rectangle) -> Vec<(Rectangle, f32)> {
+        let max_w = body_frame.width() - SPACING * 2.0;
+        let max_h = body_frame.height() - SPACING * 2.0 - CAPTION_H;
+        let mut frames = Vec::new();
+        let (mut xpos, mut ypos) = (body_frame.x() + SPACING, body_frame.y() + SPACING);
+        let mut row_h: f32 = 0.0;
+        for companion in self.document.companions() {
+            let (width, height) = (companion.width as f32, companion.height as f32);
+            let scale = (max_w / width).min(max_h / height).min(1.0);
+            let size = (width * scale, height * scale);
+            if xpos + size.0 > body_frame.x() + body_frame.width() - SPACING && row_h > 0.0 {
+                xpos = body_frame.x() + SPACING;
+                ypos += row_h + SPACING;
+                row_h = 0.0;
+            }
+            frames.push((Rectangle::new((xpos, ypos + CAPTION_H), size), scale));
+            xpos += size.0 + SPACING;
+            row_h = row_h.max(size.1 + CAPTION_H);
+        }
+        frames
+    }
+
+    /// A local path for a static resource, or None for remote URLs
+    fn local_path(&self, uri: &str) -> Option<PathBuf> {
+        if uri.starts_with("http://") || uri.starts_with("https://") || uri.starts_with("//") {
+            return None;
+        }
+        Some(self.base_dir.join(uri.trim_start_matches("file://")))
+    }
+
+    /// Load a local static resource. SVGs keep their aspect ratio inside the frame and other images are drawn at
+    /// the declared size, so an image with the wrong dimensions looks stretched.
+    fn load_image(
+        &self,
+        resource: &CompanionResource,
+        frame: &Rectangle,
+        svg_cache: &mut SvgCache,
+    ) -> Option<(DrawImage, (f32, f32))> {
+        let (creative_type, uri) = match resource {
+            CompanionResource::Static { creative_type, uri } => (creative_type, uri),
+            _ => return None,
+        };
+        let local_path = self.local_path(uri)?;
+        let path = local_path.to_string_lossy();
+        let result = if creative_type == "image/svg+xml" || path.ends_with(".svg") {
+            // The svg cache works with paths relative to the static folder
+            let svg_path = svg_cache.static_path(&local_path).ok_or_else(|| {
+                Error::ContextError("SVG companions must be in the asset root folder".to_string())
+            });
+            svg_path.and_then(|svg_path| {
+                let size = svg_cache.fitted_size(&svg_path, (frame.width(), frame.height()))?;
+                let image = svg_cache.load_image(&svg_path, size)?;
+                Ok((image, (size.0 as f32, size.1 as f32)))
+            })
+        } else {
+            DrawImage::load_image_file(&path).map(|image| (image, (frame.width(), frame.height())))
+        };
+        match result {
+            Ok(result) => Some(result),
+            Err(e) => {
+                log::error!("Failed to load companion {}: {:?}", path, e);
+                None
+            }
+        }
+    }
+
+    /// The companions in a Scene. Each one is a Button that registers a CompanionEvent::Click.
+    pub fn scene(&self, body_frame: &Rectangle, svg_cache: &mut SvgCache) -> Scene {
+        let mut scene = Scene::new(body_frame.clone()).with_id(GALLERY_ID, "Companions");
+        if self.document.companions().is_empty() {
+            let subframe = Rectangle::new((body_frame.x() + SPACING, body_frame.y() + SPACING), (400.0, 20.0));
+            let mut text = Text::new(subframe, "The VAST document has no companion ads");
+            text.layer.font_style = FontStyle::new(14.0, Color::WHITE);
+            scene.add_control(Box::new(text));
+            return scene;
+        }
+        let companions = self.document.companions();
+        for (index, (frame, scale)) in self.layout(body_frame).into_iter().enumerate() {
+            let companion = companions[index];
+            let resource = companion.resources.first();
+            let mut caption = companion.label();
+            if let Some(resource) = resource {
+                caption = format!("{} {}", caption, resource.name());
+            }
+            if scale < 1.0 {
+                caption = format!("{} (shown at {:.0}%)", caption, scale * 100.0);
+            }
+            let subframe = Rectangle::new((frame.x(), frame.y() - CAPTION_H), (frame.width().max(300.0), CAPTION_H));
+            let mut text = Text::new(subframe, &caption);
+            text.layer.font_style = FontStyle::new(12.0, Color::WHITE);
+            scene.add_control(Box::new(text));
+
+            let image = resource.and_then(|resource| self.load_image(resource, &frame, svg_cache));
+            let mut button = Button::new(frame.clone());
+            match image {
+                Some((image, size)) => {
+                    let mut label = Label::new(Rectangle::new(frame.center_origin(size), size));
+                    label.set_image(image);
+                    label.display = LabelDisplay::Image;
+                    button.set_label(label);
+                }
+                None => {
+                    // A placeholder with the resource, since remote and HTML companions cannot be rendered here
+                    add_rectangle(&mut scene, &frame, Some(Color::from_hex("#777777")), Some(Color::WHITE));
+                    let text = match resource {
+                        Some(CompanionResource::Static { uri, .. }) => uri.clone(),
+                        Some(CompanionResource::IFrame(uri)) => uri.clone(),
+                        Some(CompanionResource::Html(html)) => html.clone(),
+                        None => "No resource".to_string(),
+                    };
+                    button = button.with_text(companion.alt_text.as_ref().unwrap_or(&text));
+                    button.layer.font_style = FontStyle::new(12.0, Color::WHITE);
+                }
+            }
+            button.set_onclick(move |state| {
+                state.event_bus.register_event(CompanionEvent::Click(index));
+            });
+            scene.add_control(Box::new(button));
+        }
+        scene
+    }
+
+    /// Log the creativeView tracking for every companion. Call once when the companions are loaded.
+    pub fn track_views(&self) {
+        for companion in self.document.companions() {
+            for uri in companion.tracking_uris("creativeView") {
+                log::info!("Companion {} creativeView url={}", companion.label(), uri);
+            }
+        }
+    }
+
+    /// The viewer never opens a click-through. It logs the URL and the click tracking URLs.
+    pub fn handle_click(&self, index: usize) {
+        let companion = match self.document.companions().get(index) {
+            Some(companion) => *companion,
+            None => return,
+        };
+        match &companion.click_through {
+            Some(url) => log::info!("Companion {} click-through url={}", companion.label(), url),
+            None => log::warn!("Companion {} has no CompanionClickThrough", companion.label()),
+        }
+        for url in companion.click_tracking.iter() {
+            log::info!("Companion {} click tracking url={}", companion.label(), url);
+        }
+    }
+}
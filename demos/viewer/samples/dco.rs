@@ -0,0 +1,361 @@
+/// Builds the variants of a DCO template. The same DcoLayout is used to build a Scene for the viewer and to draw
+/// a PNG file for each variant, so that exported images match the preview.
+use super::*;
+use crate::ads::*;
+use crate::app::*;
+
+use quicksilver::{
+    geom::{Rectangle, Vector},
+    graphics::Color,
+    Error, Result,
+};
+
+use tweek::{
+    core::*,
+    gui::*,
+    tools::*,
+};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const DCO_ID: u32 = 460;
+
+/// The folder that dco-export writes to when none is specified, relative to the working directory
+pub const DEFAULT_DCO_EXPORT_DIR: &str = "dco-export";
+
+const MIN_FONT_SIZE: f32 = 10.0;
+const MAX_FONT_SIZE: f32 = 22.0;
+const MAX_CTA_SIZE: (f32, f32) = (140.0, 40.0);
+
+/// Frames of the slots and the CTA, relative to the ad frame. Wide sizes put the image on the left and the CTA on
+/// the right, tall sizes stack everything under the image and other sizes put the text next to the image.
+#[derive(Clone, Debug)]
+pub struct DcoLayout {
+    pub image: Rectangle,
+    pub product_name: Rectangle,
+    pub price: Rectangle,
+    /// The badge in the corner of the image
+    pub discount: Rectangle,
+    pub cta: Rectangle,
+    pub name_size: f32,
+    pub price_size: f32,
+    pub discount_size: f32,
+}
+
+impl DcoLayout {
+    pub fn new(size: (u32, u32)) -> Self {
+        let (w, h) = (size.0 as f32, size.1 as f32);
+        let pad = (w.min(h) * 0.05).max(4.0).round();
+        let font_size = |size: f32| size.max(MIN_FONT_SIZE).min(MAX_FONT_SIZE).round();
+
+        let (image, product_name, price, cta, name_size) = if w >= h * 2.0 {
+            let name_size = font_size(h * 0.2);
+            let price_h = name_size * 1.4;
+            let side = h - pad * 2.0;
+            let cta_size = ((w * 0.2).min(MAX_CTA_SIZE.0), (h * 0.45).min(MAX_CTA_SIZE.1));
+            let cta = Rectangle::new((w - pad - cta_size.0, (h - cta_size.1) / 2.0), cta_size);
+            let text_x = pad * 2.0 + side;
+            let text_w = cta.x() - pad - text_x;
+            (
+                Rectangle::new((pad, pad), (side, side)),
+                Rectangle::new((text_x, pad), (text_w, side - price_h)),
+                Rectangle::new((text_x, h - pad - price_h), (text_w, price_h)),
+                cta,
+                name_size,
+            )
+        } else if h >= w * 2.0 {
+            let name_size = font_size(w * 0.11);
+            let price_h = name_size * 1.4;
+            let side = w - pad * 2.0;
+            let cta_h = (w * 0.25).min(MAX_CTA_SIZE.1);
+            let cta = Rectangle::new((pad, h - pad - cta_h), (side, cta_h));
+            let name_y = pad * 2.0 + side;
+            let price_y = cta.y() - pad - price_h;
+            (
+                Rectangle::new((pad, pad), (side, side)),
+                Rectangle::new((pad, name_y), (side, price_y - name_y)),
+                Rectangle::new((pad, price_y), (side, price_h)),
+                cta,
+                name_size,
+            )
+        } else {
+            let name_size = font_size(w.min(h) * 0.07);
+            let price_h = name_size * 1.4;
+            let image_w = (w - pad * 3.0) / 2.0;
+            let text_x = pad * 2.0 + image_w;
+            let cta_size = (image_w.min(MAX_CTA_SIZE.0), (h * 0.15).min(MAX_CTA_SIZE.1));
+            let cta = Rectangle::new((text_x, h - pad - cta_size.1), cta_size);
+            let price_y = cta.y() - pad - price_h;
+            (
+                Rectangle::new((pad, pad), (image_w, h - pad * 2.0)),
+                Rectangle::new((text_x, pad), (image_w, price_y - pad)),
+                Rectangle::new((text_x, price_y), (image_w, price_h)),
+                cta,
+                name_size,
+            )
+        };
+        let price_size = (name_size * 1.2).round();
+        let discount_size = (name_size * 0.8).max(MIN_FONT_SIZE).round();
+        let discount = Rectangle::new(image.pos, (discount_size * 5.0, discount_size * 1.8));
+        DcoLayout { image, product_name, price, discount, cta, name_size, price_size, discount_size }
+    }
+
+    /// The same layout with the frames moved to an ad frame at the origin
+    pub fn offset(&self, origin: Vector) -> DcoLayout {
+        let offset = |rect: &Rectangle| Rectangle::new((rect.x() + origin.x, rect.y() + origin.y), rect.size);
+        DcoLayout {
+            image: offset(&self.image),
+            product_name: offset(&self.product_name),
+            price: offset(&self.price),
+            discount: offset(&self.discount),
+            cta: offset(&self.cta),
+            ..self.clone()
+        }
+    }
+}
+
+pub struct DcoAdBuilder {
+    brand_kit: BrandKit,
+    template: DcoTemplate,
+}
+
+impl DcoAdBuilder {
+    pub fn new(brand_kit: BrandKit, template: DcoTemplate) -> Self {
+        DcoAdBuilder { brand_kit, template }
+    }
+
+    pub fn template(&self) -> &DcoTemplate {
+        &self.template
+    }
+
+    fn name_block(&self, variant: &DcoVariant, layout: &DcoLayout) -> TextBlock {
+        let font = self.brand_kit.font("body");
+        TextBlock::new(layout.name_size, self.brand_kit.color("text"))
+            .with_family(&font.family)
+            .span(TextSpan::new(&variant.product_name).with_weight(font.weight))
+    }
+
+    fn price_block(&self, variant: &DcoVariant, layout: &DcoLayout) -> TextBlock {
+        let font = self.brand_kit.font("headline");
+        TextBlock::new(layout.price_size, self.brand_kit.color("primary"))
+            .with_family(&font.family)
+            .span(TextSpan::new(&variant.price).with_weight(font.weight))
+    }
+
+    fn discount_block(&self, discount: &str, layout: &DcoLayout) -> TextBlock {
+        TextBlock::new(layout.discount_size, self.brand_kit.color("cta_text"))
+            .with_align(TextAlign::Center)
+            .span(TextSpan::new(discount).bold())
+    }
+
+    /// The text frame inside the discount badge, which centers one line vertically
+    fn discount_text_frame(layout: &DcoLayout) -> Rectangle {
+        let badge = &layout.discount;
+        let line_h = layout.discount_size * 1.2;
+        Rectangle::new((badge.x(), badge.y() + (badge.height() - line_h) / 2.0), (badge.width(), line_h))
+    }
+
+    /// Build the variant in the frame for the viewer. Product images are loaded from disk, so that the feed can
+    /// be edited without changing the manifest.
+    pub fn build_stage(&self, stage: &mut Stage, frame: &Rectangle, variant: &DcoVariant, assets: &mut AdAssets) {
+        stage.title = format!("{} DCO", self.template.name);
+        let layout = DcoLayout::new(variant.size).offset(frame.pos);
+        let mut scene = Scene::new(frame.clone()).with_id(DCO_ID, "DCO ad");
+        scene.layer.border_style = BorderStyle::SolidLine(self.brand_kit.color("border"), 1.0);
+        scene.layer.bg_style = BackgroundStyle::Solid(self.brand_kit.color("background"));
+
+        match self.image_label(variant, &layout.image, &mut assets.svg_cache) {
+            Some(label) => {
+                let mut button = Button::new(label.layer.frame.clone());
+                button.set_label(label);
+                let exit = self.template.exit.clone();
+                button.set_onclick(move |state| {
+                    state.event_bus.register_event(AdEvent::Click(exit.clone()));
+                });
+                scene.add_control(Box::new(button));
+            }
+            None => add_rectangle(&mut scene, &layout.image, Some(Color::from_hex("#DDDDDD")), None),
+        }
+        if let Some(discount) = &variant.discount {
+            add_rectangle(&mut scene, &layout.discount, Some(self.brand_kit.color("accent")), None);
+            let block = self.discount_block(discount, &layout);
+            for label in block.make_labels(&DcoAdBuilder::discount_text_frame(&layout), &mut assets.fonts) {
+                scene.add_control(Box::new(label));
+            }
+        }
+
+        let blocks = [
+            (DcoSlot::ProductName, self.name_block(variant, &layout), &layout.product_name),
+            (DcoSlot::Price, self.price_block(variant, &layout), &layout.price),
+        ];
+        for (slot, block, rect) in blocks.iter() {
+            let labels = block.make_labels(rect, &mut assets.fonts);
+            let count = labels.len();
+            let bottom = rect.y() + rect.height() + 0.5;
+            // Lines that do not fit in the frame are dropped
+            let labels: Vec<Label> = labels
+                .into_iter()
+                .filter(|label| label.layer.frame.y() + label.layer.frame.height() <= bottom)
+                .collect();
+            if labels.len() < count {
+                log::warn!("Row {} at {}x{} cuts off the {}", variant.row, variant.size.0, variant.size.1, slot.name());
+            }
+            for label in labels {
+                scene.add_control(Box::new(label));
+            }
+        }
+
+        let mut button = Button::new(layout.cta.clone());
+        let text = &self.template.cta_text;
+        self.brand_kit.apply_button_style("cta", text, &mut button, &mut assets.fonts);
+        button.layer.font_style = FontStyle::new(layout.name_size, self.brand_kit.color("cta_text"));
+        self.brand_kit.set_button_text("cta", text, layout.name_size, &mut button, &mut assets.fonts);
+        let exit = self.template.exit.clone();
+        button.set_onclick(move |state| {
+            state.event_bus.register_event(AdEvent::Click(exit.clone()));
+        });
+        scene.add_control(Box::new(button));
+        stage.add_scene(scene);
+    }
+
+    /// A Label with the product image fitted and centered in the frame. SVGs are rasterized through the svg
+    /// cache, so they must be inside the asset root.
+    fn image_label(&self, variant: &DcoVariant, frame: &Rectangle, svg_cache: &mut SvgCache) -> Option<Label> {
+        let path = variant.image.as_ref()?;
+        let max_size = (frame.width(), frame.height());
+        let result = image_size(path).and_then(|natural| {
+            let (width, height) = fit_size(natural, max_size);
+            let image = if path.extension().map_or(false, |ext| ext.eq_ignore_ascii_case("svg")) {
+                let svg_path = svg_cache.static_path(path).ok_or_else(|| {
+                    Error::ContextError("SVG product images must be in the asset root folder".to_string())
+                })?;
+                svg_cache.load_image(&svg_path, (width, height))?
+            } else {
+                DrawImage::load_image_file(&path.to_string_lossy())?
+            };
+            Ok((image, (width as f32, height as f32)))
+        });
+        match result {
+            Ok((image, size)) => {
+                let mut label = Label::new(Rectangle::new(frame.center_origin(size), size));
+                label.set_image(image);
+                label.display = LabelDisplay::Image;
+                Some(label)
+            }
+            Err(e) => {
+                log::error!("Failed to load product image {:?}: {:?}", path, e);
+                None
+            }
+        }
+    }
+
+    /// Draw the variant on a canvas of its size
+    pub fn render(&self, variant: &DcoVariant, fonts: &mut FontRegistry) -> Result<Canvas> {
+        let layout = DcoLayout::new(variant.size);
+        let mut canvas = Canvas::new(variant.size.0, variant.size.1)?;
+        canvas.fill(self.brand_kit.color("background"));
+
+        let drawn = match &variant.image {
+            Some(path) => match canvas.draw_image(path, &layout.image) {
+                Ok(()) => true,
+                Err(e) => {
+                    log::error!("Failed to draw product image {:?}: {:?}", path, e);
+                    false
+                }
+            },
+            None => false,
+        };
+        if !drawn {
+            canvas.fill_rect(&layout.image, Color::from_hex("#DDDDDD"));
+        }
+        if let Some(discount) = &variant.discount {
+            canvas.fill_rect(&layout.discount, self.brand_kit.color("accent"));
+            let block = self.discount_block(discount, &layout);
+            canvas.draw_text(&block, &DcoAdBuilder::discount_text_frame(&layout), fonts);
+        }
+        if !canvas.draw_text(&self.name_block(variant, &layout), &layout.product_name, fonts) {
+            log::warn!("Row {} at {}x{} cuts off the product_name", variant.row, variant.size.0, variant.size.1);
+        }
+        canvas.draw_text(&self.price_block(variant, &layout), &layout.price, fonts);
+
+        // The CTA is drawn like the Button in the viewer: the brand kit's cta button style for the fill, border and
+        // corners, with the label at the product name size in the cta_text color
+        let style = self.brand_kit.button_style("cta");
+        let fill = style.as_ref().and_then(|style| style.fill).unwrap_or_else(|| self.brand_kit.color("primary"));
+        let radius = style.as_ref().and_then(|style| style.radius).unwrap_or(0.0);
+        canvas.fill_rounded_rect(&layout.cta, fill, radius);
+        if let Some((color, width)) = style.as_ref().and_then(|style| style.border) {
+            canvas.stroke_rounded_rect(&layout.cta, color, width, radius);
+        }
+        let cta = TextBlock::new(layout.name_size, self.brand_kit.color("cta_text"))
+            .with_align(TextAlign::Center)
+            .text(&self.template.cta_text);
+        let line_h = layout.name_size * 1.2;
+        let cta_text = Rectangle::new(
+            (layout.cta.x(), layout.cta.y() + (layout.cta.height() - line_h) / 2.0),
+            (layout.cta.width(), line_h),
+        );
+        canvas.draw_text(&cta, &cta_text, fonts);
+
+        let bounds = Rectangle::new_sized((variant.size.0 as f32, variant.size.1 as f32));
+        canvas.stroke_rect(&bounds, self.brand_kit.color("border"), 1.0);
+        Ok(canvas)
+    }
+
+    /// Write each variant to a PNG file named by template, row and size. Returns the paths of the files.
+    pub fn export<P: AsRef<Path>>(
+        &self,
+        variants: &[DcoVariant],
+        out_dir: P,
+        fonts: &mut FontRegistry,
+    ) -> Result<Vec<PathBuf>> {
+        fs::create_dir_all(out_dir.as_ref())?;
+        let slug: String = self
+            .template
+            .name
+            .to_lowercase()
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '-' })
+            .collect();
+        let mut paths = Vec::new();
+        for variant in variants.iter() {
+            let file_name = format!("{}-row{:02}-{}x{}.png", slug, variant.row, variant.size.0, variant.size.1);
+            let path = out_dir.as_ref().join(file_name);
+            self.render(variant, fonts)?.save_png(&path)?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+}
+
+/// Load a DCO template and its feed, and log the problems in the feed
+pub fn load_dco_variants<P: AsRef<Path>>(template_path: P) -> Result<(DcoTemplate, Vec<DcoVariant>)> {
+    let template = DcoTemplate::load_file(template_path.as_ref())?;
+    let feed_path = template.feed_path();
+    let feed = ProductFeed::load_file(&feed_path)
+        .map_err(|e| Error::ContextError(format!("Failed to load feed {:?}: {:?}", feed_path, e)))?;
+    for column in template.missing_columns(&feed) {
+        log::warn!("DCO feed {:?} has no column {:?}", feed_path, column);
+    }
+    let variants = template.variants(&feed);
+    for variant in variants.iter().filter(|variant| variant.size == template.sizes[0]) {
+        for warning in variant.warnings.iter() {
+            log::warn!("DCO feed row {}: {}", variant.row, warning);
+        }
+    }
+    log::info!("DCO template {} has {} rows at {} sizes", template.name, feed.rows.len(), template.sizes.len());
+    Ok((template, variants))
+}
+
+/// Render every variant of the template to PNG files in out_dir, using the brand kit in AD_BRAND_KIT and the
+/// fonts of the asset root in AD_ASSET_ROOT, like the viewer
+pub fn export_dco<P: AsRef<Path>>(template_path: P, out_dir: P) -> Result<Vec<PathBuf>> {
+    let kit_path = env_asset_path("AD_BRAND_KIT", DEFAULT_BRAND_KIT);
+    let brand_kit = BrandKit::load_file(&kit_path)?;
+    let asset_root = asset_root();
+    let mut fonts = FontRegistry::new();
+    brand_kit.load_fonts(&asset_root, &mut fonts)?;
+    let (template, variants) = load_dco_variants(template_path)?;
+    DcoAdBuilder::new(brand_kit, template).export(&variants, out_dir, &mut fonts)
+}
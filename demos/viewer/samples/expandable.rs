@@ -0,0 +1,149 @@
+/// Displays an expandable ad. The creative is built at the expanded size and this view covers the part that
+/// is outside of the visible frame, which grows and shrinks as the ad expands and collapses. The covers are
+/// drawn in the color behind the ad, so the ad appears clipped.
+use super::*;
+use crate::ads::*;
+
+use quicksilver::{
+    geom::{Rectangle, Vector},
+    graphics::Color,
+    lifecycle::Window,
+};
+
+use tweek::{
+    core::AppState,
+    gui::*,
+    tools::*,
+};
+
+const CLIP_ID: u32 = 410;
+
+/// The close button convention for expanded ads: a labeled button in the top right corner
+const CLOSE_TEXT: &str = "Close X";
+const CLOSE_SIZE: (f32, f32) = (64.0, 24.0);
+const CLOSE_MARGIN: f32 = 4.0;
+
+pub struct ExpandableView {
+    spec: ExpandableSpec,
+    expanded_frame: Rectangle,
+    bg_color: Color,
+    /// 0.0 when collapsed and 1.0 when expanded
+    progress: f64,
+    is_expanded: bool,
+    clip: Scene,
+}
+
+impl ExpandableView {
+    pub fn new(spec: ExpandableSpec, expanded_frame: &Rectangle, bg_color: Color) -> Self {
+        let mut view = ExpandableView {
+            spec,
+            expanded_frame: expanded_frame.clone(),
+            bg_color,
+            progress: 0.0,
+            is_expanded: false,
+            clip: Scene::new(expanded_frame.clone()),
+        };
+        view.clip = view.clip_scene();
+        view
+    }
+
+    pub fn spec(&self) -> &ExpandableSpec {
+        &self.spec
+    }
+
+    pub fn expanded_frame(&self) -> Rectangle {
+        self.expanded_frame.clone()
+    }
+
+    /// The part of the ad that is currently visible
+    pub fn visible_frame(&self) -> Rectangle {
+        self.spec.visible_frame(&self.expanded_frame, ExpandableView::ease(self.progress) as f32)
+    }
+
+    /// True if the ad is expanded or expanding
+    pub fn is_expanded(&self) -> bool {
+        self.is_expanded
+    }
+
+    /// Start expanding or collapsing. Returns false if the ad is already in that state.
+    pub fn set_expanded(&mut self, is_expanded: bool) -> bool {
+        if is_expanded == self.is_expanded {
+            return false;
+        }
+        self.is_expanded = is_expanded;
+        true
+    }
+
+    fn ease(t: f64) -> f64 {
+        (1.0 - (t * std::f64::consts::PI).cos()) / 2.0
+    }
+
+    /// Advance the animation
+    pub fn update(&mut self, seconds: f64) {
+        let target = if self.is_expanded { 1.0 } else { 0.0 };
+        if self.progress == target {
+            return;
+        }
+        let step = if self.spec.duration > 0.0 { seconds / self.spec.duration } else { 1.0 };
+        self.progress = if target > self.progress {
+            (self.progress + step).min(target)
+        } else {
+            (self.progress - step).max(target)
+        };
+        self.clip = self.clip_scene();
+    }
+
+    /// Covers for the area outside of the visible frame, with the close button when fully expanded
+    fn clip_scene(&self) -> Scene {
+        let mut scene = Scene::new(self.expanded_frame.clone()).with_id(CLIP_ID, "Expandable Clip");
+        let outer = &self.expanded_frame;
+        let inner = self.visible_frame();
+        let covers = [
+            Rectangle::new(outer.pos, (outer.width(), inner.y() - outer.y())),
+            Rectangle::new(
+                (outer.x(), inner.y() + inner.height()),
+                (outer.width(), outer.y() + outer.height() - inner.y() - inner.height()),
+            ),
+            Rectangle::new((outer.x(), inner.y()), (inner.x() - outer.x(), inner.height())),
+            Rectangle::new(
+                (inner.x() + inner.width(), inner.y()),
+                (outer.x() + outer.width() - inner.x() - inner.width(), inner.height()),
+            ),
+        ];
+        for rect in covers.iter().filter(|r| r.width() > 0.0 && r.height() > 0.0) {
+            let mut shape = DrawShape::rectangle(rect, Some(self.bg_color), None, 0.0, 0.0);
+            let view = ShapeView::new(rect.clone(), ShapeDef::Rectangle).with_mesh(&mut shape);
+            scene.add_view(Box::new(view));
+        }
+
+        if self.is_expanded && self.progress >= 1.0 && self.spec.trigger == ExpandTrigger::Click {
+            let pos = Vector::new(inner.x() + inner.width() - CLOSE_SIZE.0 - CLOSE_MARGIN, inner.y() + CLOSE_MARGIN);
+            let mut button = Button::new(Rectangle::new(pos, CLOSE_SIZE)).with_text(CLOSE_TEXT);
+            button.layer.font_style = FontStyle::new(12.0, Color::WHITE);
+            button.layer.bg_style = BackgroundStyle::Solid(Color::BLACK.with_alpha(0.6));
+            button.layer.corner_radius = 3.0;
+            button.set_onclick(move |state| {
+                state.event_bus.register_event(AdEvent::Collapse);
+            });
+            scene.add_control(Box::new(button));
+        }
+        scene
+    }
+
+    /// Render the clip, which is drawn after the Stage so that it covers the ad
+    pub fn render(&mut self, theme: &mut Theme, window: &mut Window) {
+        self.clip.render(theme, window);
+    }
+
+    pub fn handle_mouse_at(&mut self, pt: &Vector, window: &mut Window) -> bool {
+        self.clip.handle_mouse_at(pt, window)
+    }
+
+    pub fn handle_mouse_down(&mut self, pt: &Vector, state: &mut AppState) -> bool {
+        self.clip.handle_mouse_down(pt, state)
+    }
+
+    pub fn handle_mouse_up(&mut self, pt: &Vector, state: &mut AppState) -> bool {
+        self.clip.handle_mouse_up(pt, state)
+    }
+}
@@ -0,0 +1,205 @@
+/// The Teapot ad on its own, which is what the wasm build shows in an HTML5 bundle
+///
+/// The ad is built at the size in the page's ad.size meta tag, or at the size of the window when the page has none.
+/// The HTML5_CONFIG_FILES and the manifest's assets are loaded from the DEFAULT_ASSET_ROOT next to index.html, and a
+/// click opens the landing URL in the exit's clickTag variable. The ad is shown once its initial assets have loaded,
+/// and is built again with the polite assets when they arrive.
+///
+use super::*;
+use crate::ads::*;
+use crate::app::*;
+
+use tweek::{
+    core::*,
+    events::*,
+    gui::*,
+};
+
+use quicksilver::{
+    geom::Rectangle,
+    input::{ButtonState, MouseButton},
+    lifecycle::{Event, State, Window},
+    Error, Result,
+};
+
+/// The size of the ad on the page, from the ad.size meta tag or else the window
+pub fn page_ad_size() -> (u32, u32) {
+    let content = js! {
+        var meta = document.querySelector("meta[name='ad.size']");
+        return meta ? meta.content : "width=" + window.innerWidth + ",height=" + window.innerHeight;
+    };
+    content.into_string().and_then(|content| parse_ad_size(&content)).unwrap_or(DEFAULT_SIZE)
+}
+
+/// The config that is read before the assets are loaded
+struct Html5Config {
+    brand_kit: BrandKit,
+    click_config: ClickConfig,
+    strings: StringTable,
+}
+
+pub struct Html5Ad {
+    spec: AdSpec,
+    frame: Rectangle,
+    theme: Theme,
+    app_state: AppState,
+    stage: Stage,
+    files: FileLoader,
+    config: Option<Html5Config>,
+    loader: Option<AssetLoader>,
+    is_built: bool,
+}
+
+impl Html5Ad {
+    /// Start loading the config for an ad of the given size. Sizes in AD_SIZES_MAP use the same AdSpec as the viewer.
+    pub fn new(size: (u32, u32)) -> Result<Html5Ad> {
+        let id = format!("{}x{}", size.0, size.1);
+        let spec = match AD_SIZES_MAP.values().find(|spec| spec.id == id) {
+            Some(spec) => spec.clone(),
+            None => AdSpec::new(size.0, size.1, "Page size"),
+        };
+        let frame = Rectangle::new((0.0, 0.0), (spec.width, spec.height));
+        let mut files = FileLoader::new(DEFAULT_ASSET_ROOT);
+        for path in HTML5_CONFIG_FILES.iter() {
+            files.request(path);
+        }
+        // The brand kit's theme tokens are applied when the config has loaded
+        let theme = base_theme();
+        let mut app_state = AppState::new();
+        app_state.window_size = (spec.width, spec.height);
+
+        Ok(Html5Ad {
+            spec,
+            frame: frame.clone(),
+            theme,
+            app_state,
+            stage: Stage::new(frame),
+            files,
+            config: None,
+            loader: None,
+            is_built: false,
+        })
+    }
+
+    /// Parse the config files and start loading the manifest's assets
+    fn load_config(&mut self) -> Result<()> {
+        let manifest = AssetManifest::from_toml(&self.config_text(TEAPOT_MANIFEST)?)?;
+        let brand_kit = BrandKit::from_toml(&self.config_text(DEFAULT_BRAND_KIT)?)?;
+        let click_config = ClickConfig::from_toml(&self.config_text(TEAPOT_CLICK_CONFIG)?)?;
+        let strings = StringTable::from_toml(DEFAULT_LOCALE, &self.config_text(TEAPOT_DEFAULT_STRINGS)?)?;
+        brand_kit.apply_to_theme(&mut self.theme);
+
+        let mut loader = AssetLoader::new(DEFAULT_ASSET_ROOT);
+        loader.enqueue(&manifest);
+        self.loader = Some(loader);
+        self.config = Some(Html5Config { brand_kit, click_config, strings });
+        Ok(())
+    }
+
+    fn config_text(&mut self, path: &str) -> Result<String> {
+        match self.files.take_text(path) {
+            Some(text) => text,
+            None => Err(Error::ContextError(format!("Config file {} is not loaded", path))),
+        }
+    }
+
+    /// Build the ad with the assets that have loaded so far
+    fn build_ad(&mut self, assets: &mut AdAssets) {
+        let config = match &self.config {
+            Some(config) => config,
+            None => return,
+        };
+        let mut stage = Stage::new(self.frame.clone());
+        let mut builder = TeapotAdBuilder::new(config.brand_kit.clone()).with_strings(config.strings.clone());
+        builder.build_stage(&mut stage, &self.frame, &self.spec, assets);
+        stage.set_theme(&mut self.theme);
+        stage.notify(&DisplayEvent::Ready);
+        self.stage = stage;
+        self.is_built = true;
+    }
+
+    /// Open the landing URL that the page has in the exit's clickTag variable. Nothing is opened when the page does
+    /// not define the variable.
+    fn open_exit(&self, exit: &str) {
+        if let Some(config) = &self.config {
+            let click_tag = config.click_config.click_tag_for(exit);
+            let opened = js! {
+                var url = window[@{click_tag}];
+                if (url === undefined) {
+                    return false;
+                }
+                window.open(url, "_blank");
+                return true;
+            };
+            if opened != stdweb::Value::Bool(true) {
+                log::warn!("Exit {:?} was not opened because the page does not define {}", exit, click_tag);
+            }
+        }
+    }
+}
+
+impl State for Html5Ad {
+    fn new() -> Result<Html5Ad> {
+        Err(Error::ContextError("Use run_with to execute custom new method".to_string()))
+    }
+
+    fn update(&mut self, window: &mut Window) -> Result<()> {
+        if self.config.is_none() {
+            self.files.poll();
+            if !self.files.is_complete() {
+                return Ok(());
+            }
+            self.load_config()?;
+        }
+        // The ad is shown with the initial assets, and built again once the polite assets have loaded
+        if let Some(mut loader) = self.loader.take() {
+            loader.load_next();
+            if loader.is_complete() {
+                let mut assets = loader.finish()?;
+                self.build_ad(&mut assets);
+            } else {
+                if !self.is_built && loader.is_initial_complete() {
+                    if !loader.errors().is_empty() {
+                        return Err(Error::ContextError(loader.errors().join("\n")));
+                    }
+                    self.build_ad(loader.assets_mut());
+                }
+                self.loader = Some(loader);
+            }
+        }
+        self.stage.update(window, &mut self.app_state);
+        let mut exits = Vec::new();
+        for event in self.app_state.event_bus.into_iter() {
+            if let Ok(AdEvent::Click(exit)) = event.downcast_ref::<AdEvent>() {
+                exits.push(exit.clone());
+            }
+            self.stage.handle_event(&event);
+        }
+        for exit in exits.iter() {
+            self.open_exit(exit);
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, window: &mut Window) -> Result<()> {
+        window.clear(self.theme.bg_color)?;
+        self.stage.render(&mut self.theme, window);
+        Ok(())
+    }
+
+    fn event(&mut self, event: &Event, window: &mut Window) -> Result<()> {
+        match event {
+            Event::MouseMoved(pt) => {
+                self.stage.handle_mouse_at(pt, window);
+            }
+            Event::MouseButton(MouseButton::Left, ButtonState::Pressed) => {
+                self.stage.handle_mouse_down(&window.mouse().pos(), &mut self.app_state);
+            }
+            Event::MouseButton(MouseButton::Left, ButtonState::Released) => {
+                self.stage.handle_mouse_up(&window.mouse().pos(), &mut self.app_state);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
@@ -1,9 +1,29 @@
 pub use self::ad_viewer::*;
+pub use self::bid_view::*;
+pub use self::companion::*;
+pub use self::dco::*;
+pub use self::expandable::*;
+#[cfg(target_arch = "wasm32")]
+pub use self::html5_ad::*;
+pub use self::native::*;
+pub use self::page_view::*;
+pub use self::placement::*;
 pub use self::teapot::*;
 
 mod ad_viewer;
+mod bid_view;
+mod companion;
+mod dco;
+mod expandable;
+#[cfg(target_arch = "wasm32")]
+mod html5_ad;
+mod native;
+mod page_view;
+mod placement;
 mod teapot;
 
+use quicksilver::geom::Rectangle;
+
 use std::collections::BTreeMap;
 
 // Square – 250 x 250.
@@ -37,10 +57,25 @@ impl AdSpec {
             width: w as f32,
             height: h as f32,
             name: name.to_string(),
-            scale_x: 1.0,
-            scale_y: 1.0,
+            scale_x,
+            scale_y,
         }
     }
+
+    /// Scale a rectangle from the DEFAULT_SIZE prototype to this spec. The result is relative to the ad frame.
+    pub fn scale_rect(&self, rect: &Rectangle) -> Rectangle {
+        Rectangle::new(
+            (rect.x() * self.scale_x, rect.y() * self.scale_y),
+            (rect.width() * self.scale_x, rect.height() * self.scale_y),
+        )
+    }
+
+    /// Scale a size from the DEFAULT_SIZE prototype to this spec, keeping the aspect ratio. This is the
+    /// size that images and vector assets should be rendered at.
+    pub fn scaled_size(&self, size: (f32, f32)) -> (f32, f32) {
+        let scale = self.scale_x.min(self.scale_y);
+        (size.0 * scale, size.1 * scale)
+    }
 }
 
 impl Default for AdSpec {
@@ -49,6 +84,82 @@ impl Default for AdSpec {
     }
 }
 
+/// The edge that an expandable ad grows toward
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExpandDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// The user action that expands an ad
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExpandTrigger {
+    /// Expand when the mouse enters the ad and collapse when it leaves
+    Hover,
+    /// Expand on click and collapse with the close button
+    Click,
+}
+
+/// An ad with a collapsed and an expanded size in the same placement. The creative is built at the expanded
+/// size and clipped to the collapsed size, so the collapsed ad shows the edge opposite to the direction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExpandableSpec {
+    pub collapsed: AdSpec,
+    pub expanded: AdSpec,
+    pub direction: ExpandDirection,
+    pub trigger: ExpandTrigger,
+    /// Seconds to expand or collapse
+    pub duration: f64,
+}
+
+impl ExpandableSpec {
+    pub fn new(collapsed: AdSpec, expanded: AdSpec) -> Self {
+        let direction = ExpandDirection::Down;
+        ExpandableSpec { collapsed, expanded, direction, trigger: ExpandTrigger::Click, duration: 0.4 }
+    }
+
+    pub fn with_direction(mut self, direction: ExpandDirection) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    pub fn with_trigger(mut self, trigger: ExpandTrigger) -> Self {
+        self.trigger = trigger;
+        self
+    }
+
+    /// The expanded frame for an ad placed at the collapsed frame
+    pub fn expanded_frame(&self, collapsed_frame: &Rectangle) -> Rectangle {
+        let (dw, dh) = (self.expanded.width - self.collapsed.width, self.expanded.height - self.collapsed.height);
+        let (x, y) = match self.direction {
+            ExpandDirection::Up => (collapsed_frame.x(), collapsed_frame.y() - dh),
+            ExpandDirection::Down => (collapsed_frame.x(), collapsed_frame.y()),
+            ExpandDirection::Left => (collapsed_frame.x() - dw, collapsed_frame.y()),
+            ExpandDirection::Right => (collapsed_frame.x(), collapsed_frame.y()),
+        };
+        Rectangle::new((x, y), (self.expanded.width, self.expanded.height))
+    }
+
+    /// The collapsed frame inside an expanded frame
+    pub fn collapsed_frame(&self, expanded_frame: &Rectangle) -> Rectangle {
+        self.visible_frame(expanded_frame, 0.0)
+    }
+
+    /// The visible part of the expanded frame, where progress is 0.0 when collapsed and 1.0 when expanded
+    pub fn visible_frame(&self, expanded_frame: &Rectangle, progress: f32) -> Rectangle {
+        let width = self.collapsed.width + (self.expanded.width - self.collapsed.width) * progress;
+        let height = self.collapsed.height + (self.expanded.height - self.collapsed.height) * progress;
+        let (x, y) = match self.direction {
+            ExpandDirection::Up => (expanded_frame.x(), expanded_frame.y() + expanded_frame.height() - height),
+            ExpandDirection::Down | ExpandDirection::Right => (expanded_frame.x(), expanded_frame.y()),
+            ExpandDirection::Left => (expanded_frame.x() + expanded_frame.width() - width, expanded_frame.y()),
+        };
+        Rectangle::new((x, y), (width, height))
+    }
+}
+
 lazy_static! {
     #[allow(missing_docs)]
     pub static ref AD_SIZES_MAP: BTreeMap<&'static str, AdSpec> = {
@@ -59,4 +170,22 @@ lazy_static! {
         map.insert("120x600", AdSpec::new(120, 600, "Skyscraper"));
         map
     };
+
+    /// Expandable formats from the IAB guidelines, keyed by the collapsed size
+    pub static ref EXPANDABLE_SIZES_MAP: BTreeMap<&'static str, ExpandableSpec> = {
+        let mut map = BTreeMap::new();
+        map.insert("728x90", ExpandableSpec::new(
+            AdSpec::new(728, 90, "Leaderboard"),
+            AdSpec::new(728, 315, "Expanded Leaderboard"),
+        ));
+        map.insert("300x250", ExpandableSpec::new(
+            AdSpec::new(300, 250, "Inline Rectangle"),
+            AdSpec::new(600, 250, "Expanded Rectangle"),
+        ).with_direction(ExpandDirection::Left));
+        map.insert("468x60", ExpandableSpec::new(
+            AdSpec::new(468, 60, "Banner"),
+            AdSpec::new(468, 240, "Expanded Banner"),
+        ).with_trigger(ExpandTrigger::Hover));
+        map
+    };
 }
@@ -0,0 +1,408 @@
+/// Builds a native ad from its components. Each NativeTemplate arranges the same components with the
+/// LayoutBuilder the way a kind of feed would, and the feed scene draws placeholder content around the ad so
+/// that it can be previewed in context.
+use super::*;
+use crate::ads::*;
+use crate::app::*;
+
+use quicksilver::{
+    geom::{Rectangle, Vector},
+    graphics::Color,
+};
+
+use tweek::{
+    core::*,
+    gui::*,
+    tools::*,
+};
+
+use stretch::{
+    geometry::*,
+    node::Node,
+    style::*,
+};
+
+const NATIVE_ID: u32 = 430;
+const FEED_ID: u32 = 431;
+
+const PADDING: f32 = 12.0;
+const SPACING: f32 = 8.0;
+const CTA_SIZE: (f32, f32) = (96.0, 32.0);
+
+/// The disclosure that every native ad must show
+const DISCLOSURE_TEXT: &str = "Sponsored by";
+
+const FEED_COLOR: &str = "#EEEEEE";
+const FEED_CARD_COLOR: &str = "#FFFFFF";
+const FEED_TEXT_COLOR: &str = "#333333";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NativeTemplate {
+    /// A card in a social or news feed: sponsor header, headline, body, large image and CTA
+    InFeed,
+    /// A thumbnail with a headline in a content recommendation widget below an article
+    ContentRecommendation,
+    /// An app store style card with a large image, app icon and install button
+    AppInstall,
+    /// A single row with icon, headline and CTA in a list of app content
+    Banner,
+}
+
+impl NativeTemplate {
+    pub const ALL: [NativeTemplate; 4] = [
+        NativeTemplate::InFeed,
+        NativeTemplate::ContentRecommendation,
+        NativeTemplate::AppInstall,
+        NativeTemplate::Banner,
+    ];
+
+    /// Short name for the toolbar
+    pub fn name(&self) -> &'static str {
+        match self {
+            NativeTemplate::InFeed => "In-Feed",
+            NativeTemplate::ContentRecommendation => "Content",
+            NativeTemplate::AppInstall => "App",
+            NativeTemplate::Banner => "Banner",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<NativeTemplate> {
+        NativeTemplate::ALL.iter().find(|template| template.name() == name).cloned()
+    }
+
+    /// The size of the ad in the feed
+    pub fn ad_spec(&self) -> AdSpec {
+        match self {
+            NativeTemplate::InFeed => AdSpec::new(360, 440, "In-Feed Native"),
+            NativeTemplate::ContentRecommendation => AdSpec::new(600, 140, "Content Recommendation"),
+            NativeTemplate::AppInstall => AdSpec::new(320, 480, "App Install"),
+            NativeTemplate::Banner => AdSpec::new(320, 60, "Native Banner"),
+        }
+    }
+
+    /// The kind of feed that the template is previewed in
+    fn feed_name(&self) -> &'static str {
+        match self {
+            NativeTemplate::InFeed => "News Feed",
+            NativeTemplate::ContentRecommendation => "Around the Web",
+            NativeTemplate::AppInstall => "Apps For You",
+            NativeTemplate::Banner => "Messages",
+        }
+    }
+}
+
+/// Frames of the components that a template shows, in absolute coordinates
+pub type NativeLayout = Vec<(NativeComponent, Rectangle)>;
+
+pub struct NativeAdBuilder {
+    brand_kit: BrandKit,
+    ad: NativeAd,
+    template: NativeTemplate,
+}
+
+impl NativeAdBuilder {
+    pub fn new(brand_kit: BrandKit, ad: NativeAd, template: NativeTemplate) -> Self {
+        NativeAdBuilder { brand_kit, ad, template }
+    }
+
+    fn font_size(&self, component: NativeComponent) -> f32 {
+        let compact = match self.template {
+            NativeTemplate::ContentRecommendation | NativeTemplate::Banner => true,
+            _ => false,
+        };
+        match (component, compact) {
+            (NativeComponent::Headline, false) => 20.0,
+            (NativeComponent::Headline, true) => 16.0,
+            (NativeComponent::Body, _) | (NativeComponent::Cta, _) => 14.0,
+            _ => 12.0,
+        }
+    }
+
+    fn text_block(&self, component: NativeComponent) -> TextBlock {
+        let size = self.font_size(component);
+        match component {
+            NativeComponent::Headline => {
+                let font = self.brand_kit.font("headline");
+                TextBlock::new(size, self.brand_kit.color("text"))
+                    .with_family(&font.family)
+                    .span(TextSpan::new(&self.ad.headline).with_weight(font.weight))
+            }
+            NativeComponent::Sponsor => TextBlock::new(size, self.brand_kit.color("text").with_alpha(0.6))
+                .text(&format!("{} {}", DISCLOSURE_TEXT, self.ad.sponsor)),
+            _ => TextBlock::new(size, self.brand_kit.color("text"))
+                .with_family(&self.brand_kit.font("body").family)
+                .text(self.ad.component(component)),
+        }
+    }
+
+    /// The size of a text component when it wraps within the width
+    fn text_size(&self, component: NativeComponent, width: f32, fonts: &mut FontRegistry) -> Size<f32> {
+        let size = self.text_block(component).measure(width, fonts);
+        Size { width, height: size.y.ceil() }
+    }
+
+    /// A fixed size object, or a spacer when one side is 0.0
+    fn object_size(width: f32, height: f32) -> Size<f32> {
+        Size { width, height }
+    }
+
+    /// A node that takes the remaining space in its parent, for images that scale with the ad
+    fn flexible_style() -> Style {
+        Style { flex_grow: 1.0, align_self: AlignSelf::Stretch, ..Default::default() }
+    }
+
+    /// Lay out the components for the template inside the frame
+    pub fn layout(&self, frame: &Rectangle, fonts: &mut FontRegistry) -> NativeLayout {
+        let padding = Rect {
+            start: Dimension::Points(PADDING),
+            end: Dimension::Points(PADDING),
+            top: Dimension::Points(PADDING),
+            bottom: Dimension::Points(PADDING),
+        };
+        let direction = match self.template {
+            NativeTemplate::ContentRecommendation | NativeTemplate::Banner => FlexDirection::Row,
+            _ => FlexDirection::Column,
+        };
+        let mut builder = LayoutBuilder::new().with_style(Style {
+            size: Size { width: Dimension::Points(frame.width()), height: Dimension::Points(frame.height()) },
+            flex_direction: direction,
+            align_items: if direction == FlexDirection::Row { AlignItems::Center } else { AlignItems::FlexStart },
+            padding,
+            ..Default::default()
+        });
+        let root = builder.root;
+        let inner_w = frame.width() - PADDING * 2.0;
+        let inner_h = frame.height() - PADDING * 2.0;
+        let mut nodes = Vec::new();
+
+        match self.template {
+            NativeTemplate::InFeed => {
+                const ICON_SIZE: f32 = 40.0;
+                let header = builder.add_row(root, ICON_SIZE, None);
+                let icon = builder.add_object(header, NativeAdBuilder::object_size(ICON_SIZE, ICON_SIZE));
+                nodes.push((icon, NativeComponent::Icon));
+                builder.add_object(header, NativeAdBuilder::object_size(SPACING, 0.0));
+                let sponsor_size = self.text_size(NativeComponent::Sponsor, inner_w - ICON_SIZE - SPACING, fonts);
+                nodes.push((builder.add_object(header, sponsor_size), NativeComponent::Sponsor));
+                builder.add_object(root, NativeAdBuilder::object_size(0.0, SPACING));
+                for component in [NativeComponent::Headline, NativeComponent::Body].iter() {
+                    let size = self.text_size(*component, inner_w, fonts);
+                    nodes.push((builder.add_object(root, size), *component));
+                    builder.add_object(root, NativeAdBuilder::object_size(0.0, SPACING));
+                }
+                let image = builder.add_row(root, 0.0, Some(NativeAdBuilder::flexible_style()));
+                nodes.push((image, NativeComponent::MainImage));
+                builder.add_object(root, NativeAdBuilder::object_size(0.0, SPACING));
+                let footer = builder.add_row(root, CTA_SIZE.1, Some(Style {
+                    size: Size { width: Dimension::Points(inner_w), height: Dimension::Points(CTA_SIZE.1) },
+                    justify_content: JustifyContent::FlexEnd,
+                    ..Default::default()
+                }));
+                let cta = builder.add_object(footer, NativeAdBuilder::object_size(CTA_SIZE.0, CTA_SIZE.1));
+                nodes.push((cta, NativeComponent::Cta));
+            }
+            NativeTemplate::ContentRecommendation => {
+                // A 16:9 thumbnail with the text to its right
+                let image_w = (inner_h * 16.0 / 9.0).round();
+                let image = builder.add_column(root, image_w, Some(Style {
+                    size: Size { width: Dimension::Points(image_w), height: Dimension::Points(inner_h) },
+                    ..Default::default()
+                }));
+                nodes.push((image, NativeComponent::MainImage));
+                builder.add_object(root, NativeAdBuilder::object_size(SPACING, 0.0));
+                let column_w = inner_w - image_w - SPACING;
+                let column = builder.add_column(root, column_w, None);
+                for component in [NativeComponent::Headline, NativeComponent::Sponsor].iter() {
+                    let size = self.text_size(*component, column_w, fonts);
+                    nodes.push((builder.add_object(column, size), *component));
+                    builder.add_object(column, NativeAdBuilder::object_size(0.0, SPACING));
+                }
+            }
+            NativeTemplate::AppInstall => {
+                const ICON_SIZE: f32 = 64.0;
+                let image = builder.add_row(root, 0.0, Some(NativeAdBuilder::flexible_style()));
+                nodes.push((image, NativeComponent::MainImage));
+                builder.add_object(root, NativeAdBuilder::object_size(0.0, SPACING));
+                let title_row = builder.add_row(root, ICON_SIZE, None);
+                let icon = builder.add_object(title_row, NativeAdBuilder::object_size(ICON_SIZE, ICON_SIZE));
+                nodes.push((icon, NativeComponent::Icon));
+                builder.add_object(title_row, NativeAdBuilder::object_size(SPACING, 0.0));
+                let column_w = inner_w - ICON_SIZE - SPACING;
+                let column = builder.add_column(title_row, column_w, None);
+                for component in [NativeComponent::Headline, NativeComponent::Sponsor].iter() {
+                    let size = self.text_size(*component, column_w, fonts);
+                    nodes.push((builder.add_object(column, size), *component));
+                }
+                builder.add_object(root, NativeAdBuilder::object_size(0.0, SPACING));
+                let size = self.text_size(NativeComponent::Body, inner_w, fonts);
+                nodes.push((builder.add_object(root, size), NativeComponent::Body));
+                builder.add_object(root, NativeAdBuilder::object_size(0.0, SPACING));
+                let cta = builder.add_object(root, NativeAdBuilder::object_size(inner_w, CTA_SIZE.1));
+                nodes.push((cta, NativeComponent::Cta));
+            }
+            NativeTemplate::Banner => {
+                let icon_size = inner_h;
+                let icon = builder.add_object(root, NativeAdBuilder::object_size(icon_size, icon_size));
+                nodes.push((icon, NativeComponent::Icon));
+                builder.add_object(root, NativeAdBuilder::object_size(SPACING, 0.0));
+                let column_w = inner_w - icon_size - CTA_SIZE.0 - SPACING * 2.0;
+                let column = builder.add_column(root, column_w, None);
+                for component in [NativeComponent::Headline, NativeComponent::Sponsor].iter() {
+                    let size = self.text_size(*component, column_w, fonts);
+                    nodes.push((builder.add_object(column, size), *component));
+                }
+                builder.add_object(root, NativeAdBuilder::object_size(SPACING, 0.0));
+                let cta = builder.add_object(root, NativeAdBuilder::object_size(CTA_SIZE.0, CTA_SIZE.1));
+                nodes.push((cta, NativeComponent::Cta));
+            }
+        }
+
+        let layout = builder.absolute_layout(root, (frame.x(), frame.y()));
+        let mut result = Vec::new();
+        NativeAdBuilder::collect_frames(&layout, &nodes, &mut result);
+        result
+    }
+
+    /// Find the frames of the component nodes in the layout tree
+    fn collect_frames(layout: &NodeLayout, nodes: &[(Node, NativeComponent)], result: &mut NativeLayout) {
+        for child in layout.children.iter() {
+            if let Some((_, component)) = nodes.iter().find(|(node, _)| *node == child.id) {
+                let size = (child.size.width, child.size.height);
+                result.push((*component, Rectangle::new((child.location.x, child.location.y), size)));
+            }
+            NativeAdBuilder::collect_frames(child, nodes, result);
+        }
+    }
+
+    /// Build the ad using assets that were preloaded from the ad's manifest
+    pub fn build_stage(&mut self, stage: &mut Stage, frame: &Rectangle, assets: &mut AdAssets) {
+        stage.title = format!("{} Native Ad", self.template.name());
+        let mut scene = Scene::new(frame.clone()).with_id(NATIVE_ID, "Native ad");
+        scene.layer.border_style = BorderStyle::SolidLine(self.brand_kit.color("border"), 1.0);
+        scene.layer.bg_style = BackgroundStyle::Solid(self.brand_kit.color("background"));
+
+        // Text and images are static sprites, so they are added to a timeline that starts with the ad
+        let mut timeline = Timeline::new(frame.clone());
+        for (component, rect) in self.layout(frame, &mut assets.fonts) {
+            match component {
+                NativeComponent::Headline | NativeComponent::Body | NativeComponent::Sponsor => {
+                    let block = self.text_block(component);
+                    let labels = block.make_labels(&rect, &mut assets.fonts);
+                    let count = labels.len();
+                    let bottom = rect.y() + rect.height() + 0.5;
+                    // Lines that do not fit in the frame are dropped
+                    let labels: Vec<Label> = labels
+                        .into_iter()
+                        .filter(|label| label.layer.frame.y() + label.layer.frame.height() <= bottom)
+                        .collect();
+                    if labels.len() < count {
+                        log::warn!("The {} template cuts off the {}", self.template.name(), component.name());
+                    }
+                    for label in labels {
+                        timeline.add_sprite(Box::new(label), 0.0);
+                    }
+                }
+                NativeComponent::Icon => match self.image_label(&self.ad.icon, &rect, None, assets) {
+                    Some(label) => timeline.add_sprite(Box::new(label), 0.0),
+                    None => log::error!("Failed to load native icon {:?}", self.ad.icon),
+                },
+                NativeComponent::MainImage => {
+                    let natural_size = self.ad.main_image_size;
+                    match self.image_label(&self.ad.main_image, &rect, natural_size, assets) {
+                        Some(label) => {
+                            let mut button = Button::new(label.layer.frame.clone());
+                            button.set_label(label);
+                            let exit = self.ad.exit.clone();
+                            button.set_onclick(move |state| {
+                                state.event_bus.register_event(AdEvent::Click(exit.clone()));
+                            });
+                            scene.add_control(Box::new(button));
+                        }
+                        None => log::error!("Failed to load native image {:?}", self.ad.main_image),
+                    }
+                }
+                NativeComponent::Cta => {
+                    let mut button = Button::new(rect);
+                    let (text, font_size) = (&self.ad.cta_text, self.font_size(component));
+                    self.brand_kit.apply_button_style("cta", text, &mut button, &mut assets.fonts);
+                    button.layer.font_style = FontStyle::new(font_size, self.brand_kit.color("cta_text"));
+                    self.brand_kit.set_button_text("cta", text, font_size, &mut button, &mut assets.fonts);
+                    let exit = self.ad.exit.clone();
+                    button.set_onclick(move |state| {
+                        state.event_bus.register_event(AdEvent::Click(exit.clone()));
+                    });
+                    scene.add_control(Box::new(button));
+                }
+            }
+        }
+        &timeline.play();
+        scene.set_timeline(timeline);
+        stage.add_scene(scene);
+    }
+
+    /// A Label with the image centered in the frame. SVG assets are rasterized at the fitted size and other
+    /// images are fitted using their natural size, or stretched to the frame if it is unknown.
+    fn image_label(
+        &self,
+        id: &str,
+        frame: &Rectangle,
+        natural_size: Option<(f32, f32)>,
+        assets: &mut AdAssets,
+    ) -> Option<Label> {
+        let max_size = (frame.width(), frame.height());
+        let (img, size) = match assets.svg_image(id, max_size) {
+            Ok(result) => result,
+            Err(_) => {
+                let img = assets.image(id)?;
+                let size = natural_size.map_or(max_size, |source| {
+                    let (width, height) = fit_size(source, max_size);
+                    (width as f32, height as f32)
+                });
+                (img, size)
+            }
+        };
+        let origin = frame.center_origin(size);
+        let mut label = Label::new(Rectangle::new(origin, size));
+        label.set_image(img);
+        label.display = LabelDisplay::Image;
+        Some(label)
+    }
+}
+
+/// Placeholder posts above and below the ad in a column of the same width, with the feed name at the top
+pub fn native_feed_scene(template: NativeTemplate, body_frame: &Rectangle, ad_frame: &Rectangle) -> Scene {
+    let mut scene = Scene::new(body_frame.clone()).with_id(FEED_ID, "Native Feed");
+    let column = Rectangle::new(
+        (ad_frame.x() - SPACING, body_frame.y()),
+        (ad_frame.width() + SPACING * 2.0, body_frame.height()),
+    );
+    add_rectangle(&mut scene, &column, Some(Color::from_hex(FEED_COLOR)), None);
+    let text_color = Color::from_hex(FEED_TEXT_COLOR);
+
+    let header = Rectangle::new(column.pos, (column.width(), 28.0));
+    let subframe = Rectangle::new((header.x() + SPACING, header.y() + 6.0), (header.width() - SPACING * 2.0, 16.0));
+    let mut text = Text::new(subframe, template.feed_name());
+    text.layer.font_style = FontStyle::new(14.0, text_color);
+    scene.add_control(Box::new(text));
+
+    // Posts fill the space above and below the ad, leaving a gap around it
+    let top = header.y() + header.height();
+    let bottom = body_frame.y() + body_frame.height();
+    let areas = [
+        (top, ad_frame.y() - SPACING),
+        (ad_frame.y() + ad_frame.height() + SPACING, bottom),
+    ];
+    for (start, end) in areas.iter() {
+        let mut ypos = *start;
+        while ypos + PAGE_LINE_H * 2.0 <= *end {
+            let height = (*end - ypos).min(96.0);
+            let card = Rectangle::new((ad_frame.x(), ypos), (ad_frame.width(), height));
+            add_rectangle(&mut scene, &card, Some(Color::from_hex(FEED_CARD_COLOR)), None);
+            let content =
+                Rectangle::new((card.x() + PADDING, card.y() + 4.0), (card.width() - PADDING * 2.0, height - 8.0));
+            add_text_lines(&mut scene, &content, text_color);
+            ypos += height + SPACING;
+        }
+    }
+    scene
+}
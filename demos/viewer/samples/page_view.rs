@@ -0,0 +1,174 @@
+/// Displays an ad inside a mock publisher page. The page is drawn at a fixed position and a simulated browser
+/// viewport scrolls over it. Content outside of the viewport is dimmed and the viewability status is shown
+/// below the page.
+use super::*;
+use crate::app::*;
+
+use quicksilver::{
+    geom::{Rectangle, Vector},
+    graphics::Color,
+    lifecycle::Window,
+};
+
+use tweek::{
+    gui::*,
+    tools::*,
+};
+
+const PAGE_ID: u32 = 400;
+const OVERLAY_ID: u32 = 401;
+
+/// Pixels that the viewport moves for each step of the mouse wheel
+const SCROLL_STEP: f32 = 20.0;
+const STATUS_H: f32 = 20.0;
+
+fn page_color(value: &str) -> Color {
+    match parse_hex_color(value) {
+        Ok(color) => color,
+        Err(e) => {
+            log::warn!("Mock page: {:?}", e);
+            Color::MAGENTA
+        }
+    }
+}
+
+pub(crate) fn add_rectangle(scene: &mut Scene, rect: &Rectangle, fill: Option<Color>, border: Option<Color>) {
+    let mut shape = DrawShape::rectangle(rect, fill, border, 2.0, 0.0);
+    let view = ShapeView::new(rect.clone(), ShapeDef::Rectangle).with_mesh(&mut shape);
+    scene.add_view(Box::new(view));
+}
+
+/// Placeholder lines with a short last line, like a paragraph
+pub(crate) fn add_text_lines(scene: &mut Scene, rect: &Rectangle, color: Color) {
+    let lines = (rect.height() / PAGE_LINE_H).floor() as usize;
+    for i in 0..lines {
+        let width = if i + 1 == lines { rect.width() * 0.6 } else { rect.width() };
+        let ypos = rect.y() + i as f32 * PAGE_LINE_H + 4.0;
+        let line = Rectangle::new((rect.x(), ypos), (width, PAGE_LINE_H - 8.0));
+        add_rectangle(scene, &line, Some(color.with_alpha(0.35)), None);
+    }
+}
+
+pub struct PageView {
+    page: MockPage,
+    /// The screen area for the page
+    frame: Rectangle,
+    /// The top left of the page on screen
+    origin: Vector,
+    scroll_y: f32,
+    status: String,
+    overlay: Scene,
+}
+
+impl PageView {
+    /// Center the page horizontally at the top of the frame
+    pub fn new(page: MockPage, frame: &Rectangle) -> Self {
+        let origin = Vector::new(frame.x() + ((frame.width() - page.width) / 2.0).max(0.0), frame.y());
+        let mut view = PageView {
+            page,
+            frame: frame.clone(),
+            origin,
+            scroll_y: 0.0,
+            status: String::new(),
+            overlay: Scene::new(frame.clone()),
+        };
+        view.overlay = view.overlay_scene();
+        view
+    }
+
+    /// The size of the page's ad slot
+    pub fn ad_spec(&self) -> AdSpec {
+        let slot = self.page.ad_slot_frame(Vector::ZERO);
+        AdSpec::new(slot.width() as u32, slot.height() as u32, &format!("{} ad slot", self.page.name))
+    }
+
+    /// The color behind the ad
+    pub fn bg_color(&self) -> Color {
+        page_color(&self.page.bg_color)
+    }
+
+    /// The ad slot on screen
+    pub fn ad_frame(&self) -> Rectangle {
+        self.page.ad_slot_frame(self.origin)
+    }
+
+    /// The simulated browser viewport on screen
+    pub fn viewport(&self) -> Rectangle {
+        Rectangle::new((self.origin.x, self.origin.y + self.scroll_y), (self.page.width, self.page.viewport_height))
+    }
+
+    /// Move the viewport by a number of mouse wheel steps. Returns true if it moved.
+    pub fn scroll_by(&mut self, steps: f32) -> bool {
+        let scroll_y = (self.scroll_y + steps * SCROLL_STEP).max(0.0).min(self.page.max_scroll());
+        if scroll_y == self.scroll_y {
+            return false;
+        }
+        self.scroll_y = scroll_y;
+        self.overlay = self.overlay_scene();
+        true
+    }
+
+    /// Show a line of text below the page
+    pub fn set_status(&mut self, status: String) {
+        if status != self.status {
+            self.status = status;
+            self.overlay = self.overlay_scene();
+        }
+    }
+
+    /// The page content, which is added to the Stage before the ad is built in the slot
+    pub fn page_scene(&self) -> Scene {
+        let mut scene = Scene::new(self.frame.clone()).with_id(PAGE_ID, "Page");
+        let text_color = page_color(&self.page.text_color);
+
+        let page_rect = Rectangle::new(self.origin, (self.page.width, self.page.height()));
+        add_rectangle(&mut scene, &page_rect, Some(page_color(&self.page.bg_color)), None);
+
+        for (block, rect) in self.page.block_frames(self.origin) {
+            let color = block.color.as_ref().map(|c| page_color(c));
+            match block.kind {
+                PageBlockKind::Header => {
+                    add_rectangle(&mut scene, &rect, color.or(Some(Color::BLACK)), None);
+                    let subframe = Rectangle::new((rect.x() + 12.0, rect.y()), (rect.width() - 24.0, rect.height()));
+                    let mut text = Text::new(subframe, &block.text);
+                    text.layer.font_style = FontStyle::new(18.0, text_color);
+                    scene.add_control(Box::new(text));
+                }
+                PageBlockKind::Text => add_text_lines(&mut scene, &rect, color.unwrap_or(text_color)),
+                PageBlockKind::Image => {
+                    add_rectangle(&mut scene, &rect, color.or(Some(Color::from_hex("#888888"))), None);
+                }
+                PageBlockKind::AdSlot => (),
+            }
+        }
+        scene
+    }
+
+    /// Dims the page outside of the viewport and shows the status
+    fn overlay_scene(&self) -> Scene {
+        let mut scene = Scene::new(self.frame.clone()).with_id(OVERLAY_ID, "Viewport");
+        let viewport = self.viewport();
+        let shade = Some(Color::BLACK.with_alpha(0.6));
+
+        let above = Rectangle::new(self.origin, (self.page.width, self.scroll_y));
+        let below_y = viewport.y() + viewport.height();
+        let below_h = self.origin.y + self.page.height() - below_y;
+        let below = Rectangle::new((self.origin.x, below_y), (self.page.width, below_h));
+        for rect in [above, below].iter().filter(|r| r.height() > 0.0) {
+            add_rectangle(&mut scene, rect, shade, None);
+        }
+        add_rectangle(&mut scene, &viewport, None, Some(Color::from_hex("#FFCC00")));
+
+        let ypos = (self.origin.y + self.page.height() + 4.0).min(self.frame.y() + self.frame.height() - STATUS_H);
+        let subframe = Rectangle::new((self.origin.x, ypos), (self.page.width, STATUS_H));
+        let mut text = Text::new(subframe, &self.status);
+        text.layer.font_style = FontStyle::new(14.0, Color::WHITE);
+        scene.add_control(Box::new(text));
+        scene
+    }
+
+    /// Render the overlay, which is drawn after the Stage so that it covers the ad
+    pub fn render(&mut self, theme: &mut Theme, window: &mut Window) {
+        self.overlay.render(theme, window);
+    }
+}
@@ -0,0 +1,286 @@
+/// Displays an ad in the chrome of the placement it runs in, instead of centered in the viewer. The page
+/// content around the ad is drawn with placeholder blocks.
+use super::*;
+use crate::ads::*;
+
+use quicksilver::{
+    geom::{Rectangle, Vector},
+    graphics::Color,
+    lifecycle::Window,
+};
+
+use tweek::{
+    core::AppState,
+    gui::*,
+    tools::*,
+};
+
+const CHROME_ID: u32 = 420;
+const PLACEMENT_OVERLAY_ID: u32 = 421;
+
+/// Seconds before an interstitial can be closed
+pub const INTERSTITIAL_CLOSE_DELAY: f64 = 5.0;
+const INTERSTITIAL_BAR_H: f32 = 28.0;
+const ANCHOR_PADDING: f32 = 8.0;
+const ANCHOR_TAB_SIZE: (f32, f32) = (32.0, 20.0);
+const RAIL_W: f32 = 320.0;
+const MARGIN: f32 = 16.0;
+
+const CONTENT_COLOR: &str = "#FFFFFF";
+const CONTENT_TEXT_COLOR: &str = "#333333";
+const CHROME_COLOR: &str = "#F0F0F0";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Placement {
+    /// Full screen with a countdown before the ad can be closed
+    Interstitial,
+    /// Fixed to the bottom of the screen over the page content, with a tab to close it
+    StickyAnchor,
+    /// At the top of a rail next to the page content
+    SidebarRail,
+}
+
+impl Placement {
+    pub fn from_name(name: &str) -> Option<Placement> {
+        match name {
+            "interstitial" => Some(Placement::Interstitial),
+            "anchor" => Some(Placement::StickyAnchor),
+            "sidebar" => Some(Placement::SidebarRail),
+            _ => None,
+        }
+    }
+
+    /// The MRAID placement type that the creative sees
+    pub fn placement_type(&self) -> PlacementType {
+        match self {
+            Placement::Interstitial => PlacementType::Interstitial,
+            _ => PlacementType::Inline,
+        }
+    }
+
+    /// The ad size that the placement starts with
+    pub fn default_spec(&self, body_frame: &Rectangle) -> AdSpec {
+        match self {
+            Placement::Interstitial => {
+                let height = body_frame.height() - INTERSTITIAL_BAR_H;
+                AdSpec::new(body_frame.width() as u32, height as u32, "Interstitial")
+            }
+            Placement::StickyAnchor => AdSpec::new(728, 90, "Leaderboard"),
+            Placement::SidebarRail => AdSpec::new(300, 250, "Inline Rectangle"),
+        }
+    }
+}
+
+pub struct PlacementView {
+    placement: Placement,
+    /// The screen area for the placement
+    frame: Rectangle,
+    ad_frame: Rectangle,
+    /// Seconds until an interstitial can be closed
+    countdown: f64,
+    is_closed: bool,
+    overlay: Scene,
+}
+
+impl PlacementView {
+    pub fn new(placement: Placement, frame: &Rectangle) -> Self {
+        PlacementView {
+            placement,
+            frame: frame.clone(),
+            ad_frame: frame.clone(),
+            countdown: INTERSTITIAL_CLOSE_DELAY,
+            is_closed: false,
+            overlay: Scene::new(frame.clone()),
+        }
+    }
+
+    pub fn placement(&self) -> Placement {
+        self.placement
+    }
+
+    /// The largest area the ad can use
+    pub fn max_size(&self) -> Rectangle {
+        match self.placement {
+            Placement::Interstitial => self.interstitial_frame(),
+            _ => self.frame.clone(),
+        }
+    }
+
+    fn interstitial_frame(&self) -> Rectangle {
+        let frame = &self.frame;
+        let bar_h = INTERSTITIAL_BAR_H;
+        Rectangle::new((frame.x(), frame.y() + bar_h), (frame.width(), frame.height() - bar_h))
+    }
+
+    fn rail_frame(&self) -> Rectangle {
+        let frame = &self.frame;
+        Rectangle::new((frame.x() + frame.width() - RAIL_W, frame.y()), (RAIL_W, frame.height()))
+    }
+
+    fn anchor_bar_frame(&self) -> Rectangle {
+        let frame = &self.frame;
+        let height = self.ad_frame.height() + ANCHOR_PADDING * 2.0;
+        Rectangle::new((frame.x(), frame.y() + frame.height() - height), (frame.width(), height))
+    }
+
+    /// Place an ad of this size in the placement and return its frame
+    pub fn layout(&mut self, spec: &AdSpec) -> Rectangle {
+        let frame = &self.frame;
+        self.ad_frame = match self.placement {
+            Placement::Interstitial => self.interstitial_frame(),
+            Placement::StickyAnchor => {
+                let x = frame.x() + (frame.width() - spec.width) / 2.0;
+                let y = frame.y() + frame.height() - spec.height - ANCHOR_PADDING;
+                Rectangle::new((x, y), (spec.width, spec.height))
+            }
+            Placement::SidebarRail => {
+                let rail = self.rail_frame();
+                let x = rail.x() + (rail.width() - spec.width) / 2.0;
+                Rectangle::new((x, rail.y() + MARGIN), (spec.width, spec.height))
+            }
+        };
+        self.overlay = self.overlay_scene();
+        self.ad_frame.clone()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.is_closed
+    }
+
+    pub fn close(&mut self) {
+        self.is_closed = true;
+        self.overlay = self.overlay_scene();
+    }
+
+    /// Show the ad again after it was closed
+    pub fn reopen(&mut self) {
+        self.is_closed = false;
+        self.countdown = INTERSTITIAL_CLOSE_DELAY;
+        self.overlay = self.overlay_scene();
+    }
+
+    /// Advance the countdown to close
+    pub fn update(&mut self, seconds: f64) {
+        if self.placement != Placement::Interstitial || self.is_closed || self.countdown <= 0.0 {
+            return;
+        }
+        let shown = self.countdown.ceil();
+        self.countdown = (self.countdown - seconds).max(0.0);
+        if self.countdown.ceil() != shown {
+            self.overlay = self.overlay_scene();
+        }
+    }
+
+    /// The page content and chrome behind the ad
+    pub fn chrome_scene(&self) -> Scene {
+        let mut scene = Scene::new(self.frame.clone()).with_id(CHROME_ID, "Placement");
+        let frame = &self.frame;
+        let text_color = Color::from_hex(CONTENT_TEXT_COLOR);
+        match self.placement {
+            Placement::Interstitial => {
+                let bar = Rectangle::new(frame.pos, (frame.width(), INTERSTITIAL_BAR_H));
+                add_rectangle(&mut scene, &bar, Some(Color::from_hex("#222222")), None);
+                let subframe = Rectangle::new((bar.x() + MARGIN, bar.y()), (200.0, bar.height()));
+                let mut text = Text::new(subframe, "Advertisement");
+                text.layer.font_style = FontStyle::new(12.0, Color::WHITE);
+                scene.add_control(Box::new(text));
+                if self.is_closed {
+                    let size = Vector::new(420.0, 20.0);
+                    let subframe = Rectangle::new(frame.pos + (frame.size - size) * 0.5, size);
+                    let mut text = Text::new(subframe, "The interstitial was closed. Press Play to show it again.");
+                    text.layer.font_style = FontStyle::new(14.0, Color::WHITE);
+                    scene.add_control(Box::new(text));
+                }
+            }
+            Placement::StickyAnchor => {
+                add_rectangle(&mut scene, frame, Some(Color::from_hex(CONTENT_COLOR)), None);
+                let content = Rectangle::new(
+                    (frame.x() + MARGIN * 4.0, frame.y() + MARGIN),
+                    (frame.width() - MARGIN * 8.0, frame.height() - MARGIN * 2.0),
+                );
+                add_text_lines(&mut scene, &content, text_color);
+                if !self.is_closed {
+                    let bar = self.anchor_bar_frame();
+                    add_rectangle(&mut scene, &bar, Some(Color::from_hex(CHROME_COLOR)), None);
+                    let line = Rectangle::new(bar.pos, (bar.width(), 1.0));
+                    add_rectangle(&mut scene, &line, Some(Color::from_hex("#CCCCCC")), None);
+                }
+            }
+            Placement::SidebarRail => {
+                add_rectangle(&mut scene, frame, Some(Color::from_hex(CONTENT_COLOR)), None);
+                let rail = self.rail_frame();
+                add_rectangle(&mut scene, &rail, Some(Color::from_hex(CHROME_COLOR)), None);
+                let header = Rectangle::new(
+                    (frame.x() + MARGIN, frame.y() + MARGIN),
+                    (rail.x() - frame.x() - MARGIN * 2.0, 40.0),
+                );
+                add_rectangle(&mut scene, &header, Some(text_color.with_alpha(0.6)), None);
+                let content = Rectangle::new(
+                    (header.x(), header.y() + header.height() + MARGIN),
+                    (header.width(), frame.height() - header.height() - MARGIN * 3.0),
+                );
+                add_text_lines(&mut scene, &content, text_color);
+            }
+        }
+        scene
+    }
+
+    /// The close controls, which are drawn over the ad
+    fn overlay_scene(&self) -> Scene {
+        let mut scene = Scene::new(self.frame.clone()).with_id(PLACEMENT_OVERLAY_ID, "Placement Controls");
+        if self.is_closed {
+            return scene;
+        }
+        match self.placement {
+            Placement::Interstitial => {
+                let subframe = Rectangle::new(
+                    (self.frame.x() + self.frame.width() - 100.0 - MARGIN, self.frame.y() + 2.0),
+                    (100.0, INTERSTITIAL_BAR_H - 4.0),
+                );
+                if self.countdown > 0.0 {
+                    let mut text = Text::new(subframe, &format!("Close in {}", self.countdown.ceil()));
+                    text.layer.font_style = FontStyle::new(12.0, Color::from_hex("#AAAAAA"));
+                    scene.add_control(Box::new(text));
+                } else {
+                    scene.add_control(Box::new(PlacementView::close_button(subframe, "Close X")));
+                }
+            }
+            Placement::StickyAnchor => {
+                let bar = self.anchor_bar_frame();
+                let pos = (bar.x() + bar.width() - ANCHOR_TAB_SIZE.0 - MARGIN, bar.y() - ANCHOR_TAB_SIZE.1);
+                let button = PlacementView::close_button(Rectangle::new(pos, ANCHOR_TAB_SIZE), "X");
+                scene.add_control(Box::new(button));
+            }
+            Placement::SidebarRail => (),
+        }
+        scene
+    }
+
+    fn close_button(frame: Rectangle, title: &str) -> Button {
+        let mut button = Button::new(frame).with_text(title);
+        button.layer.font_style = FontStyle::new(12.0, Color::BLACK);
+        button.layer.bg_style = BackgroundStyle::Solid(Color::from_hex(CHROME_COLOR));
+        button.layer.corner_radius = 3.0;
+        button.set_onclick(move |state| {
+            state.event_bus.register_event(AdEvent::Close);
+        });
+        button
+    }
+
+    /// Render the close controls, which are drawn after the Stage so that they cover the ad
+    pub fn render(&mut self, theme: &mut Theme, window: &mut Window) {
+        self.overlay.render(theme, window);
+    }
+
+    pub fn handle_mouse_at(&mut self, pt: &Vector, window: &mut Window) -> bool {
+        self.overlay.handle_mouse_at(pt, window)
+    }
+
+    pub fn handle_mouse_down(&mut self, pt: &Vector, state: &mut AppState) -> bool {
+        self.overlay.handle_mouse_down(pt, state)
+    }
+
+    pub fn handle_mouse_up(&mut self, pt: &Vector, state: &mut AppState) -> bool {
+        self.overlay.handle_mouse_up(pt, state)
+    }
+}
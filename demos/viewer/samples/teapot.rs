@@ -2,6 +2,8 @@
 ///
 ///
 use super::*;
+use crate::ads::*;
+use crate::app::*;
 
 use tweek::{
     core::*,
@@ -13,6 +15,7 @@ use tweek::{
 use quicksilver::{
     geom::{Rectangle, Vector},
     graphics::Color,
+    Error, Result,
 };
 
 use stretch::{
@@ -22,110 +25,763 @@ use stretch::{
     style::*
 };
 
+use std::fs;
+use std::path::{Path, PathBuf};
+
 const INTRO_FONT_SIZE: f32 = 80.0;
 const TITLE_FONT_SIZE: f32 = 68.0;
 const SUBTITLE_FONT_SIZE: f32 = 55.0;
+const TAGLINE_FONT_SIZE: f32 = 34.0;
 
 const BG_HEADER_H: f32 = 160.0;
+/// Logo size in the 500x500 prototype
+const LOGO_SIZE: (f32, f32) = (160.0, 120.0);
+
+/// The asset manifest for this ad, relative to the asset root
+pub const TEAPOT_MANIFEST: &'static str = "teapot/manifest.toml";
+/// The default trafficking setup for click-throughs, relative to the asset root
+pub const TEAPOT_CLICK_CONFIG: &'static str = "teapot/clickthrough.toml";
+
+/// The folder that html5-export reads the wasm build from when none is specified, which is where
+/// `cargo web deploy --release` writes it
+pub const DEFAULT_WASM_BUILD_DIR: &str = "target/deploy";
+/// The folder that html5-export writes to when none is specified, relative to the working directory
+pub const DEFAULT_HTML5_EXPORT_DIR: &str = "html5-export";
+/// The files of the wasm build. The script loads the wasm file and starts the ad.
+const WASM_BUILD_FILES: [&str; 2] = ["teapot.js", "teapot.wasm"];
+/// The string table of the DEFAULT_LOCALE, which is the only locale in an HTML5 bundle
+pub const TEAPOT_DEFAULT_STRINGS: &str = "teapot/strings/en.toml";
+/// The config files that the wasm build reads before loading the manifest's assets, relative to the asset root
+pub const HTML5_CONFIG_FILES: [&str; 4] =
+    [TEAPOT_MANIFEST, DEFAULT_BRAND_KIT, TEAPOT_CLICK_CONFIG, TEAPOT_DEFAULT_STRINGS];
+
+/// Exit names for click-throughs
+const CTA_EXIT: &'static str = "cta";
+const LOGO_EXIT: &'static str = "logo";
 
-const INTRO_TEXT_1: &'static str = "Introducing";
-const INTRO_TEXT_2: &'static str = "A Very Basic";
-const INTRO_TEXT_3: &'static str = "Animated Ad";
+// Built-in English copy for when the ad has no string table. The keys of the copy in string tables and variants are
+// intro, headline, headline_accent, tagline, tagline_accent and cta_text.
+const INTRO_TEXT: &'static str = "Introducing";
+const HEADLINE_TEXT: &'static str = "A Very Basic\nAnimated Ad";
+const HEADLINE_ACCENT: &'static str = "Animated";
+const TAGLINE_TEXT: &'static str = "Brew something\nbeautiful";
+const TAGLINE_ACCENT: &'static str = "beautiful";
+/// The text in the CTA image. Any other CTA text is drawn on a text button.
+const CTA_TEXT: &'static str = "Shop Now";
 
-const INTRO_1_ID: u32 = 221;
+/// The runs of the intro blocks and the tagline are numbered with run_id, so they don't collide with other ids
+const INTRO_1_ID: u32 = 211;
 const INTRO_2_ID: u32 = 222;
-const INTRO_3_ID: u32 = 223;
+const PRODUCT_ID: u32 = 231;
+const CTA_ID: u32 = 232;
+const TAGLINE_ID: u32 = 233;
+const LOGO_ID: u32 = 241;
+
+/// Natural size of img-teapot.png
+const PRODUCT_IMAGE_SIZE: (f32, f32) = (462.0, 548.0);
+
+// End card layout in the 500x500 prototype, relative to the ad frame
+const PRODUCT_FRAME: ((f32, f32), (f32, f32)) = ((30.0, 180.0), (200.0, 300.0));
+const TAGLINE_FRAME: ((f32, f32), (f32, f32)) = ((250.0, 220.0), (230.0, 120.0));
+const CTA_FRAME: ((f32, f32), (f32, f32)) = ((280.0, 380.0), (180.0, 48.0));
+
+/// Seconds for each intro text to slide in, and again to slide out
+const INTRO_SLIDE: f64 = 0.5;
 
-pub struct TeapotAdBuilder {}
+/// Total length of the ad animation with the default timing, used for tracking quartiles
+pub fn teapot_duration() -> f64 {
+    TeapotAdBuilder::new(BrandKit::default()).duration()
+}
+
+/// The id of the run at index in the text block with block_id
+fn run_id(block_id: u32, index: usize) -> u32 {
+    block_id * 100 + index as u32
+}
+
+pub struct TeapotAdBuilder {
+    brand_kit: BrandKit,
+    /// The copy, colors and timing that replace the defaults. The default variant has no overrides.
+    variant: CreativeVariant,
+    /// The localized copy. Keys that are not in the table use the built-in English.
+    strings: StringTable,
+    /// Text that does not fit its frame, from the last build
+    warnings: Vec<String>,
+    /// Whether the last build made a CTA image or a CTA button with text, or None before the first build
+    has_cta: Option<bool>,
+    /// The frame rate that drawing is capped at, or None when it is not capped
+    frame_rate: Option<f64>,
+}
 
 impl TeapotAdBuilder {
-    pub fn build_stage(&mut self, stage: &mut Stage, frame: &Rectangle, theme: &mut Theme, spec: &AdSpec) {
+    pub fn new(brand_kit: BrandKit) -> Self {
+        TeapotAdBuilder {
+            brand_kit,
+            variant: CreativeVariant::default(),
+            strings: StringTable { locale: DEFAULT_LOCALE.to_string(), ..StringTable::default() },
+            warnings: Vec::new(),
+            has_cta: None,
+            frame_rate: None,
+        }
+    }
+
+    pub fn with_frame_rate(mut self, frame_rate: Option<f64>) -> Self {
+        self.frame_rate = frame_rate;
+        self
+    }
+
+    pub fn with_strings(mut self, strings: StringTable) -> Self {
+        self.set_strings(strings);
+        self
+    }
+
+    pub fn set_strings(&mut self, strings: StringTable) {
+        self.strings = strings;
+    }
+
+    /// Copy that overflowed its frame in the last build_stage, e.g. after translation
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Build the variant instead of the default ad. The variant's colors must be hex values or palette tokens.
+    pub fn with_variant(mut self, variant: CreativeVariant) -> Result<Self> {
+        self.brand_kit = self
+            .brand_kit
+            .with_palette(&variant.colors)
+            .map_err(|e| Error::ContextError(format!("Variant {}: {:?}", variant.id, e)))?;
+        self.variant = variant;
+        Ok(self)
+    }
+
+    pub fn variant(&self) -> &CreativeVariant {
+        &self.variant
+    }
+
+    /// Total length of the ad animation with this variant's timing. The end card starts when both intro texts are
+    /// gone, and its product image, tagline and CTA slide in one stagger apart.
+    pub fn duration(&self) -> f64 {
+        self.cta_start() + self.variant.timing.slide
+    }
+
+    /// The second intro text starts when the first has left
+    fn intro_2_start(&self) -> f64 {
+        2.0 * INTRO_SLIDE + self.variant.timing.intro_hold
+    }
+
+    fn product_start(&self) -> f64 {
+        2.0 * self.intro_2_start()
+    }
+
+    fn tagline_start(&self) -> f64 {
+        self.product_start() + self.variant.timing.stagger
+    }
+
+    fn cta_start(&self) -> f64 {
+        self.tagline_start() + self.variant.timing.stagger
+    }
+
+    /// Build the ad using assets that were preloaded from TEAPOT_MANIFEST. The copy is drawn with fonts from the
+    /// assets' FontRegistry.
+    pub fn build_stage(&mut self, stage: &mut Stage, frame: &Rectangle, spec: &AdSpec, assets: &mut AdAssets) {
         stage.title = "Teapot Ad".to_string();
         log::debug!("build_stage frame={:?}", frame);
         log::debug!("build_stage spec={:?}", spec);
-        let background = self.background_scene(frame, spec);
+        self.load_script_font(assets);
+        let background = self.background_scene(frame, spec, assets);
         stage.add_scene(background);
 
         // let body_frame = Rectangle::new
-        let intro = self.intro_scene(frame, spec, theme);
+        let intro = self.intro_scene(frame, spec, assets);
         stage.add_scene(intro);
 
+        let end_card = self.make_final_scene(frame, spec, assets);
+        stage.add_scene(end_card);
+
+        self.warnings = self.copy_warnings(frame, spec, &mut assets.fonts);
+        for warning in self.warnings.iter() {
+            log::warn!("Copy in {} at {}: {}", self.strings.locale, spec.id, warning);
+        }
+    }
+
+    /// Register the locale's font, if it has one, and use it for copy that the brand fonts have no glyphs for
+    fn load_script_font(&self, assets: &mut AdAssets) {
+        let family = match &self.strings.script.font_family {
+            Some(family) => family,
+            None => return,
+        };
+        if !assets.fonts.contains(family, FontWeight::Regular) {
+            if let Some(folder) = &self.strings.script.font_folder {
+                let dir = assets.root().join(folder);
+                if let Err(e) = assets.fonts.load_folder(&dir) {
+                    log::error!("Failed to load fonts for locale {} from {:?}: {:?}", self.strings.locale, dir, e);
+                }
+            }
+        }
+        for token in ["headline", "body"].iter() {
+            assets.fonts.set_fallbacks(&self.brand_kit.font(token).family, &[family.as_str()]);
+        }
+    }
+
+    fn is_rtl(&self) -> bool {
+        self.strings.is_rtl()
+    }
+
+    fn direction(&self) -> TextDirection {
+        if self.is_rtl() {
+            TextDirection::RightToLeft
+        } else {
+            TextDirection::LeftToRight
+        }
+    }
+
+    /// The copy for a key from the variant, then the string table, then the built-in English
+    fn copy(&self, key: &str, default: &'static str) -> &str {
+        self.variant.copy.get(key).or_else(|| self.strings.get(key)).unwrap_or(default)
+    }
+
+    /// The copy for a key and its accent. The accent comes from the same place as the text.
+    fn accented_copy(
+        &self,
+        key: &str,
+        accent_key: &str,
+        default: (&'static str, &'static str),
+    ) -> (&str, Option<&str>) {
+        if let Some(text) = self.variant.copy.get(key) {
+            return (text, self.variant.copy.get(accent_key));
+        }
+        if let Some(text) = self.strings.get(key) {
+            return (text, self.strings.get(accent_key));
+        }
+        (default.0, Some(default.1))
+    }
+
+    /// The CTA text, or None when the CTA image has the right text
+    fn cta_text(&self) -> Option<&str> {
+        Some(self.copy("cta_text", CTA_TEXT)).filter(|text| *text != CTA_TEXT)
+    }
+
+    fn intro_block(&self, font_size: f32) -> TextBlock {
+        let body = self.brand_kit.font("body");
+        TextBlock::new(font_size, self.brand_kit.color("text"))
+            .with_family(&body.family)
+            .with_align(TextAlign::Center)
+            .with_direction(self.direction())
+            .text(self.copy("intro", INTRO_TEXT))
+    }
+
+    fn headline_block(&self, font_size: f32) -> TextBlock {
+        let headline = self.brand_kit.font("headline");
+        let (text, accent) = self.accented_copy("headline", "headline_accent", (HEADLINE_TEXT, HEADLINE_ACCENT));
+        let (before, accent, after) = split_accent(text, accent);
+        let block = TextBlock::new(font_size, self.brand_kit.color("text"))
+            .with_family(&headline.family)
+            .with_align(TextAlign::Center)
+            .with_direction(self.direction())
+            .with_line_height(1.1);
+        let accent = TextSpan::new(accent).with_weight(headline.weight).with_color(self.brand_kit.color("accent"));
+        add_accented(block, before, accent, after)
+    }
+
+    fn tagline_block(&self, spec: &AdSpec) -> TextBlock {
+        let body = self.brand_kit.font("body");
+        let (text, accent) = self.accented_copy("tagline", "tagline_accent", (TAGLINE_TEXT, TAGLINE_ACCENT));
+        let (before, accent, after) = split_accent(text, accent);
+        let block = TextBlock::new(TAGLINE_FONT_SIZE * spec.scale_y, self.brand_kit.color("text"))
+            .with_family(&body.family)
+            .with_align(TextAlign::Center)
+            .with_direction(self.direction());
+        let accent = TextSpan::new(accent).bold().with_color(self.brand_kit.color("accent"));
+        add_accented(block, before, accent, after)
+    }
+
+    /// Check that each piece of copy fits its frame at this spec, and that accents are in their text
+    fn copy_warnings(&self, frame: &Rectangle, spec: &AdSpec, fonts: &mut FontRegistry) -> Vec<String> {
+        let mut warnings = Vec::new();
+        let font_size = INTRO_FONT_SIZE * spec.scale_y;
+        let tagline_frame = self.end_card_frame(frame, spec, TAGLINE_FRAME);
+        let accents = [
+            ("headline", "headline_accent", (HEADLINE_TEXT, HEADLINE_ACCENT)),
+            ("tagline", "tagline_accent", (TAGLINE_TEXT, TAGLINE_ACCENT)),
+        ];
+        for (key, accent_key, default) in accents.iter() {
+            if let (text, Some(accent)) = self.accented_copy(key, accent_key, *default) {
+                if !text.contains(accent) {
+                    warnings.push(format!("{} {:?} is not in the {}", accent_key, accent, key));
+                }
+            }
+        }
+
+        let blocks = [
+            ("intro", self.intro_block(font_size), frame),
+            ("headline", self.headline_block(font_size), frame),
+            ("tagline", self.tagline_block(spec), &tagline_frame),
+        ];
+        for (key, block, frame) in blocks.iter() {
+            let overflow = block.overflow(frame, fonts);
+            if overflow.x > 0.5 {
+                warnings.push(format!("{} is {:.0}px too wide", key, overflow.x));
+            }
+            if overflow.y > 0.5 {
+                warnings.push(format!("{} is {:.0}px too tall", key, overflow.y));
+            }
+        }
 
+        if let Some(text) = self.cta_text() {
+            let cta_frame = self.end_card_frame(frame, spec, CTA_FRAME);
+            let font = self.brand_kit.button_font("cta");
+            let block = TextBlock::new(font.size, Color::BLACK)
+                .with_family(&font.family)
+                .span(TextSpan::new(text).with_weight(font.weight));
+            let text_w = block.measure(f32::MAX, fonts).x;
+            if text_w > cta_frame.width() {
+                warnings.push(format!("cta_text is {:.0}px wider than the button", text_w - cta_frame.width()));
+            }
+        }
+        warnings
     }
 
-    fn background_scene(&self, frame: &Rectangle, spec: &AdSpec) -> Scene {
+    fn background_scene(&mut self, frame: &Rectangle, spec: &AdSpec, assets: &mut AdAssets) -> Scene {
         let mut scene = Scene::new(frame.clone()).with_id(201, "Ad scene");
-        scene.layer.border_style = BorderStyle::SolidLine(Color::BLACK, 1.0);
-        scene.layer.bg_style = BackgroundStyle::Solid(Color::WHITE);
+        scene.layer.border_style = BorderStyle::SolidLine(self.brand_kit.color("border"), 1.0);
+        scene.layer.bg_style = BackgroundStyle::Solid(self.brand_kit.color("background"));
         let h = BG_HEADER_H * spec.scale_y;
         let rect = Rectangle::new(frame.pos, (frame.width(), h));
-        let fill_color = Color::from_hex("#003300");
+        let fill_color = self.brand_kit.color("primary");
         let mut shape = DrawShape::rectangle(&rect, Some(fill_color), None, 0.0, 0.0);
         let header_bg = ShapeView::new(rect, ShapeDef::Rectangle).with_mesh(&mut shape);
         scene.add_view(Box::new(header_bg));
 
+        // The logo is rasterized at the size required by the spec and centered in the header
+        let max_size = spec.scaled_size(LOGO_SIZE);
+        let max_size = (max_size.0, max_size.1.min(h * 0.8));
+        match assets.svg_image("logo", max_size) {
+            Ok((img, size)) => {
+                let origin = rect.center_origin(size);
+                let subframe = Rectangle::new(origin, size);
+                let mut label = Label::new(subframe.clone());
+                label.set_image(img);
+                label.display = LabelDisplay::Image;
+                let mut button = Button::new(subframe);
+                button.set_id(LOGO_ID);
+                button.set_label(label);
+                button.set_onclick(move |state| {
+                    state.event_bus.register_event(AdEvent::Click(LOGO_EXIT.to_string()));
+                });
+                scene.add_control(Box::new(button));
+            }
+            Err(e) => log::error!("Failed to load logo: {:?}", e),
+        }
+
         scene
     }
 
-    /// Create a Scene with 3 text elements that will animate from the right to the middle, wait,
-    /// and then animate out to the left. Each sequentially
-    fn intro_scene(&self, frame: &Rectangle, spec: &AdSpec, theme: &mut Theme) -> Scene {
+    /// Create a Scene with 2 text blocks that will animate from the right to the middle, wait, and then animate
+    /// out to the left, or the other way around for right-to-left copy. Each sequentially. The second one is a
+    /// multi-line TextBlock where each styled run is animated together.
+    fn intro_scene(&mut self, frame: &Rectangle, spec: &AdSpec, assets: &mut AdAssets) -> Scene {
         let mut scene = Scene::new(frame.clone());
 
         let mut timeline = Timeline::new(frame.clone());
         let font_size = INTRO_FONT_SIZE * spec.scale_y;
 
-        let label = self.make_intro_text(INTRO_TEXT_1, font_size, frame, theme, INTRO_1_ID);
-        // label.layer.enable_debug();
-        timeline.add_sprite(Box::new(label), 0.0);
-
-        let label = self.make_intro_text(INTRO_TEXT_2, font_size, frame, theme, INTRO_2_ID);
-        timeline.add_sprite(Box::new(label), 2.0);
+        let block = self.intro_block(font_size);
+        for label in self.make_intro_block(&block, frame, INTRO_1_ID, &mut assets.fonts) {
+            timeline.add_sprite(Box::new(label), 0.0);
+        }
 
-        let label = self.make_intro_text(INTRO_TEXT_3, font_size, frame, theme, INTRO_3_ID);
-        timeline.add_sprite(Box::new(label), 4.0);
+        let block = self.headline_block(font_size);
+        for label in self.make_intro_block(&block, frame, INTRO_2_ID, &mut assets.fonts) {
+            timeline.add_sprite(Box::new(label), self.intro_2_start());
+        }
         &timeline.play();
         scene.set_timeline(timeline);
 
         scene
     }
 
-    fn make_intro_text(&self, text: &str, font_size: f32, frame: &Rectangle, theme: &mut Theme, id: u32) -> Label {
-        let text_size = theme.default_font.measure_text(text, font_size);
-        let origin = frame.center_origin(text_size);
-        // log::trace!("text_size={:?} origin={:?}", text_size, origin);
-        let xpos = frame.x() + frame.width() + 10.0;
-        let ypos = origin.1;
-        let subframe = Rectangle::new((xpos, ypos), (text_size.0, font_size));
-
-        // Intro #1
-        let mut label = Label::new(subframe.clone());
-        label.set_id(id);
-        label.set_text(text);
-        label.display = LabelDisplay::Text;
-        label.layer.font_style = FontStyle::new(font_size, Color::BLACK);
-        label.layer.lock_style = true;
-        let final_x = -text_size.0 - 10.0 + frame.x();
-        let mut tween = Tween::with(id, &label.layer)
-            .to(&[position(origin.0, ypos)])
-            .duration(0.5)
+    /// Layout the TextBlock centered in the frame and give every run the same slide in, hold and slide out
+    /// animation, so that the block moves as a single unit. Ids are assigned with run_id from block_id.
+    fn make_intro_block(
+        &self,
+        block: &TextBlock,
+        frame: &Rectangle,
+        block_id: u32,
+        fonts: &mut FontRegistry,
+    ) -> Vec<Label> {
+        let block_size = block.measure(frame.width(), fonts);
+        let ypos = frame.y() + (frame.height() - block_size.y) / 2.0;
+        let block_frame = Rectangle::new((frame.x(), ypos), (frame.width(), block_size.y));
+        // Offset of the block when it starts just off the right edge and ends just off the left edge. Right-to-left
+        // copy reads in from the left instead.
+        let mut start_dx = frame.width() + 10.0;
+        if self.is_rtl() {
+            start_dx = -start_dx;
+        }
+        let final_dx = -start_dx;
+
+        let mut labels = block.make_labels(&block_frame, fonts);
+        for (i, label) in labels.iter_mut().enumerate() {
+            let id = run_id(block_id, i);
+            let pos = label.layer.frame.pos;
+            label.set_id(id);
+            label.layer.frame.pos.x += start_dx;
+            let tween = self.make_intro_tween(id, &label.layer, pos.x, pos.x + final_dx, pos.y);
+            label.layer.set_animation(tween);
+        }
+        labels
+    }
+
+    fn make_intro_tween(&self, id: u32, layer: &Layer, center_x: f32, final_x: f32, ypos: f32) -> Tween {
+        Tween::with(id, layer)
+            .to(&[position(center_x, ypos)])
+            .duration(INTRO_SLIDE)
             .ease(Ease::SineIn)
             .to(&[])
-            .duration(1.0)
+            .duration(self.variant.timing.intro_hold)
             .to(&[position(final_x, ypos)])
-            .duration(0.5)
+            .duration(INTRO_SLIDE)
             .ease(Ease::SineOut)
-            ;
-        label.layer.set_animation(tween);
-        label
     }
 
-    fn make_final_scene(&self, frame: &Rectangle) -> Scene {
+    /// Create the end card that follows the intro: the product image slides in from the left, then the
+    /// tagline from the right, then the CTA button rises from the bottom. Everything stays in place at the end.
+    /// For right-to-left copy the layout is mirrored, with the product on the right.
+    fn make_final_scene(&mut self, frame: &Rectangle, spec: &AdSpec, assets: &mut AdAssets) -> Scene {
         let mut scene = Scene::new(frame.clone());
+        let mut timeline = Timeline::new(frame.clone());
 
+        // 1. Product image reveal
+        let product_frame = self.end_card_frame(frame, spec, PRODUCT_FRAME);
+        if let Some(img) = assets.image("teapot") {
+            let (width, height) = fit_size(PRODUCT_IMAGE_SIZE, (product_frame.width(), product_frame.height()));
+            let size = (width as f32, height as f32);
+            let origin = product_frame.center_origin(size);
+            let start_x = if self.is_rtl() { frame.x() + frame.width() + 10.0 } else { frame.x() - size.0 - 10.0 };
+            let mut label = Label::new(Rectangle::new((start_x, origin.1), size));
+            label.set_id(PRODUCT_ID);
+            label.set_image(img);
+            label.display = LabelDisplay::Image;
+            let tween = self.make_slide_tween(PRODUCT_ID, &label.layer, origin.0, origin.1);
+            label.layer.set_animation(tween);
+            timeline.add_sprite(Box::new(label), self.product_start());
+        }
 
+        // 2. Tagline
+        let tagline_frame = self.end_card_frame(frame, spec, TAGLINE_FRAME);
+        let block = self.tagline_block(spec);
+        let mut labels = block.make_labels(&tagline_frame, &mut assets.fonts);
+        let start_dx = if self.is_rtl() {
+            frame.x() - 10.0 - (tagline_frame.x() + tagline_frame.width())
+        } else {
+            frame.x() + frame.width() + 10.0 - tagline_frame.x()
+        };
+        for (i, label) in labels.iter_mut().enumerate() {
+            let id = run_id(TAGLINE_ID, i);
+            let pos = label.layer.frame.pos;
+            label.set_id(id);
+            label.layer.frame.pos.x += start_dx;
+            let tween = self.make_slide_tween(id, &label.layer, pos.x, pos.y);
+            label.layer.set_animation(tween);
+        }
+        for label in labels {
+            timeline.add_sprite(Box::new(label), self.tagline_start());
+        }
 
+        // 3. CTA button
+        let cta_frame = self.end_card_frame(frame, spec, CTA_FRAME);
+        let start_y = frame.y() + frame.height() + 10.0;
+        let mut button = Button::new(Rectangle::new((cta_frame.x(), start_y), cta_frame.size));
+        // Other CTA text than the image's, like a translation or a variant's, is drawn on a text button
+        let cta_text = match self.cta_text() {
+            Some(text) => Some(text),
+            None => match assets.svg_image("cta", (cta_frame.width(), cta_frame.height())) {
+                Ok((img, size)) => {
+                    let mut label = Label::new(Rectangle::new((cta_frame.x(), start_y), size));
+                    label.set_image(img);
+                    label.display = LabelDisplay::Image;
+                    button.set_label(label);
+                    None
+                }
+                Err(e) => {
+                    log::error!("Failed to load CTA image, using text: {:?}", e);
+                    Some(CTA_TEXT)
+                }
+            },
+        };
+        // A blank CTA text makes a button without a label, which does not count as a CTA
+        let has_cta = !matches!(cta_text, Some(text) if text.trim().is_empty());
+        if let Some(text) = cta_text {
+            let text = if self.is_rtl() { visual_rtl_line(text) } else { text.to_string() };
+            self.brand_kit.apply_button_style("cta", &text, &mut button, &mut assets.fonts);
+        }
+        self.has_cta = Some(has_cta);
+        button.set_id(CTA_ID);
+        button.set_onclick(move |state| {
+            state.event_bus.register_event(AdEvent::Click(CTA_EXIT.to_string()));
+        });
+        let tween = self.make_slide_tween(CTA_ID, &button.layer, cta_frame.x(), cta_frame.y());
+        button.layer.set_animation(tween);
+        timeline.add_sprite(Box::new(button), self.cta_start());
 
+        &timeline.play();
+        scene.set_timeline(timeline);
         scene
     }
-}
\ No newline at end of file
+
+    /// Convert an end card frame from the prototype layout to absolute coordinates for this spec. Right-to-left
+    /// copy mirrors the prototype layout.
+    fn end_card_frame(&self, frame: &Rectangle, spec: &AdSpec, rect: ((f32, f32), (f32, f32))) -> Rectangle {
+        let ((mut x, y), size) = rect;
+        if self.is_rtl() {
+            x = DEFAULT_SIZE.0 as f32 - x - size.0;
+        }
+        let rect = spec.scale_rect(&Rectangle::new((x, y), size));
+        Rectangle::new((frame.x() + rect.x(), frame.y() + rect.y()), rect.size)
+    }
+
+    /// Slide into the final position and stay there
+    fn make_slide_tween(&self, id: u32, layer: &Layer, x: f32, y: f32) -> Tween {
+        Tween::with(id, layer).to(&[position(x, y)]).duration(self.variant.timing.slide).ease(Ease::SineOut)
+    }
+
+    /// Describe the ad at this spec for the lint rules. The load weight is measured by the caller. Before the ad
+    /// is built, the CTA is expected to be the CTA image or a text button with the CTA text.
+    pub fn lint_facts(&self, spec: &AdSpec) -> CreativeFacts {
+        let font_size = INTRO_FONT_SIZE * spec.scale_y;
+        let mut text_sizes = vec![
+            ("intro".to_string(), font_size),
+            ("headline".to_string(), font_size),
+            ("tagline".to_string(), TAGLINE_FONT_SIZE * spec.scale_y),
+        ];
+        // The CTA image has its own text, but a text button uses the cta button style at its natural size
+        if self.cta_text().is_some() {
+            text_sizes.push(("cta_text".to_string(), self.brand_kit.button_font_size("cta")));
+        }
+        let background = self.brand_kit.color("background");
+        let border = self.brand_kit.color("border");
+        CreativeFacts {
+            name: "Teapot".to_string(),
+            spec_id: spec.id.clone(),
+            duration: self.duration(),
+            // The timeline plays once and the end card stays
+            loops: 1,
+            initial_load_bytes: None,
+            polite_load_bytes: None,
+            // The animation is timed in seconds, so only the app's frame cap limits its frame rate
+            frame_rate: self.frame_rate,
+            has_cta: self.has_cta.unwrap_or_else(|| !matches!(self.cta_text(), Some(text) if text.trim().is_empty())),
+            background_luminance: relative_luminance(background.r, background.g, background.b),
+            border_width: if border == background { 0.0 } else { 1.0 },
+            text_sizes,
+        }
+    }
+}
+
+/// The variants of the Teapot ad, as builders that are ready to build panes side by side
+pub fn load_variant_builders<P: AsRef<Path>>(
+    path: P,
+    ids: &[&str],
+    brand_kit: &BrandKit,
+) -> Result<Vec<TeapotAdBuilder>> {
+    let set = VariantSet::load_file(path)?;
+    let mut builders = Vec::new();
+    for variant in set.select(ids)? {
+        builders.push(TeapotAdBuilder::new(brand_kit.clone()).with_variant(variant)?);
+    }
+    if builders.len() < 2 {
+        return Err(Error::ContextError("At least 2 variants are needed for a comparison".to_string()));
+    }
+    Ok(builders)
+}
+
+/// The viewer's sizes without duplicates, since some keys in AD_SIZES_MAP share an AdSpec
+fn teapot_specs() -> Vec<&'static AdSpec> {
+    let mut specs: Vec<&AdSpec> = Vec::new();
+    for spec in AD_SIZES_MAP.values() {
+        if !specs.iter().any(|other| other.id == spec.id) {
+            specs.push(spec);
+        }
+    }
+    specs
+}
+
+/// The Teapot ad's manifest and the asset root it is relative to, which is read from the same env var as in the viewer
+fn load_teapot_manifest() -> Result<(AssetManifest, String)> {
+    let asset_root = asset_root();
+    let manifest = AssetManifest::load_file(Path::new(&asset_root).join(TEAPOT_MANIFEST))?;
+    Ok((manifest, asset_root))
+}
+
+/// Lint the Teapot ad at each of the viewer's sizes. The brand kit, asset root and frame cap are read from the same
+/// env vars as in the viewer.
+pub fn lint_teapot<P: AsRef<Path>>(rules_path: P) -> Result<Vec<LintReport>> {
+    let config = LintConfig::load_file(rules_path)?;
+    let kit_path = env_asset_path("AD_BRAND_KIT", DEFAULT_BRAND_KIT);
+    let brand_kit = BrandKit::load_file(&kit_path)?;
+    let (manifest, asset_root) = load_teapot_manifest()?;
+
+    let builder = TeapotAdBuilder::new(brand_kit.clone()).with_frame_rate(env_frame_rate());
+    let mut reports = Vec::new();
+    for spec in teapot_specs() {
+        let weight =
+            LoadWeight::measure(&manifest, &asset_root, &spec.id)?.with_brand_fonts(&brand_kit, &asset_root)?;
+        let facts = CreativeFacts {
+            initial_load_bytes: Some(weight.total(LoadPhase::Initial)),
+            polite_load_bytes: Some(weight.total(LoadPhase::Polite)),
+            ..builder.lint_facts(spec)
+        };
+        reports.push(lint(&facts, &config.rules_for(&spec.id)));
+    }
+    Ok(reports)
+}
+
+/// Weigh the Teapot ad's files and brand fonts at each of the viewer's sizes against the load budgets in the lint
+/// rules. The brand kit and asset root are read from the same env vars as in the viewer.
+pub fn weigh_teapot<P: AsRef<Path>>(rules_path: P) -> Result<Vec<LoadWeightReport>> {
+    let config = LintConfig::load_file(rules_path)?;
+    let kit_path = env_asset_path("AD_BRAND_KIT", DEFAULT_BRAND_KIT);
+    let brand_kit = BrandKit::load_file(&kit_path)?;
+    let (manifest, asset_root) = load_teapot_manifest()?;
+
+    let mut reports = Vec::new();
+    for spec in teapot_specs() {
+        let weight =
+            LoadWeight::measure(&manifest, &asset_root, &spec.id)?.with_brand_fonts(&brand_kit, &asset_root)?;
+        let rules = config.rules_for(&spec.id);
+        reports.push(LoadWeightReport::new(weight, rules.max_initial_load_kb, rules.max_polite_load_kb));
+    }
+    Ok(reports)
+}
+
+/// Package the Teapot ad as an HTML5 bundle for each of the viewer's sizes. Each zip has an index.html with the
+/// ad size and clickTags, the wasm build, the HTML5_CONFIG_FILES and the manifest files that are used at that size.
+/// The fonts in the brand kit's font folder are zipped too, and added to the bundled manifest as [[fonts]] entries so
+/// that the wasm build loads them like the manifest's fonts.
+/// Every file in the zip is weighed against the load budgets in the lint rules, and bundles that are over budget are
+/// logged. The asset root, brand kit, click config, string tables and lint rules are read from the same env vars as
+/// in the viewer, and are stored in the zip at the default paths that the wasm build reads.
+pub fn export_teapot_html5<P: AsRef<Path>>(build_dir: P, out_dir: P) -> Result<Vec<PathBuf>> {
+    let (manifest, asset_root) = load_teapot_manifest()?;
+    let brand_kit = BrandKit::load_file(env_asset_path("AD_BRAND_KIT", DEFAULT_BRAND_KIT))?;
+    // The wasm build cannot list the kit's font folder, so its fonts are added to the bundled manifest
+    let mut kit_fonts = brand_kit.font_assets(&asset_root)?;
+    kit_fonts.retain(|font| !manifest.paths().contains(&font.path.as_str()));
+    let click_path = match std::env::var("AD_CLICK_CONFIG") {
+        Ok(path) => PathBuf::from(path),
+        Err(_) => Path::new(&asset_root).join(TEAPOT_CLICK_CONFIG),
+    };
+    let click_config = ClickConfig::load_file(&click_path)?;
+    let lint_config = LintConfig::load_file(env_asset_path("AD_LINT_RULES", DEFAULT_LINT_RULES))?;
+    let mut build = Vec::new();
+    for name in WASM_BUILD_FILES.iter() {
+        let path = build_dir.as_ref().join(name);
+        let data = fs::read(&path).map_err(|e| {
+            Error::ContextError(format!("Cannot read {}: {}. Run cargo web deploy --release.", path.display(), e))
+        })?;
+        build.push((*name, data));
+    }
+    let mut config_files = Vec::new();
+    for name in HTML5_CONFIG_FILES.iter() {
+        let path = match *name {
+            DEFAULT_BRAND_KIT => PathBuf::from(env_asset_path("AD_BRAND_KIT", DEFAULT_BRAND_KIT)),
+            TEAPOT_CLICK_CONFIG => click_path.clone(),
+            TEAPOT_DEFAULT_STRINGS => Path::new(&env_asset_path("AD_STRINGS", DEFAULT_STRINGS_DIR))
+                .join(format!("{}.toml", DEFAULT_LOCALE)),
+            _ => Path::new(&asset_root).join(name),
+        };
+        let mut data =
+            fs::read(&path).map_err(|e| Error::ContextError(format!("Cannot read {}: {}", path.display(), e)))?;
+        if *name == TEAPOT_MANIFEST {
+            for font in kit_fonts.iter() {
+                data.extend_from_slice(font.to_toml().as_bytes());
+            }
+        }
+        config_files.push((format!("{}/{}", DEFAULT_ASSET_ROOT, name), data));
+    }
+
+    fs::create_dir_all(out_dir.as_ref())?;
+    let mut paths = Vec::new();
+    for spec in teapot_specs() {
+        let title = format!("{} {}", manifest.name, spec.id);
+        let mut page = Html5Page::new(&title, (spec.width as u32, spec.height as u32), WASM_BUILD_FILES[0]);
+        for click_tag in click_config.click_tags.keys() {
+            page = page.with_click_tag(click_tag, &click_config.landing_url(click_tag)?);
+        }
+        let mut zip = ZipWriter::new();
+        let mut weight = LoadWeight::new(&manifest.name, &spec.id);
+        let html = page.to_html()?;
+        zip.add_file(HTML5_INDEX, html.as_bytes())?;
+        weight.add_data(HTML5_INDEX, AssetCategory::Code, LoadPhase::Initial, html.as_bytes())?;
+        // The bundled fonts are compiled into the wasm build, so they are weighed as part of it
+        for (name, data) in build.iter() {
+            zip.add_file(name, data)?;
+            weight.add_data(name, AssetCategory::Code, LoadPhase::Initial, data)?;
+        }
+        // The build loads its config and assets from the default asset root next to index.html
+        for (name, data) in config_files.iter() {
+            zip.add_file(name, data)?;
+            weight.add_data(name, AssetCategory::Code, LoadPhase::Initial, data)?;
+        }
+        let font_files = kit_fonts.iter().map(|font| (font.path.as_str(), AssetCategory::Font, font.load));
+        for (path, category, phase) in manifest.files_for_spec(&spec.id).into_iter().chain(font_files) {
+            let data = fs::read(Path::new(&asset_root).join(path))?;
+            let name = format!("{}/{}", DEFAULT_ASSET_ROOT, path);
+            zip.add_file(&name, &data)?;
+            weight.add_data(&name, category, phase, &data)?;
+        }
+        let zip_path = out_dir.as_ref().join(format!("{}-{}.zip", manifest.name.to_lowercase(), spec.id));
+        fs::write(&zip_path, zip.finish()?)?;
+        log::info!("Exported {}", zip_path.display());
+
+        let rules = lint_config.rules_for(&spec.id);
+        let report = LoadWeightReport::new(weight, rules.max_initial_load_kb, rules.max_polite_load_kb);
+        log::info!("{}", report);
+        if !report.is_within_budgets() {
+            log::warn!("{} is over its load budget", zip_path.display());
+        }
+        paths.push(zip_path);
+    }
+    Ok(paths)
+}
+
+/// Split the text into the parts before, at and after the first occurrence of the accent. Without an accent, the
+/// whole text is before.
+fn split_accent<'a>(text: &'a str, accent: Option<&str>) -> (&'a str, &'a str, &'a str) {
+    match accent.and_then(|accent| text.find(accent).map(|start| (start, start + accent.len()))) {
+        Some((start, end)) => (&text[..start], &text[start..end], &text[end..]),
+        None => (text, "", ""),
+    }
+}
+
+/// Add the text around an accent span to the block, skipping empty parts
+fn add_accented(block: TextBlock, before: &str, accent: TextSpan, after: &str) -> TextBlock {
+    let mut block = block;
+    if !before.is_empty() {
+        block = block.text(before);
+    }
+    if !accent.text.is_empty() {
+        block = block.span(accent);
+    }
+    if !after.is_empty() {
+        block = block.text(after);
+    }
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_rate_rule_runs_with_a_frame_cap() {
+        let spec = AdSpec::new(300, 250, "Medium Rectangle");
+        let frame_rate_status = |frame_rate: Option<f64>| {
+            let builder = TeapotAdBuilder::new(BrandKit::default()).with_frame_rate(frame_rate);
+            let report = lint(&builder.lint_facts(&spec), &LintRules::default());
+            report.checks.iter().find(|check| check.rule == "frame rate").map(|check| check.status)
+        };
+        assert_eq!(frame_rate_status(None), Some(LintStatus::Skipped));
+        assert_eq!(frame_rate_status(Some(24.0)), Some(LintStatus::Pass));
+        assert_eq!(frame_rate_status(Some(60.0)), Some(LintStatus::Fail));
+    }
+}